@@ -0,0 +1,77 @@
+//! Generates the man pages of colb and its verbs from the command line interface, into
+//! `$OUT_DIR/man` and, for packaging, into `$COLB_MAN_DIR` if set
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::CommandFactory;
+use clap_mangen::Man;
+use roff::{roman, Roff};
+
+#[path = "src/cli.rs"]
+#[allow(dead_code)]
+mod cli;
+
+/// The recipes for `verb` as an EXAMPLES section
+fn examples(verb: &str) -> Roff {
+    let mut roff = Roff::new();
+    let examples = cli::examples(verb);
+    if examples.is_empty() {
+        return roff;
+    }
+    roff.control("SH", ["EXAMPLES"]);
+    for (description, command) in examples {
+        roff.control("PP", []).text([roman(*description)]);
+        roff.control("RS", [])
+            .control("nf", [])
+            .text([roman(*command)])
+            .control("fi", [])
+            .control("RE", []);
+    }
+    roff
+}
+
+/// Write the man page of `cmd` and of all its visible subcommands, `verb` names the verb the
+/// examples are looked up for
+fn generate(cmd: &clap::Command, verb: Option<&str>, dir: &Path) -> io::Result<()> {
+    for sub in cmd.get_subcommands().filter(|s| !s.is_hide_set()) {
+        let nested = verb.is_none().then_some(sub.get_name());
+        generate(sub, nested, dir)?;
+    }
+    let man = Man::new(cmd.clone());
+    let mut file = fs::File::create(dir.join(man.get_filename()))?;
+    man.render_title(&mut file)?;
+    man.render_name_section(&mut file)?;
+    man.render_synopsis_section(&mut file)?;
+    man.render_description_section(&mut file)?;
+    if cmd.get_arguments().any(|a| !a.is_hide_set()) {
+        man.render_options_section(&mut file)?;
+    }
+    if cmd.get_subcommands().any(|s| !s.is_hide_set()) {
+        man.render_subcommands_section(&mut file)?;
+    }
+    if let Some(verb) = verb {
+        examples(verb).to_writer(&mut file)?;
+    }
+    if cmd.get_version().is_some() {
+        man.render_version_section(&mut file)?;
+    }
+    file.flush()
+}
+
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed=src/cli.rs");
+    println!("cargo:rerun-if-env-changed=COLB_MAN_DIR");
+    let mut cmd = cli::Cli::command().disable_help_subcommand(true);
+    cmd.build();
+    let mut dirs = vec![PathBuf::from(env::var_os("OUT_DIR").expect("set by cargo")).join("man")];
+    dirs.extend(env::var_os("COLB_MAN_DIR").map(PathBuf::from));
+    for dir in dirs {
+        fs::create_dir_all(&dir)?;
+        generate(&cmd, None, &dir)?;
+    }
+    Ok(())
+}
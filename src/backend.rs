@@ -0,0 +1,61 @@
+//! The interface between colb and the tools building packages.
+//!
+//! colb resolves what to build and with which arguments, a [`Backend`] turns that into the
+//! commands carrying it out. Backends can wrap other backends, e.g. to run their commands in a
+//! container or on another machine.
+
+use std::process::Command;
+
+/// The packages to build and how, as colb resolved them from the configuration
+pub struct Build<'a> {
+    pub workspace: &'a str,
+    /// colcon build arguments: bases, executor, event handlers, mixins and CMake arguments
+    pub args: &'a [String],
+    /// colcon package selection arguments, e.g. `--packages-select my_package`
+    pub selection: &'a [String],
+    /// Variables to set, e.g. from the `environment_setup` script
+    pub env: &'a [(String, String)],
+}
+
+/// Turns builds into the commands that carry them out. colb prints and runs them one after the
+/// other, until one fails
+pub trait Backend: Send + Sync {
+    /// The name the backend is selected by in the configuration
+    fn name(&self) -> &str;
+
+    /// The commands building the selected packages, run in order until one fails
+    fn build(&self, build: &Build) -> Vec<Command>;
+
+    /// The commands rebuilding a single target of an already configured package,
+    /// the fast path for running single tests
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command>;
+
+    /// Packages the backend builds that colcon doesn't find in the workspace, like Bazel targets.
+    /// They are built before the packages colcon selects, which may depend on them
+    fn packages(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// The packages colcon finds that `package`, one of [`Backend::packages`], depends on. They are
+    /// built with the dependencies before it
+    fn dependencies(&self, _package: &str) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// `cmd` appended to the arguments of `outer`, run in the same folder and with the same variables,
+/// for backends running the commands of others through another tool
+pub fn wrap(cmd: &Command, mut outer: Command) -> Command {
+    let res = &mut outer;
+    res.arg(cmd.get_program()).args(cmd.get_args());
+    if let Some(dir) = cmd.get_current_dir() {
+        res.current_dir(dir);
+    }
+    for (k, v) in cmd.get_envs() {
+        match v {
+            Some(v) => res.env(k, v),
+            None => res.env_remove(k),
+        };
+    }
+    outer
+}
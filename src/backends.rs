@@ -0,0 +1,672 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsStr,
+    fs,
+    os::unix::fs::MetadataExt,
+    path::Path,
+    process::{Command, ExitStatus},
+    sync::OnceLock,
+    thread,
+};
+
+pub use colb::backend::{Backend, Build};
+use serde::{Deserialize, Serialize};
+
+use crate::{bases, bazel, colcon_args, config_diff, environment, output::*, targets};
+
+/// The build tool CMake generated a build folder for
+pub enum Generator {
+    Ninja,
+    Make,
+}
+
+impl Generator {
+    /// The generator cached in the folder's `CMakeCache.txt`, or else whether it has a `Makefile`
+    /// but no `build.ninja`
+    pub fn of(build_dir: &Path) -> Generator {
+        let cached = fs::read_to_string(build_dir.join("CMakeCache.txt"))
+            .ok()
+            .and_then(|cache| {
+                cache
+                    .lines()
+                    .find_map(|l| l.strip_prefix("CMAKE_GENERATOR:INTERNAL="))
+                    .map(String::from)
+            });
+        match cached {
+            Some(generator) if generator.ends_with("Makefiles") => Generator::Make,
+            Some(_) => Generator::Ninja,
+            None if build_dir.join("Makefile").exists()
+                && !build_dir.join("build.ninja").exists() =>
+            {
+                Generator::Make
+            }
+            None => Generator::Ninja,
+        }
+    }
+
+    /// A command building `target` in the build folder, in parallel like ninja does by default
+    pub fn build(&self, build_dir: &Path, target: &str) -> Command {
+        let mut cmd = match self {
+            Generator::Ninja => environment::tool("ninja"),
+            Generator::Make => environment::tool("make"),
+        };
+        cmd.arg("-C").arg(build_dir);
+        if let Generator::Make = self {
+            let jobs = thread::available_parallelism().map_or(1, |n| n.get());
+            cmd.arg(format!("-j{jobs}"));
+        }
+        cmd.arg(target);
+        cmd
+    }
+}
+
+/// Builds everything through colcon and single targets with ninja or make in the package's build folder
+pub struct Colcon;
+
+impl Backend for Colcon {
+    fn name(&self) -> &str {
+        "colcon"
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let mut cmd = environment::colcon();
+        cmd.current_dir(build.workspace);
+        cmd.envs(build.env.iter().map(|(k, v)| (k, v)));
+        cmd.args(colcon_args(build.args));
+        cmd.args(build.selection);
+        vec![cmd]
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        let build_dir = Path::new(workspace).join(bases::build()).join(package);
+        vec![Generator::of(&build_dir).build(&build_dir, target)]
+    }
+}
+
+/// Run the commands of a backend one after the other. Their output goes through colcon's event
+/// parsing, so colcon reports its progress also when a wrapper runs it
+pub fn run(commands: Vec<Command>) -> ExitStatus {
+    for mut cmd in commands {
+        let program = cmd.get_program().to_string_lossy().to_string();
+        print_command(&cmd);
+        let status = crate::output::run_colcon(&mut cmd)
+            .unwrap_or_else(|e| panic!("Couldn't run '{program}': {e}"));
+        if !status.success() {
+            return status;
+        }
+    }
+    ExitStatus::default()
+}
+
+/// A plain CMake project rather than a ROS package
+pub fn is_plain_cmake(source: &Path) -> bool {
+    !source.join("package.xml").exists() && source.join("CMakeLists.txt").exists()
+}
+
+/// Environment hooks colcon writes for CMake packages, by name
+const HOOKS: &[(&str, &str)] = &[
+    (
+        "cmake_prefix_path",
+        "prepend-non-duplicate;CMAKE_PREFIX_PATH;",
+    ),
+    (
+        "ld_library_path_lib",
+        "prepend-non-duplicate;LD_LIBRARY_PATH;lib",
+    ),
+    ("path", "prepend-non-duplicate-if-exists;PATH;bin"),
+    (
+        "pkg_config_path",
+        "prepend-non-duplicate-if-exists;PKG_CONFIG_PATH;lib/pkgconfig",
+    ),
+];
+
+/// The command registering `package` installed into `prefix` like colcon does, so the setup
+/// scripts of the install base find it and add its folders to `CMAKE_PREFIX_PATH`, `PATH`,
+/// `LD_LIBRARY_PATH` and `PKG_CONFIG_PATH`
+pub fn register(prefix: &Path, package: &str) -> Command {
+    let quote = |path: &Path| shell_quote(&path.to_string_lossy());
+    let share = prefix.join("share");
+    let packages = share.join("colcon-core").join("packages");
+    let hooks = share.join(package).join("hook");
+    // An empty list of runtime dependencies, colb doesn't know them without a package.xml
+    let mut script = format!(
+        "set -e\nmkdir -p {} {}\n: > {}\n",
+        quote(&packages),
+        quote(&hooks),
+        quote(&packages.join(package))
+    );
+    let mut dsv = String::new();
+    for (name, line) in HOOKS {
+        script.push_str(&format!(
+            "printf '%s\\n' {} > {}\n",
+            shell_quote(line),
+            quote(&hooks.join(format!("{name}.dsv")))
+        ));
+        dsv.push_str(&format!("source;share/{package}/hook/{name}.dsv\n"));
+    }
+    script.push_str(&format!(
+        "printf %s {} > {}\n",
+        shell_quote(&dsv),
+        quote(&share.join(package).join("package.dsv"))
+    ));
+    let mut cmd = environment::tool("sh");
+    cmd.arg("-c").arg(script);
+    cmd
+}
+
+/// CMake arguments of the mixins colb configures by default, which need colcon otherwise
+fn mixin_args(mixin: &str) -> Option<&'static [&'static str]> {
+    Some(match mixin {
+        "ninja" => &["-G", "Ninja"],
+        "ccache" => &[
+            "-DCMAKE_C_COMPILER_LAUNCHER=ccache",
+            "-DCMAKE_CXX_COMPILER_LAUNCHER=ccache",
+        ],
+        "compile-commands" => &["-DCMAKE_EXPORT_COMPILE_COMMANDS=ON"],
+        "mold" => &["-DCMAKE_LINKER_TYPE=MOLD"],
+        "debug" => &["-DCMAKE_BUILD_TYPE=Debug"],
+        "release" => &["-DCMAKE_BUILD_TYPE=Release"],
+        "rel-with-deb-info" => &["-DCMAKE_BUILD_TYPE=RelWithDebInfo"],
+        _ => return None,
+    })
+}
+
+/// Configures, builds and installs plain CMake projects with cmake itself, into the same build
+/// and install bases colcon would use. The colcon arguments are translated where CMake has an
+/// equivalent, e.g. `--cmake-args` and the common mixins
+pub struct DirectCmake;
+
+impl Backend for DirectCmake {
+    fn name(&self) -> &str {
+        "direct-cmake"
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let flags = config_diff::flags(build.args);
+        let values = |name: &str| {
+            flags
+                .iter()
+                .filter(|(n, _)| n == name)
+                .flat_map(|(_, v)| v.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        let has = |name: &str| flags.iter().any(|(n, _)| n == name);
+        let workspace = Path::new(build.workspace);
+        let build_base = workspace.join(values("--build-base").pop().unwrap_or("build".into()));
+        let install_base =
+            workspace.join(values("--install-base").pop().unwrap_or("install".into()));
+        let merged = has("--merge-install");
+        let mut cmake_args = vec![];
+        for mixin in values("--mixin") {
+            match mixin_args(&mixin) {
+                Some(args) => cmake_args.extend(args.iter().map(|a| a.to_string())),
+                None => eprintln!("Ignoring the '{mixin}' mixin, it needs colcon"),
+            }
+        }
+        cmake_args.extend(values("--cmake-args"));
+        // Find the packages installed so far, like sourcing their setup scripts would
+        let mut prefixes = if merged {
+            vec![install_base.clone()]
+        } else {
+            fs::read_dir(&install_base)
+                .map(|entries| entries.flatten().map(|e| e.path()).collect())
+                .unwrap_or_default()
+        };
+        prefixes.extend(
+            env::var_os("CMAKE_PREFIX_PATH")
+                .iter()
+                .flat_map(env::split_paths),
+        );
+        let prefix_path = env::join_paths(prefixes).unwrap_or_default();
+        let selection = build
+            .selection
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let mut res = vec![];
+        for (package, source) in crate::colcon_list(build.workspace, &selection) {
+            let build_dir = build_base.join(&package);
+            let install_dir = if merged {
+                install_base.clone()
+            } else {
+                install_base.join(&package)
+            };
+            let cmake = |args: &[&OsStr]| {
+                let mut cmd = environment::tool("cmake");
+                cmd.current_dir(workspace);
+                cmd.envs(build.env.iter().map(|(k, v)| (k, v)));
+                cmd.env("CMAKE_PREFIX_PATH", &prefix_path);
+                cmd.args(args);
+                cmd
+            };
+            let mut configure = cmake(&[
+                "-S".as_ref(),
+                source.as_ref(),
+                "-B".as_ref(),
+                build_dir.as_ref(),
+            ]);
+            configure.arg(format!(
+                "-DCMAKE_INSTALL_PREFIX={}",
+                install_dir.to_string_lossy()
+            ));
+            configure.args(&cmake_args);
+            res.push(configure);
+            let mut compile = cmake(&["--build".as_ref(), build_dir.as_ref()]);
+            if let Some(n) = values("--parallel-workers").pop() {
+                compile.args(["--parallel", &n]);
+            }
+            let targets = values("--cmake-target");
+            if !targets.is_empty() {
+                // Like colcon, only build the targets and don't install. Targets that may be
+                // unavailable (e.g. to only configure) are left out, cmake can't skip them
+                if !has("--cmake-target-skip-unavailable") {
+                    compile.arg("--target").args(targets);
+                    res.push(compile);
+                }
+                continue;
+            }
+            res.push(compile);
+            res.push(cmake(&["--install".as_ref(), build_dir.as_ref()]));
+            let mut register = register(&install_dir, &package);
+            register.current_dir(workspace);
+            res.push(register);
+        }
+        res
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        let mut cmd = environment::tool("cmake");
+        cmd.arg("--build");
+        cmd.arg(format!(
+            "{workspace}/{}/{package}",
+            bases::build().to_string_lossy()
+        ));
+        cmd.args(["--target", target]);
+        vec![cmd]
+    }
+}
+
+/// Builds with a program taking the arguments of colcon, e.g. a script distributing the build.
+/// Single targets are rebuilt in the package's build folder, like with colcon
+pub struct External {
+    pub name: String,
+    pub command: Vec<String>,
+}
+
+impl Backend for External {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let Some((program, args)) = self.command.split_first() else {
+            return vec![];
+        };
+        let mut cmd = environment::tool(program);
+        cmd.current_dir(build.workspace);
+        cmd.envs(build.env.iter().map(|(k, v)| (k, v)));
+        cmd.args(args).args(build.args).args(build.selection);
+        vec![cmd]
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        Colcon.build_target(workspace, package, target)
+    }
+}
+
+/// Runs the commands of another backend through the `prefix` of [`sandbox::prefix`], without
+/// network access
+pub struct Sandboxed {
+    pub prefix: Vec<String>,
+    pub inner: Box<dyn Backend>,
+}
+
+impl Sandboxed {
+    fn wrap(&self, cmd: &Command) -> Command {
+        let mut outer = environment::tool(&self.prefix[0]);
+        outer.args(&self.prefix[1..]);
+        colb::backend::wrap(cmd, outer)
+    }
+}
+
+impl Backend for Sandboxed {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let commands = self.inner.build(build);
+        commands.iter().map(|cmd| self.wrap(cmd)).collect()
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        let commands = self.inner.build_target(workspace, package, target);
+        commands.iter().map(|cmd| self.wrap(cmd)).collect()
+    }
+
+    fn packages(&self) -> Vec<String> {
+        self.inner.packages()
+    }
+
+    fn dependencies(&self, package: &str) -> Vec<String> {
+        self.inner.dependencies(package)
+    }
+}
+
+/// Runs the commands of another backend in a container of `image` with podman or docker. The
+/// workspace is mounted at the same path, so the build and install folders work outside as well
+pub struct Container {
+    pub image: String,
+    pub inner: Box<dyn Backend>,
+}
+
+impl Container {
+    fn wrap(&self, workspace: &str, cmd: &Command) -> Command {
+        let runtime = if environment::which("podman").is_some() {
+            "podman"
+        } else {
+            "docker"
+        };
+        let workspace = fs::canonicalize(workspace).unwrap_or(workspace.into());
+        let mount = workspace.to_string_lossy();
+        let mut outer = environment::tool(runtime);
+        outer.args(["run", "--rm", "--volume", &format!("{mount}:{mount}")]);
+        outer
+            .arg("--workdir")
+            .arg(cmd.get_current_dir().unwrap_or(&workspace));
+        // Files in the workspace stay owned by its owner rather than the container's root
+        if let Ok(meta) = fs::metadata(&workspace) {
+            outer.args(["--user", &format!("{}:{}", meta.uid(), meta.gid())]);
+        }
+        for (k, v) in cmd.get_envs() {
+            if let Some(v) = v {
+                outer
+                    .arg("--env")
+                    .arg(format!("{}={}", k.to_string_lossy(), v.to_string_lossy()));
+            }
+        }
+        outer.arg(&self.image);
+        colb::backend::wrap(cmd, outer)
+    }
+}
+
+impl Backend for Container {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let commands = self.inner.build(build);
+        commands
+            .iter()
+            .map(|cmd| self.wrap(build.workspace, cmd))
+            .collect()
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        let commands = self.inner.build_target(workspace, package, target);
+        commands
+            .iter()
+            .map(|cmd| self.wrap(workspace, cmd))
+            .collect()
+    }
+
+    fn packages(&self) -> Vec<String> {
+        self.inner.packages()
+    }
+
+    fn dependencies(&self, package: &str) -> Vec<String> {
+        self.inner.dependencies(package)
+    }
+}
+
+/// Runs the commands of another backend on `host` over ssh. The workspace is synced to the same
+/// path there with rsync before, and the build and install bases are synced back after building
+pub struct Remote {
+    pub host: String,
+    pub inner: Box<dyn Backend>,
+}
+
+impl Remote {
+    fn workspace(workspace: &str) -> String {
+        fs::canonicalize(workspace)
+            .map(|w| w.to_string_lossy().to_string())
+            .unwrap_or(workspace.into())
+    }
+
+    fn rsync(from: &str, to: &str) -> Command {
+        let mut cmd = environment::tool("rsync");
+        cmd.args(["--archive", "--delete"]).arg(from).arg(to);
+        cmd
+    }
+
+    fn wrap(&self, workspace: &str, cmd: &Command) -> Command {
+        let dir = cmd
+            .get_current_dir()
+            .map(|d| d.to_string_lossy().to_string())
+            .unwrap_or(Self::workspace(workspace));
+        let mut script = format!("cd {} && env", shell_quote(&dir));
+        for (k, v) in cmd.get_envs() {
+            if let Some(v) = v {
+                script.push(' ');
+                script.push_str(&shell_quote(&format!(
+                    "{}={}",
+                    k.to_string_lossy(),
+                    v.to_string_lossy()
+                )));
+            }
+        }
+        script.push(' ');
+        script.push_str(&command_line(cmd));
+        let mut res = environment::tool("ssh");
+        res.arg(&self.host).arg(script);
+        res
+    }
+
+    /// Sync the sources there, without what colcon generates
+    fn push(&self, workspace: &str) -> Command {
+        let workspace = Self::workspace(workspace);
+        let mut cmd = Self::rsync(
+            &format!("{workspace}/"),
+            &format!("{}:{workspace}/", self.host),
+        );
+        for generated in [bases::build(), bases::install(), "log".into()] {
+            cmd.arg(format!("--exclude=/{}/", generated.to_string_lossy()));
+        }
+        cmd
+    }
+
+    /// Sync `folder` of the workspace back, e.g. the install base
+    fn pull(&self, workspace: &str, folder: &Path) -> Command {
+        let folder = Path::new(&Self::workspace(workspace)).join(folder);
+        let folder = folder.to_string_lossy();
+        Self::rsync(&format!("{}:{folder}/", self.host), &format!("{folder}/"))
+    }
+}
+
+impl Backend for Remote {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let mut res = vec![self.push(build.workspace)];
+        let commands = self.inner.build(build);
+        res.extend(commands.iter().map(|cmd| self.wrap(build.workspace, cmd)));
+        // Tests run here, from the build folders
+        res.push(self.pull(build.workspace, &bases::build()));
+        res.push(self.pull(build.workspace, &bases::install()));
+        res
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        let mut res = vec![self.push(workspace)];
+        let commands = self.inner.build_target(workspace, package, target);
+        res.extend(commands.iter().map(|cmd| self.wrap(workspace, cmd)));
+        res.push(self.pull(workspace, &bases::build().join(package)));
+        res
+    }
+
+    fn packages(&self) -> Vec<String> {
+        self.inner.packages()
+    }
+
+    fn dependencies(&self, package: &str) -> Vec<String> {
+        self.inner.dependencies(package)
+    }
+}
+
+/// Which backend builds which package, and where the build commands run
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct BackendsConfig {
+    /// Backends by package name pattern with `*` and `?`, e.g. `{ "vendor_*" = "direct-cmake" }`.
+    /// The longest matching pattern wins
+    #[serde(default)]
+    pub packages: BTreeMap<String, String>,
+    /// Programs taking the arguments of colcon, by the backend name they are selected with
+    #[serde(default)]
+    pub commands: BTreeMap<String, Vec<String>>,
+    /// Run every build command in a container of this image
+    pub container: Option<String>,
+    /// Build on this host over ssh
+    pub remote: Option<String>,
+}
+
+/// What a backend is picked for
+enum Rule {
+    /// Packages whose name matches the pattern
+    Pattern(String),
+    /// The packages the backend builds itself, see [`Backend::packages`]
+    Own,
+    /// CMake projects without a package.xml
+    PlainCmake,
+}
+
+/// The configured backends, each wrapped into the sandbox, container and remote backends if
+/// enabled, and the rules selecting them
+struct Backends {
+    rules: Vec<(Rule, Box<dyn Backend>)>,
+    colcon: Box<dyn Backend>,
+}
+
+static BACKENDS: OnceLock<Backends> = OnceLock::new();
+
+/// Set up the backends from the configuration, from now on [`of`] picks one for each package
+pub fn configure(
+    config: &BackendsConfig,
+    bazel: &BTreeMap<String, bazel::BazelPackage>,
+    direct_cmake: bool,
+    sandbox: Option<Vec<String>>,
+) -> Result<(), String> {
+    let named = |name: &str| -> Result<Box<dyn Backend>, String> {
+        let backend: Box<dyn Backend> = match name {
+            "colcon" => Box::new(Colcon),
+            "direct-cmake" => Box::new(DirectCmake),
+            "bazel" => Box::new(bazel::Bazel {
+                packages: bazel.clone(),
+            }),
+            _ => match config.commands.get(name) {
+                Some(command) if !command.is_empty() => Box::new(External {
+                    name: name.into(),
+                    command: command.clone(),
+                }),
+                _ => {
+                    let mut known = ["colcon", "direct-cmake", "bazel"]
+                        .map(String::from)
+                        .to_vec();
+                    known.extend(config.commands.keys().cloned());
+                    return Err(format!(
+                        "Unknown backend '{name}', known are '{}'",
+                        known.join("', '")
+                    ));
+                }
+            },
+        };
+        let mut backend = backend;
+        if let Some(prefix) = &sandbox {
+            backend = Box::new(Sandboxed {
+                prefix: prefix.clone(),
+                inner: backend,
+            });
+        }
+        if let Some(image) = &config.container {
+            backend = Box::new(Container {
+                image: image.clone(),
+                inner: backend,
+            });
+        }
+        if let Some(host) = &config.remote {
+            backend = Box::new(Remote {
+                host: host.clone(),
+                inner: backend,
+            });
+        }
+        Ok(backend)
+    };
+    let mut patterns = config.packages.iter().collect::<Vec<_>>();
+    patterns.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.len()));
+    let mut rules = vec![];
+    for (pattern, name) in patterns {
+        rules.push((Rule::Pattern(pattern.clone()), named(name)?));
+    }
+    if !bazel.is_empty() {
+        rules.push((Rule::Own, named("bazel")?));
+    }
+    if direct_cmake {
+        rules.push((Rule::PlainCmake, named("direct-cmake")?));
+    }
+    let _ = BACKENDS.set(Backends {
+        rules,
+        colcon: named("colcon")?,
+    });
+    Ok(())
+}
+
+fn backends() -> &'static Backends {
+    BACKENDS.get_or_init(|| Backends {
+        rules: vec![],
+        colcon: Box::new(Colcon),
+    })
+}
+
+/// The backend building `package`, whose sources are in `source` if it was listed
+pub fn of(package: &str, source: Option<&Path>) -> &'static dyn Backend {
+    let backends = backends();
+    backends
+        .rules
+        .iter()
+        .find(|(rule, backend)| match rule {
+            Rule::Pattern(pattern) => targets::glob(pattern, package),
+            Rule::Own => backend.packages().iter().any(|p| p == package),
+            Rule::PlainCmake => source.is_some_and(is_plain_cmake),
+        })
+        .map_or(backends.colcon.as_ref(), |(_, backend)| backend.as_ref())
+}
+
+/// The backend building the packages colcon selects when no other one is configured
+pub fn colcon() -> &'static dyn Backend {
+    backends().colcon.as_ref()
+}
+
+/// Whether other backends than colcon are configured, so packages need to be listed to pick theirs
+pub fn selective() -> bool {
+    !backends().rules.is_empty()
+}
+
+/// Whether picking the backend of a package needs its sources
+pub fn needs_source() -> bool {
+    backends()
+        .rules
+        .iter()
+        .any(|(rule, _)| matches!(rule, Rule::PlainCmake))
+}
+
+/// Packages built by backends which colcon doesn't find, like Bazel targets
+pub fn own_packages() -> Vec<String> {
+    backends()
+        .rules
+        .iter()
+        .flat_map(|(_, backend)| backend.packages())
+        .collect()
+}
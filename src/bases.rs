@@ -0,0 +1,71 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+static PREFIX: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `<prefix>/build` and `<prefix>/install` instead of `build` and `install` from now on,
+/// relative to the workspace
+pub fn set_prefix(prefix: &str) {
+    let _ = PREFIX.set(prefix.into());
+}
+
+fn base(name: &str) -> PathBuf {
+    match PREFIX.get() {
+        Some(prefix) => prefix.join(name),
+        None => name.into(),
+    }
+}
+
+/// The build base, relative to the workspace
+pub fn build() -> PathBuf {
+    base("build")
+}
+
+/// The install base, relative to the workspace
+pub fn install() -> PathBuf {
+    base("install")
+}
+
+/// Whether colcon installed the packages into one prefix, with `--merge-install`
+pub fn is_merged(install: &Path) -> bool {
+    fs::read_to_string(install.join(".colcon_install_layout")).is_ok_and(|l| l.trim() == "merged")
+}
+
+/// The install prefix of `package` in the workspace: the install base itself in a merged install
+/// space, the package's own folder in it otherwise
+pub fn package_install(workspace: &Path, package: &str) -> PathBuf {
+    let install = workspace.join(install());
+    if is_merged(&install) {
+        install
+    } else {
+        install.join(package)
+    }
+}
+
+/// Where colb keeps what it knows about the installed `package`, e.g. what it was built from. In a
+/// merged install space the prefix is shared, so it's the package's share folder
+pub fn package_state(workspace: &Path, package: &str) -> PathBuf {
+    let install = workspace.join(install());
+    if is_merged(&install) {
+        install.join("share").join(package)
+    } else {
+        install.join(package)
+    }
+}
+
+/// Whether `package` is installed in the workspace, colcon registers every package it installs in
+/// `share/colcon-core/packages`
+pub fn is_installed(workspace: &Path, package: &str) -> bool {
+    let prefix = package_install(workspace, package);
+    if is_merged(&workspace.join(install())) {
+        prefix
+            .join("share/colcon-core/packages")
+            .join(package)
+            .exists()
+    } else {
+        prefix.is_dir()
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+use colb::backend::{Backend, Build};
+
+use crate::{backends, bases, environment, output::shell_quote};
+
+/// Bazel targets colb builds, installs and tests as if they were a package
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct BazelPackage {
+    /// Folder of the Bazel workspace, relative to the colcon workspace (default: the colcon workspace)
+    pub directory: Option<String>,
+    /// Labels to build and install, e.g. `//perception:detector`
+    pub targets: Vec<String>,
+    /// Labels to run with `bazel test` (default: `targets`)
+    #[serde(default)]
+    pub tests: Vec<String>,
+    /// colcon packages the targets use, built with the dependencies before them
+    #[serde(default)]
+    pub depends: Vec<String>,
+}
+
+impl BazelPackage {
+    fn directory(&self, workspace: &str) -> PathBuf {
+        match &self.directory {
+            Some(dir) => Path::new(workspace).join(dir),
+            None => workspace.into(),
+        }
+    }
+
+    fn bazel(&self, workspace: &str) -> Command {
+        let mut cmd = environment::tool("bazel");
+        cmd.current_dir(self.directory(workspace));
+        cmd
+    }
+
+    /// Run the tests of the package, only those matching `filter` if given
+    pub fn test(&self, workspace: &str, filter: Option<&str>) -> Command {
+        let mut cmd = self.bazel(workspace);
+        cmd.arg("test");
+        if let Some(filter) = filter {
+            cmd.arg(format!("--test_filter={filter}"));
+        }
+        if self.tests.is_empty() {
+            cmd.args(&self.targets);
+        } else {
+            cmd.args(&self.tests);
+        }
+        cmd
+    }
+}
+
+/// Where Bazel puts the output of a label like `//a/b:c` (or `//a/b`, short for `//a/b:b`)
+fn output(label: &str) -> Option<PathBuf> {
+    let label = label.strip_prefix("//")?;
+    let (package, name) = match label.split_once(':') {
+        Some((package, name)) => (package, name),
+        None => (label, label.rsplit('/').next()?),
+    };
+    Some(Path::new("bazel-bin").join(package).join(name))
+}
+
+/// Builds Bazel targets and links their outputs into the install base like a package, so the
+/// workspace's setup scripts put them on PATH and LD_LIBRARY_PATH
+pub struct Bazel {
+    pub packages: BTreeMap<String, BazelPackage>,
+}
+
+impl Bazel {
+    /// Link the outputs of the package into its install folder and register it for colcon's setup
+    /// scripts
+    fn install(&self, build: &Build, name: &str, package: &BazelPackage) -> Vec<Command> {
+        let workspace = Path::new(build.workspace);
+        let install_base = workspace.join(bases::install());
+        let prefix = if build.args.iter().any(|a| a == "--merge-install") {
+            install_base
+        } else {
+            install_base.join(name)
+        };
+        let quote = |path: &Path| shell_quote(&path.to_string_lossy());
+        let mut script = format!(
+            "set -e\nmkdir -p {} {}\n",
+            quote(&prefix.join("bin")),
+            quote(&prefix.join("lib"))
+        );
+        for label in &package.targets {
+            let Some(output) = output(label) else {
+                eprintln!(
+                    "Skipping '{label}', only absolute labels (//package:target) can be installed"
+                );
+                continue;
+            };
+            let file = output.file_name().unwrap_or_default().to_string_lossy();
+            let dir = if file.ends_with(".so") || file.contains(".so.") || file.ends_with(".a") {
+                "lib"
+            } else {
+                "bin"
+            };
+            script.push_str(&format!(
+                "ln -sfn {} {}\n",
+                quote(&package.directory(build.workspace).join(&output)),
+                quote(&prefix.join(dir).join(file.as_ref()))
+            ));
+        }
+        let mut cmd = environment::tool("sh");
+        cmd.arg("-c").arg(script);
+        vec![cmd, backends::register(&prefix, name)]
+    }
+}
+
+impl Backend for Bazel {
+    fn name(&self) -> &str {
+        "bazel"
+    }
+
+    fn build(&self, build: &Build) -> Vec<Command> {
+        let mut res = vec![];
+        for name in build.selection.iter().filter(|s| !s.starts_with("--")) {
+            let Some(package) = self.packages.get(name) else {
+                continue;
+            };
+            let mut cmd = package.bazel(build.workspace);
+            cmd.envs(build.env.iter().map(|(k, v)| (k, v)));
+            cmd.arg("build").args(&package.targets);
+            res.push(cmd);
+            res.extend(self.install(build, name, package));
+        }
+        res
+    }
+
+    fn build_target(&self, workspace: &str, package: &str, target: &str) -> Vec<Command> {
+        let Some(package) = self.packages.get(package) else {
+            return vec![];
+        };
+        let mut cmd = package.bazel(workspace);
+        cmd.arg("build").arg(target);
+        vec![cmd]
+    }
+
+    fn packages(&self) -> Vec<String> {
+        self.packages.keys().cloned().collect()
+    }
+
+    fn dependencies(&self, package: &str) -> Vec<String> {
+        self.packages
+            .get(package)
+            .map(|p| p.depends.clone())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,916 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::environment;
+
+/// Name of the file in an install folder that records which cache entry it matches
+const KEY_FILE: &str = ".colb_cache_key";
+/// Name of the file in an install folder that records the interface it was built from
+const INTERFACE_FILE: &str = ".colb_interface_key";
+/// Name of the file in a build folder that records the sources without comments it was built from
+const SEMANTIC_FILE: &str = ".colb_semantic_key";
+
+/// Files that make up the interface of a package towards its dependents
+const INTERFACE_EXTENSIONS: &[&str] = &[
+    "h", "hh", "hpp", "hxx", "inl", "ipp", "tpp", "msg", "srv", "action", "idl", "cmake",
+];
+const INTERFACE_FILES: &[&str] = &["package.xml", "CMakeLists.txt"];
+
+/// Files with `//` and `/* */` comments, see [`Syntax`]
+const C_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cpp", "cxx", "h", "hh", "hpp", "hxx", "inl", "ipp", "tpp",
+];
+/// Interface definitions with `#` comments
+const HASH_EXTENSIONS: &[&str] = &["msg", "srv", "action"];
+
+fn is_interface(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    INTERFACE_FILES.contains(&name.as_ref()) || INTERFACE_EXTENSIONS.contains(&extension.as_ref())
+}
+
+/// A content addressed store of per-package install folders
+pub struct InstallCache {
+    dir: PathBuf,
+}
+
+/// Where cache entries are shared, e.g. by CI
+pub enum Remote {
+    /// Plain HTTP(S): GET to download, PUT to upload
+    Http(String),
+    /// An S3 bucket and prefix, accessed through the aws cli
+    S3(String),
+    /// A folder on another machine, accessed through scp
+    Ssh(String),
+}
+
+fn quiet(mut cmd: Command) -> bool {
+    cmd.stdout(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+impl Remote {
+    pub fn parse(url: &str) -> Remote {
+        let url = url.trim_end_matches('/');
+        if url.starts_with("http://") || url.starts_with("https://") {
+            Remote::Http(url.into())
+        } else if url.starts_with("s3://") {
+            Remote::S3(url.into())
+        } else if let Some(rest) = url.strip_prefix("ssh://") {
+            // ssh://host/path -> host:/path
+            match rest.split_once('/') {
+                Some((host, path)) => Remote::Ssh(format!("{host}:/{path}")),
+                None => Remote::Ssh(format!("{rest}:")),
+            }
+        } else {
+            Remote::Ssh(url.into())
+        }
+    }
+
+    fn download(&self, name: &str, to: &Path) -> bool {
+        let mut cmd = match self {
+            Remote::Http(url) => {
+                let mut cmd = environment::tool("curl");
+                cmd.args(["-fsSL", "-o"])
+                    .arg(to)
+                    .arg(format!("{url}/{name}"));
+                cmd
+            }
+            Remote::S3(url) => {
+                let mut cmd = environment::tool("aws");
+                cmd.args(["s3", "cp", "--only-show-errors"]);
+                cmd.arg(format!("{url}/{name}")).arg(to);
+                cmd
+            }
+            Remote::Ssh(url) => {
+                let mut cmd = environment::tool("scp");
+                cmd.arg("-q").arg(format!("{url}/{name}")).arg(to);
+                cmd
+            }
+        };
+        // A missing entry is expected, don't clutter the output with it
+        cmd.stderr(Stdio::null());
+        quiet(cmd)
+    }
+
+    fn upload(&self, from: &Path, name: &str) -> bool {
+        let cmd = match self {
+            Remote::Http(url) => {
+                let mut cmd = environment::tool("curl");
+                cmd.args(["-fsS", "-T"])
+                    .arg(from)
+                    .arg(format!("{url}/{name}"));
+                cmd
+            }
+            Remote::S3(url) => {
+                let mut cmd = environment::tool("aws");
+                cmd.args(["s3", "cp", "--only-show-errors"]);
+                cmd.arg(from).arg(format!("{url}/{name}"));
+                cmd
+            }
+            Remote::Ssh(url) => {
+                let mut cmd = environment::tool("scp");
+                cmd.arg("-q").arg(from).arg(format!("{url}/{name}"));
+                cmd
+            }
+        };
+        quiet(cmd)
+    }
+}
+
+pub fn sha256(file: &Path) -> Option<String> {
+    let output = environment::tool("sha256sum").arg(file).output().ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    output.split_whitespace().next().map(String::from)
+}
+
+/// 64 bit FNV-1a, stable across builds and platforms
+struct Hasher(u64);
+
+impl Hasher {
+    fn new() -> Self {
+        Hasher(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // Separate consecutive writes, so ("ab", "c") and ("a", "bc") differ
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// How comments and literals look in a file hashed without comments
+#[derive(Clone, Copy, PartialEq)]
+enum Syntax {
+    /// `//` and `/* */` comments, string, character and raw string literals
+    C,
+    /// `#` comments, strings and docstrings
+    Python,
+    /// `#` and `#[[ ]]` comments, quoted and bracket arguments
+    Cmake,
+    /// `#` comments, lines with quotes are kept as they are
+    Interface,
+}
+
+impl Syntax {
+    fn of(path: &Path) -> Option<Syntax> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        if C_EXTENSIONS.contains(&extension.as_ref()) {
+            Some(Syntax::C)
+        } else if extension == "py" {
+            Some(Syntax::Python)
+        } else if name == "CMakeLists.txt" || extension == "cmake" {
+            Some(Syntax::Cmake)
+        } else if HASH_EXTENSIONS.contains(&extension.as_ref()) {
+            Some(Syntax::Interface)
+        } else {
+            None
+        }
+    }
+}
+
+/// Code without comments, with whitespace outside of literals normalized: no blank lines, no
+/// whitespace at the end of lines, or at their start except for Python's indentation, and single
+/// spaces in between
+struct Stripped {
+    code: String,
+    /// Where the last literal ends in `code`, nothing before is normalized
+    literal_end: usize,
+    line_start: bool,
+    indentation: bool,
+}
+
+impl Stripped {
+    fn new(syntax: Syntax) -> Stripped {
+        Stripped {
+            code: String::new(),
+            literal_end: 0,
+            line_start: true,
+            indentation: syntax == Syntax::Python,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        let after_literal = self.code.len() == self.literal_end;
+        if c == '\n' {
+            while self.code.len() > self.literal_end && self.code.ends_with([' ', '\t']) {
+                self.code.pop();
+            }
+            if !self.line_start {
+                self.code.push('\n');
+            }
+            self.line_start = true;
+        } else if c.is_whitespace() {
+            if self.line_start {
+                if self.indentation {
+                    self.code.push(c);
+                }
+            } else if after_literal || !self.code.ends_with(' ') {
+                self.code.push(' ');
+            }
+        } else {
+            self.code.push(c);
+            self.line_start = false;
+        }
+    }
+
+    fn literal(&mut self, chars: &[char]) {
+        self.code.extend(chars);
+        self.literal_end = self.code.len();
+        self.line_start = false;
+    }
+
+    fn finish(mut self) -> String {
+        self.push('\n');
+        self.code
+    }
+
+    /// The letters right before the current position, e.g. the prefix of a string literal
+    fn word(&self) -> &str {
+        let code = &self.code[self.literal_end..];
+        let start = code
+            .rfind(|c: char| !c.is_alphanumeric() && c != '_')
+            .map_or(0, |i| i + 1);
+        &code[start..]
+    }
+}
+
+/// Index after the first `end` from `from` on, `None` if there is none
+fn find(chars: &[char], from: usize, end: &[char]) -> Option<usize> {
+    (from..chars.len())
+        .find(|j| chars[*j..].starts_with(end))
+        .map(|j| j + end.len())
+}
+
+/// Index after the quote `quote` closing the literal starting at `from`, skipping escaped
+/// characters. `None` if it isn't closed, or not on the same line unless `multiline`
+fn closing_quote(chars: &[char], from: usize, quote: &[char], multiline: bool) -> Option<usize> {
+    let mut j = from;
+    while j < chars.len() {
+        if chars[j] == '\\' {
+            j += 2;
+        } else if chars[j..].starts_with(quote) {
+            return Some(j + quote.len());
+        } else if chars[j] == '\n' && !multiline {
+            return None;
+        } else {
+            j += 1;
+        }
+    }
+    None
+}
+
+/// Index after the end of the line at `from`, not including the newline
+fn line_end(chars: &[char], from: usize) -> usize {
+    (from..chars.len())
+        .find(|j| chars[*j] == '\n')
+        .unwrap_or(chars.len())
+}
+
+/// The `=` of a CMake bracket `[==[` at `from`, `None` if there is no bracket
+fn bracket_level(chars: &[char], from: usize) -> Option<usize> {
+    if chars.get(from) != Some(&'[') {
+        return None;
+    }
+    let level = chars[from + 1..].iter().take_while(|c| **c == '=').count();
+    (chars.get(from + 1 + level) == Some(&'[')).then_some(level)
+}
+
+/// Index after the CMake bracket `]==]` closing one opened at `from` with `level` `=`
+fn bracket_end(chars: &[char], from: usize, level: usize) -> Option<usize> {
+    let mut end = vec![']'];
+    end.extend(std::iter::repeat_n('=', level));
+    end.push(']');
+    find(chars, from + level + 2, &end)
+}
+
+fn strip_c(chars: &[char], res: &mut Stripped) -> Option<()> {
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        if rest.starts_with(&['/', '/']) {
+            let end = line_end(chars, i);
+            // A backslash continues the comment on the next line
+            if chars[i..end].ends_with(&['\\']) {
+                return None;
+            }
+            i = end;
+        } else if rest.starts_with(&['/', '*']) {
+            i = find(chars, i + 2, &['*', '/'])?;
+            res.push(' ');
+        } else if c == 'R' && rest.get(1) == Some(&'"') {
+            let prefix = res.word();
+            if !["", "u8", "u", "U", "L"].contains(&prefix) {
+                res.push(c);
+                i += 1;
+                continue;
+            }
+            let open = find(chars, i + 2, &['('])?;
+            let delimiter = &chars[i + 2..open - 1];
+            if delimiter.len() > 16 || delimiter.iter().any(|c| c.is_whitespace() || *c == '\\') {
+                return None;
+            }
+            let mut close = vec![')'];
+            close.extend(delimiter);
+            close.push('"');
+            let end = find(chars, open, &close)?;
+            res.literal(&chars[i..end]);
+            i = end;
+        } else if c == '"' || c == '\'' && !res.word().starts_with(|c: char| c.is_ascii_digit()) {
+            // Quotes in numbers are digit separators
+            let end = closing_quote(chars, i + 1, &[c], false)?;
+            res.literal(&chars[i..end]);
+            i = end;
+        } else {
+            res.push(c);
+            i += 1;
+        }
+    }
+    Some(())
+}
+
+/// Whether the f-string `literal` may have ended at a quote inside a replacement field, e.g.
+/// `f"{x["key"]}"`, which only has the opening brace so far
+fn unbalanced_fields(literal: &[char]) -> bool {
+    let text = literal
+        .iter()
+        .collect::<String>()
+        .replace("{{", "")
+        .replace("}}", "");
+    text.matches('{').count() != text.matches('}').count()
+}
+
+fn strip_python(chars: &[char], res: &mut Stripped) -> Option<()> {
+    let mut depth = 0usize;
+    // At the start of a statement, where a string on its own is a docstring
+    let mut statement = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '#' {
+            i = line_end(chars, i);
+        } else if c == '"' || c == '\'' {
+            let prefix = res.word().to_lowercase();
+            let formatted = prefix.contains(['f', 't']);
+            let triple = chars[i..].starts_with(&[c; 3]);
+            let end = if triple {
+                closing_quote(chars, i + 3, &[c; 3], true)?
+            } else {
+                closing_quote(chars, i + 1, &[c], false)?
+            };
+            if formatted && unbalanced_fields(&chars[i..end]) {
+                return None;
+            }
+            // Only a string that is the whole statement is a docstring, not one in an expression
+            let after = &chars[end..line_end(chars, end)];
+            let alone = after
+                .iter()
+                .position(|c| !c.is_whitespace())
+                .is_none_or(|j| after[j] == '#');
+            if !(triple && statement && prefix.is_empty() && alone) {
+                res.literal(&chars[i..end]);
+            }
+            statement = false;
+            i = end;
+        } else {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.checked_sub(1)?,
+                _ => {}
+            }
+            if c == '\n' {
+                // Not after a line continuation
+                statement = depth == 0 && !res.code.ends_with('\\');
+            } else if !c.is_whitespace() {
+                statement = false;
+            }
+            res.push(c);
+            i += 1;
+        }
+    }
+    Some(())
+}
+
+fn strip_cmake(chars: &[char], res: &mut Stripped) -> Option<()> {
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '#' {
+            i = match bracket_level(chars, i + 1) {
+                Some(level) => {
+                    res.push(' ');
+                    bracket_end(chars, i + 1, level)?
+                }
+                None => line_end(chars, i),
+            };
+        } else if let Some(level) = bracket_level(chars, i) {
+            let end = bracket_end(chars, i, level)?;
+            res.literal(&chars[i..end]);
+            i = end;
+        } else if c == '"' {
+            let end = closing_quote(chars, i + 1, &['"'], true)?;
+            res.literal(&chars[i..end]);
+            i = end;
+        } else {
+            res.push(c);
+            i += 1;
+        }
+    }
+    Some(())
+}
+
+/// Messages, services and actions: string constants and defaults may contain `#`
+fn strip_interface(chars: &[char], res: &mut Stripped) -> Option<()> {
+    let mut i = 0;
+    while i < chars.len() {
+        let end = line_end(chars, i);
+        let line = &chars[i..end];
+        if line.iter().any(|c| *c == '"' || *c == '\'') {
+            res.literal(line);
+        } else {
+            let code = line.iter().position(|c| *c == '#').unwrap_or(line.len());
+            for c in &line[..code] {
+                res.push(*c);
+            }
+        }
+        if end < chars.len() {
+            res.push('\n');
+        }
+        i = end + 1;
+    }
+    Some(())
+}
+
+/// The contents without comments, Python docstrings that are statements of their own and
+/// whitespace outside of literals, so edits to only those keep the key. Literals are kept as they
+/// are. Content that can't be parsed unambiguously, e.g. with an unterminated literal, is kept
+/// entirely, so a change is never mistaken for one in a comment
+fn without_comments(path: &Path, content: &[u8]) -> Vec<u8> {
+    let Some(syntax) = Syntax::of(path) else {
+        return content.to_vec();
+    };
+    let Ok(text) = std::str::from_utf8(content) else {
+        return content.to_vec();
+    };
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut res = Stripped::new(syntax);
+    let parsed = match syntax {
+        Syntax::C => strip_c(&chars, &mut res),
+        Syntax::Python => strip_python(&chars, &mut res),
+        Syntax::Cmake => strip_cmake(&chars, &mut res),
+        Syntax::Interface => strip_interface(&chars, &mut res),
+    };
+    match parsed {
+        Some(()) => res.finish().into_bytes(),
+        None => content.to_vec(),
+    }
+}
+
+fn hash_tree(
+    hasher: &mut Hasher,
+    root: &Path,
+    dir: &Path,
+    include: &dyn Fn(&Path) -> bool,
+    strip: bool,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for e in entries {
+        if e.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = e.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let file_type = e.file_type()?;
+        if file_type.is_dir() {
+            hasher.write(relative.as_os_str().as_encoded_bytes());
+            hash_tree(hasher, root, &path, include, strip)?;
+            continue;
+        }
+        if !include(&path) {
+            continue;
+        }
+        hasher.write(relative.as_os_str().as_encoded_bytes());
+        if file_type.is_symlink() {
+            hasher.write(fs::read_link(&path)?.as_os_str().as_encoded_bytes());
+        } else if strip {
+            hasher.write(&without_comments(&path, &fs::read(&path)?));
+        } else {
+            hasher.write(&fs::read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+/// Copy a folder recursively, preserving symlinks and permissions
+pub fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for e in fs::read_dir(from)? {
+        let e = e?;
+        let target = to.join(e.file_name());
+        let file_type = e.file_type()?;
+        if file_type.is_dir() {
+            copy_tree(&e.path(), &target)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(e.path())?, &target)?;
+        } else {
+            fs::copy(e.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn fingerprint_file(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("fingerprint")
+}
+
+/// The keys dependencies were last built from, if `colb prefetch` was used in the workspace
+pub fn load_fingerprint(workspace: &Path) -> Option<BTreeMap<String, String>> {
+    let content = fs::read_to_string(fingerprint_file(workspace)).ok()?;
+    Some(
+        content
+            .lines()
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(dep, key)| (dep.to_string(), key.to_string()))
+            .collect(),
+    )
+}
+
+/// Remember the keys dependencies were built from, keeping the ones of other dependencies
+pub fn store_fingerprint(workspace: &Path, keys: &[(String, String)]) -> io::Result<()> {
+    let mut fingerprint = load_fingerprint(workspace).unwrap_or_default();
+    fingerprint.extend(keys.iter().cloned());
+    let file = fingerprint_file(workspace);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content = fingerprint
+        .iter()
+        .map(|(dep, key)| format!("{dep}\t{key}\n"))
+        .collect::<String>();
+    fs::write(file, content)
+}
+
+impl InstallCache {
+    pub fn new(dir: Option<&str>) -> InstallCache {
+        let dir = match dir {
+            Some(d) => PathBuf::from(d),
+            None => env::var("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".cache")))
+                .unwrap_or(env::temp_dir())
+                .join("colb")
+                .join("installs"),
+        };
+        InstallCache { dir }
+    }
+
+    /// The cache key for a package with sources in `source` built with `config`
+    pub fn key(source: &Path, config: &str) -> io::Result<String> {
+        let mut hasher = Hasher::new();
+        hasher.write(config.as_bytes());
+        hash_tree(&mut hasher, source, source, &|_| true, false)?;
+        Ok(hasher.finish())
+    }
+
+    /// Key over the headers, interface definitions and build files of a package in `source`
+    pub fn interface_key(source: &Path) -> io::Result<String> {
+        let mut hasher = Hasher::new();
+        hash_tree(&mut hasher, source, source, &is_interface, false)?;
+        Ok(hasher.finish())
+    }
+
+    /// Key over the sources of a package in `source` without their comments and docstrings
+    pub fn semantic_key(source: &Path) -> io::Result<String> {
+        let mut hasher = Hasher::new();
+        hash_tree(&mut hasher, source, source, &|_| true, true)?;
+        Ok(hasher.finish())
+    }
+
+    /// Whether the package in `build_dir` was last built from sources that only differ from `key`
+    /// in comments. Builds without a recorded key since then rewrote `colcon_build.rc`
+    pub fn semantically_unchanged(build_dir: &Path, key: &str) -> bool {
+        let recorded = build_dir.join(SEMANTIC_FILE);
+        let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+        let current = match (
+            modified(&recorded),
+            modified(&build_dir.join("colcon_build.rc")),
+        ) {
+            (Some(recorded), Some(built)) => recorded >= built,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        current && fs::read_to_string(recorded).is_ok_and(|k| k == key)
+    }
+
+    pub fn record_semantic(build_dir: &Path, key: &str) -> io::Result<()> {
+        fs::write(build_dir.join(SEMANTIC_FILE), key)
+    }
+
+    /// Whether `install_dir` was built from sources with a different interface than `key`
+    pub fn interface_changed(install_dir: &Path, key: &str) -> bool {
+        fs::read_to_string(install_dir.join(INTERFACE_FILE)).map_or(true, |k| k != key)
+    }
+
+    pub fn record_interface(install_dir: &Path, key: &str) -> io::Result<()> {
+        fs::write(install_dir.join(INTERFACE_FILE), key)
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    /// Whether `install_dir` already holds the outputs for `key`
+    pub fn is_current(install_dir: &Path, key: &str) -> bool {
+        fs::read_to_string(install_dir.join(KEY_FILE)).is_ok_and(|k| k == key)
+    }
+
+    /// Replace `install_dir` with the cached outputs for `key`, if there are any
+    pub fn restore(&self, key: &str, install_dir: &Path) -> io::Result<bool> {
+        let entry = self.entry(key);
+        if !entry.is_dir() {
+            return Ok(false);
+        }
+        if install_dir.exists() {
+            fs::remove_dir_all(install_dir)?;
+        }
+        copy_tree(&entry, install_dir)?;
+        fs::write(install_dir.join(KEY_FILE), key)?;
+        Ok(true)
+    }
+
+    /// Keys of all complete entries
+    pub fn keys(&self) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+        let mut res = entries
+            .flatten()
+            .filter(|e| e.path().is_dir())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|k| !k.ends_with(".partial"))
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    fn pushed_marker(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.pushed"))
+    }
+
+    /// Upload an entry as a tarball with a checksum next to it, unless it was uploaded before
+    pub fn push(&self, key: &str, remote: &Remote) -> Result<bool, String> {
+        if self.pushed_marker(key).exists() {
+            return Ok(false);
+        }
+        let tarball = self.dir.join(format!("{key}.tar.gz"));
+        let mut tar = environment::tool("tar");
+        tar.arg("-czf")
+            .arg(&tarball)
+            .arg("-C")
+            .arg(self.entry(key))
+            .arg(".");
+        if !quiet(tar) {
+            return Err("Couldn't pack entry".into());
+        }
+        let checksum_file = self.dir.join(format!("{key}.tar.gz.sha256"));
+        let res = match sha256(&tarball) {
+            Some(sum) => fs::write(&checksum_file, sum)
+                .map_err(|e| e.to_string())
+                .and_then(|_| {
+                    let uploaded = remote.upload(&tarball, &format!("{key}.tar.gz"))
+                        && remote.upload(&checksum_file, &format!("{key}.tar.gz.sha256"));
+                    uploaded.then_some(true).ok_or("Upload failed".into())
+                }),
+            None => Err("Couldn't compute checksum".into()),
+        };
+        let _ = fs::remove_file(&tarball);
+        let _ = fs::remove_file(&checksum_file);
+        if res.is_ok() {
+            let _ = fs::write(self.pushed_marker(key), "");
+        }
+        res
+    }
+
+    /// Download an entry, verifying its checksum. Returns false if the remote doesn't have it
+    pub fn pull(&self, key: &str, remote: &Remote) -> Result<bool, String> {
+        if self.entry(key).is_dir() {
+            return Ok(true);
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let tarball = self.dir.join(format!("{key}.tar.gz"));
+        let checksum_file = self.dir.join(format!("{key}.tar.gz.sha256"));
+        let cleanup = || {
+            let _ = fs::remove_file(&tarball);
+            let _ = fs::remove_file(&checksum_file);
+        };
+        if !remote.download(&format!("{key}.tar.gz.sha256"), &checksum_file)
+            || !remote.download(&format!("{key}.tar.gz"), &tarball)
+        {
+            cleanup();
+            return Ok(false);
+        }
+        let expected = fs::read_to_string(&checksum_file).unwrap_or_default();
+        if sha256(&tarball).as_deref() != Some(expected.trim()) {
+            cleanup();
+            return Err(format!("Checksum mismatch for '{key}'"));
+        }
+        let partial = self.dir.join(format!("{key}.partial"));
+        let _ = fs::remove_dir_all(&partial);
+        fs::create_dir_all(&partial).map_err(|e| e.to_string())?;
+        let mut tar = environment::tool("tar");
+        tar.arg("-xzf").arg(&tarball).arg("-C").arg(&partial);
+        let unpacked = quiet(tar);
+        cleanup();
+        if !unpacked {
+            return Err(format!("Couldn't unpack '{key}'"));
+        }
+        fs::rename(&partial, self.entry(key)).map_err(|e| e.to_string())?;
+        // It came from the remote, no need to push it back
+        let _ = fs::write(self.pushed_marker(key), "");
+        Ok(true)
+    }
+
+    /// Push all entries that weren't pushed yet
+    pub fn push_all(&self, remote: &Remote) -> usize {
+        let mut pushed = 0;
+        for key in self.keys() {
+            match self.push(&key, remote) {
+                Ok(true) => {
+                    context!("Pushed '{key}'");
+                    pushed += 1;
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("Couldn't push '{key}': {e}"),
+            }
+        }
+        pushed
+    }
+
+    /// Add the outputs in `install_dir` to the cache under `key`
+    pub fn store(&self, key: &str, install_dir: &Path) -> io::Result<()> {
+        let entry = self.entry(key);
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
+        }
+        // Copy to a temporary name first, so an interrupted copy never looks like a valid entry
+        let partial = self.dir.join(format!("{key}.partial"));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        copy_tree(install_dir, &partial)?;
+        let _ = fs::remove_file(partial.join(KEY_FILE));
+        fs::rename(&partial, &entry)?;
+        fs::write(install_dir.join(KEY_FILE), key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(name: &str, a: &str, b: &str) -> bool {
+        let path = Path::new(name);
+        without_comments(path, a.as_bytes()) == without_comments(path, b.as_bytes())
+    }
+
+    #[test]
+    fn c_comments_and_whitespace() {
+        assert!(same(
+            "a.cpp",
+            "int f() {\n  return 1; // one\n}\n",
+            "/* doc */\nint  f() {\n\n    return 1;\n}"
+        ));
+        assert!(!same("a.cpp", "return 1;", "return 2;"));
+        assert!(!same("a.cpp", "#define F (x)", "#define F(x)"));
+    }
+
+    #[test]
+    fn c_literals() {
+        assert!(!same("a.cpp", "s = \"a // b\";", "s = \"a // c\";"));
+        assert!(!same("a.cpp", "s = R\"(// a)\";", "s = R\"(// b)\";"));
+        assert!(!same(
+            "a.cpp",
+            "s = R\"x(a\n  )\" // b\n)x\";",
+            "s = R\"x(a\n  )\" // c\n)x\";"
+        ));
+        assert!(!same(
+            "a.cpp",
+            "s = u8R\"(/* a */)\";",
+            "s = u8R\"(/* b */)\";"
+        ));
+        assert!(same("a.cpp", "n = 1'000; // a", "n = 1'000; // b"));
+        assert!(!same("a.cpp", "c = '\"'; x = 1;", "c = '\"'; x = 2;"));
+    }
+
+    #[test]
+    fn c_ambiguous_kept() {
+        // An unterminated literal, the comment may be part of the code
+        assert!(!same("a.cpp", "#error don't // a", "#error don't // b"));
+        assert!(!same("a.cpp", "// a \\\nx = 1;", "// a \\\nx = 2;"));
+    }
+
+    #[test]
+    fn python_docstrings() {
+        assert!(same(
+            "a.py",
+            "def f():\n    \"\"\"Old.\"\"\"\n    return 1  # one\n",
+            "def f():\n    \"\"\"New,\n    longer.\n    \"\"\"\n\n    return 1\n"
+        ));
+        assert!(!same(
+            "a.py",
+            "x = (\n    \"\"\"a\"\"\"\n)\n",
+            "x = (\n    \"\"\"b\"\"\"\n)\n"
+        ));
+        assert!(!same(
+            "a.py",
+            "x = \\\n\"\"\"a\"\"\"\n",
+            "x = \\\n\"\"\"b\"\"\"\n"
+        ));
+        assert!(!same(
+            "a.py",
+            "\"\"\"a\"\"\".strip()\n",
+            "\"\"\"b\"\"\".strip()\n"
+        ));
+        assert!(!same("a.py", "f\"\"\"a\"\"\"\n", "f\"\"\"b\"\"\"\n"));
+    }
+
+    #[test]
+    fn python_literals_and_indentation() {
+        assert!(!same("a.py", "s = '# a'\n", "s = '# b'\n"));
+        assert!(!same(
+            "a.py",
+            "s = \"\"\"a\n# b\"\"\"\n",
+            "s = \"\"\"a\n# c\"\"\"\n"
+        ));
+        assert!(!same(
+            "a.py",
+            "s = \"\"\"a \n\"\"\"\n",
+            "s = \"\"\"a\n\"\"\"\n"
+        ));
+        assert!(!same(
+            "a.py",
+            "if x:\n    y()\n    z()\n",
+            "if x:\n    y()\nz()\n"
+        ));
+        assert!(!same(
+            "a.py",
+            "s = f\"{d[\"#\"]} a\"\n",
+            "s = f\"{d[\"#\"]} b\"\n"
+        ));
+    }
+
+    #[test]
+    fn cmake_comments_and_arguments() {
+        assert!(same(
+            "CMakeLists.txt",
+            "add_library(a a.cpp) # a\n#[[ old\n]]\n",
+            "#[==[ new ]==]\nadd_library(a  a.cpp)\n"
+        ));
+        assert!(!same(
+            "CMakeLists.txt",
+            "set(X \"a\n# b\")\n",
+            "set(X \"a\n# c\")\n"
+        ));
+        assert!(!same(
+            "CMakeLists.txt",
+            "set(X [[a # b]])\n",
+            "set(X [[a # c]])\n"
+        ));
+        assert!(!same(
+            "CMakeLists.txt",
+            "set(X \"a # b\n",
+            "set(X \"a # c\n"
+        ));
+    }
+
+    #[test]
+    fn interface_constants() {
+        assert!(same("A.msg", "int32 a # one\n", "# header\nint32 a\n"));
+        assert!(!same(
+            "A.msg",
+            "string A=\"x # y\"\n",
+            "string A=\"x # z\"\n"
+        ));
+    }
+
+    #[test]
+    fn other_files_kept() {
+        assert!(!same("a.txt", "a # b", "a # c"));
+    }
+}
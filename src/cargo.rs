@@ -0,0 +1,52 @@
+use std::{
+    path::Path,
+    process::{Command, ExitStatus},
+};
+
+use crate::{bases, environment, output::*, package_xml};
+
+/// Whether the package in `source` is built by cargo, through colcon-ros-cargo or colcon-cargo
+pub fn is_cargo_package(source: &Path) -> bool {
+    if source.join("package.xml").exists() {
+        package_xml::build_type(source).as_deref() == Some("ament_cargo")
+    } else {
+        source.join("Cargo.toml").exists()
+    }
+}
+
+/// cargo running `verb` on the package, sharing colcon's target directory in `build/<package>`.
+/// Runs in the workspace so the `.cargo/config.toml` colcon-ros-cargo writes there applies
+fn cargo(workspace: &str, package: &str, source: &Path, verb: &str) -> Command {
+    let mut cmd = environment::tool("cargo");
+    cmd.current_dir(workspace);
+    cmd.arg(verb);
+    cmd.arg("--manifest-path").arg(source.join("Cargo.toml"));
+    cmd.arg("--target-dir")
+        .arg(Path::new(workspace).join(bases::build()).join(package));
+    cmd
+}
+
+/// Build the binary `bin` of the package, it ends up in `build/<package>/debug`
+pub fn build(workspace: &str, package: &str, source: &Path, bin: &str) -> ExitStatus {
+    let mut cmd = cargo(workspace, package, source, "build");
+    cmd.args(["--bin", bin]);
+    print_command(&cmd);
+    run(&mut cmd).expect("'cargo' not found")
+}
+
+/// Build and run the tests of the package whose name contains `filter`, like ninja and ctest for CMake packages
+pub fn test(
+    workspace: &str,
+    package: &str,
+    source: &Path,
+    filter: &str,
+    verbose: bool,
+) -> ExitStatus {
+    let mut cmd = cargo(workspace, package, source, "test");
+    cmd.arg(filter);
+    if verbose {
+        cmd.args(["--", "--nocapture"]);
+    }
+    print_command(&cmd);
+    run(&mut cmd).expect("'cargo' not found")
+}
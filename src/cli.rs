@@ -0,0 +1,941 @@
+//! The command line interface, also used by the build script to generate the man pages
+
+use clap::{CommandFactory, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+/// The name of the hidden verb printing the packages of the workspace, for the completion scripts
+pub const PACKAGES_VERB: &str = "__complete-packages";
+
+/// A colcon wrapper for faster change compile test cycles
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[arg(short, long)]
+    pub workspace: Option<String>,
+
+    /// How to format colb's own output
+    #[arg(long, value_enum, default_value_t = OutputMode::Human)]
+    pub output: OutputMode,
+
+    /// Only print the commands that would run, as lines that can be pasted into a shell
+    #[arg(long, default_value_t = false)]
+    pub print_command_only: bool,
+
+    /// Show the commands and removals every verb would do, without running or removing anything
+    #[arg(long, global = true, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// On failure, write config, commands, environment changes, logs and tool versions to this tarball
+    #[arg(long, value_name = "FILE")]
+    pub repro_bundle: Option<String>,
+
+    /// On failure, write a Markdown issue with the command, errors, failed tests and tool versions to this file
+    #[arg(long, value_name = "FILE")]
+    pub issue_template: Option<String>,
+
+    /// Build with this profile from the configuration, in its own build and install base
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Keep configure and build from the network, failing right away if a package tries to download
+    #[arg(long, global = true, default_value_t = false)]
+    pub offline: bool,
+
+    /// Build the packages with AddressSanitizer and run their tests with its options, in a separate build and install base
+    #[arg(long, global = true, default_value_t = false, conflicts_with = "tsan")]
+    pub asan: bool,
+
+    /// Build the packages with ThreadSanitizer and run their tests with its options, in a separate build and install base
+    #[arg(long, global = true, default_value_t = false)]
+    pub tsan: bool,
+
+    /// Build the packages with UndefinedBehaviorSanitizer and run their tests with its options, in a separate build and install base
+    #[arg(long, global = true, default_value_t = false)]
+    pub ubsan: bool,
+
+    #[command(subcommand)]
+    pub verb: Verbs,
+}
+
+#[derive(Subcommand)]
+pub enum Verbs {
+    /// Write default configuration file
+    Init {
+        /// Wheter to overwrite existing config files
+        #[arg(short, long, default_value_t = false)]
+        force: bool,
+
+        /// Start from defaults suited to how the workspace's repositories are organized
+        #[arg(long, value_enum)]
+        layout: Option<Layout>,
+    },
+    /// Build a package
+    Build {
+        /// The packages to build (default: current directory)
+        packages: Vec<String>,
+
+        /// Whether to skip rebuilding dependencies
+        #[arg(short, long, default_value_t = false)]
+        skip_dependencies: bool,
+
+        /// Whether to skip building tests
+        #[arg(short = 't', long, default_value_t = false)]
+        skip_tests: bool,
+
+        /// Overwrite the build type from the config file
+        #[arg(short, long)]
+        build_type: Option<BuildType>,
+
+        /// Build the packages matching this regex instead (colcon's --packages-select-regex)
+        #[arg(long, conflicts_with = "packages")]
+        select_regex: Vec<String>,
+
+        /// Build the packages in this path instead (colcon's --paths)
+        #[arg(long, conflicts_with = "packages")]
+        paths: Vec<String>,
+
+        /// Never build this package, neither as dependency nor as selected package
+        #[arg(long)]
+        skip: Vec<String>,
+
+        /// How to select the packages to build, all but `split` use the package configuration only
+        #[arg(long, value_enum, default_value_t = Strategy::Split)]
+        strategy: Strategy,
+
+        /// Also build everything depending on the packages, their dependencies with the upstream configuration
+        #[arg(long, default_value_t = false, conflicts_with = "strategy")]
+        downstream: bool,
+
+        /// Build the packages and those between them one at a time in dependency order, after their other dependencies
+        #[arg(long, default_value_t = false, conflicts_with = "strategy")]
+        ordered: bool,
+
+        /// Build without network access and with only the workspace and its underlays visible
+        #[arg(long, default_value_t = false)]
+        sandbox: bool,
+
+        /// Keep colcon's logs of the build for `colb gantt`
+        #[arg(long, default_value_t = false)]
+        timings: bool,
+
+        /// Suggest packages to pin or split, from the dependency graph and the build times so far
+        #[arg(long, default_value_t = false)]
+        advise: bool,
+
+        /// Install files as symlinks where possible, overriding the config file
+        #[arg(long, default_value_t = false)]
+        symlink_install: bool,
+
+        /// Install all packages into one prefix, overriding the config file. `--merge-install=false`
+        /// installs them into one prefix each
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+        merge_install: Option<bool>,
+
+        /// Skip packages whose sources only changed in comments since their last build with this
+        #[arg(long, default_value_t = false)]
+        abi_skip: bool,
+
+        /// Which builds get the colcon arguments given after `--`
+        #[arg(long, value_enum, default_value_t = PassTo::All)]
+        pass_to: PassTo,
+
+        /// Arguments added to the colcon invocations, after `--`
+        #[arg(last = true)]
+        colcon_args: Vec<String>,
+    },
+
+    /// Run tests for a package
+    Test {
+        /// The package to test (default: current directory)
+        package: Option<String>,
+
+        /// Build and run only this test (default: run all tests)
+        #[arg(short, long)]
+        test: Option<String>,
+
+        /// Run only the tests that failed in the previous run of the package
+        #[arg(long, default_value_t = false, conflicts_with = "test")]
+        failed: bool,
+
+        /// Build and run the test compiled from this file, only the gtest test at LINE if given
+        #[arg(long, value_name = "FILE[:LINE]", conflicts_with_all = ["test", "failed"])]
+        file: Option<String>,
+
+        /// Also run the tests listed in `tests.quarantine`
+        #[arg(long, default_value_t = false)]
+        include_quarantined: bool,
+
+        /// Run through ctest directly (only works for single tests, default: use colcon test)
+        #[arg(short, long, default_value_t = false)]
+        direct: bool,
+
+        /// Don't rebuild the package
+        #[arg(short, long, default_value_t = false)]
+        skip_rebuild: bool,
+
+        /// Rebuild dependencies of package, `auto` only does so if their headers or interfaces changed
+        #[arg(
+            short,
+            long,
+            value_enum,
+            num_args = 0..=1,
+            require_equals = true,
+            default_value_t = RebuildDependencies::Never,
+            default_missing_value = "always"
+        )]
+        rebuild_dependencies: RebuildDependencies,
+
+        /// Run the test under `rr record` until it fails (requires --test)
+        #[arg(long, default_value_t = false, requires = "test")]
+        record: bool,
+
+        /// Run the test program under gdb instead of through ctest (requires --test)
+        #[arg(
+            long,
+            default_value_t = false,
+            requires = "test",
+            conflicts_with = "record"
+        )]
+        gdb: bool,
+
+        /// Run the test program under lldb instead of through ctest (requires --test)
+        #[arg(
+            long,
+            default_value_t = false,
+            requires = "test",
+            conflicts_with_all = ["record", "gdb"]
+        )]
+        lldb: bool,
+
+        /// Run the tests this many times, then print how often each one failed
+        #[arg(long, value_name = "N", conflicts_with_all = ["record", "gdb", "lldb"])]
+        repeat: Option<u32>,
+
+        /// Stop repeating at the first failing run
+        #[arg(long, default_value_t = false, requires = "repeat")]
+        until_failure: bool,
+
+        #[command(flatten)]
+        ctest: CtestOptions,
+
+        /// Point HOME, ROS_HOME, ROS_LOG_DIR and the XDG directories at a temporary directory
+        #[arg(long, default_value_t = false)]
+        hermetic: bool,
+
+        /// Run the tests with this middleware (sets RMW_IMPLEMENTATION)
+        #[arg(long, value_enum)]
+        rmw: Option<Rmw>,
+
+        /// Check for a stale ros2 daemon, other nodes on the domain and leftover processes first
+        #[arg(long, default_value_t = false)]
+        preflight: bool,
+
+        /// Stop processes the tests leave behind, e.g. gzserver or component containers
+        #[arg(long, default_value_t = false)]
+        kill_orphans: bool,
+    },
+    /// Build packages with coverage flags, run their tests and report the line coverage
+    Coverage {
+        /// The packages to check (default: current directory)
+        packages: Vec<String>,
+
+        /// Only report the coverage of the last run again, without building and testing
+        #[arg(short, long, default_value_t = false)]
+        skip_rebuild: bool,
+
+        /// Fail if the coverage is below `min_coverage`, listing the files with the largest gaps
+        #[arg(long, default_value_t = false)]
+        check: bool,
+    },
+    /// Build and test packages for every combination configured in [matrix], like a CI matrix
+    Matrix {
+        /// The packages to build and test (default: current directory)
+        packages: Vec<String>,
+    },
+    /// Launch a launch file with the workspace and the simulation assets in it set up
+    Launch {
+        /// The package containing the launch file
+        package: String,
+
+        /// The launch file
+        file: String,
+
+        /// Run with this middleware (sets RMW_IMPLEMENTATION)
+        #[arg(long, value_enum)]
+        rmw: Option<Rmw>,
+
+        /// Only print the simulation and middleware variables that would be set
+        #[arg(long, default_value_t = false)]
+        print_env: bool,
+
+        /// Arguments passed on to `ros2 launch`, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Build a package and run one of its executables with the workspace sourced
+    Run {
+        /// The executable to run
+        executable: String,
+
+        /// The package containing it (default: current directory)
+        #[arg(short, long)]
+        package: Option<String>,
+
+        /// Don't rebuild the package
+        #[arg(long, default_value_t = false)]
+        skip_rebuild: bool,
+
+        /// Whether to skip rebuilding dependencies
+        #[arg(short, long, default_value_t = false)]
+        skip_dependencies: bool,
+
+        /// Run with this middleware (sets RMW_IMPLEMENTATION)
+        #[arg(long, value_enum)]
+        rmw: Option<Rmw>,
+
+        /// Arguments for the executable, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Build a package, then rebuild it whenever its sources change
+    Watch {
+        /// The package to watch (default: current directory)
+        package: Option<String>,
+
+        /// Also run this test after every rebuild
+        #[arg(short, long)]
+        test: Option<String>,
+
+        /// Whether to skip building dependencies before watching
+        #[arg(short, long, default_value_t = false)]
+        skip_dependencies: bool,
+    },
+    /// Build a Python package with --symlink-install, run its node and restart it when the sources change
+    ///
+    /// The executable or launch file to run is configured in `[dev.<package>]`. With `--exe`, a C++
+    /// executable is rebuilt with ninja instead and only restarted if it builds
+    Dev {
+        /// The package to run (default: current directory)
+        package: Option<String>,
+
+        /// Whether to skip building dependencies first
+        #[arg(short, long, default_value_t = false)]
+        skip_dependencies: bool,
+
+        /// Rebuild and restart this executable, keeping the running one if the rebuild fails
+        #[arg(long, value_name = "NAME")]
+        exe: Option<String>,
+
+        /// Arguments passed on to the executable or launch file, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Render the package timings of the last `build --timings` as a Gantt chart
+    Gantt {
+        /// Read the colcon log folder instead, e.g. log/latest_build
+        #[arg(long)]
+        log: Option<String>,
+
+        /// Where to write the HTML page (default: .colb/timings/gantt.html)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the packages in the workspace with their path and build type
+    List {
+        /// Print a JSON array instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Run any colcon verb in the workspace, with colb's bases, log base and environment setup
+    ///
+    /// For example `colb colcon -- graph --dot` or `colb colcon -- test-result --all`
+    Colcon {
+        /// The colcon verb and its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Remove build and install folders of packages
+    ///
+    /// In merged install spaces, the files listed in the package's install manifest are removed
+    Clean {
+        /// The packages to clean, or patterns like 'my_project_*' (default: current directory)
+        packages: Vec<String>,
+
+        /// Remove the whole build and install folders of the workspace
+        #[arg(long, default_value_t = false, conflicts_with_all = ["packages", "cache_only"])]
+        all: bool,
+
+        /// Move the folders to the trash instead of removing them, so they can be restored
+        #[arg(long, default_value_t = false)]
+        trash: bool,
+
+        /// Only remove the build folders
+        #[arg(long, default_value_t = false, conflicts_with_all = ["install_only", "cache_only"])]
+        build_only: bool,
+
+        /// Only remove the install folders
+        #[arg(long, default_value_t = false, conflicts_with = "cache_only")]
+        install_only: bool,
+
+        /// Only remove CMakeCache.txt and CMakeFiles, so the next build configures from scratch
+        #[arg(long, default_value_t = false)]
+        cache_only: bool,
+    },
+    /// Remove the build and install folders of packages and build them again
+    Rebuild {
+        /// The packages to rebuild (default: current directory)
+        packages: Vec<String>,
+
+        /// Whether to skip rebuilding dependencies
+        #[arg(short, long, default_value_t = false)]
+        skip_dependencies: bool,
+
+        /// Overwrite the build type from the config file
+        #[arg(short, long)]
+        build_type: Option<BuildType>,
+    },
+    /// Opens the configuration file in $EDITOR
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
+    /// Check that required tools and services are available
+    Doctor {},
+    /// Show the sizes of a package's installed executables and libraries and how they changed since the previous build
+    Size {
+        /// The package to show (default: current directory)
+        package: Option<String>,
+
+        /// Also list the N largest symbols of each binary, with bloaty if installed or else nm
+        #[arg(long, value_name = "N")]
+        symbols: Option<usize>,
+    },
+    /// Check install folders for broken symlinks, missing shared libraries and Python modules that don't compile
+    VerifyInstall {
+        /// The packages to check (default: all installed packages)
+        packages: Vec<String>,
+
+        /// Also flag RPATHs and RUNPATHs into the build folder, the workspace or home folders, for deployment
+        #[arg(long, default_value_t = false)]
+        relocatable: bool,
+    },
+    /// Write a Markdown or HTML report on the health of the workspace
+    Report {
+        /// Format of the report
+        #[arg(short, long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+
+        /// Write the report to this file (default: stdout)
+        #[arg(short = 'o', long = "output-file")]
+        file: Option<String>,
+    },
+    /// Compare recorded test runs
+    Results {
+        #[command(subcommand)]
+        action: ResultsAction,
+    },
+    /// Pull the sources and rebuild the dependencies of packages, e.g. from a nightly timer,
+    /// so the next build can skip its dependency phase
+    Prefetch {
+        /// The packages whose dependencies to rebuild
+        #[arg(required = true)]
+        packages: Vec<String>,
+
+        /// Don't pull the repositories in src with vcstool
+        #[arg(long, default_value_t = false)]
+        no_pull: bool,
+    },
+    /// Configure packages with network access, so FetchContent downloads are there for offline builds
+    PrefetchDeps {
+        /// The packages to configure (default: current directory)
+        packages: Vec<String>,
+
+        /// Whether to skip building dependencies first
+        #[arg(short, long, default_value_t = false)]
+        skip_dependencies: bool,
+    },
+    /// Run a sequence of colb invocations defined in [pipelines], e.g. before committing
+    Pipeline {
+        /// Name of the pipeline
+        name: String,
+    },
+    /// Compare the environments of two builds, by default of the latest failing and the last working one
+    EnvDiff {
+        /// Number of the earlier build
+        a: Option<u64>,
+        /// Number of the later build (default: latest)
+        b: Option<u64>,
+    },
+    /// Show statistics collected locally
+    Stats {
+        #[command(subcommand)]
+        action: StatsAction,
+    },
+    /// Print the version of colb
+    Version {
+        /// Also print the versions of colcon, the build tools, the compiler and the ROS distro
+        #[arg(short, long, default_value_t = false)]
+        verbose: bool,
+    },
+    /// Replace colb with the latest release from the configured URL
+    SelfUpdate {
+        /// Only check whether an update is available
+        #[arg(long, default_value_t = false)]
+        check: bool,
+    },
+    /// Print a completion script, e.g. `colb completions bash > ~/.local/share/bash-completion/completions/colb`
+    Completions {
+        /// The shell to complete in
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the names of the workspace's packages, for the completion scripts
+    #[command(name = PACKAGES_VERB, hide = true)]
+    CompletePackages {},
+    /// Print shell functions for the configured aliases, use with `eval "$(colb alias)"`
+    Alias {
+        /// The shell to generate functions for
+        #[arg(short, long, value_enum, default_value_t = Shell::Bash)]
+        shell: Shell,
+    },
+    /// Debug a freshly built binary on a remote machine through gdbserver
+    Debug {
+        /// The package containing the binary (default: current directory)
+        package: Option<String>,
+
+        /// The ninja target or test to build and debug
+        #[arg(short, long)]
+        target: String,
+
+        /// The machine to run gdbserver on, as [user@]host:port
+        #[arg(short, long)]
+        remote: String,
+
+        /// Local copy of the remote's root filesystem, used to find its libraries
+        #[arg(long)]
+        sysroot: Option<String>,
+
+        /// Don't rebuild the target
+        #[arg(short, long, default_value_t = false)]
+        skip_rebuild: bool,
+    },
+    /// Never rebuild a package as a dependency, as long as it is installed
+    Pin {
+        /// The package to pin
+        package: String,
+    },
+    /// Rebuild a pinned package as a dependency again
+    Unpin {
+        /// The package to unpin
+        package: String,
+    },
+    /// Manage prebuilt install spaces used as underlays
+    Underlay {
+        #[command(subcommand)]
+        action: UnderlayAction,
+    },
+    /// Share the install cache through the configured remote
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Manage sysroots for cross compilation in .colb/sysroots
+    Sysroot {
+        #[command(subcommand)]
+        action: SysrootAction,
+    },
+    /// Run nodes from the workspace persistently as systemd user services
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Package the workspace's install space as a container image
+    Image {
+        #[command(subcommand)]
+        action: ImageAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ResultsAction {
+    /// List the recorded test runs
+    List {},
+    /// Show the tests whose outcome changed between two runs and how their setup differed
+    Diff {
+        /// Id of the earlier run
+        a: String,
+        /// Id of the later run (default: latest)
+        #[arg(default_value = "latest")]
+        b: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ServiceAction {
+    /// Install, enable and start a service running `ros2 run <package> <executable>`
+    Install {
+        /// The package containing the node
+        package: String,
+
+        /// The executable to run
+        executable: String,
+
+        /// Name of the service (default: the executable)
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Arguments for the node, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Stop, disable and remove a service
+    Uninstall { name: String },
+    /// Restart a service, e.g. after rebuilding its node
+    Restart { name: String },
+    /// Show whether a service is running
+    Status { name: String },
+    /// Show the output of a service
+    Logs {
+        name: String,
+
+        /// Keep printing new output
+        #[arg(short, long, default_value_t = false)]
+        follow: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Warn about settings that contradict each other or have no effect
+    Doctor {},
+    /// Show how the colcon arguments of two configurations differ
+    Diff {
+        /// `upstream`, `package` or the name of a profile (its package configuration)
+        a: String,
+        /// `upstream`, `package` or the name of a profile (its package configuration)
+        b: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImageAction {
+    /// Build an image with the install space on top of a base image, using buildah, podman or docker
+    Build {
+        /// The image to build on (default: ros:$ROS_DISTRO)
+        #[arg(long)]
+        base: Option<String>,
+
+        /// Name of the image (default: <workspace folder>:latest)
+        #[arg(long)]
+        tag: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StatsAction {
+    /// Time spent per verb and flag, as recorded when `usage_stats` is enabled
+    Usage {
+        /// Delete the recorded invocations
+        #[arg(long, default_value_t = false)]
+        reset: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UnderlayAction {
+    /// Download a prebuilt install space and add it to the configured underlays
+    Fetch {
+        /// A manifest with name, url and sha256 of the tarball, or the url of a tarball
+        manifest_url: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Upload all local entries that weren't uploaded yet
+    Push {},
+    /// Download the entries for the dependencies of a package
+    Pull {
+        /// The package whose dependencies to pull (default: current directory)
+        package: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SysrootAction {
+    /// Assemble a sysroot from a rootfs tarball, a directory or debootstrap
+    Create {
+        /// Name of the sysroot, referenced by the `sysroot` config option
+        target: String,
+
+        /// Rootfs tarball or directory to assemble the sysroot from
+        #[arg(short, long, conflicts_with = "suite")]
+        from: Option<String>,
+
+        /// Debootstrap this distribution suite instead (e.g. jammy)
+        #[arg(short, long, required_unless_present = "from")]
+        suite: Option<String>,
+
+        /// Architecture to debootstrap (e.g. arm64)
+        #[arg(short, long, requires = "suite")]
+        arch: Option<String>,
+
+        /// Mirror to debootstrap from
+        #[arg(short, long, requires = "suite")]
+        mirror: Option<String>,
+    },
+    /// Re-assemble a sysroot from the source it was created from
+    Update {
+        /// Name of the sysroot
+        target: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, clap::ValueEnum, Default, Clone)]
+pub enum BuildType {
+    #[default]
+    Debug,
+    Release,
+    RelWithDebInfo,
+}
+
+/// Options passed on to ctest, both through colcon and when running it directly
+#[derive(clap::Args, Clone, Default)]
+pub struct CtestOptions {
+    /// Print the output of all tests, not only of failing ones
+    #[arg(short = 'V', long, default_value_t = false)]
+    pub verbose: bool,
+
+    /// Only run the tests that failed in the previous run
+    #[arg(long, default_value_t = false)]
+    pub rerun_failed: bool,
+
+    /// Stop at the first failing test
+    #[arg(long, default_value_t = false)]
+    pub stop_on_failure: bool,
+
+    /// Only run every n-th test starting at the i-th (1-based), to split a suite across jobs
+    #[arg(long, value_name = "I/N", value_parser = parse_shard)]
+    pub shard: Option<(usize, usize)>,
+
+    /// Names of tests to leave out, e.g. the quarantined ones
+    #[arg(skip)]
+    pub exclude: Vec<String>,
+}
+
+fn parse_shard(s: &str) -> Result<(usize, usize), String> {
+    let (i, n) = s.split_once('/').ok_or("expected I/N, e.g. 2/4")?;
+    let i = i
+        .parse::<usize>()
+        .map_err(|e| format!("invalid shard index: {e}"))?;
+    let n = n
+        .parse::<usize>()
+        .map_err(|e| format!("invalid shard count: {e}"))?;
+    if i == 0 || i > n {
+        return Err(format!("shard index must be between 1 and {n}"));
+    }
+    Ok((i, n))
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// ROS middleware implementations selectable through `RMW_IMPLEMENTATION`
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Rmw {
+    Fastrtps,
+    Cyclonedds,
+    Zenoh,
+    Connextdds,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+pub enum RebuildDependencies {
+    Never,
+    Always,
+    /// Only if the headers, interface definitions or build files of a dependency changed since it was built
+    Auto,
+}
+
+/// How the packages given to `colb build` are turned into colcon invocations
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq)]
+pub enum Strategy {
+    /// Build dependencies with the upstream configuration first, then the packages
+    #[default]
+    Split,
+    /// Build the packages and their dependencies in one go
+    UpTo,
+    /// Build the packages and everything that depends on them
+    Above,
+    /// Build the packages, everything that depends on them and all their dependencies
+    AboveAndDependencies,
+    /// Treat the packages as regexes and build all matching packages
+    Regex,
+}
+
+/// Which colcon invocations of `colb build` get the arguments after `--`
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+pub enum PassTo {
+    /// Both the build of the dependencies and of the packages
+    All,
+    /// Only the build of the dependencies
+    Dependencies,
+    /// Only the build of the packages
+    Package,
+}
+
+/// How the packages of a workspace are organized, for `colb init --layout`
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Layout {
+    /// One large repository with most packages, developed together
+    Monorepo,
+    /// Many small repositories (e.g. from a .repos file), most of them upstream dependencies
+    Multirepo,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq)]
+pub enum OutputMode {
+    /// Decorated output on stdout
+    #[default]
+    Human,
+    /// Decorations go to stderr, so stdout only carries the output of the invoked tools
+    Porcelain,
+    /// One JSON event per line on stdout, the output of the invoked tools goes to stderr
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// Common recipes per verb, shown by `colb help <verb> --examples`, in the long help and in the man pages
+pub const EXAMPLES: &[(&str, &[(&str, &str)])] = &[
+    (
+        "build",
+        &[
+            (
+                "Build a package and its dependencies",
+                "colb build my_package",
+            ),
+            (
+                "Rebuild only the package in the current directory",
+                "colb build --skip-dependencies",
+            ),
+            ("Build without tests", "colb build --skip-tests my_package"),
+            (
+                "Build with a different build type",
+                "colb build my_package --build-type Release",
+            ),
+        ],
+    ),
+    (
+        "test",
+        &[
+            (
+                "Rebuild a package and run all of its tests",
+                "colb test my_package",
+            ),
+            (
+                "Rebuild dependencies as well before testing",
+                "colb test --rebuild-dependencies my_package",
+            ),
+            (
+                "Build and run a single test",
+                "colb test my_package --test my_unit_test",
+            ),
+            (
+                "Run a single test through ctest directly",
+                "colb test my_package --test my_unit_test --direct",
+            ),
+            (
+                "Record a flaky test with rr until it fails",
+                "colb test my_package --test my_unit_test --record",
+            ),
+        ],
+    ),
+    (
+        "matrix",
+        &[(
+            "Build and test a package for every combination in [matrix]",
+            "colb matrix my_package",
+        )],
+    ),
+    (
+        "results",
+        &[(
+            "Show which tests changed their outcome since run 3",
+            "colb results diff 3 latest",
+        )],
+    ),
+    (
+        "launch",
+        &[
+            (
+                "Launch a simulation with the workspace's models available",
+                "colb launch my_sim_package world.launch.py -- headless:=true",
+            ),
+            (
+                "Show the simulation resource paths colb sets",
+                "colb launch my_sim_package world.launch.py --print-env",
+            ),
+        ],
+    ),
+    (
+        "run",
+        &[(
+            "Rebuild a package and run one of its nodes with ROS arguments",
+            "colb run my_node -- --ros-args -p rate:=10",
+        )],
+    ),
+    (
+        "watch",
+        &[(
+            "Rebuild a package and run one of its tests whenever its sources change",
+            "colb watch my_package --test my_unit_test",
+        )],
+    ),
+    (
+        "clean",
+        &[(
+            "Remove build and install folders of a package",
+            "colb clean my_package",
+        )],
+    ),
+    (
+        "alias",
+        &[(
+            "Load the configured aliases into the current shell",
+            "eval \"$(colb alias)\"",
+        )],
+    ),
+];
+
+pub fn examples(verb: &str) -> &'static [(&'static str, &'static str)] {
+    EXAMPLES
+        .iter()
+        .find(|(v, _)| *v == verb)
+        .map(|(_, e)| *e)
+        .unwrap_or(&[])
+}
+
+/// The command line interface with the examples of each verb at the end of its long help
+pub fn command() -> clap::Command {
+    let mut cmd = Cli::command();
+    for (verb, examples) in EXAMPLES {
+        let text = examples
+            .iter()
+            .map(|(description, command)| format!("  # {description}\n  {command}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        cmd = cmd.mut_subcommand(verb, |sub| {
+            sub.after_long_help(format!("Examples:\n{text}"))
+        });
+    }
+    cmd
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::{config_diff, environment};
+
+/// Verbs colb invokes, the first of them in the arguments is the one being run
+const VERBS: &[&str] = &["build", "test", "test-result", "list", "graph"];
+
+/// Arguments after which everything is passed on to another tool
+const PASS_THROUGH: &[&str] = &[
+    "--cmake-args",
+    "--ctest-args",
+    "--pytest-args",
+    "--make-args",
+];
+
+/// Packages providing flags that may be missing on minimal installations
+const PROVIDERS: &[(&str, &str)] = &[
+    ("--mixin", "colcon-mixin"),
+    ("--cmake-args", "colcon-cmake"),
+    ("--ctest-args", "colcon-cmake"),
+    ("--symlink-install", "colcon-core >= 0.6"),
+    ("--continue-on-error", "colcon-core >= 0.6"),
+];
+
+/// What the installed colcon supports, probed once and cached per colcon executable
+#[derive(Default)]
+struct Features {
+    flags: BTreeMap<String, BTreeSet<String>>,
+    event_handlers: Option<BTreeSet<String>>,
+}
+
+static FEATURES: Mutex<Option<Features>> = Mutex::new(None);
+static CACHE_FILE: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Arguments given after `--`, for the builds of the dependencies and of the packages
+static EXTRA_ARGS: OnceLock<(Vec<String>, Vec<String>)> = OnceLock::new();
+
+/// Add `args` to the colcon builds of the dependencies and of the packages from now on
+pub fn pass_through(dependencies: Vec<String>, packages: Vec<String>) {
+    let _ = EXTRA_ARGS.set((dependencies, packages));
+}
+
+/// The arguments passed through to the builds of the dependencies, or else of the packages
+pub fn passed_through(dependencies: bool) -> &'static [String] {
+    match EXTRA_ARGS.get() {
+        Some((args, _)) if dependencies => args,
+        Some((_, args)) => args,
+        None => &[],
+    }
+}
+
+/// Regular expressions of the package names no verb selects
+static IGNORED: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Verbs that select packages and take `--packages-ignore-regex`
+const SELECTING: &[&str] = &["build", "test", "list", "graph"];
+
+/// Leave out the packages whose names match one of the `patterns` with `*` and `?` from now on
+pub fn ignore(patterns: &[String]) {
+    let _ = IGNORED.set(patterns.iter().map(|p| glob_regex(p)).collect());
+}
+
+fn glob_regex(pattern: &str) -> String {
+    let mut res = "^".to_string();
+    for c in pattern.chars() {
+        match c {
+            '*' => res.push_str(".*"),
+            '?' => res.push('.'),
+            c => res.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    res.push('$');
+    res
+}
+
+/// `args` with the ignored packages left out, added to a `--packages-ignore-regex` already given
+pub fn with_ignored(mut args: Vec<String>) -> Vec<String> {
+    let Some(ignored) = IGNORED.get().filter(|i| !i.is_empty()) else {
+        return args;
+    };
+    let Some(verb) = args.iter().position(|a| VERBS.contains(&a.as_str())) else {
+        return args;
+    };
+    if !SELECTING.contains(&args[verb].as_str()) {
+        return args;
+    }
+    let at = match args.iter().position(|a| a == "--packages-ignore-regex") {
+        Some(flag) => flag + 1,
+        None => {
+            args.insert(verb + 1, "--packages-ignore-regex".into());
+            verb + 2
+        }
+    };
+    args.splice(at..at, ignored.iter().cloned());
+    args
+}
+
+/// Add the options in `extra` to `args`. colcon only keeps the last occurrence of an option,
+/// so the values of options `args` already has are appended to them instead
+pub fn merge(args: &[String], extra: &[String]) -> Vec<String> {
+    let mut res = config_diff::flags(args);
+    for (name, values) in config_diff::flags(extra) {
+        match res.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => existing.extend(values),
+            None => res.push((name, values)),
+        }
+    }
+    res.into_iter()
+        .flat_map(|(name, values)| std::iter::once(name).chain(values))
+        .collect()
+}
+
+/// Cache file for the colcon that is run, named after its location and modification time
+fn cache_file() -> Option<PathBuf> {
+    CACHE_FILE
+        .get_or_init(|| {
+            let colcon = environment::colcon_executable()?;
+            let modified = fs::metadata(&colcon)
+                .and_then(|m| m.modified())
+                .ok()?
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .ok()?
+                .as_secs();
+            let name = colcon
+                .to_string_lossy()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect::<String>();
+            let dir = env::var("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".cache")))
+                .unwrap_or(env::temp_dir());
+            Some(
+                dir.join("colb")
+                    .join("colcon-features")
+                    .join(format!("{name}-{modified}")),
+            )
+        })
+        .clone()
+}
+
+fn colcon_output(args: &[&str]) -> Option<String> {
+    let output = environment::colcon()
+        .args(["--log-base", "/dev/null"])
+        .args(args)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// All `--flags` mentioned in the help of a verb
+fn probe_flags(verb: &str) -> Option<BTreeSet<String>> {
+    let help = colcon_output(&[verb, "--help"])?;
+    Some(
+        help.split(|c: char| c.is_whitespace() || "[],={}()".contains(c))
+            .filter(|w| w.starts_with("--") && w.len() > 2)
+            .map(|w| w.trim_end_matches(['.', ':']).to_string())
+            .collect(),
+    )
+}
+
+/// Names of the installed event handlers, from `colcon extensions`
+fn probe_event_handlers() -> Option<BTreeSet<String>> {
+    let extensions = colcon_output(&["extensions"])?;
+    let mut res = BTreeSet::new();
+    let mut in_group = false;
+    for line in extensions.lines() {
+        if !line.starts_with(' ') {
+            in_group = line.trim_end_matches(':') == "colcon_core.event_handler";
+        } else if in_group {
+            if let Some(name) = line.split_whitespace().next() {
+                res.insert(name.trim_end_matches(':').to_string());
+            }
+        }
+    }
+    Some(res)
+}
+
+impl Features {
+    fn load(file: &Path) -> Option<Features> {
+        let mut res = Features::default();
+        for line in fs::read_to_string(file).ok()?.lines() {
+            match line.split_once('\t')? {
+                ("event_handler", name) => {
+                    res.event_handlers
+                        .get_or_insert_with(BTreeSet::new)
+                        .insert(name.into());
+                }
+                (verb, flag) => {
+                    res.flags
+                        .entry(verb.into())
+                        .or_default()
+                        .insert(flag.into());
+                }
+            }
+        }
+        Some(res)
+    }
+
+    fn save(&self, file: &Path) {
+        let mut content = String::new();
+        for (verb, flags) in &self.flags {
+            for flag in flags {
+                content.push_str(&format!("{verb}\t{flag}\n"));
+            }
+        }
+        for name in self.event_handlers.iter().flatten() {
+            content.push_str(&format!("event_handler\t{name}\n"));
+        }
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(file, content);
+    }
+
+    /// Flags of `verb`, or None if colcon couldn't be probed
+    fn flags(&mut self, verb: &str) -> Option<&BTreeSet<String>> {
+        if !self.flags.contains_key(verb) {
+            let flags = probe_flags(verb)?;
+            self.flags.insert(verb.into(), flags);
+            if self.event_handlers.is_none() {
+                self.event_handlers = probe_event_handlers();
+            }
+            if let Some(file) = cache_file() {
+                self.save(&file);
+            }
+        }
+        self.flags.get(verb)
+    }
+}
+
+/// Adapt colcon arguments to what the installed colcon supports.
+/// Unknown event handlers are dropped, unsupported flags are reported as an error
+pub fn adapt(args: Vec<String>) -> Result<Vec<String>, String> {
+    let args = with_ignored(args);
+    let Some(verb) = args.iter().find(|a| VERBS.contains(&a.as_str())).cloned() else {
+        return Ok(args);
+    };
+    let mut features = FEATURES.lock().map_err(|e| e.to_string())?;
+    let features = features.get_or_insert_with(|| {
+        cache_file()
+            .and_then(|f| Features::load(&f))
+            .unwrap_or_default()
+    });
+    let Some(flags) = features.flags(&verb).cloned() else {
+        // colcon couldn't be probed, let it report problems itself
+        return Ok(args);
+    };
+    let mut res = vec![];
+    let mut after_verb = false;
+    let mut in_event_handlers = false;
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if PASS_THROUGH.contains(&arg.as_str()) {
+            if after_verb && !flags.contains(&arg) {
+                return Err(unsupported(&verb, &arg));
+            }
+            res.push(arg);
+            res.extend(iter);
+            break;
+        }
+        if arg == verb {
+            after_verb = true;
+        } else if arg.starts_with("--") {
+            in_event_handlers = arg == "--event-handlers";
+            if after_verb && !flags.contains(&arg) {
+                return Err(unsupported(&verb, &arg));
+            }
+        } else if in_event_handlers {
+            let name = arg.trim_end_matches(['+', '-']);
+            if let Some(known) = &features.event_handlers {
+                if !known.contains(name) {
+                    continue;
+                }
+            }
+        }
+        res.push(arg);
+    }
+    // Don't leave an --event-handlers without handlers behind
+    let mut i = 0;
+    while i < res.len() {
+        let empty = res[i] == "--event-handlers"
+            && res.get(i + 1).is_none_or(|next| next.starts_with("--"));
+        if empty {
+            res.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    Ok(res)
+}
+
+fn unsupported(verb: &str, flag: &str) -> String {
+    let hint = PROVIDERS
+        .iter()
+        .find(|(f, _)| *f == flag)
+        .map(|(_, p)| format!(", it is provided by {p}"))
+        .unwrap_or_default();
+    format!("The installed colcon doesn't support '{flag}' for '{verb}'{hint}")
+}
+
+/// Folder of the cached `colcon list` outputs, if they are cached
+static LIST_CACHE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Cache what `colcon list` prints in `folder` from now on. Listing a large workspace takes
+/// seconds, and colb lists packages several times per build
+pub fn cache_lists(folder: PathBuf) {
+    let _ = LIST_CACHE.set(folder);
+}
+
+/// Files that make a folder a package for colcon
+const MANIFESTS: &[&str] = &[
+    "package.xml",
+    "CMakeLists.txt",
+    "setup.py",
+    "setup.cfg",
+    "Cargo.toml",
+];
+
+/// Files that make colcon skip a folder, like the build, install and log bases
+const IGNORE_MARKERS: &[&str] = &["COLCON_IGNORE", "AMENT_IGNORE", "CATKIN_IGNORE"];
+
+/// Add the modification times of the folders below `dir` and of the manifests in them to `res`,
+/// down to the packages. Adding or removing a package changes the time of its parent folder
+fn fingerprint(dir: &Path, res: &mut String) {
+    if IGNORE_MARKERS.iter().any(|m| dir.join(m).exists()) {
+        res.push_str(&format!("{} ignored\n", dir.to_string_lossy()));
+        return;
+    }
+    let time = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+    };
+    res.push_str(&format!("{} {:?}\n", dir.to_string_lossy(), time(dir)));
+    let mut package = false;
+    for manifest in MANIFESTS {
+        if let Some(time) = time(&dir.join(manifest)) {
+            res.push_str(&format!("{manifest} {time}\n"));
+            package = true;
+        }
+    }
+    if package {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut dirs = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    for dir in dirs {
+        fingerprint(&dir, res);
+    }
+}
+
+/// The output of colcon with `args`, and whether it succeeded
+fn run_list(workspace: &str, args: &[String]) -> (String, bool) {
+    let output = environment::colcon()
+        .current_dir(workspace)
+        .args(["--log-base", "/dev/null"])
+        .args(args)
+        .output()
+        .expect("'colcon' not found");
+    (
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        output.status.success(),
+    )
+}
+
+/// What `colcon list` printed during this run, by workspace and arguments
+static LISTED: Mutex<BTreeMap<(String, Vec<String>), String>> = Mutex::new(BTreeMap::new());
+
+/// What `colcon list` prints with the selection `args` in `workspace`, listed once per run. From
+/// the cache as long as no package was added, removed or changed its manifest, if it is enabled
+pub fn list(workspace: &str, selection: &[&str]) -> String {
+    let mut args = vec!["list".to_string()];
+    args.extend(selection.iter().map(|a| a.to_string()));
+    let args = with_ignored(args);
+    let key = (workspace.to_string(), args);
+    if let Some(listed) = LISTED.lock().ok().and_then(|l| l.get(&key).cloned()) {
+        return listed;
+    }
+    let res = cached_list(workspace, &key.1);
+    if let Ok(mut listed) = LISTED.lock() {
+        listed.insert(key, res.clone());
+    }
+    res
+}
+
+fn cached_list(workspace: &str, args: &[String]) -> String {
+    let Some(folder) = LIST_CACHE.get() else {
+        return run_list(workspace, args).0;
+    };
+    let mut key = DefaultHasher::new();
+    (workspace, args).hash(&mut key);
+    let file = folder.join(format!("{:016x}", key.finish()));
+    let mut current = String::new();
+    fingerprint(Path::new(workspace), &mut current);
+    let mut hasher = DefaultHasher::new();
+    current.hash(&mut hasher);
+    let current = format!("{:016x}\n", hasher.finish());
+    if let Some(cached) = fs::read_to_string(&file)
+        .ok()
+        .and_then(|c| c.strip_prefix(&current).map(str::to_string))
+    {
+        return cached;
+    }
+    let (res, success) = run_list(workspace, args);
+    if success {
+        let _ = fs::create_dir_all(folder).and_then(|_| fs::write(&file, current + &res));
+    }
+    res
+}
@@ -0,0 +1,96 @@
+use clap::{builder::PossibleValuesParser, Arg, Command};
+use clap_complete::Shell;
+
+use crate::cli::PACKAGES_VERB;
+
+/// Stands in for the package names in the generated script, replaced by a call to colb
+const PACKAGES: &str = "__colb_packages__";
+
+fn list_packages() -> String {
+    format!("colb {PACKAGES_VERB} 2>/dev/null")
+}
+
+fn is_package(arg: &Arg) -> bool {
+    matches!(arg.get_id().as_str(), "package" | "packages" | "skip")
+        && arg.get_action().takes_values()
+}
+
+/// Complete the arguments naming packages in `cmd` and its verbs with the sentinel
+fn mark_packages(mut cmd: Command) -> Command {
+    // Changed arguments move to the end, so the positional ones all get their index fixed
+    let changed = cmd
+        .get_arguments()
+        .filter(|a| a.is_positional() || is_package(a))
+        .map(|a| (a.get_id().to_string(), a.is_positional(), is_package(a)))
+        .collect::<Vec<_>>();
+    let mut index = 0;
+    for (id, positional, package) in changed {
+        index += usize::from(positional);
+        cmd = cmd.mut_arg(id, |mut a| {
+            if package {
+                a = a.value_parser(PossibleValuesParser::new([PACKAGES]));
+            }
+            if positional {
+                a = a.index(index);
+            }
+            a
+        });
+    }
+    let verbs = cmd
+        .get_subcommands()
+        .map(|v| v.get_name().to_string())
+        .collect::<Vec<_>>();
+    for verb in verbs {
+        cmd = cmd.mut_subcommand(verb, mark_packages);
+    }
+    cmd
+}
+
+/// `cmd` without its hidden verbs, which the generators would offer as well
+fn visible(cmd: &Command) -> Command {
+    Command::new("colb")
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(cmd.get_arguments().cloned())
+        .subcommands(cmd.get_subcommands().filter(|v| !v.is_hide_set()).cloned())
+}
+
+/// The fish generator doesn't complete positional arguments, add the package names for the verbs
+/// taking them
+fn fish_positionals(cmd: &Command) -> String {
+    cmd.get_subcommands()
+        .filter(|v| v.get_positionals().any(is_package))
+        .map(|v| {
+            format!(
+                "complete -c colb -n \"__fish_colb_using_subcommand {}\" -f -a \"({})\"\n",
+                v.get_name(),
+                list_packages()
+            )
+        })
+        .collect()
+}
+
+/// A completion script for `shell`, completing package names through colb itself in bash, zsh and
+/// fish
+pub fn script(cmd: Command, shell: Shell) -> String {
+    let mut cmd = visible(&cmd);
+    if matches!(shell, Shell::Bash | Shell::Zsh | Shell::Fish) {
+        cmd = mark_packages(cmd);
+    }
+    let mut res = vec![];
+    clap_complete::generate(shell, &mut cmd, "colb", &mut res);
+    let res = String::from_utf8_lossy(&res).to_string();
+    match shell {
+        Shell::Bash => res.replace(PACKAGES, &format!("$({})", list_packages())),
+        Shell::Zsh => res.replace(
+            &format!("({PACKAGES})"),
+            &format!("{{compadd -- $({})}}", list_packages()),
+        ),
+        Shell::Fish => {
+            res.replace(
+                &format!("\"{PACKAGES}\\t''\""),
+                &format!("\"({})\"", list_packages()),
+            ) + &fish_positionals(&cmd)
+        }
+        _ => res,
+    }
+}
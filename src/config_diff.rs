@@ -0,0 +1,64 @@
+/// A colcon option with its values, e.g. `--mixin ninja ccache`
+type Flag = (String, Vec<String>);
+
+/// Group colcon arguments by option, values starting with a single dash (like `-DFOO=ON`) belong to the option before them
+pub fn flags(args: &[String]) -> Vec<Flag> {
+    let mut res: Vec<Flag> = vec![];
+    for arg in args {
+        match res.last_mut() {
+            Some((_, values)) if !arg.starts_with("--") => values.push(arg.clone()),
+            _ => res.push((arg.clone(), vec![])),
+        }
+    }
+    res
+}
+
+/// A line for an option that only one side has
+fn option(name: &str, values: &[String], sign: char) -> String {
+    let mut res = format!("{sign} {name}");
+    for v in values {
+        res.push(' ');
+        res.push_str(v);
+    }
+    res.push('\n');
+    res
+}
+
+/// The options whose values differ, with `-` for values only in `a` and `+` for values only in `b`
+pub fn diff(a: &[String], b: &[String]) -> String {
+    let (a, b) = (flags(a), flags(b));
+    let mut names = a.iter().map(|(n, _)| n).collect::<Vec<_>>();
+    names.extend(
+        b.iter()
+            .map(|(n, _)| n)
+            .filter(|n| !a.iter().any(|(m, _)| m == *n)),
+    );
+    let values = |flags: &[Flag], name: &str| {
+        flags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    };
+    let mut res = String::new();
+    for name in names {
+        match (values(&a, name), values(&b, name)) {
+            (Some(x), Some(y)) if x == y => {}
+            (Some(x), None) => res.push_str(&option(name, &x, '-')),
+            (None, Some(y)) => res.push_str(&option(name, &y, '+')),
+            (Some(x), Some(y)) => {
+                res.push_str(&format!("  {name}\n"));
+                for v in x.iter().filter(|v| !y.contains(v)) {
+                    res.push_str(&format!("-   {v}\n"));
+                }
+                for v in y.iter().filter(|v| !x.contains(v)) {
+                    res.push_str(&format!("+   {v}\n"));
+                }
+                if x.iter().all(|v| y.contains(v)) && y.iter().all(|v| x.contains(v)) {
+                    res.push_str("    (same values in a different order)\n");
+                }
+            }
+            (None, None) => unreachable!("names come from either side"),
+        }
+    }
+    res
+}
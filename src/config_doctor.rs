@@ -0,0 +1,159 @@
+use std::thread;
+
+use crate::{metrics, owners, BuildConfiguration, Config};
+
+/// A setting that contradicts another one or has no effect
+pub struct Finding {
+    pub warning: String,
+    pub fix: String,
+}
+
+fn finding(warning: String, fix: String) -> Finding {
+    Finding { warning, fix }
+}
+
+/// Value of the last `-D<name>=<value>` or `-D<name>:<type>=<value>` in the CMake arguments
+fn cmake_variable<'a>(config: &'a BuildConfiguration, name: &str) -> Option<&'a str> {
+    config.cmake_args.iter().rev().find_map(|a| {
+        let rest = a.strip_prefix(&format!("-D{name}"))?;
+        if !rest.starts_with(['=', ':']) {
+            return None;
+        }
+        rest.split_once('=').map(|(_, v)| v)
+    })
+}
+
+/// Contradictions within the `upstream` or `package` configuration
+fn check_build(section: &str, config: &BuildConfiguration, res: &mut Vec<Finding>) {
+    let ccache = config.mixins.iter().any(|m| m == "ccache");
+    for launcher in ["CMAKE_C_COMPILER_LAUNCHER", "CMAKE_CXX_COMPILER_LAUNCHER"] {
+        if ccache && cmake_variable(config, launcher).is_some() {
+            res.push(finding(
+                format!("[{section}] uses the ccache mixin and sets {launcher} in cmake_args"),
+                format!("remove either the mixin or -D{launcher} from [{section}]"),
+            ));
+        }
+    }
+    if cmake_variable(config, "CMAKE_BUILD_TYPE").is_some() {
+        res.push(finding(
+            format!("-DCMAKE_BUILD_TYPE in [{section}] cmake_args is overridden by build_type"),
+            format!("set build_type in [{section}] instead"),
+        ));
+    }
+    if let Some(value) = cmake_variable(config, "BUILD_TESTING") {
+        let on = matches!(value.to_uppercase().as_str(), "ON" | "1" | "TRUE" | "YES");
+        if on != config.build_tests {
+            res.push(finding(
+                format!(
+                    "-DBUILD_TESTING={value} in [{section}] cmake_args contradicts build_tests = {}",
+                    config.build_tests
+                ),
+                format!("remove -DBUILD_TESTING from [{section}] and use build_tests"),
+            ));
+        }
+    }
+    let mut seen = vec![];
+    for mixin in &config.mixins {
+        if seen.contains(&mixin) {
+            res.push(finding(
+                format!("[{section}] lists the mixin '{mixin}' twice"),
+                format!("remove the duplicate from [{section}] mixins"),
+            ));
+        }
+        seen.push(mixin);
+    }
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get()) as u32;
+    if let Some(jobs) = config.parallel_jobs.filter(|j| *j > 4 * cpus) {
+        res.push(finding(
+            format!("[{section}] parallel_jobs = {jobs} is more than 4 times the {cpus} CPUs"),
+            format!("use parallel_jobs = {cpus} in [{section}]"),
+        ));
+    }
+}
+
+/// Whether colb invocations configured somewhere run tests
+fn runs_tests(config: &Config) -> bool {
+    let invokes_test = |args: &str| args.split_whitespace().next() == Some("test");
+    config.defaults.contains_key("test")
+        || config.aliases.values().any(|a| invokes_test(a))
+        || config.pipelines.values().flatten().any(|step| {
+            step.args()
+                .is_ok_and(|args| args.first().map(String::as_str) == Some("test"))
+        })
+}
+
+/// Settings that contradict each other or have no effect in the resolved configuration
+pub fn check(config: &Config) -> Vec<Finding> {
+    let mut res = vec![];
+    check_build("upstream", &config.upstream, &mut res);
+    check_build("package", &config.package, &mut res);
+    if !config.package.build_tests && runs_tests(config) {
+        res.push(finding(
+            "[package] build_tests = false, but defaults, aliases or pipelines run `colb test`"
+                .into(),
+            "set build_tests = true in [package]".into(),
+        ));
+    }
+    if config.conda_environment.is_some() && config.virtualenv.is_some() {
+        res.push(finding(
+            "Both conda_environment and virtualenv are set, colcon ends up in the virtualenv"
+                .into(),
+            "remove one of them".into(),
+        ));
+    }
+    if config.cache.remote.is_some() && !config.cache.enabled {
+        res.push(finding(
+            "[cache] remote is set, but the cache is disabled".into(),
+            "set enabled = true in [cache]".into(),
+        ));
+    }
+    let ccache_used = [&config.upstream, &config.package].iter().any(|c| {
+        c.mixins.iter().any(|m| m == "ccache")
+            || cmake_variable(c, "CMAKE_CXX_COMPILER_LAUNCHER")
+                .is_some_and(|l| l.contains("ccache"))
+    });
+    if config.ccache.remote_storage.is_some() && !ccache_used {
+        res.push(finding(
+            "[ccache] remote_storage is set, but no build uses ccache".into(),
+            "add the ccache mixin to [upstream] and [package]".into(),
+        ));
+    }
+    let metrics = metrics::user_config().unwrap_or_default();
+    if !metrics.labels.is_empty() && !metrics.enabled() {
+        res.push(finding(
+            "[metrics] labels are set in the user config, but there is no pushgateway or otlp endpoint"
+                .into(),
+            "set pushgateway or otlp in [metrics]".into(),
+        ));
+    }
+    for (pattern, e) in owners::invalid(&config.tests.owners) {
+        res.push(finding(
+            format!("[tests.owners] \"{pattern}\" isn't a valid regular expression: {e}"),
+            "fix or remove the pattern".into(),
+        ));
+    }
+    for (name, overrides) in &config.packages {
+        let unchanged = |c: &BuildConfiguration| {
+            let applied = overrides.apply(c);
+            applied.cmake_args == c.cmake_args
+                && applied.build_type.name() == c.build_type.name()
+                && applied.mixins == c.mixins
+                && applied.build_tests == c.build_tests
+        };
+        if unchanged(&config.upstream) && unchanged(&config.package) {
+            res.push(finding(
+                format!("[packages.{name}] doesn't change anything"),
+                format!("remove [packages.{name}]"),
+            ));
+        }
+        if config.pinned.contains(name) {
+            res.push(finding(
+                format!(
+                    "'{name}' is pinned, so [packages.{name}] only applies until it is installed"
+                ),
+                format!("colb unpin {name}"),
+            ));
+        }
+    }
+    res
+}
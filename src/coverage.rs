@@ -0,0 +1,169 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{environment, output::*};
+
+/// Minimum line coverage in percent for `colb coverage --check`
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct MinCoverage {
+    /// For all checked packages together, and for every package without its own threshold
+    pub overall: Option<f64>,
+    /// By package name
+    #[serde(default)]
+    pub packages: BTreeMap<String, f64>,
+}
+
+impl MinCoverage {
+    pub fn for_package(&self, package: &str) -> Option<f64> {
+        self.packages.get(package).copied().or(self.overall)
+    }
+}
+
+/// Line coverage of a source file
+pub struct FileCoverage {
+    pub path: PathBuf,
+    pub lines: u64,
+    pub covered: u64,
+}
+
+impl FileCoverage {
+    pub fn uncovered(&self) -> u64 {
+        self.lines - self.covered
+    }
+}
+
+pub fn percent(lines: u64, covered: u64) -> f64 {
+    if lines == 0 {
+        return 100.0;
+    }
+    100.0 * covered as f64 / lines as f64
+}
+
+/// Lines and covered lines of all files together
+pub fn total(files: &[FileCoverage]) -> (u64, u64) {
+    files
+        .iter()
+        .fold((0, 0), |(l, c), f| (l + f.lines, c + f.covered))
+}
+
+/// Where `colb coverage` builds the packages with coverage flags, relative to the workspace
+pub const PREFIX: &str = ".colb/coverage";
+
+/// Compiler and linker flags for gcov counters
+pub const FLAGS: &str = "--coverage";
+
+/// Where the tracefile and the HTML report of a package go
+pub fn report_dir(workspace: &Path, package: &str) -> PathBuf {
+    workspace
+        .join(PREFIX)
+        .join("build")
+        .join(package)
+        .join("coverage")
+}
+
+fn lcov(args: &[&OsStr]) -> Result<(), String> {
+    let mut cmd = environment::tool("lcov");
+    cmd.args(args).arg("--quiet");
+    print_command(&cmd);
+    let status = run(&mut cmd).map_err(|e| format!("Couldn't run 'lcov': {e}"))?;
+    if !status.success() && !commands_only() {
+        return Err("lcov failed".into());
+    }
+    Ok(())
+}
+
+/// Collect the coverage counters of the package's last test run with lcov, keeping only the
+/// package's own sources, returns the tracefile
+pub fn capture(workspace: &Path, package: &str, source: &Path) -> Result<PathBuf, String> {
+    let build = workspace.join(PREFIX).join("build").join(package);
+    let dir = report_dir(workspace, package);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let all = dir.join("all.info");
+    lcov(&[
+        "--capture".as_ref(),
+        "--directory".as_ref(),
+        build.as_ref(),
+        "--output-file".as_ref(),
+        all.as_ref(),
+    ])
+    .map_err(|_| format!("No coverage data for '{package}', did its tests run?"))?;
+    let tracefile = dir.join("coverage.info");
+    let pattern = source.join("*");
+    lcov(&[
+        "--extract".as_ref(),
+        all.as_ref(),
+        pattern.as_ref(),
+        "--output-file".as_ref(),
+        tracefile.as_ref(),
+    ])?;
+    Ok(tracefile)
+}
+
+/// Render the tracefile as HTML next to it, returns the index page
+pub fn html(tracefile: &Path) -> Result<PathBuf, String> {
+    let dir = tracefile.with_file_name("html");
+    let mut cmd = environment::tool("genhtml");
+    cmd.arg(tracefile)
+        .arg("--output-directory")
+        .arg(&dir)
+        .arg("--quiet");
+    print_command(&cmd);
+    let status = run(&mut cmd).map_err(|e| format!("Couldn't run 'genhtml': {e}"))?;
+    if !status.success() && !commands_only() {
+        return Err("genhtml failed".into());
+    }
+    Ok(dir.join("index.html"))
+}
+
+/// Files below `source` in an lcov tracefile, leaving out system and generated headers
+pub fn parse(tracefile: &Path, source: &Path) -> Result<Vec<FileCoverage>, String> {
+    let content = fs::read_to_string(tracefile)
+        .map_err(|e| format!("Couldn't read '{}': {e}", tracefile.to_string_lossy()))?;
+    let mut res: Vec<FileCoverage> = vec![];
+    let mut current = None;
+    for line in content.lines() {
+        if let Some(path) = line.strip_prefix("SF:") {
+            current = Some(FileCoverage {
+                path: path.into(),
+                lines: 0,
+                covered: 0,
+            });
+        } else if let Some(n) = line.strip_prefix("LF:") {
+            if let Some(file) = &mut current {
+                file.lines = n.parse().unwrap_or(0);
+            }
+        } else if let Some(n) = line.strip_prefix("LH:") {
+            if let Some(file) = &mut current {
+                file.covered = n.parse().unwrap_or(0);
+            }
+        } else if line == "end_of_record" {
+            // The same file shows up once per object it was compiled into
+            if let Some(file) = current.take().filter(|f| f.path.starts_with(source)) {
+                match res.iter_mut().find(|f| f.path == file.path) {
+                    Some(known) if file.lines > known.lines => *known = file,
+                    Some(_) => {}
+                    None => res.push(file),
+                }
+            }
+        }
+    }
+    res.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(res)
+}
+
+/// The `n` files with the most uncovered lines
+pub fn largest_gaps(files: &[FileCoverage], n: usize) -> Vec<&FileCoverage> {
+    let mut res = files
+        .iter()
+        .filter(|f| f.uncovered() > 0)
+        .collect::<Vec<_>>();
+    res.sort_by_key(|f| std::cmp::Reverse(f.uncovered()));
+    res.truncate(n);
+    res
+}
@@ -0,0 +1,223 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::environment;
+
+/// A single test as registered in a `CTestTestfile.cmake`
+pub struct CTest {
+    pub name: String,
+    /// The full command line ctest runs, including wrappers like ament's `run_test.py`
+    pub command: Vec<String>,
+    pub working_directory: Option<PathBuf>,
+}
+
+impl CTest {
+    /// The actual test program and its arguments, skipping ament's `run_test.py` wrapper
+    pub fn program(&self) -> &[String] {
+        match self.command.iter().position(|a| a == "--command") {
+            Some(i) => &self.command[i + 1..],
+            None => &self.command,
+        }
+    }
+
+    /// The test running its program without arguments, e.g. every case of a gtest executable
+    /// instead of the single one `gtest_discover_tests` registered it for
+    pub fn without_arguments(&self) -> CTest {
+        let start = self
+            .command
+            .iter()
+            .position(|a| a == "--command")
+            .map_or(0, |i| i + 1);
+        CTest {
+            name: self.name.clone(),
+            command: self.command[..(start + 1).min(self.command.len())].to_vec(),
+            working_directory: self.working_directory.clone(),
+        }
+    }
+
+    /// A command running the test program directly through `launcher` (e.g. `rr record`),
+    /// with the working directory and environment the ament wrapper would use
+    pub fn direct_command(&self, launcher: &[&str]) -> Option<Command> {
+        let program = self.program();
+        let mut cmd = match launcher.split_first() {
+            Some((first, rest)) => {
+                let mut cmd = environment::tool(first);
+                cmd.args(rest).args(program);
+                cmd
+            }
+            None => {
+                let (first, rest) = program.split_first()?;
+                let mut cmd = environment::tool(first);
+                cmd.args(rest);
+                cmd
+            }
+        };
+        if let Some(dir) = &self.working_directory {
+            cmd.current_dir(dir);
+        }
+        let end = self
+            .command
+            .iter()
+            .position(|a| a == "--command")
+            .unwrap_or(0);
+        let mut append = false;
+        for arg in &self.command[..end] {
+            match arg.as_str() {
+                "--env" => append = false,
+                "--append-env" => append = true,
+                a if a.starts_with("--") => {}
+                a => {
+                    let Some((k, v)) = a.split_once('=') else {
+                        continue;
+                    };
+                    match env::var(k) {
+                        Ok(old) if append && !old.is_empty() => cmd.env(k, format!("{old}:{v}")),
+                        _ => cmd.env(k, v),
+                    };
+                }
+            }
+        }
+        Some(cmd)
+    }
+}
+
+/// Split the argument list of a cmake command invocation into its arguments
+fn split_cmake_args(mut s: &str) -> Vec<String> {
+    let mut res = vec![];
+    loop {
+        s = s.trim_start();
+        if s.is_empty() {
+            return res;
+        }
+        if let Some(rest) = s.strip_prefix('"') {
+            let mut arg = String::new();
+            let mut chars = rest.char_indices();
+            s = "";
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        s = &rest[i + 1..];
+                        break;
+                    }
+                    '\\' => arg.extend(chars.next().map(|(_, e)| e)),
+                    c => arg.push(c),
+                }
+            }
+            res.push(arg);
+        } else if let Some(rest) = s.strip_prefix('[') {
+            // Bracket argument: [=[content]=] with any number of '='
+            let level = rest.len() - rest.trim_start_matches('=').len();
+            let rest = rest[level..].strip_prefix('[').unwrap_or(&rest[level..]);
+            let close = format!("]{}]", "=".repeat(level));
+            let end = rest.find(&close).unwrap_or(rest.len());
+            res.push(rest[..end].to_string());
+            s = rest.get(end + close.len()..).unwrap_or("");
+        } else {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            res.push(s[..end].to_string());
+            s = &s[end..];
+        }
+    }
+}
+
+/// Return the argument lists of all invocations of `function` in a cmake script
+fn invocations<'a>(content: &'a str, function: &'a str) -> impl Iterator<Item = Vec<String>> + 'a {
+    content.lines().filter_map(move |line| {
+        let line = line.trim();
+        let rest = line
+            .strip_prefix(function)?
+            .trim_start()
+            .strip_prefix('(')?;
+        let rest = rest.strip_suffix(')').unwrap_or(rest);
+        Some(split_cmake_args(rest))
+    })
+}
+
+/// Collect all tests registered in a build directory, following `subdirs()`
+pub fn tests_in(build_dir: &Path) -> Vec<CTest> {
+    let mut res = vec![];
+    let Ok(content) = fs::read_to_string(build_dir.join("CTestTestfile.cmake")) else {
+        return res;
+    };
+    for args in invocations(&content, "add_test") {
+        if let Some((name, command)) = args.split_first() {
+            res.push(CTest {
+                name: name.clone(),
+                command: command.to_vec(),
+                working_directory: None,
+            });
+        }
+    }
+    for args in invocations(&content, "set_tests_properties") {
+        let Some(props) = args.iter().position(|a| a == "PROPERTIES") else {
+            continue;
+        };
+        for pair in args[props + 1..].chunks(2) {
+            if let [key, value] = pair {
+                if key == "WORKING_DIRECTORY" {
+                    for t in res.iter_mut().filter(|t| args[..props].contains(&t.name)) {
+                        t.working_directory = Some(PathBuf::from(value));
+                    }
+                }
+            }
+        }
+    }
+    for args in invocations(&content, "subdirs") {
+        for dir in args {
+            res.extend(tests_in(&build_dir.join(dir)));
+        }
+    }
+    res
+}
+
+/// Find a test by its ctest name
+pub fn find_test(build_dir: &Path, name: &str) -> Option<CTest> {
+    tests_in(build_dir).into_iter().find(|t| t.name == name)
+}
+
+/// Outcome of each test in the last ctest run of a build directory, e.g. `passed` or `failed`
+pub fn last_results(build_dir: &Path) -> Vec<(String, String)> {
+    let log = build_dir
+        .join("Testing")
+        .join("Temporary")
+        .join("LastTest.log");
+    let Ok(content) = fs::read_to_string(log) else {
+        return vec![];
+    };
+    let mut res = vec![];
+    let mut current = None;
+    for line in content.lines() {
+        if let Some((_, name)) = line.split_once(" Testing: ") {
+            current = Some(name.trim().to_string());
+        } else if let Some(outcome) = line.strip_prefix("Test ").and_then(|o| o.strip_suffix('.')) {
+            if let Some(name) = current.take() {
+                res.push((name, outcome.to_lowercase()));
+            }
+        }
+    }
+    res
+}
+
+/// Names of the tests that failed in the last ctest run of a build directory
+pub fn last_failed(build_dir: &Path) -> Vec<String> {
+    // ctest lists them as `<index>:<name>`, for --rerun-failed
+    let log = build_dir
+        .join("Testing")
+        .join("Temporary")
+        .join("LastTestsFailed.log");
+    if let Ok(content) = fs::read_to_string(log) {
+        return content
+            .lines()
+            .filter_map(|l| l.split_once(':'))
+            .map(|(_, name)| name.trim().to_string())
+            .collect();
+    }
+    last_results(build_dir)
+        .into_iter()
+        .filter(|(_, outcome)| outcome != "passed")
+        .map(|(name, _)| name)
+        .collect()
+}
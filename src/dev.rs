@@ -0,0 +1,112 @@
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{environment, output::*};
+
+/// What `colb dev` runs for a package, either an executable or a launch file
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct DevConfig {
+    /// Executable to start with `ros2 run`
+    pub executable: Option<String>,
+    /// Launch file to start with `ros2 launch`
+    pub launch: Option<String>,
+    /// Arguments for the executable or launch file, e.g. `["--ros-args", "-p", "rate:=10"]`
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl DevConfig {
+    /// The `ros2 run` or `ros2 launch` command, with `args` after the configured ones
+    pub fn command(&self, package: &str, args: &[String]) -> Result<Command, String> {
+        let mut cmd = environment::tool("ros2");
+        match (&self.executable, &self.launch) {
+            (Some(executable), None) => cmd.args(["run", package, executable]),
+            (None, Some(launch)) => cmd.args(["launch", package, launch]),
+            _ => {
+                return Err(format!(
+                    "Set either 'executable' or 'launch' in [dev.{package}] of the configuration"
+                ))
+            }
+        };
+        cmd.args(&self.args).args(args);
+        Ok(cmd)
+    }
+}
+
+/// Whether the changes need a rebuild. With `--symlink-install`, changes to Python modules take
+/// effect right away, but removed files and changes to `setup.py` (e.g. entry points), `setup.cfg`,
+/// `package.xml` or data files are only installed by a build
+pub fn needs_rebuild(changed: &[PathBuf]) -> bool {
+    changed.iter().any(|path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        !path.exists() || path.extension().is_none_or(|e| e != "py") || name == "setup.py"
+    })
+}
+
+/// The executable `name` built in a package's build folder. The installed copy is only updated by
+/// a colcon build, so after a ninja or make rebuild the node runs from here
+pub fn built_executable(build_dir: &Path, name: &str) -> Option<PathBuf> {
+    let entries = fs::read_dir(build_dir).ok()?;
+    let mut folders = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_name = entry.file_name();
+        if path.is_dir() {
+            if file_name != "CMakeFiles" && file_name != "Testing" && !path.is_symlink() {
+                folders.push(path);
+            }
+        } else if file_name == name
+            && path
+                .metadata()
+                .is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+        {
+            return Some(path);
+        }
+    }
+    folders.sort();
+    folders.iter().find_map(|f| built_executable(f, name))
+}
+
+/// A started node, stopped like with Ctrl-C before it is restarted
+pub struct Node {
+    child: Child,
+}
+
+impl Node {
+    pub fn start(mut cmd: Command) -> Result<Node, String> {
+        print_command(&cmd);
+        let program = cmd.get_program().to_string_lossy().to_string();
+        let child = cmd
+            .spawn()
+            .map_err(|e| format!("Couldn't run '{program}': {e}"))?;
+        Ok(Node { child })
+    }
+
+    /// Send SIGINT, which `ros2 run` and `ros2 launch` pass on, and kill the node if it is still
+    /// running after five seconds
+    pub fn stop(mut self) {
+        if let Ok(Some(_)) = self.child.try_wait() {
+            return;
+        }
+        let _ = environment::tool("kill")
+            .args(["-INT", &self.child.id().to_string()])
+            .output();
+        for _ in 0..25 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        context!("Still running after 5s, killing it");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
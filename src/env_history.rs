@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Number of snapshots kept, older ones are removed when recording a new one
+const KEEP: usize = 20;
+
+/// Variables that differ between every shell or terminal and only add noise to a diff
+const NOISE: &[&str] = &[
+    "_",
+    "OLDPWD",
+    "SHLVL",
+    "TERM_SESSION_ID",
+    "WINDOWID",
+    "SSH_AUTH_SOCK",
+    "SSH_AGENT_PID",
+    "SSH_CLIENT",
+    "SSH_CONNECTION",
+    "SSH_TTY",
+    "GPG_AGENT_INFO",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "XDG_SESSION_ID",
+    "INVOCATION_ID",
+    "JOURNAL_STREAM",
+];
+
+/// Name parts of variables whose values are left out of snapshots
+const SECRETS: &[&str] = &[
+    "SECRET",
+    "TOKEN",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "API_KEY",
+];
+
+static ARMED: OnceLock<PathBuf> = OnceLock::new();
+
+fn snapshots_dir(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("environments")
+}
+
+/// Record the environment of this build in the workspace when colb exits, see [`finish`]
+pub fn arm(workspace: &Path) {
+    let _ = ARMED.set(workspace.into());
+}
+
+fn sanitized() -> BTreeMap<String, String> {
+    env::vars_os()
+        .map(|(k, v)| {
+            (
+                k.to_string_lossy().to_string(),
+                v.to_string_lossy().to_string(),
+            )
+        })
+        .filter(|(k, _)| !NOISE.contains(&k.as_str()))
+        .map(|(k, v)| {
+            let upper = k.to_uppercase();
+            if SECRETS.iter().any(|s| upper.contains(s)) {
+                (k, "<redacted>".into())
+            } else {
+                (k, v)
+            }
+        })
+        .collect()
+}
+
+/// Ids of the recorded snapshots, oldest first
+fn ids(workspace: &Path) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(snapshots_dir(workspace)) else {
+        return vec![];
+    };
+    let mut res = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.strip_suffix(".env")?.parse().ok()
+        })
+        .collect::<Vec<u64>>();
+    res.sort();
+    res
+}
+
+/// Write the snapshot with the exit code of the build, if recording was armed
+pub fn finish(code: i32) {
+    let Some(workspace) = ARMED.get() else {
+        return;
+    };
+    let dir = snapshots_dir(workspace);
+    if fs::create_dir_all(&dir).is_err() {
+        return;
+    }
+    let ids = ids(workspace);
+    let id = ids.last().map_or(1, |last| last + 1);
+    let mut content = format!("# exit code {code}\n");
+    for (k, v) in sanitized() {
+        content.push_str(&format!("{k}={}\n", v.replace('\n', "\\n")));
+    }
+    // Recording is best effort, never fail the actual command because of it
+    let _ = fs::write(dir.join(format!("{id}.env")), content);
+    for old in ids.iter().rev().skip(KEEP - 1) {
+        let _ = fs::remove_file(dir.join(format!("{old}.env")));
+    }
+}
+
+struct Snapshot {
+    id: u64,
+    code: i32,
+    vars: BTreeMap<String, String>,
+}
+
+fn load(workspace: &Path, id: u64) -> Result<Snapshot, String> {
+    let file = snapshots_dir(workspace).join(format!("{id}.env"));
+    let content =
+        fs::read_to_string(&file).map_err(|_| format!("There is no environment snapshot {id}"))?;
+    let mut res = Snapshot {
+        id,
+        code: 0,
+        vars: BTreeMap::new(),
+    };
+    for line in content.lines() {
+        if let Some(code) = line.strip_prefix("# exit code ") {
+            res.code = code.parse().unwrap_or(-1);
+        } else if let Some((k, v)) = line.split_once('=') {
+            res.vars.insert(k.into(), v.into());
+        }
+    }
+    Ok(res)
+}
+
+/// The snapshots to compare: the given ones, or the latest failing build and the successful one before it
+fn pick(workspace: &Path, a: Option<u64>, b: Option<u64>) -> Result<(Snapshot, Snapshot), String> {
+    if let (Some(a), Some(b)) = (a, b) {
+        return Ok((load(workspace, a)?, load(workspace, b)?));
+    }
+    let snapshots = ids(workspace)
+        .into_iter()
+        .filter_map(|id| load(workspace, id).ok())
+        .collect::<Vec<_>>();
+    if let Some(a) = a {
+        let b = snapshots
+            .into_iter()
+            .last()
+            .ok_or("No builds recorded yet")?;
+        return Ok((load(workspace, a)?, b));
+    }
+    let failing = snapshots
+        .iter()
+        .rposition(|s| s.code != 0)
+        .ok_or("No failing build recorded, give the snapshots to compare")?;
+    let working = snapshots[..failing]
+        .iter()
+        .rposition(|s| s.code == 0)
+        .ok_or("No successful build before the latest failing one")?;
+    let mut snapshots = snapshots;
+    let b = snapshots.swap_remove(failing);
+    let a = snapshots.swap_remove(working);
+    Ok((a, b))
+}
+
+fn is_path_list(name: &str, value: &str) -> bool {
+    name.ends_with("PATH") || (value.contains(':') && value.contains('/'))
+}
+
+/// The differences between two recorded build environments, path lists are compared per entry
+pub fn diff(workspace: &Path, a: Option<u64>, b: Option<u64>) -> Result<String, String> {
+    let (a, b) = pick(workspace, a, b)?;
+    let mut res = format!(
+        "--- build {} (exit code {})\n+++ build {} (exit code {})\n",
+        a.id, a.code, b.id, b.code
+    );
+    let names = a.vars.keys().chain(b.vars.keys()).collect::<BTreeSet<_>>();
+    let mut changed = false;
+    for name in names {
+        match (a.vars.get(name), b.vars.get(name)) {
+            (Some(old), Some(new)) if old == new => {}
+            (Some(old), Some(new)) if is_path_list(name, old) => {
+                changed = true;
+                res.push_str(&format!("~{name}\n"));
+                let old_entries = old.split(':').collect::<Vec<_>>();
+                let new_entries = new.split(':').collect::<Vec<_>>();
+                for e in old_entries.iter().filter(|e| !new_entries.contains(e)) {
+                    res.push_str(&format!("  -{e}\n"));
+                }
+                for e in new_entries.iter().filter(|e| !old_entries.contains(e)) {
+                    res.push_str(&format!("  +{e}\n"));
+                }
+                let old_common = old_entries.iter().filter(|e| new_entries.contains(e));
+                let new_common = new_entries.iter().filter(|e| old_entries.contains(e));
+                if !old_common.eq(new_common) {
+                    res.push_str("  (order changed)\n");
+                }
+            }
+            (old, new) => {
+                changed = true;
+                if let Some(old) = old {
+                    res.push_str(&format!("-{name}={old}\n"));
+                }
+                if let Some(new) = new {
+                    res.push_str(&format!("+{name}={new}\n"));
+                }
+            }
+        }
+    }
+    if !changed {
+        res.push_str("The environments are identical\n");
+    }
+    Ok(res)
+}
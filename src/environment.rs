@@ -0,0 +1,287 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::OnceLock,
+};
+
+static WRAPPER: OnceLock<Vec<String>> = OnceLock::new();
+/// The environment colb was started with, before activating anything
+static INITIAL: OnceLock<Vec<(OsString, OsString)>> = OnceLock::new();
+
+/// Remember the current environment, to later see what colb changed
+pub fn snapshot() {
+    let _ = INITIAL.set(env::vars_os().collect());
+}
+
+/// Variables colb changed since [`snapshot`], with their old and new values
+pub fn changes() -> Vec<(String, Option<String>, Option<String>)> {
+    let Some(initial) = INITIAL.get() else {
+        return vec![];
+    };
+    let lossy = |v: &OsString| v.to_string_lossy().to_string();
+    let mut res = vec![];
+    for (k, v) in env::vars_os() {
+        let old = initial.iter().find(|(ik, _)| *ik == k).map(|(_, iv)| iv);
+        if old != Some(&v) {
+            res.push((lossy(&k), old.map(lossy), Some(lossy(&v))));
+        }
+    }
+    for (k, v) in initial {
+        if env::var_os(k).is_none() {
+            res.push((lossy(k), Some(lossy(v)), None));
+        }
+    }
+    res.sort();
+    res
+}
+
+/// Run every tool created through [`tool`] through `wrapper`, e.g. `nix develop -c`
+pub fn set_wrapper(wrapper: Vec<String>) {
+    let _ = WRAPPER.set(wrapper);
+}
+
+/// A command running `program` through the configured wrapper, if any
+pub fn tool<S: AsRef<OsStr>>(program: S) -> Command {
+    match WRAPPER.get().and_then(|w| w.split_first()) {
+        Some((first, rest)) => {
+            let mut cmd = Command::new(first);
+            cmd.args(rest).arg(program);
+            cmd
+        }
+        None => Command::new(program),
+    }
+}
+
+/// The variables that change when sourcing `script` (e.g. a Yocto SDK `environment-setup-*`)
+pub fn sourced(script: &Path) -> Result<Vec<(String, String)>, String> {
+    let script = script.to_string_lossy();
+    captured(r#". "$1""#, &[&script]).map_err(|e| format!("Sourcing '{script}' failed: {e}"))
+}
+
+/// The variables that change when evaluating the output of `hook`, e.g. `conda shell.bash activate`
+pub fn evaluated(hook: &[&str]) -> Result<Vec<(String, String)>, String> {
+    captured(r#"eval "$("$@")""#, hook)
+        .map_err(|e| format!("Evaluating '{}' failed: {e}", hook.join(" ")))
+}
+
+/// Prefix of the active conda environment (also set by pixi)
+pub fn conda_prefix() -> Option<String> {
+    env::var("CONDA_PREFIX").ok().filter(|p| !p.is_empty())
+}
+
+/// The colcon of the active conda environment, e.g. RoboStack's, which may not be first in `PATH`
+fn conda_colcon() -> Option<PathBuf> {
+    let prefix = PathBuf::from(conda_prefix()?);
+    let colcon = if cfg!(windows) {
+        prefix.join("Scripts").join("colcon.exe")
+    } else {
+        prefix.join("bin").join("colcon")
+    };
+    colcon.is_file().then_some(colcon)
+}
+
+/// The colcon that is run: the conda environment's if there is one, otherwise the one in `PATH`
+pub fn colcon_executable() -> Option<PathBuf> {
+    conda_colcon().or_else(|| which("colcon"))
+}
+
+/// A command running colcon, from the conda environment's prefix if it has one
+pub fn colcon() -> Command {
+    match conda_colcon() {
+        Some(colcon) => tool(colcon),
+        None => tool("colcon"),
+    }
+}
+
+/// The active Python virtualenv
+pub fn virtualenv() -> Option<String> {
+    env::var("VIRTUAL_ENV").ok().filter(|p| !p.is_empty())
+}
+
+fn venv_python(venv: &Path) -> PathBuf {
+    venv.join("bin").join("python3")
+}
+
+/// The variables activating the virtualenv at `venv`, also exposing its packages to other interpreters
+pub fn virtualenv_activation(venv: &Path) -> Result<Vec<(String, String)>, String> {
+    let python = venv_python(venv);
+    if !python.exists() {
+        return Err(format!("'{}' is not a virtualenv", venv.to_string_lossy()));
+    }
+    let output = tool(&python)
+        .args([
+            "-c",
+            "import sysconfig; print(sysconfig.get_paths()['purelib'])",
+        ])
+        .output()
+        .map_err(|e| format!("Couldn't run '{}': {e}", python.to_string_lossy()))?;
+    let site_packages = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let prepend = |var: &str, value: String| {
+        let mut paths = vec![PathBuf::from(&value)];
+        paths.extend(
+            env::var_os(var)
+                .map(|v| env::split_paths(&v).collect::<Vec<_>>())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|p| *p != Path::new(&value)),
+        );
+        env::join_paths(paths)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or(value)
+    };
+    let mut res = vec![
+        (
+            "VIRTUAL_ENV".to_string(),
+            venv.to_string_lossy().to_string(),
+        ),
+        (
+            "PATH".to_string(),
+            prepend("PATH", venv.join("bin").to_string_lossy().to_string()),
+        ),
+    ];
+    if !site_packages.is_empty() {
+        res.push(("PYTHONPATH".into(), prepend("PYTHONPATH", site_packages)));
+    }
+    Ok(res)
+}
+
+/// The Python modules of `modules` that can't be imported in the virtualenv at `venv`
+pub fn missing_modules<'a>(venv: &Path, modules: &[&'a str]) -> Vec<&'a str> {
+    modules
+        .iter()
+        .filter(|m| {
+            !tool(venv_python(venv))
+                .args(["-c", &format!("import {m}")])
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success())
+        })
+        .copied()
+        .collect()
+}
+
+fn captured(snippet: &str, args: &[&str]) -> Result<Vec<(String, String)>, String> {
+    let output = tool("bash")
+        .arg("-c")
+        .arg(format!("{snippet} >/dev/null && env -0"))
+        .arg("bash")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Couldn't run 'bash': {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(output
+        .stdout
+        .split(|b| *b == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (k, v) = entry.split_once('=')?;
+            let changed = env::var(k).map_or(true, |old| old != v);
+            (changed && k != "_" && k != "SHLVL").then(|| (k.to_string(), v.to_string()))
+        })
+        .collect())
+}
+
+/// Replace `${VAR}` with the value of the environment variable `VAR`, e.g. to keep credentials out of configs
+pub fn expand(s: &str) -> Result<String, String> {
+    let mut res = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        res.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("Unterminated variable in '{s}'"));
+        };
+        let name = &rest[start + 2..start + end];
+        res.push_str(&env::var(name).map_err(|_| format!("Variable '{name}' is not set"))?);
+        rest = &rest[start + end + 1..];
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
+/// Whether the ament package `package` is registered in `AMENT_PREFIX_PATH` or its install prefix
+/// `prefix` in the workspace
+pub fn ament_package_installed(package: &str, prefix: &Path) -> bool {
+    let mut prefixes = env::var_os("AMENT_PREFIX_PATH")
+        .map(|v| env::split_paths(&v).collect::<Vec<_>>())
+        .unwrap_or_default();
+    prefixes.push(prefix.to_path_buf());
+    prefixes.iter().any(|prefix| {
+        prefix
+            .join("share/ament_index/resource_index/packages")
+            .join(package)
+            .exists()
+    })
+}
+
+/// Find `program` in `$PATH`
+pub fn which(program: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|p| p.is_file())
+}
+
+/// A temporary home and runtime directory for tests, until it is dropped
+pub struct Hermetic {
+    dir: PathBuf,
+    previous: Vec<(&'static str, Option<OsString>)>,
+}
+
+impl Hermetic {
+    /// Point HOME, the ROS and the XDG directories at a fresh temporary directory
+    pub fn enter() -> io::Result<Hermetic> {
+        let dir = env::temp_dir().join(format!("colb-hermetic-{}", process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        let runtime = dir.join("run");
+        let vars = [
+            ("HOME", dir.clone()),
+            ("ROS_HOME", dir.join(".ros")),
+            ("ROS_LOG_DIR", dir.join(".ros").join("log")),
+            ("XDG_CONFIG_HOME", dir.join(".config")),
+            ("XDG_CACHE_HOME", dir.join(".cache")),
+            ("XDG_DATA_HOME", dir.join(".local").join("share")),
+            ("XDG_STATE_HOME", dir.join(".local").join("state")),
+            ("XDG_RUNTIME_DIR", runtime.clone()),
+        ];
+        for (_, path) in &vars {
+            fs::create_dir_all(path)?;
+        }
+        fs::set_permissions(&runtime, fs::Permissions::from_mode(0o700))?;
+        let mut previous = vec![];
+        // colcon keeps its mixins and defaults in the real home
+        if env::var_os("COLCON_HOME").is_none() {
+            if let Some(home) = env::var_os("HOME") {
+                previous.push(("COLCON_HOME", None));
+                env::set_var("COLCON_HOME", Path::new(&home).join(".colcon"));
+            }
+        }
+        for (name, path) in vars {
+            previous.push((name, env::var_os(name)));
+            env::set_var(name, path);
+        }
+        Ok(Hermetic { dir, previous })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Hermetic {
+    fn drop(&mut self) {
+        for (name, value) in &self.previous {
+            match value {
+                Some(v) => env::set_var(name, v),
+                None => env::remove_var(name),
+            }
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
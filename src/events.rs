@@ -0,0 +1,139 @@
+use std::{
+    io::{self, BufRead, BufReader},
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// Progress of a colcon invocation, as printed by its `console_start_end` and `summary` event handlers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Started {
+        package: String,
+    },
+    Finished {
+        package: String,
+        /// Duration as printed by colcon, e.g. `1min 2.3s`
+        time: String,
+    },
+    Failed {
+        package: String,
+        time: String,
+        /// Exit code of the failing step, if colcon reported one
+        code: Option<i32>,
+    },
+    Aborted {
+        package: String,
+    },
+    /// The summary line at the end, e.g. `3 packages finished`
+    Summary {
+        text: String,
+    },
+}
+
+/// Receives the events and output of a colcon invocation run through [`run`]
+pub trait Progress {
+    fn on_event(&mut self, event: &Event);
+
+    /// Every line colcon prints on stdout, including the ones that were turned into events
+    fn on_output(&mut self, _line: &str) {}
+}
+
+/// Split `Failed   <<< pkg [1.2s, exited with code 2]` into package, time and code
+fn package_and_time(rest: &str) -> (String, String, Option<i32>) {
+    let rest = rest.trim();
+    let Some((package, bracket)) = rest.split_once(" [") else {
+        return (rest.into(), String::new(), None);
+    };
+    let bracket = bracket.trim_end_matches(']');
+    let (time, code) = match bracket.split_once(", exited with code ") {
+        Some((time, code)) => (time, code.trim().parse().ok()),
+        None => (bracket, None),
+    };
+    (package.trim().into(), time.into(), code)
+}
+
+/// The event a line of colcon output stands for, if any
+pub fn parse(line: &str) -> Option<Event> {
+    let line = line.trim_end();
+    if let Some(package) = line.strip_prefix("Starting >>> ") {
+        return Some(Event::Started {
+            package: package.trim().into(),
+        });
+    }
+    if let Some(text) = line.strip_prefix("Summary: ") {
+        return Some(Event::Summary { text: text.into() });
+    }
+    let (kind, rest) = line.split_once("<<< ")?;
+    let (package, time, code) = package_and_time(rest);
+    match kind.trim_end() {
+        "Finished" => Some(Event::Finished { package, time }),
+        "Failed" => Some(Event::Failed {
+            package,
+            time,
+            code,
+        }),
+        "Aborted" => Some(Event::Aborted { package }),
+        _ => None,
+    }
+}
+
+/// Run `cmd`, reporting each line of its stdout and the events parsed from it to `progress`.
+/// stderr is left untouched
+pub fn run(cmd: &mut Command, progress: &mut dyn Progress) -> io::Result<ExitStatus> {
+    cmd.stdout(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    for line in BufReader::new(stdout).split(b'\n').map_while(Result::ok) {
+        let line = String::from_utf8_lossy(&line);
+        progress.on_output(&line);
+        if let Some(event) = parse(&line) {
+            progress.on_event(&event);
+        }
+    }
+    child.wait()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn console_start_end_and_summary_lines_are_events() {
+        assert_eq!(
+            parse("Starting >>> my_package"),
+            Some(Event::Started {
+                package: "my_package".into()
+            })
+        );
+        assert_eq!(
+            parse("Finished <<< my_package [1.2s]"),
+            Some(Event::Finished {
+                package: "my_package".into(),
+                time: "1.2s".into()
+            })
+        );
+        assert_eq!(
+            parse("Failed   <<< my_package [0.5s, exited with code 2]"),
+            Some(Event::Failed {
+                package: "my_package".into(),
+                time: "0.5s".into(),
+                code: Some(2)
+            })
+        );
+        assert_eq!(
+            parse("Aborted  <<< other_package [1min 2.3s]"),
+            Some(Event::Aborted {
+                package: "other_package".into()
+            })
+        );
+        assert_eq!(
+            parse("Summary: 3 packages finished [4.56s]"),
+            Some(Event::Summary {
+                text: "3 packages finished [4.56s]".into()
+            })
+        );
+        assert_eq!(
+            parse("[ 50%] Building CXX object CMakeFiles/node.dir/node.cpp.o"),
+            None
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::Mutex;
+
+/// A known failure: how to recognize it in the output, and what usually fixes it
+struct Signature {
+    /// All of these must be in the line
+    needles: &'static [&'static str],
+    /// Text after which the name of the missing thing is quoted, for `{}` in `hint`
+    subject_after: Option<&'static str>,
+    hint: &'static str,
+}
+
+/// Checked in order, the first match wins
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        needles: &["ament_cmake", "Could not find"],
+        subject_after: None,
+        hint: "ament_cmake isn't found, so no ROS distro is sourced. \
+               Run `source /opt/ros/$ROS_DISTRO/setup.bash` or add it to `underlays` in .colb.toml",
+    },
+    Signature {
+        needles: &["Could not find a package configuration file provided by"],
+        subject_after: Some("provided by"),
+        hint: "CMake can't find the package '{}'. Install it (e.g. `rosdep install --from-paths src --ignore-src -y`), \
+               or source the underlay providing it, or add it to `underlays` in .colb.toml",
+    },
+    Signature {
+        needles: &["By not providing \"Find"],
+        subject_after: Some("By not providing"),
+        hint: "CMake has no find module '{}'. Install the development package providing it, \
+               or point CMAKE_PREFIX_PATH at its installation",
+    },
+    Signature {
+        needles: &["undefined reference to", "rclcpp::"],
+        subject_after: None,
+        hint: "rclcpp symbols don't resolve, which usually means parts were built against a different ROS distro or underlay \
+               than the one sourced now. `colb clean` the affected packages and rebuild with the same underlay",
+    },
+    Signature {
+        needles: &["undefined symbol:", "rcl"],
+        subject_after: None,
+        hint: "A ROS library misses symbols at runtime, usually because the workspace was built against another ROS installation. \
+               Rebuild the workspace with the underlay that is sourced now",
+    },
+    Signature {
+        needles: &["Package '", "' not found"],
+        subject_after: Some("Package"),
+        hint: "The package '{}' isn't found, is the underlay or workspace providing it sourced? \
+               Check `underlays` in .colb.toml, and that the package was built",
+    },
+    Signature {
+        needles: &["ModuleNotFoundError: No module named"],
+        subject_after: Some("No module named"),
+        hint: "Python can't import '{}'. If colcon runs in a virtualenv, install it there \
+               (e.g. `pip install catkin_pkg empy lark` for the ROS build tools)",
+    },
+    Signature {
+        needles: &["fatal error:", "No such file or directory"],
+        subject_after: Some("fatal error:"),
+        hint: "The header {} isn't found. Add the package providing it to package.xml \
+               and to the target's dependencies (`ament_target_dependencies` or `target_link_libraries`)",
+    },
+    Signature {
+        needles: &["-fuse-ld=mold"],
+        subject_after: None,
+        hint: "The linker mold isn't available. Install it, or remove the `mold` mixin from .colb.toml",
+    },
+    Signature {
+        needles: &["ccache: not found"],
+        subject_after: None,
+        hint: "ccache isn't installed. Install it, or remove the `ccache` mixin from .colb.toml",
+    },
+    Signature {
+        needles: &["transport '", "' not allowed"],
+        subject_after: None,
+        hint: "git tried to fetch from the network while offline. Run `colb prefetch-deps` with network access first, \
+               so FetchContent finds its sources in fetchcontent_dir",
+    },
+    Signature {
+        needles: &["127.0.0.1", "port 9"],
+        subject_after: None,
+        hint: "Something tried to download while offline. Run the build once without --offline, \
+               or `colb prefetch-deps` for FetchContent downloads",
+    },
+    Signature {
+        needles: &["Could not find a version that satisfies the requirement"],
+        subject_after: Some("requirement"),
+        hint: "pip tried to install '{}' while offline or without access to the index. Install it into the environment beforehand",
+    },
+    Signature {
+        needles: &["Duplicate package names not supported"],
+        subject_after: None,
+        hint: "Two packages in the workspace have the same name, remove one or add a COLCON_IGNORE file next to it",
+    },
+];
+
+/// Hints for the failures seen so far, in the order they were first seen
+static HINTS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// The first quoted (or the first) word after `marker`
+fn subject(line: &str, marker: &str) -> Option<String> {
+    let rest = line.split_once(marker)?.1.trim_start();
+    let word = match rest.chars().next()? {
+        quote @ ('"' | '\'' | '`') => rest[1..].split(quote).next()?,
+        _ => rest.split([' ', ':']).next()?,
+    };
+    Some(word.to_string())
+}
+
+/// Remember a hint if the output line matches a known failure
+pub fn observe(line: &str) {
+    for signature in SIGNATURES {
+        if !signature.needles.iter().all(|n| line.contains(n)) {
+            continue;
+        }
+        let subject = signature
+            .subject_after
+            .and_then(|marker| subject(line, marker))
+            .unwrap_or_default();
+        let hint = signature.hint.replace("{}", &subject);
+        if let Ok(mut hints) = HINTS.lock() {
+            if !hints.contains(&hint) {
+                hints.push(hint);
+            }
+        }
+        return;
+    }
+}
+
+/// The hints for the failures seen so far
+pub fn hints() -> Vec<String> {
+    HINTS.lock().map(|h| h.clone()).unwrap_or_default()
+}
+
+/// Print the hints for the failures seen, after the failing command's output
+pub fn print_hints() {
+    let hints = hints();
+    if hints.is_empty() {
+        return;
+    }
+    header!("Possible causes");
+    for hint in hints {
+        context!("{hint}");
+    }
+}
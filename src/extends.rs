@@ -0,0 +1,165 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+use crate::environment;
+
+/// Where a config file was read from, to resolve relative `extends` against
+enum Origin {
+    Dir(PathBuf),
+    Url(String),
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+fn cached_copy(url: &str) -> PathBuf {
+    let name = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".cache")))
+        .unwrap_or(env::temp_dir())
+        .join("colb")
+        .join("configs")
+        .join(format!("{name}.toml"))
+}
+
+/// Download a remote config, falling back to the copy from the last successful download
+fn fetch(url: &str) -> Result<String, String> {
+    let cached = cached_copy(url);
+    let output = environment::tool("curl")
+        .args(["-fsSL", "--max-time", "10", url])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            let content = String::from_utf8_lossy(&output.stdout).to_string();
+            if let Some(dir) = cached.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::write(&cached, &content);
+            Ok(content)
+        }
+        _ => {
+            let content = fs::read_to_string(&cached)
+                .map_err(|_| format!("Couldn't download '{url}' and there is no cached copy"))?;
+            eprintln!("Couldn't download '{url}', using the cached copy");
+            Ok(content)
+        }
+    }
+}
+
+/// Read the config at `location`, returning its content, a unique name and its origin
+fn read(location: &str, from: &Origin) -> Result<(String, String, Origin), String> {
+    let url = match from {
+        _ if is_url(location) => Some(location.to_string()),
+        Origin::Url(dir) if !location.starts_with('/') => Some(format!("{dir}/{location}")),
+        _ => None,
+    };
+    if let Some(url) = url {
+        let dir = url.rsplit_once('/').map(|(d, _)| d).unwrap_or(&url);
+        return Ok((fetch(&url)?, url.clone(), Origin::Url(dir.into())));
+    }
+    let path = match from {
+        Origin::Dir(dir) => dir.join(location),
+        Origin::Url(_) => PathBuf::from(location),
+    };
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Couldn't read '{}': {e}", path.to_string_lossy()))?;
+    let path = path.canonicalize().unwrap_or(path);
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok((
+        content,
+        path.to_string_lossy().to_string(),
+        Origin::Dir(dir),
+    ))
+}
+
+/// Overlay `overlay` onto `base`: tables are merged, everything else (including lists) is replaced
+pub fn merge(base: &mut Table, overlay: Table) {
+    for (k, v) in overlay {
+        match (base.get_mut(&k), v) {
+            (Some(Value::Table(b)), Value::Table(o)) => merge(b, o),
+            (_, v) => {
+                base.insert(k, v);
+            }
+        }
+    }
+}
+
+fn load_from(location: &str, from: &Origin, seen: &mut Vec<String>) -> Result<Table, String> {
+    let (content, name, origin) = read(location, from)?;
+    if seen.contains(&name) {
+        return Err(format!("'{name}' extends itself"));
+    }
+    seen.push(name.clone());
+    let table =
+        toml::from_str::<Table>(&content).map_err(|e| format!("Couldn't parse '{name}': {e}"))?;
+    match table.get("extends") {
+        Some(Value::String(parent)) => {
+            let mut res = load_from(&parent.clone(), &origin, seen)?;
+            merge(&mut res, table);
+            Ok(res)
+        }
+        Some(_) => Err(format!("'extends' in '{name}' must be a string")),
+        None => Ok(table),
+    }
+}
+
+/// The config file at `path`, merged onto the configs it extends
+pub fn load(path: &Path) -> Result<Table, String> {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    load_from(&name, &Origin::Dir(dir), &mut vec![])
+}
+
+/// The user's defaults for all workspaces
+pub fn user_config_file() -> PathBuf {
+    env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".config")))
+        .unwrap_or(env::temp_dir())
+        .join("colb")
+        .join("config.toml")
+}
+
+/// The user config merged onto the configs it extends, if there is one
+pub fn load_user() -> Result<Option<Table>, String> {
+    let path = user_config_file();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut res = load(&path)?;
+    // The workspace config only extends what its own file says
+    res.remove("extends");
+    Ok(Some(res))
+}
+
+/// The merged baseline that `extends` in the config file at `path` refers to
+pub fn base(extends: &str, path: &Path) -> Result<Table, String> {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    load_from(extends, &Origin::Dir(dir), &mut vec![])
+}
+
+/// The entries of `full` that differ from `base`, i.e. what a config extending `base` has to set
+pub fn overrides(base: &Table, full: Table) -> Table {
+    let mut res = Table::new();
+    for (k, v) in full {
+        match (base.get(&k), v) {
+            (Some(b), v) if *b == v => {}
+            (Some(Value::Table(b)), Value::Table(t)) => {
+                res.insert(k, Value::Table(overrides(b, t)));
+            }
+            (_, v) => {
+                res.insert(k, v);
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{report::escape_html, usage::format_duration};
+
+/// Where colcon logs the builds of this run, see [`record`]
+static LOG_BASE: OnceLock<String> = OnceLock::new();
+
+/// Where `colb build --timings` keeps the colcon logs of the last build
+pub fn timings_dir(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("timings")
+}
+
+/// Let colcon log the builds from now on, replacing the logs of the previous recording
+pub fn record(workspace: &Path) -> Result<(), String> {
+    let dir = timings_dir(workspace);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let _ = LOG_BASE.set(dir.to_string_lossy().to_string());
+    Ok(())
+}
+
+/// The log base for builds, if they are recorded
+pub fn log_base() -> Option<&'static str> {
+    LOG_BASE.get().map(String::as_str)
+}
+
+/// A package's build, in seconds since the first colcon invocation started
+pub struct Job {
+    pub package: String,
+    pub start: f64,
+    pub end: f64,
+    pub success: bool,
+    pub dependencies: Vec<String>,
+}
+
+/// Every other quoted string in `text`, i.e. the names of `[('name', 'path'), ...]` or `{'name': 'path', ...}`
+fn names(text: &str) -> Vec<String> {
+    text.split('\'')
+        .skip(1)
+        .step_by(2)
+        .step_by(2)
+        .map(String::from)
+        .collect()
+}
+
+/// Parse colcon's `events.log`, with lines like `[1.234567] (pkg) JobEnded: {'identifier': 'pkg', 'rc': 0}`
+fn parse(content: &str, offset: f64, res: &mut Vec<Job>) -> f64 {
+    let mut last = 0.0;
+    for line in content.lines() {
+        let Some((time, rest)) = line.strip_prefix('[').and_then(|l| l.split_once("] (")) else {
+            continue;
+        };
+        let Ok(time) = time.trim_end_matches('s').parse::<f64>() else {
+            continue;
+        };
+        last = time;
+        let Some((package, rest)) = rest.split_once(") ") else {
+            continue;
+        };
+        let Some((event, data)) = rest.split_once(": ") else {
+            continue;
+        };
+        let job = res.iter().rposition(|j| j.package == package);
+        match (event, job) {
+            ("JobQueued", _) => {
+                let dependencies = data
+                    .split_once("'dependencies': ")
+                    .map(|(_, d)| names(d.split(['}', ']']).next().unwrap_or_default()))
+                    .unwrap_or_default();
+                res.push(Job {
+                    package: package.into(),
+                    start: f64::NAN,
+                    end: f64::NAN,
+                    success: false,
+                    dependencies,
+                });
+            }
+            ("JobStarted", Some(i)) => res[i].start = offset + time,
+            ("JobEnded", Some(i)) => {
+                res[i].end = offset + time;
+                res[i].success = data.contains("'rc': 0}") || data.contains("'rc': 0,");
+            }
+            _ => {}
+        }
+    }
+    offset + last
+}
+
+/// The `events.log` files in `dir`, or in the invocation folders below it, oldest first
+fn event_logs(dir: &Path) -> Vec<PathBuf> {
+    if dir.join("events.log").is_file() {
+        return vec![dir.join("events.log")];
+    }
+    let mut res = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| !e.file_name().to_string_lossy().starts_with("latest"))
+                .map(|e| e.path().join("events.log"))
+                .filter(|p| p.is_file())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    // The folders are named after the time colcon started, e.g. build_2024-01-31_12-00-00
+    res.sort();
+    res
+}
+
+/// The jobs that ran in the colcon invocations logged in `dir`, one after the other
+pub fn load(dir: &Path) -> Result<Vec<Job>, String> {
+    let logs = event_logs(dir);
+    if logs.is_empty() {
+        return Err(format!(
+            "No colcon event logs in '{}', build with --timings first",
+            dir.to_string_lossy()
+        ));
+    }
+    let mut res = vec![];
+    let mut offset = 0.0;
+    for log in logs {
+        let content = fs::read_to_string(&log)
+            .map_err(|e| format!("Couldn't read '{}': {e}", log.to_string_lossy()))?;
+        offset = parse(&content, offset, &mut res);
+    }
+    res.retain(|j| !j.start.is_nan() && !j.end.is_nan());
+    res.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(res)
+}
+
+/// The chain of packages that determined when the last one finished, first to last:
+/// starting at the last job, always step to the dependency that finished last
+pub fn critical_path(jobs: &[Job]) -> Vec<&Job> {
+    let mut res = vec![];
+    let mut current = jobs.iter().max_by(|a, b| a.end.total_cmp(&b.end));
+    while let Some(job) = current {
+        res.push(job);
+        current = jobs
+            .iter()
+            .filter(|j| job.dependencies.contains(&j.package) && j.end <= job.start + 0.5)
+            .max_by(|a, b| a.end.total_cmp(&b.end));
+    }
+    res.reverse();
+    res
+}
+
+/// A standalone HTML page with the jobs as SVG bars, the critical path highlighted
+pub fn render(title: &str, jobs: &[Job]) -> String {
+    const ROW: f64 = 22.0;
+    const LABEL: f64 = 220.0;
+    const WIDTH: f64 = 900.0;
+    let critical = critical_path(jobs)
+        .iter()
+        .map(|j| j.package.clone())
+        .collect::<Vec<_>>();
+    let start = jobs.iter().map(|j| j.start).fold(f64::INFINITY, f64::min);
+    let end = jobs.iter().map(|j| j.end).fold(0.0, f64::max);
+    let total = (end - start).max(0.001);
+    let scale = WIDTH / total;
+    let height = ROW * (jobs.len() + 2) as f64;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{height}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+        LABEL + WIDTH + 20.0
+    );
+    // A tick roughly every tenth of the build
+    let step = [1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+        .into_iter()
+        .find(|s| total / s <= 10.0)
+        .unwrap_or(1200.0);
+    let mut tick = 0.0;
+    while tick <= total {
+        let x = LABEL + tick * scale;
+        svg.push_str(&format!(
+            "<line x1=\"{x:.1}\" y1=\"0\" x2=\"{x:.1}\" y2=\"{:.1}\" stroke=\"#ddd\"/>\
+             <text x=\"{x:.1}\" y=\"{:.1}\" fill=\"#666\">{tick}s</text>\n",
+            height - ROW,
+            height - 6.0
+        ));
+        tick += step;
+    }
+    for (i, job) in jobs.iter().enumerate() {
+        let y = ROW * i as f64;
+        let color = match (job.success, critical.contains(&job.package)) {
+            (false, _) => "#999",
+            (true, true) => "#d9534f",
+            (true, false) => "#5b9bd5",
+        };
+        let name = escape_html(&job.package);
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{name}</text>\
+             <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{color}\">\
+             <title>{name}: {:.1}s</title></rect>\n",
+            LABEL - 6.0,
+            y + 15.0,
+            LABEL + (job.start - start) * scale,
+            y + 3.0,
+            ((job.end - job.start) * scale).max(1.0),
+            ROW - 6.0,
+            job.end - job.start
+        ));
+    }
+    svg.push_str("</svg>\n");
+    let mut path = String::new();
+    for job in critical_path(jobs) {
+        path.push_str(&format!(
+            "<li>{} ({:.1}s)</li>\n",
+            escape_html(&job.package),
+            job.end - job.start
+        ));
+    }
+    let title = escape_html(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n\
+         <p>{} packages in {total:.1}s, the critical path is red, failed packages are grey.</p>\n{svg}\
+         <h2>Critical path</h2>\n<ol>\n{path}</ol>\n</body>\n</html>\n",
+        jobs.len()
+    )
+}
+
+/// Packages that directly or indirectly depend on `package` among the jobs
+fn dependents<'a>(jobs: &'a [Job], package: &str) -> Vec<&'a str> {
+    let mut res: Vec<&str> = vec![];
+    let mut queue = vec![package];
+    while let Some(current) = queue.pop() {
+        for job in jobs {
+            let name = job.package.as_str();
+            if job.dependencies.iter().any(|d| d == current) && !res.contains(&name) {
+                res.push(name);
+                queue.push(name);
+            }
+        }
+    }
+    res
+}
+
+fn durations_file(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("durations")
+}
+
+/// Average build duration and number of builds by package, from `.colb/durations`
+fn load_durations(workspace: &Path) -> BTreeMap<String, (f64, u32)> {
+    let content = fs::read_to_string(durations_file(workspace)).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split('\t');
+            let package = fields.next()?;
+            let seconds = fields.next()?.parse().ok()?;
+            let count = fields.next()?.parse().ok()?;
+            Some((package.to_string(), (seconds, count)))
+        })
+        .collect()
+}
+
+/// Add the durations of the successful jobs to the averages in `.colb/durations`, returns the averages
+pub fn update_durations(workspace: &Path, jobs: &[Job]) -> Result<BTreeMap<String, f64>, String> {
+    let mut durations = load_durations(workspace);
+    for job in jobs.iter().filter(|j| j.success) {
+        let (seconds, count) = durations.entry(job.package.clone()).or_insert((0.0, 0));
+        *seconds = (*seconds * *count as f64 + job.end - job.start) / (*count + 1) as f64;
+        *count += 1;
+    }
+    let content = durations
+        .iter()
+        .map(|(p, (s, c))| format!("{p}\t{s:.3}\t{c}\n"))
+        .collect::<String>();
+    fs::write(durations_file(workspace), content).map_err(|e| e.to_string())?;
+    Ok(durations.into_iter().map(|(p, (s, _))| (p, s)).collect())
+}
+
+/// Suggestions for making the build faster, based on the graph of the jobs and the average durations
+pub fn advice(jobs: &[Job], durations: &BTreeMap<String, f64>, pinned: &[String]) -> Vec<String> {
+    if jobs.len() < 2 {
+        return vec![];
+    }
+    let duration = |j: &Job| {
+        durations
+            .get(&j.package)
+            .copied()
+            .unwrap_or(j.end - j.start)
+    };
+    let start = jobs.iter().map(|j| j.start).fold(f64::INFINITY, f64::min);
+    let end = jobs.iter().map(|j| j.end).fold(0.0, f64::max);
+    let wall = (end - start).max(0.001);
+    let mut res = vec![];
+    let path = critical_path(jobs);
+    let critical_time: f64 = path.iter().map(|j| duration(j)).sum();
+    for job in &path {
+        let blocked = dependents(jobs, &job.package).len();
+        let seconds = duration(job);
+        if blocked == 0 || seconds < 0.1 * critical_time || pinned.contains(&job.package) {
+            continue;
+        }
+        res.push(format!(
+            "'{}' blocks {blocked} packages and takes {} on average, consider pinning it (colb pin {}) or splitting it",
+            job.package,
+            format_duration(seconds),
+            job.package
+        ));
+    }
+    let busy: f64 = jobs.iter().map(|j| j.end - j.start).sum();
+    let parallelism = busy / wall;
+    if parallelism < 1.5 && path.len() > 2 {
+        res.push(format!(
+            "Only {parallelism:.1} packages built at a time on average, {} of the {} are a chain of {} packages that can't build in parallel",
+            format_duration(critical_time),
+            format_duration(wall),
+            path.len()
+        ));
+    }
+    res
+}
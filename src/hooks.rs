@@ -0,0 +1,92 @@
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{environment, output::*};
+
+/// Shell commands run in the workspace around builds and tests, once per package with its name
+/// in `COLB_PACKAGE`
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Hooks {
+    /// Before a package and its dependencies are built, e.g. to generate code. Nothing is built if
+    /// it fails
+    pub pre_build: Option<String>,
+    /// After a package was built successfully
+    pub post_build: Option<String>,
+    /// Before the tests of a package run, after it was built. The tests don't run if it fails
+    pub pre_test: Option<String>,
+    /// After the tests of a package ran, whether they passed or not, with the exit code of the test
+    /// run in `COLB_TEST_STATUS`
+    pub post_test: Option<String>,
+}
+
+/// Run the hook `name` with `sh` for each of the `packages`, stopping at the first one failing.
+/// `env` is added to the environment of the hook
+pub fn run(
+    name: &str,
+    command: Option<&str>,
+    workspace: &str,
+    packages: &[String],
+    env: &[(&str, String)],
+) -> ExitStatus {
+    let Some(command) = command else {
+        return ExitStatus::from_raw(0);
+    };
+    for package in packages {
+        header!("Running the {name} hook for '{package}'");
+        let mut cmd = environment::tool("sh");
+        cmd.args(["-c", command])
+            .current_dir(workspace)
+            .env("COLB_PACKAGE", package)
+            .env("COLB_HOOK", name)
+            .envs(env.iter().map(|(k, v)| (k, v)));
+        print_command(&cmd);
+        let status = crate::output::run(&mut cmd).unwrap_or_else(|e| {
+            eprintln!("Couldn't run the {name} hook: {e}");
+            failure()
+        });
+        if !status.success() {
+            eprintln!("The {name} hook failed for '{package}'");
+            return status;
+        }
+    }
+    ExitStatus::from_raw(0)
+}
+
+impl Hooks {
+    /// Run the `pre_build` hook for `packages`, before they or their dependencies are built
+    pub fn pre_build(&self, workspace: &str, packages: &[String]) -> ExitStatus {
+        run(
+            "pre_build",
+            self.pre_build.as_deref(),
+            workspace,
+            packages,
+            &[],
+        )
+    }
+
+    /// Run `build` between the `pre_build` and `post_build` hooks of `packages`. Nothing is built
+    /// if `pre_build` fails, and `post_build` only runs after a successful build
+    pub fn around_build(
+        &self,
+        workspace: &str,
+        packages: &[String],
+        build: impl FnOnce() -> ExitStatus,
+    ) -> ExitStatus {
+        let status = self.pre_build(workspace, packages);
+        if !status.success() {
+            return status;
+        }
+        let status = build();
+        if !status.success() {
+            return status;
+        }
+        run(
+            "post_build",
+            self.post_build.as_deref(),
+            workspace,
+            packages,
+            &[],
+        )
+    }
+}
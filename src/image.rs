@@ -0,0 +1,99 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use crate::{bases, environment, output::*};
+
+/// Where the workspace's install space ends up in the image
+const INSTALL: &str = "/opt/colb/install";
+
+/// Container tools that can build an image from a Containerfile, in order of preference
+const TOOLS: &[&str] = &["buildah", "podman", "docker"];
+
+/// A Containerfile copying the install space (the build context) onto `base`,
+/// with an entrypoint that sources it before running the command
+fn containerfile(base: &str) -> String {
+    format!(
+        "FROM {base}\n\
+         COPY . {INSTALL}\n\
+         RUN printf '%s\\n' '#!/bin/bash' 'set -e' 'source {INSTALL}/setup.bash' 'exec \"$@\"' \
+         > /colb_entrypoint.sh && chmod +x /colb_entrypoint.sh\n\
+         ENTRYPOINT [\"/colb_entrypoint.sh\"]\n\
+         CMD [\"bash\"]\n"
+    )
+}
+
+/// The first symlink below `dir` pointing out of `install`, as left by `--symlink-install`
+fn outside_link(dir: &Path, install: &Path) -> Option<PathBuf> {
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_symlink() {
+            let target = fs::read_link(&path).ok()?;
+            let target = path.parent().unwrap_or(dir).join(target);
+            if !target.starts_with(install) {
+                return Some(path);
+            }
+        } else if file_type.is_dir() {
+            if let Some(link) = outside_link(&path, install) {
+                return Some(link);
+            }
+        }
+    }
+    None
+}
+
+/// Build an image named `tag` with the install space of the workspace on top of `base`
+pub fn build(workspace: &Path, base: &str, tag: &str) -> Result<ExitStatus, String> {
+    let install = workspace.join(bases::install());
+    if !install.join("setup.bash").is_file() {
+        return Err("The workspace has no install space yet, build it first".into());
+    }
+    if let Some(link) = outside_link(&install, &install) {
+        return Err(format!(
+            "'{}' links out of the install space, rebuild without --symlink-install",
+            link.to_string_lossy()
+        ));
+    }
+    let tool = TOOLS
+        .iter()
+        .find(|t| environment::which(t).is_some())
+        .ok_or("Building images needs buildah, podman or docker")?;
+    let dir = workspace.join(".colb").join("image");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let file = dir.join("Containerfile");
+    fs::write(&file, containerfile(base)).map_err(|e| e.to_string())?;
+    context!("Using {}", file.to_string_lossy());
+    let mut cmd = environment::tool(tool);
+    // buildah calls it build-using-dockerfile, but accepts `build` as well
+    cmd.arg("build")
+        .arg("-f")
+        .arg(&file)
+        .args(["-t", tag])
+        .arg(&install);
+    print_command(&cmd);
+    run_attached(&mut cmd).map_err(|e| format!("Couldn't run '{tool}': {e}"))
+}
+
+/// The tag for images of `workspace` if none is given: its folder name
+pub fn default_tag(workspace: &Path) -> String {
+    let name = workspace
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or("workspace".into());
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("{name}:latest")
+}
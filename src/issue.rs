@@ -0,0 +1,131 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::{bases, ctest, explain, output::*, repro, versions};
+
+/// Error lines kept for the excerpt, the first ones are usually the cause
+const ERROR_LINES: usize = 20;
+
+struct Armed {
+    file: PathBuf,
+    verb: String,
+    workspace: PathBuf,
+    started: SystemTime,
+}
+
+static ARMED: OnceLock<Armed> = OnceLock::new();
+
+/// The first error lines in the output of the commands run so far
+static ERRORS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Write an issue template to `file` if a build or test fails later on
+pub fn arm(file: &Path, verb: &str, workspace: &Path) {
+    let _ = ARMED.set(Armed {
+        file: file.into(),
+        verb: verb.into(),
+        workspace: workspace.into(),
+        started: SystemTime::now(),
+    });
+}
+
+/// Keep an error line of a command's output for the excerpt
+pub fn observe_error(line: &str) {
+    if ARMED.get().is_none() {
+        return;
+    }
+    if let Ok(mut errors) = ERRORS.lock() {
+        if errors.len() < ERROR_LINES {
+            errors.push(line.trim_end().to_string());
+        }
+    }
+}
+
+/// Tests that didn't pass, by package, of the packages tested during this invocation
+fn failed_tests(armed: &Armed) -> Vec<(String, Vec<String>)> {
+    let build_base = armed.workspace.join(bases::build());
+    repro::test_logs(&armed.workspace, armed.started)
+        .into_iter()
+        .map(|(package, _)| {
+            let failed = ctest::last_results(&build_base.join(&package))
+                .into_iter()
+                .filter(|(_, outcome)| outcome != "passed")
+                .map(|(name, outcome)| format!("{name} ({outcome})"))
+                .collect::<Vec<_>>();
+            (package, failed)
+        })
+        .filter(|(_, failed)| !failed.is_empty())
+        .collect()
+}
+
+fn render(armed: &Armed, code: i32) -> String {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let errors = ERRORS.lock().map(|e| e.clone()).unwrap_or_default();
+    let failed = failed_tests(armed);
+    let summary = match (errors.first(), failed.first()) {
+        (Some(error), _) => error.trim().to_string(),
+        (None, Some((package, tests))) => format!("{} tests of '{package}' fail", tests.len()),
+        (None, None) => format!("exits with {code}"),
+    };
+    let mut res = format!(
+        "# `colb {}`: {summary}\n\n## Command\n\n```console\ncolb {}\n```\n\nExited with {code}.\n",
+        armed.verb,
+        args.iter()
+            .map(|a| shell_quote(a))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    if !errors.is_empty() {
+        res.push_str(&format!("\n## Errors\n\n```\n{}\n```\n", errors.join("\n")));
+    }
+    if !failed.is_empty() {
+        res.push_str("\n## Failed tests\n\n");
+        for (package, tests) in &failed {
+            for test in tests {
+                res.push_str(&format!("- `{package}`: {test}\n"));
+            }
+        }
+    }
+    let hints = explain::hints();
+    if !hints.is_empty() {
+        res.push_str("\n## Possible causes\n\n");
+        for hint in hints {
+            res.push_str(&format!("- {hint}\n"));
+        }
+    }
+    let commands = command_history();
+    if !commands.is_empty() {
+        res.push_str(&format!(
+            "\n## Commands run\n\n```console\n{}\n```\n",
+            commands.join("\n")
+        ));
+    }
+    res.push_str(&format!(
+        "\n## Environment\n\n| Tool | Version |\n| --- | --- |\n| colb | {} |\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    for (name, version) in versions::tool_versions() {
+        res.push_str(&format!("| {name} | {} |\n", version.replace('|', "\\|")));
+    }
+    res
+}
+
+/// Write the issue template, if one was requested
+pub fn write(code: i32) {
+    let Some(armed) = ARMED.get() else {
+        return;
+    };
+    if commands_only() {
+        return;
+    }
+    match fs::write(&armed.file, render(armed, code)) {
+        Ok(()) => {
+            header!("Issue template");
+            context!("Wrote {}", armed.file.to_string_lossy());
+        }
+        Err(e) => eprintln!("Couldn't write issue template: {e}"),
+    }
+}
@@ -0,0 +1,8 @@
+//! Building blocks of colb for tools embedding it, e.g. GUIs and editor plugins.
+//!
+//! The commands colb would run can be obtained with `colb --print-command-only <verb> ...`,
+//! running them through [`events::run`] reports the progress of each package as [`events::Event`]s.
+//! Other build tools plug in as a [`backend::Backend`].
+
+pub mod backend;
+pub mod events;
@@ -1,31 +1,87 @@
-use anstyle::{AnsiColor, Color, Style};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     env, fs,
-    io::{IsTerminal, Write},
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
     ops::Deref,
     path::{Path, PathBuf},
-    process::{Command, ExitStatus},
+    process::ExitStatus,
+    time::{Duration, SystemTime},
 };
 
-use clap::{Parser, Subcommand};
+use clap::{parser::ValueSource, ArgMatches, FromArgMatches};
 
-#[derive(Serialize, Deserialize, clap::ValueEnum, Default, Clone)]
-enum BuildType {
-    #[default]
-    Debug,
-    Release,
-    RelWithDebInfo,
-}
+#[macro_use]
+mod output;
+mod backends;
+mod bases;
+mod bazel;
+mod cache;
+mod cargo;
+mod cli;
+mod colcon;
+mod completions;
+mod config_diff;
+mod config_doctor;
+mod coverage;
+mod ctest;
+mod dev;
+mod env_history;
+mod environment;
+mod explain;
+mod extends;
+mod gantt;
+mod hooks;
+mod image;
+mod issue;
+mod matrix;
+mod metrics;
+mod offline;
+mod orphans;
+mod owners;
+mod package_xml;
+mod pipeline;
+mod preflight;
+mod remove;
+mod report;
+mod repro;
+mod results;
+mod sandbox;
+mod sanitizer;
+mod service;
+mod simulation;
+mod size;
+mod sysroot;
+mod targets;
+mod test_summary;
+mod underlay;
+mod update;
+mod usage;
+mod verify;
+mod versions;
+mod watch;
+
+use backends::Backend;
+use cache::InstallCache;
+use cli::{
+    BuildType, CacheAction, Cli, ConfigAction, CtestOptions, ImageAction, Layout, PassTo,
+    RebuildDependencies, ResultsAction, Rmw, ServiceAction, Shell, StatsAction, Strategy,
+    SysrootAction, UnderlayAction, Verbs,
+};
+use output::*;
 
 impl BuildType {
-    fn apply(&self, cmd: &mut ArgStack) {
-        let t = match self {
+    fn name(&self) -> &'static str {
+        match self {
             BuildType::Debug => "Debug",
             BuildType::Release => "Release",
             BuildType::RelWithDebInfo => "RelWithDebInfo",
-        };
-        cmd.arg(format!("-DCMAKE_BUILD_TYPE={t}"));
+        }
+    }
+
+    fn apply(&self, cmd: &mut ArgStack) {
+        cmd.arg(format!("-DCMAKE_BUILD_TYPE={}", self.name()));
     }
 }
 
@@ -63,6 +119,8 @@ impl Deref for ArgStack {
 struct ColconInvocation {
     args: ArgStack,
     workspace: String,
+    /// Build and install base, relative to the workspace
+    bases: (String, String),
 }
 
 struct BuildVerb {
@@ -73,20 +131,27 @@ struct BuildVerb {
 struct BasicVerb {
     args: ArgStack,
     workspace: String,
+    env: Vec<(String, String)>,
 }
 
 struct ConfiguredBuild {
     args: ArgStack,
     workspace: String,
+    env: Vec<(String, String)>,
+    /// Packages to leave out, in addition to the ones implied by [What]
+    skip: Vec<String>,
+    /// The arguments before configuring, and the configuration, to build packages with overrides
+    base: Vec<String>,
+    config: BuildConfiguration,
+    overrides: BTreeMap<String, PackageOverrides>,
 }
 
-#[derive(Default)]
 struct BuildOutput {
     symlink: bool,
     merge: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct EventHandlers {
     desktop_notification: bool,
     console_cohesion: bool,
@@ -133,7 +198,7 @@ impl EventHandlers {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct BuildConfiguration {
     mixins: Vec<String>,
     cmake_args: Vec<String>,
@@ -141,39 +206,518 @@ struct BuildConfiguration {
     parallel_jobs: Option<u32>,
     event_handlers: EventHandlers,
     build_tests: bool,
+    /// Run cross-compiled test executables through this (e.g. `["qemu-aarch64", "-L", "/sysroot"]`)
+    #[serde(default)]
+    emulator: Vec<String>,
+    /// Name of a sysroot in `.colb/sysroots` (see `colb sysroot`) or a path to one
+    sysroot: Option<String>,
+    /// Script to source before building, e.g. a Yocto SDK `environment-setup-*` script
+    environment_setup: Option<String>,
+    /// Where CMake's FetchContent keeps its downloads (FETCHCONTENT_BASE_DIR), relative to the workspace
+    fetchcontent_dir: Option<String>,
+}
+
+/// Settings of a single package that differ from the rest of the workspace
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct PackageOverrides {
+    /// Added to the configured CMake arguments
+    #[serde(default)]
+    cmake_args: Vec<String>,
+    build_type: Option<BuildType>,
+    /// Replace the configured mixins
+    mixins: Option<Vec<String>>,
+    build_tests: Option<bool>,
+}
+
+impl PackageOverrides {
+    fn apply(&self, config: &BuildConfiguration) -> BuildConfiguration {
+        let mut res = config.clone();
+        res.cmake_args.extend(self.cmake_args.iter().cloned());
+        if let Some(build_type) = &self.build_type {
+            res.build_type = build_type.clone();
+        }
+        if let Some(mixins) = &self.mixins {
+            res.mixins = mixins.clone();
+        }
+        if let Some(build_tests) = self.build_tests {
+            res.build_tests = build_tests;
+        }
+        res
+    }
+}
+
+/// A named configuration selected with `--profile`, built in its own build and install base
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct Profile {
+    #[serde(flatten)]
+    settings: PackageOverrides,
+    /// Environment variables of the profile, added to and overriding `[env]`
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Folder containing the build and install base, relative to the workspace
+    /// (default: `.colb/profiles/<name>`)
+    base: Option<String>,
+}
+
+impl Profile {
+    /// Where the build and install folders of the profile are, relative to the workspace
+    fn base(&self, name: &str) -> String {
+        self.base
+            .clone()
+            .unwrap_or_else(|| format!(".colb/profiles/{name}"))
+    }
+
+    fn apply(&self, config: &mut Config) {
+        config.upstream = self.settings.apply(&config.upstream);
+        config.package = self.settings.apply(&config.package);
+        config.env.extend(self.env.clone());
+    }
 }
 
 struct TestConfiguration {
     package: String,
-    /// If set, run only this test (using ctest-args)
+    /// If set, run only the tests matching this regex (using ctest-args), or this keyword
+    /// expression for Python packages
     test: Option<String>,
     event_handlers: EventHandlers,
+    ctest: CtestOptions,
+    /// Run the tests of an ament_python package, through pytest instead of ctest
+    python: bool,
 }
 
-struct TestResultConfig {
-    package: String,
-    verbose: bool,
-    all: bool,
+impl CtestOptions {
+    fn args(&self, test_regex: Option<&str>) -> Vec<String> {
+        let mut res = vec![];
+        if self.verbose {
+            res.push("-V".into());
+        } else {
+            res.push("--output-on-failure".into());
+        }
+        if self.rerun_failed {
+            res.push("--rerun-failed".into());
+        }
+        if self.stop_on_failure {
+            res.push("--stop-on-failure".into());
+        }
+        if let Some((i, n)) = self.shard {
+            res.push("-I".into());
+            res.push(format!("{i},,{n}"));
+        }
+        if let Some(regex) = test_regex {
+            res.push("-R".into());
+            res.push(regex.into());
+        }
+        if !self.exclude.is_empty() {
+            res.push("-E".into());
+            res.push(
+                targets::Target {
+                    build: None,
+                    tests: self.exclude.clone(),
+                }
+                .test_regex(),
+            );
+        }
+        res
+    }
+
+    /// The equivalent pytest arguments, selecting tests by the keyword expression `test`
+    fn pytest_args(&self, test: Option<&str>) -> Vec<String> {
+        let mut res = vec![];
+        if self.verbose {
+            res.push("-v".into());
+        }
+        if self.rerun_failed {
+            res.push("--last-failed".into());
+        }
+        if self.stop_on_failure {
+            res.push("-x".into());
+        }
+        let mut keywords = test
+            .map(|t| format!("({t})"))
+            .into_iter()
+            .collect::<Vec<_>>();
+        keywords.extend(self.exclude.iter().map(|t| format!("not {t}")));
+        if !keywords.is_empty() {
+            res.push("-k".into());
+            res.push(keywords.join(" and "));
+        }
+        res
+    }
+}
+
+/// Settings for running tests
+#[derive(Serialize, Deserialize, Default)]
+struct TestsConfig {
+    /// Known flaky tests by package, left out unless running with `--include-quarantined`
+    #[serde(default)]
+    quarantine: BTreeMap<String, Vec<String>>,
+    /// Owner of the tests matching each regex, shown next to failures, e.g. `"^planner\\." = "planning"`
+    #[serde(default)]
+    owners: owners::Owners,
+    /// Incoming webhooks (Slack, Mattermost, ...) told about the failing tests of each owner
+    #[serde(default)]
+    webhooks: BTreeMap<String, String>,
+}
+
+/// Opt-in cache of dependency install folders, keyed by their sources and build configuration
+#[derive(Serialize, Deserialize, Default)]
+struct CacheConfig {
+    enabled: bool,
+    /// Where to keep cached installs (default: $XDG_CACHE_HOME/colb/installs)
+    directory: Option<String>,
+    /// Shared cache to pull missing entries from and push to (https://, s3://, ssh:// or host:path)
+    remote: Option<String>,
+}
+
+/// Shared compiler cache, configured through the environment so ccache.conf can stay untouched
+#[derive(Serialize, Deserialize, Default)]
+struct CcacheConfig {
+    /// Remote storage URL (e.g. `redis://host` or `http://host/cache`), `${VAR}` is expanded
+    remote_storage: Option<String>,
+    /// Only use the remote storage, not the local cache
+    #[serde(default)]
+    remote_only: bool,
+}
+
+impl CcacheConfig {
+    fn apply(&self) -> Result<(), String> {
+        if let Some(url) = &self.remote_storage {
+            let url = environment::expand(url)?;
+            // ccache < 4.8 calls it secondary storage
+            env::set_var("CCACHE_REMOTE_STORAGE", &url);
+            env::set_var("CCACHE_SECONDARY_STORAGE", &url);
+        }
+        if self.remote_only {
+            env::set_var("CCACHE_REMOTE_ONLY", "true");
+        }
+        Ok(())
+    }
+}
+
+/// Combinations `colb matrix` builds and tests, an empty list keeps the configured setting
+#[derive(Serialize, Deserialize, Default)]
+struct MatrixConfig {
+    #[serde(default)]
+    build_types: Vec<BuildType>,
+    /// C compilers, optionally with the C++ compiler as `cc:c++` (default: derived, e.g. clang -> clang++)
+    #[serde(default)]
+    compilers: Vec<String>,
+    /// RMW implementations to run the tests with, e.g. `rmw_cyclonedds_cpp`
+    #[serde(default)]
+    rmw_implementations: Vec<String>,
+}
+
+/// Where `colb self-update` gets new releases from
+#[derive(Serialize, Deserialize)]
+struct SelfUpdateConfig {
+    /// Base URL of the release artifacts and their `.sha256` checksums
+    url: String,
+    /// minisign public key the artifacts are signed with, checks `.minisig` signatures if set
+    public_key: Option<String>,
+}
+
+impl Default for SelfUpdateConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://github.com/bi0ha2ard/colb/releases/latest/download".into(),
+            public_key: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct Config {
+    /// Path (relative to this file) or URL of a config this one is based on, overriding only some keys
+    extends: Option<String>,
+    /// Run colcon and all other tools through this command, e.g. `["nix", "develop", "-c"]`
+    #[serde(default)]
+    shell_wrapper: Vec<String>,
+    /// Conda environment (name or prefix) to activate before running anything
+    conda_environment: Option<String>,
+    /// Python virtualenv to run colcon in, relative to the workspace (default: the active one)
+    virtualenv: Option<String>,
+    /// Install spaces to source before running anything, relative to the workspace
+    #[serde(default)]
+    underlays: Vec<String>,
+    /// Environment variables set for everything colb runs, e.g. `CC`, `CXX` or `MAKEFLAGS`. `${VAR}`
+    /// in values is replaced with the variable's value
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Install files as symlinks into the build and source folders where possible (colcon's --symlink-install)
+    #[serde(default)]
+    symlink_install: bool,
+    /// Install all packages into one prefix instead of one per package (colcon's --merge-install)
+    #[serde(default)]
+    merge_install: bool,
+    /// Don't rebuild packages whose sources only changed in comments or docstrings since their last
+    /// build with this set. Line numbers in asserts and debug info can be stale afterwards
+    #[serde(default)]
+    abi_skip: bool,
+    /// Configure, build and install CMake projects without a package.xml with cmake itself instead of colcon
+    #[serde(default)]
+    direct_cmake: bool,
+    /// Packages that are never rebuilt as dependencies, as long as they are installed
+    #[serde(default)]
+    pinned: Vec<String>,
+    /// Packages no verb selects, as name patterns with `*` and `?`, e.g. `["*_examples"]`
+    #[serde(default)]
+    ignore: Vec<String>,
+    /// Cache the package lists of colcon until a package is added or removed or its manifest
+    /// changes, as listing large workspaces takes seconds
+    #[serde(default)]
+    graph_cache: bool,
+    /// Extra Gazebo model and world folders for `colb launch`, relative to the workspace
+    #[serde(default)]
+    asset_dirs: Vec<String>,
+    /// Record which verbs and flags are used and how long they take, locally (see `colb stats usage`)
+    #[serde(default = "default_true")]
+    usage_stats: bool,
     upstream: BuildConfiguration,
     package: BuildConfiguration,
+    #[serde(default)]
+    cache: CacheConfig,
+    #[serde(default)]
+    ccache: CcacheConfig,
+    #[serde(default)]
+    self_update: SelfUpdateConfig,
+    #[serde(default)]
+    matrix: MatrixConfig,
+    #[serde(default)]
+    tests: TestsConfig,
+    /// Line coverage thresholds for `colb coverage --check`
+    #[serde(default)]
+    min_coverage: coverage::MinCoverage,
+    /// Sanitizers to build the packages with, and their runtime options for the tests
+    #[serde(default)]
+    sanitizers: sanitizer::SanitizerConfig,
+    /// Bazel targets built, installed and tested like packages, by package name
+    #[serde(default)]
+    bazel: BTreeMap<String, bazel::BazelPackage>,
+    /// Which backends build which packages, and where the build commands run
+    #[serde(default)]
+    backends: backends::BackendsConfig,
+    /// Shell commands run before and after builds and tests
+    #[serde(default)]
+    hooks: hooks::Hooks,
+    /// What `colb dev` runs, by package name
+    #[serde(default)]
+    dev: BTreeMap<String, dev::DevConfig>,
+    /// Settings of single packages that differ from `upstream` and `package`, by package name
+    #[serde(default)]
+    packages: BTreeMap<String, PackageOverrides>,
+    /// Named configurations to select with `--profile`
+    #[serde(default)]
+    profile: BTreeMap<String, Profile>,
+    /// Shell functions emitted by `colb alias`, mapping names to colb arguments
+    #[serde(default = "default_aliases")]
+    aliases: BTreeMap<String, String>,
+    /// Default flags per verb, e.g. `build.skip_dependencies = true`, overridden by the command line
+    #[serde(default)]
+    defaults: BTreeMap<String, toml::Table>,
+    /// Named sequences of colb invocations for `colb pipeline`
+    #[serde(default)]
+    pipelines: BTreeMap<String, Vec<pipeline::Step>>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_aliases() -> BTreeMap<String, String> {
+    [
+        ("cb", "build --skip-dependencies"),
+        ("cbd", "build"),
+        ("ct", "test --direct --test"),
+        ("cta", "test"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
+
+/// Shell functions forwarding to colb, to be evaluated by the shell
+fn shell_aliases(aliases: &BTreeMap<String, String>, shell: Shell) -> String {
+    let mut res = String::new();
+    for (name, args) in aliases {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            eprintln!("Skipping alias with invalid name '{name}'");
+            continue;
+        }
+        res.push_str(&match shell {
+            Shell::Bash | Shell::Zsh => format!("{name}() {{ colb {args} \"$@\"; }}\n"),
+            Shell::Fish => format!("function {name}; colb {args} $argv; end\n"),
+        });
+    }
+    res
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            extends: None,
+            shell_wrapper: vec![],
+            conda_environment: None,
+            virtualenv: None,
+            underlays: vec![],
+            env: BTreeMap::new(),
+            symlink_install: false,
+            merge_install: false,
+            abi_skip: false,
+            direct_cmake: false,
+            pinned: vec![],
+            ignore: vec![],
+            graph_cache: false,
+            asset_dirs: vec![],
+            usage_stats: true,
             upstream: BuildConfiguration::upstream(),
             package: BuildConfiguration::active(),
+            cache: CacheConfig::default(),
+            ccache: CcacheConfig::default(),
+            self_update: SelfUpdateConfig::default(),
+            matrix: MatrixConfig::default(),
+            tests: TestsConfig::default(),
+            min_coverage: coverage::MinCoverage::default(),
+            sanitizers: sanitizer::SanitizerConfig::default(),
+            bazel: BTreeMap::new(),
+            backends: backends::BackendsConfig::default(),
+            hooks: hooks::Hooks::default(),
+            dev: BTreeMap::new(),
+            packages: BTreeMap::new(),
+            profile: BTreeMap::new(),
+            aliases: default_aliases(),
+            defaults: BTreeMap::new(),
+            pipelines: BTreeMap::new(),
         }
     }
 }
 
 enum What {
-    DependenciesFor(String),
+    DependenciesFor(Vec<String>),
     ThisPackage(String),
+    ThesePackages(Vec<String>),
+    /// The packages including all their dependencies
+    ThesePackagesAndUpTo(Vec<String>),
+    /// The packages and everything depending on them
+    ThesePackagesAndAbove(Vec<String>),
+    /// Like [What::ThesePackagesAndAbove], plus the dependencies of everything built
+    AboveAndDependencies(Vec<String>),
+    /// Packages whose names match any of the regexes
+    Matching(Vec<String>),
+}
+
+impl What {
+    /// The packages named in `own`, and what is left to select from the others
+    fn split(&self, own: &[String]) -> (Vec<String>, Option<What>) {
+        let packages = match self {
+            What::ThisPackage(package) => std::slice::from_ref(package),
+            What::DependenciesFor(packages)
+            | What::ThesePackages(packages)
+            | What::ThesePackagesAndUpTo(packages)
+            | What::ThesePackagesAndAbove(packages)
+            | What::AboveAndDependencies(packages) => packages.as_slice(),
+            What::Matching(regexes) => return (vec![], Some(What::Matching(regexes.clone()))),
+        };
+        let (named, rest): (Vec<_>, Vec<_>) =
+            packages.iter().cloned().partition(|p| own.contains(p));
+        if rest.is_empty() {
+            return (named, None);
+        }
+        let what = match self {
+            What::DependenciesFor(_) => What::DependenciesFor(rest),
+            What::ThisPackage(package) => What::ThisPackage(package.clone()),
+            What::ThesePackages(_) => What::ThesePackages(rest),
+            What::ThesePackagesAndUpTo(_) => What::ThesePackagesAndUpTo(rest),
+            What::ThesePackagesAndAbove(_) => What::ThesePackagesAndAbove(rest),
+            What::AboveAndDependencies(_) => What::AboveAndDependencies(rest),
+            What::Matching(_) => unreachable!("returned above"),
+        };
+        (named, Some(what))
+    }
+}
+
+impl Rmw {
+    fn implementation(self) -> &'static str {
+        match self {
+            Rmw::Fastrtps => "rmw_fastrtps_cpp",
+            Rmw::Cyclonedds => "rmw_cyclonedds_cpp",
+            Rmw::Zenoh => "rmw_zenoh_cpp",
+            Rmw::Connextdds => "rmw_connextdds",
+        }
+    }
+
+    /// Set `RMW_IMPLEMENTATION` for everything run from now on, exits if it isn't installed
+    fn activate(self, workspace: &str) {
+        let implementation = self.implementation();
+        let prefix = bases::package_install(Path::new(workspace), implementation);
+        if !environment::ament_package_installed(implementation, &prefix) {
+            eprintln!(
+                "'{implementation}' is not installed, e.g. install ros-$ROS_DISTRO-{}",
+                implementation.replace('_', "-")
+            );
+            exit(-1);
+        }
+        context!("Using '{implementation}'");
+        env::set_var("RMW_IMPLEMENTATION", implementation);
+    }
+}
+
+impl Strategy {
+    fn what(self, packages: Vec<String>) -> What {
+        match self {
+            Strategy::Split => What::ThesePackages(packages),
+            Strategy::UpTo => What::ThesePackagesAndUpTo(packages),
+            Strategy::Above => What::ThesePackagesAndAbove(packages),
+            Strategy::AboveAndDependencies => What::AboveAndDependencies(packages),
+            Strategy::Regex => What::Matching(packages),
+        }
+    }
+}
+
+impl Layout {
+    fn name(self) -> &'static str {
+        match self {
+            Layout::Monorepo => "monorepo",
+            Layout::Multirepo => "multirepo",
+        }
+    }
+
+    /// Defaults suited to the layout
+    fn apply(self, config: &mut Config) {
+        let mut set = |verb: &str, flag: &str, value: toml::Value| {
+            config
+                .defaults
+                .entry(verb.into())
+                .or_default()
+                .insert(flag.into(), value);
+        };
+        match self {
+            Layout::Monorepo => {
+                // Dependencies are code under development too: build them with the same
+                // configuration, and only rebuild them for tests when their interface changed
+                set("build", "strategy", "up-to".into());
+                set("test", "rebuild_dependencies", "auto".into());
+                // Listing thousands of packages is slow, and examples and tutorials next to them
+                // are rarely what is worked on
+                config.graph_cache = true;
+                config.ignore = ["*_examples", "*_tutorials", "*_demos"]
+                    .map(String::from)
+                    .to_vec();
+            }
+            Layout::Multirepo => {
+                // Dependencies are mostly upstream code that rarely changes: build them
+                // separately and reuse their installs across workspaces
+                set("build", "strategy", "split".into());
+                config.cache.enabled = true;
+                // Small repos list quickly and come and go with checkouts, and their examples are
+                // worked on like any other package
+                config.graph_cache = false;
+                config.ignore = vec![];
+            }
+        }
+    }
 }
 
 impl ColconInvocation {
@@ -183,22 +727,36 @@ impl ColconInvocation {
         if log {
             args.arg("log");
         } else {
-            args.arg("/dev/null");
+            args.arg(gantt::log_base().unwrap_or("/dev/null"));
         }
         ColconInvocation {
             args,
             workspace: workspace.into(),
+            bases: (
+                bases::build().to_string_lossy().to_string(),
+                bases::install().to_string_lossy().to_string(),
+            ),
         }
     }
 
+    /// Use `<prefix>/build` and `<prefix>/install` instead of the default bases
+    fn bases(mut self, prefix: &str) -> ColconInvocation {
+        self.bases = (format!("{prefix}/build"), format!("{prefix}/install"));
+        self
+    }
+
     fn build(self, base_setup: &BuildOutput) -> BuildVerb {
         let mut res = BuildVerb {
             args: self.args,
             workspace: self.workspace,
         };
         res.args.arg("build");
-        res.args
-            .args(["--build-base", "build", "--install-base", "install"]);
+        res.args.args([
+            "--build-base",
+            &self.bases.0,
+            "--install-base",
+            &self.bases.1,
+        ]);
         if base_setup.symlink {
             res.args.arg("--symlink-install");
         }
@@ -208,39 +766,57 @@ impl ColconInvocation {
         res
     }
 
-    fn test(self, config: &TestConfiguration) -> BasicVerb {
+    /// Test in the install layout `base_setup` the packages were built with
+    fn test(self, base_setup: &BuildOutput, config: &TestConfiguration) -> BasicVerb {
         let mut res = BasicVerb {
             args: self.args,
             workspace: self.workspace,
+            env: vec![],
         };
         // TODO: log is probably needed here?
         res.args.arg("test");
+        if self.bases.0 != "build" {
+            res.args.args([
+                "--build-base",
+                &self.bases.0,
+                "--install-base",
+                &self.bases.1,
+            ]);
+        }
+        if base_setup.merge {
+            res.args.arg("--merge-install");
+        }
         res.args.arg("--event-handlers");
         config.event_handlers.apply(&mut res.args);
-        res.args.args(["--ctest-args", "--output-on-failure"]);
-        if let Some(test) = &config.test {
-            res.args.arg("-R");
-            res.args.arg(format!("^{test}$"));
+        if config.python {
+            res.args.arg("--pytest-args");
+            res.args
+                .args(config.ctest.pytest_args(config.test.as_deref()));
+        } else {
+            res.args.arg("--ctest-args");
+            res.args.args(config.ctest.args(config.test.as_deref()));
         }
         res.args.args(["--packages-select", &config.package]);
         res
     }
 
-    fn test_result(self, config: &TestResultConfig) -> BasicVerb {
+    /// Any other colcon verb with its arguments as given, in colb's bases unless they are given
+    fn verb(self, args: &[String]) -> BasicVerb {
         let mut res = BasicVerb {
             args: self.args,
             workspace: self.workspace,
+            env: vec![],
         };
-        // TODO: log is probably needed here?
-        res.args.arg("test-result");
-        res.args
-            .args(["--test-result-base", &format!("build/{}", config.package)]);
-        if config.verbose {
-            res.args.arg("--verbose");
+        let (verb, rest) = args.split_first().expect("at least the verb is required");
+        res.args.arg(verb);
+        let given = |flag: &str| rest.iter().any(|a| a == flag);
+        if matches!(verb.as_str(), "build" | "test") && !given("--build-base") {
+            res.args.args(["--build-base", &self.bases.0]);
         }
-        if config.all {
-            res.args.arg("--all");
+        if matches!(verb.as_str(), "build" | "test") && !given("--install-base") {
+            res.args.args(["--install-base", &self.bases.1]);
         }
+        res.args.args(rest);
         res
     }
 }
@@ -253,6 +829,34 @@ fn cmake_arg(name: &str, value: &str) -> String {
     format!("-D{name}={value}")
 }
 
+/// Append `flags` to the value of `-D<var>=...` in `args`, or add it
+fn append_cmake_flags(args: &mut Vec<String>, var: &str, flags: &str) {
+    let prefixes = [format!("-D{var}="), format!("-D{var}:STRING=")];
+    match args
+        .iter_mut()
+        .find(|a| prefixes.iter().any(|p| a.starts_with(p.as_str())))
+    {
+        Some(arg) => {
+            arg.push(' ');
+            arg.push_str(flags);
+        }
+        None => args.push(cmake_arg(var, flags)),
+    }
+}
+
+/// Add `flags` to the compiler and linker flags in the CMake arguments `args`
+fn add_compiler_flags(args: &mut Vec<String>, flags: &str) {
+    for var in [
+        "CMAKE_C_FLAGS",
+        "CMAKE_CXX_FLAGS",
+        "CMAKE_EXE_LINKER_FLAGS",
+        "CMAKE_SHARED_LINKER_FLAGS",
+        "CMAKE_MODULE_LINKER_FLAGS",
+    ] {
+        append_cmake_flags(args, var, flags);
+    }
+}
+
 impl BuildConfiguration {
     const DEFAULT_MIXINS: &'static [&'static str] =
         &["compile-commands", "ninja", "mold", "ccache"];
@@ -267,6 +871,10 @@ impl BuildConfiguration {
             parallel_jobs: Some(8),
             event_handlers: EventHandlers::default(),
             build_tests: false,
+            emulator: vec![],
+            sysroot: None,
+            environment_setup: None,
+            fetchcontent_dir: None,
         }
     }
 
@@ -281,285 +889,1820 @@ impl BuildConfiguration {
             parallel_jobs: Some(8),
             event_handlers: EventHandlers::compile_logs_only(),
             build_tests: true,
+            emulator: vec![],
+            sysroot: None,
+            environment_setup: None,
+            fetchcontent_dir: None,
         }
     }
-}
 
-impl BuildVerb {
-    fn configure(self, config: &BuildConfiguration) -> ConfiguredBuild {
-        let mut res = ConfiguredBuild {
-            args: self.args,
-            workspace: self.workspace,
+    /// Conda environments (e.g. RoboStack) don't ship mold and bring their own Python
+    fn adapt_to_conda(&mut self, prefix: &str) {
+        self.mixins.retain(|m| m != "mold");
+        let python = if cfg!(windows) {
+            format!("{prefix}/python.exe")
+        } else {
+            format!("{prefix}/bin/python")
         };
-        if let Some(n) = config.parallel_jobs {
-            let n_arg = format!("{}", n);
-            res.args
-                .args(["--executor", "parallel", "--parallel-workers", &n_arg]);
-        }
-        config.event_handlers.apply(&mut res.args);
-        if !config.mixins.is_empty() {
-            res.args.arg("--mixin").args(config.mixins.iter());
+        self.cmake_args
+            .push(cmake_arg("Python3_EXECUTABLE", &python));
+    }
+}
+
+impl Config {
+    /// The default configuration, adapted to the environment colb runs in
+    fn detected() -> Config {
+        let mut res = Config::default();
+        if let Some(prefix) = environment::conda_prefix() {
+            res.upstream.adapt_to_conda(&prefix);
+            res.package.adapt_to_conda(&prefix);
         }
-        res.args.arg("--cmake-args");
-        res.args.arg(cmake_arg(
-            "BUILD_TESTING",
-            if config.build_tests { "ON" } else { "OFF" },
-        ));
-        res.args.args(config.cmake_args.iter());
-        config.build_type.apply(&mut res.args);
         res
     }
-}
 
-const DECO: Style = Style::new().fg_color(Some(Color::Ansi(AnsiColor::BrightBlack)));
-const HEADER: Style = Style::new()
-    .bold()
-    .fg_color(Some(Color::Ansi(AnsiColor::BrightBlue)));
+    /// The detected defaults with the user config on top, which the workspace config overrides
+    fn user_defaults() -> Result<toml::Table, String> {
+        let mut res =
+            toml::Table::try_from(Config::detected()).expect("Config should be serializable");
+        if let Some(user) = extends::load_user()? {
+            extends::merge(&mut res, user);
+        }
+        Ok(res)
+    }
 
-fn use_color() -> bool {
-    std::io::stdout().is_terminal()
+    fn build_output(&self) -> BuildOutput {
+        BuildOutput {
+            symlink: self.symlink_install,
+            merge: self.merge_install,
+        }
+    }
 }
 
-macro_rules! header {
-    ($($l:tt)*) => {
-        if use_color() {
-            print!("{DECO}┌[{DECO:#} {HEADER}");
-            print!($($l)*);
-            println!("{HEADER:#} {DECO}]{DECO:#}");
-        } else {
-            print!("┌[ ");
-            print!($($l)*);
-            println!(" ]");
+impl BuildConfiguration {
+    /// The colcon build arguments selecting this configuration
+    fn args(&self, workspace: &str) -> ArgStack {
+        let mut res = ArgStack::default();
+        if let Some(n) = self.parallel_jobs {
+            let n_arg = format!("{}", n);
+            res.args(["--executor", "parallel", "--parallel-workers", &n_arg]);
         }
-    };
-}
-macro_rules! context {
-    ($($l:tt)*) => {
-        if use_color() {
-            print!("{DECO}└>{DECO:#} ");
-            println!($($l)*);
-        } else {
-            print!("└> ");
-            println!($($l)*);
+        self.event_handlers.apply(&mut res);
+        if !self.mixins.is_empty() {
+            res.arg("--mixin").args(self.mixins.iter());
         }
-    };
-}
-
-fn print_command(command: &Command) {
-    print_command_arrow(&command.get_program().to_string_lossy());
-    for arg in command.get_args() {
-        print!(" {}", arg.to_string_lossy());
+        res.arg("--cmake-args");
+        res.arg(cmake_arg(
+            "BUILD_TESTING",
+            if self.build_tests { "ON" } else { "OFF" },
+        ));
+        res.args(self.cmake_args.iter());
+        if !self.emulator.is_empty() {
+            res.arg(cmake_arg(
+                "CMAKE_CROSSCOMPILING_EMULATOR",
+                &self.emulator.join(";"),
+            ));
+        }
+        if let Some(sysroot) = &self.sysroot {
+            let path = if sysroot.contains('/') {
+                PathBuf::from(sysroot)
+            } else {
+                sysroot::sysroot_dir(Path::new(workspace), sysroot)
+            };
+            let path = path.to_string_lossy();
+            res.arg(cmake_arg("CMAKE_SYSROOT", &path));
+            res.arg(cmake_arg("CMAKE_FIND_ROOT_PATH", &path));
+        }
+        if let Some(dir) = &self.fetchcontent_dir {
+            let dir = Path::new(workspace).join(dir);
+            res.arg(cmake_arg("FETCHCONTENT_BASE_DIR", &dir.to_string_lossy()));
+        }
+        self.build_type.apply(&mut res);
+        res
     }
-    println!();
-    divider();
 }
 
-fn print_command_arrow(command: &str) {
-    if use_color() {
-        print!("{DECO}└>{DECO:#} {}", command);
-    } else {
-        print!("└> {}", command);
+impl BuildVerb {
+    fn configure(self, config: &BuildConfiguration) -> ConfiguredBuild {
+        let mut res = ConfiguredBuild {
+            base: self.args.to_vec(),
+            args: self.args,
+            workspace: self.workspace,
+            env: vec![],
+            skip: vec![],
+            config: config.clone(),
+            overrides: BTreeMap::new(),
+        };
+        if let Some(script) = &config.environment_setup {
+            let script = Path::new(&res.workspace).join(script);
+            context!("Sourcing '{}'", script.to_string_lossy());
+            res.env = environment::sourced(&script).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+        }
+        res.args.args(config.args(&res.workspace).to_vec());
+        res
     }
 }
 
-fn divider() {
-    if use_color() {
-        println!("{DECO}[ \\ \\ \\{DECO:#} Output {DECO}/ / / ]{DECO:#}");
-    } else {
-        println!("[ \\ \\ \\ Output / / / ]");
+impl ConfiguredBuild {
+    /// Build the packages in `overrides` with their own settings
+    fn overrides(mut self, overrides: &BTreeMap<String, PackageOverrides>) -> ConfiguredBuild {
+        self.overrides = overrides.clone();
+        self
     }
-}
 
-impl ConfiguredBuild {
-    fn run(&self, what: &What) -> ExitStatus {
-        let mut cmd = Command::new("colcon");
-        cmd.current_dir(&self.workspace);
-        cmd.args(self.args.iter());
+    /// The colcon package selection arguments for `what`
+    fn selection(&self, what: &What) -> Vec<String> {
+        let mut res = ArgStack::default();
         match what {
-            What::DependenciesFor(package) => {
-                cmd.arg("--packages-up-to").arg(package);
-                cmd.arg("--packages-skip").arg(package);
+            What::DependenciesFor(packages) => {
+                res.arg("--packages-up-to").args(packages);
+                res.arg("--packages-skip").args(packages);
+                res.args(self.skip.iter());
             }
             What::ThisPackage(package) => {
-                cmd.arg("--packages-select").arg(package);
+                res.arg("--packages-select").arg(package);
+            }
+            What::ThesePackages(packages) => {
+                res.arg("--packages-select").args(packages);
+            }
+            What::ThesePackagesAndUpTo(packages) => {
+                res.arg("--packages-up-to").args(packages);
+            }
+            What::ThesePackagesAndAbove(packages) => {
+                res.arg("--packages-above").args(packages);
+            }
+            What::AboveAndDependencies(packages) => {
+                res.arg("--packages-above-and-dependencies").args(packages);
+            }
+            What::Matching(regexes) => {
+                res.arg("--packages-select-regex").args(regexes);
             }
         }
-        print_command(&cmd);
-        cmd.status().expect("'colcon' not found")
+        if !self.skip.is_empty() && !matches!(what, What::DependenciesFor(_)) {
+            res.arg("--packages-skip").args(self.skip.iter());
+        }
+        res.to_vec()
+    }
+
+    /// Build with the arguments passed through after `--`. Options the package selection has,
+    /// like `--packages-skip`, are added to it and the others to the build arguments
+    fn run_selected(
+        &self,
+        backend: &dyn Backend,
+        args: &ArgStack,
+        selection: &[String],
+        extra: &[String],
+    ) -> ExitStatus {
+        let (to_selection, to_args): (Vec<_>, Vec<_>) = config_diff::flags(extra)
+            .into_iter()
+            .partition(|(name, _)| selection.contains(name));
+        let flatten = |flags: Vec<(String, Vec<String>)>| {
+            flags
+                .into_iter()
+                .flat_map(|(name, values)| std::iter::once(name).chain(values))
+                .collect::<Vec<_>>()
+        };
+        let mut merged = ArgStack::default();
+        merged.args(colcon::merge(args, &flatten(to_args)));
+        backends::run(backend.build(&backends::Build {
+            workspace: &self.workspace,
+            args: &merged,
+            selection: &colcon::merge(selection, &flatten(to_selection)),
+            env: &self.env,
+        }))
+    }
+
+    fn run(&self, what: &What) -> ExitStatus {
+        let extra = colcon::passed_through(matches!(what, What::DependenciesFor(_)));
+        // Packages colcon doesn't know, like Bazel targets, are built first by their backends,
+        // after the packages colcon knows that they depend on
+        let (own, rest) = what.split(&backends::own_packages());
+        if let What::DependenciesFor(_) = what {
+            let dependencies = own
+                .iter()
+                .flat_map(|p| backends::of(p, None).dependencies(p))
+                .collect::<Vec<_>>();
+            if !dependencies.is_empty() {
+                let status = self.run(&What::ThesePackagesAndUpTo(dependencies));
+                if !status.success() {
+                    return status;
+                }
+            }
+        } else {
+            for package in own {
+                let selection = ["--packages-select".to_string(), package.clone()];
+                let backend = backends::of(&package, None);
+                let status = self.run_selected(backend, &self.args, &selection, extra);
+                if !status.success() {
+                    return status;
+                }
+            }
+        }
+        let what = rest;
+        let Some(what) = what else {
+            return ExitStatus::default();
+        };
+        let selection = self.selection(&what);
+        let colcon = backends::colcon();
+        let elsewhere = |package: &str, source: &Path| {
+            self.overrides.contains_key(package)
+                || backends::of(package, Some(source)).name() != colcon.name()
+        };
+        // Only list the selection in dependency order if some package of the workspace isn't
+        // built by colcon with the common settings, the list of the workspace is cached
+        if self.overrides.is_empty() && !backends::selective()
+            || !colcon_list(&self.workspace, &[])
+                .iter()
+                .any(|(p, source)| elsewhere(p, source))
+        {
+            return self.run_selected(colcon, &self.args, &selection, extra);
+        }
+        let mut list_args = vec!["--topological-order"];
+        list_args.extend(selection.iter().map(String::as_str));
+        let packages = colcon_list(&self.workspace, &list_args)
+            .into_iter()
+            .map(|(package, source)| {
+                let backend = backends::of(&package, Some(&source));
+                (package, backend)
+            })
+            .collect::<Vec<_>>();
+        if !packages
+            .iter()
+            .any(|(p, backend)| self.overrides.contains_key(p) || backend.name() != colcon.name())
+        {
+            return self.run_selected(colcon, &self.args, &selection, extra);
+        }
+        // Packages with overrides are built on their own, in between the others in dependency order,
+        // and so are the groups of packages built by another backend than colcon
+        let mut groups: Vec<(Option<&PackageOverrides>, &dyn Backend, Vec<String>)> = vec![];
+        for (package, backend) in packages {
+            let overrides = self.overrides.get(&package);
+            match groups.last_mut() {
+                Some((o, b, group))
+                    if overrides.is_none() && o.is_none() && b.name() == backend.name() =>
+                {
+                    group.push(package)
+                }
+                _ => groups.push((overrides, backend, vec![package])),
+            }
+        }
+        for (overrides, backend, group) in groups {
+            let mut args = ArgStack::default();
+            if let Some(overrides) = overrides {
+                context!("Using the overrides for '{}'", group[0]);
+                args.args(self.base.iter().cloned());
+                args.args(overrides.apply(&self.config).args(&self.workspace).to_vec());
+            } else {
+                args.args(self.args.iter().cloned());
+            }
+            let mut selection = vec!["--packages-select".to_string()];
+            selection.extend(group);
+            let status = self.run_selected(backend, &args, &selection, extra);
+            if !status.success() {
+                return status;
+            }
+        }
+        ExitStatus::default()
     }
 }
 
+/// The arguments adapted to the installed colcon, exits if it lacks required features
+fn colcon_args(args: &[String]) -> Vec<String> {
+    colcon::adapt(args.to_vec()).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(-1);
+    })
+}
+
 impl BasicVerb {
+    /// Run with the variables of the `environment_setup` script of `config`, if any
+    fn environment(mut self, config: &BuildConfiguration) -> BasicVerb {
+        if let Some(script) = &config.environment_setup {
+            let script = Path::new(&self.workspace).join(script);
+            context!("Sourcing '{}'", script.to_string_lossy());
+            self.env = environment::sourced(&script).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+        }
+        self
+    }
+
     fn run(&self) -> ExitStatus {
-        let mut cmd = Command::new("colcon");
+        let mut cmd = environment::colcon();
         cmd.current_dir(&self.workspace);
-        cmd.args(self.args.iter());
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)));
+        cmd.args(colcon_args(&self.args));
         print_command(&cmd);
-        cmd.status().expect("'colcon' not found")
+        run_colcon(&mut cmd).expect("'colcon' not found")
     }
 }
 
-fn ninja_build_target(workspace: &str, package: &str, target: &str) -> ExitStatus {
-    let mut cmd = Command::new("ninja");
-    cmd.arg("-C");
-    cmd.arg(format!("{workspace}/build/{package}"));
-    cmd.arg(target);
-    print_command(&cmd);
-    cmd.status().expect("'ninja' not found")
-}
-
-fn run_single_ctest(workspace: &str, package: &str, target: &str) -> ExitStatus {
-    let mut cmd = Command::new("ctest");
-    cmd.arg("--test-dir");
-    cmd.arg(format!("{workspace}/build/{package}"));
-    cmd.arg("--output-on-failure");
-    cmd.arg("-R");
-    cmd.arg(format!("^{target}$"));
-    print_command(&cmd);
-    cmd.status().expect("'ctest' not found")
-}
-
-fn clean_package(workspace: &Path, package: &str) {
-    let build_folder = workspace.join("build").join(package);
-    let install_folder = workspace.join("install").join(package);
-    let report_error = |err| {
-        eprintln!("Error: {err}");
-    };
-    let mut did_stuff = false;
-    if build_folder.exists() {
-        print_command_arrow("rm -r ");
-        println!("'{}'", build_folder.to_string_lossy());
-        let _ = fs::remove_dir_all(build_folder).map_err(report_error);
-        did_stuff = true;
-    }
-    if install_folder.exists() {
-        print_command_arrow("rm -r ");
-        println!("'{}'", install_folder.to_string_lossy());
-        let _ = fs::remove_dir_all(install_folder).map_err(report_error);
-        did_stuff = true;
-    }
-    if !did_stuff {
-        print_command_arrow("# Nothing to clean up\n");
-    }
+/// Names and source folders of the packages colcon selects with `selection`
+fn colcon_list(workspace: &str, selection: &[&str]) -> Vec<(String, PathBuf)> {
+    colcon::list(workspace, selection)
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split('\t');
+            let name = fields.next()?;
+            let path = fields.next()?;
+            Some((name.to_string(), Path::new(workspace).join(path)))
+        })
+        .collect()
 }
 
-fn contains_marker(path: &Path, markers: &[&str]) -> bool {
-    for m in markers {
-        let candidate = path.join(m);
-        if let Ok(x) = candidate.try_exists() {
-            if x {
-                return true;
+/// Dependencies of `packages` with their install cache keys. A package is built against its
+/// dependencies, so their keys are part of its key and a change upstream invalidates everything
+/// depending on it
+fn dependency_keys(workspace: &str, config: &Config, packages: &[String]) -> Vec<(String, String)> {
+    let settings = format!(
+        "{workspace}\n{}{}",
+        toml::to_string(&config.upstream).expect("Config should be serializable"),
+        colcon::passed_through(true).join(" ")
+    );
+    let sources = dependency_sources(workspace, packages);
+    let mut keys: BTreeMap<&str, String> = BTreeMap::new();
+    let mut res = vec![];
+    for (index, (dep, source)) in sources.iter().enumerate() {
+        let mut settings = settings.clone();
+        if let Some(overrides) = config.packages.get(dep) {
+            settings.push_str(&toml::to_string(overrides).expect("Config should be serializable"));
+        }
+        // Without a package.xml, e.g. plain CMake projects, assume it depends on everything
+        // before it in topological order
+        let upstream = match package_xml::dependencies(source) {
+            Some(names) => names
+                .into_iter()
+                .filter(|name| sources[..index].iter().any(|(d, _)| d == name))
+                .collect::<Vec<_>>(),
+            None => sources[..index].iter().map(|(d, _)| d.clone()).collect(),
+        };
+        let mut complete = true;
+        for name in &upstream {
+            match keys.get(name.as_str()) {
+                Some(key) => settings.push_str(&format!("\n{name} {key}")),
+                None => complete = false,
             }
         }
-    }
-    false
-}
-
-/// Search upward, and if we hit a package.xml, use that folder name as the package
-fn find_upwards(markers: &[&str]) -> Option<PathBuf> {
-    let mut cwd = env::current_dir().and_then(|p| p.canonicalize()).ok();
-    while let Some(p) = cwd {
-        if contains_marker(&p, markers) {
-            return Some(p.to_path_buf());
+        // A dependency without a key can't be told apart from a changed one
+        if !complete {
+            continue;
         }
-        cwd = p.parent().map(|x| x.to_path_buf());
+        let Ok(key) = InstallCache::key(source, &settings) else {
+            continue;
+        };
+        keys.insert(dep, key.clone());
+        res.push((dep.clone(), key));
     }
-    None
+    res
 }
 
-fn package_or(package: Option<String>) -> Option<String> {
-    if package.is_some() {
-        return package;
-    }
-    find_upwards(&["package.xml"])
-        .and_then(|f| f.file_name().map(|n| n.to_string_lossy().to_string()))
+/// Dependencies of `packages` whose interface (headers, messages, build files) changed since they were built
+fn dependencies_with_changed_interface(workspace: &str, packages: &[String]) -> Vec<String> {
+    dependency_sources(workspace, packages)
+        .into_iter()
+        .filter(|(dep, source)| {
+            let state = bases::package_state(Path::new(workspace), dep);
+            InstallCache::interface_key(source)
+                .map_or(true, |key| InstallCache::interface_changed(&state, &key))
+        })
+        .map(|(dep, _)| dep)
+        .collect()
 }
 
-const COLB_CONFIG_FILENAME: &str = ".colb.toml";
-
-fn detect_workspace() -> Option<String> {
-    find_upwards(&["build", COLB_CONFIG_FILENAME]).map(|n| n.to_string_lossy().to_string())
+fn dependency_sources(workspace: &str, packages: &[String]) -> Vec<(String, PathBuf)> {
+    let mut selection = vec!["--topological-order", "--packages-up-to"];
+    selection.extend(packages.iter().map(|p| p.as_str()));
+    selection.push("--packages-skip");
+    selection.extend(packages.iter().map(|p| p.as_str()));
+    colcon_list(workspace, &selection)
 }
 
-/// A colcon wrapper for faster change compile test cycles
-#[derive(Parser)]
-#[command(version, about)]
-struct Cli {
-    #[arg(short, long)]
-    workspace: Option<String>,
-
-    #[command(subcommand)]
-    verb: Verbs,
-}
-
-#[derive(Subcommand)]
-enum Verbs {
-    /// Write default configuration file
-    Init {
-        /// Wheter to overwrite existing config files
-        #[arg(short, long, default_value_t = false)]
-        force: bool,
-    },
-    /// Build a package
-    Build {
-        /// The package to build (default: current directory)
-        package: Option<String>,
-
-        /// Whether to skip rebuilding dependencies
-        #[arg(short, long, default_value_t = false)]
-        skip_dependencies: bool,
-
-        /// Whether to skip building tests
-        #[arg(short = 't', long, default_value_t = false)]
-        skip_tests: bool,
-
-        /// Overwrite the build type from the config file
-        #[arg(short, long)]
-        build_type: Option<BuildType>,
-    },
-
-    /// Run tests for a package
-    Test {
-        /// The package to test (default: current directory)
-        package: Option<String>,
-
-        /// Build and run only this test (default: run all tests)
-        #[arg(short, long)]
-        test: Option<String>,
-
-        /// Run through ctest directly (only works for single tests, default: use colcon test)
-        #[arg(short, long, default_value_t = false)]
-        direct: bool,
-
-        /// Don't rebuild the package
-        #[arg(short, long, default_value_t = false)]
-        skip_rebuild: bool,
-
-        /// Rebuild dependencies of package
-        #[arg(short, long, default_value_t = false)]
-        rebuild_dependencies: bool,
-    },
-    /// Remove build and install folders of a package
-    ///
-    /// Note: Does not support merged install spaces
-    Clean {
-        /// The package to clean
-        package: String,
-    },
-    /// Opens the configuration file in $EDITOR
-    Config {},
+/// Build `packages` (and first their dependencies, unless skipped) between the build hooks, exits
+/// if a build fails
+fn build_packages(
+    workspace: &str,
+    config: &Config,
+    packages: Vec<String>,
+    skip_dependencies: bool,
+    skip: &[String],
+    strategy: Strategy,
+) {
+    let status = config.hooks.around_build(workspace, &packages, || {
+        build_without_hooks(
+            workspace,
+            config,
+            &packages,
+            skip_dependencies,
+            skip,
+            strategy,
+        )
+    });
+    exit_on_error(status);
 }
 
-fn exit_on_error(status: ExitStatus) {
-    match status.code() {
-        Some(0) => {}
-        Some(code) => {
-            std::process::exit(code);
+/// Build `packages`, and first their dependencies unless skipped
+fn build_without_hooks(
+    workspace: &str,
+    config: &Config,
+    packages: &[String],
+    skip_dependencies: bool,
+    skip: &[String],
+    strategy: Strategy,
+) -> ExitStatus {
+    let names = packages.join("', '");
+    if !skip_dependencies && strategy == Strategy::Split {
+        header!("Building dependencies for '{names}'");
+        let status = build_dependencies(workspace, config, packages, skip);
+        if !status.success() {
+            return status;
         }
-        None => {
-            std::process::exit(-1);
+    }
+    header!("Building '{names}'");
+    let mut build = ColconInvocation::new(workspace, false)
+        .build(&config.build_output())
+        .configure(&config.package)
+        .overrides(&config.packages);
+    build.skip.extend(skip.iter().cloned());
+    let mut keys = vec![];
+    if config.abi_skip {
+        let mut selection = vec!["--packages-select"];
+        selection.extend(packages.iter().map(|p| p.as_str()));
+        let sources = colcon_list(workspace, &selection)
+            .into_iter()
+            .filter(|(name, _)| !skip.contains(name))
+            .collect();
+        let unchanged;
+        (unchanged, keys) = abi_skip(workspace, sources);
+        if strategy == Strategy::Split && unchanged.len() == packages.len() {
+            return ExitStatus::default();
         }
+        build.skip.extend(unchanged);
     }
+    let status = build.run(&strategy.what(packages.to_vec()));
+    if status.success() {
+        record_semantic_keys(workspace, &keys);
+        record_sizes(workspace, packages);
+    }
+    status
+}
+
+/// The sizes of the installed binaries of `package`, `None` if it isn't installed. In a merged
+/// install space, the binaries are the ones listed in its install manifest
+fn package_sizes(workspace: &Path, package: &str) -> Option<size::Sizes> {
+    if !bases::is_installed(workspace, package) {
+        return None;
+    }
+    let prefix = bases::package_install(workspace, package);
+    if !bases::is_merged(&workspace.join(bases::install())) {
+        return Some(size::measure(&prefix, std::slice::from_ref(&prefix)));
+    }
+    Some(size::measure(
+        &prefix,
+        &merged_install_files(workspace, package)?,
+    ))
+}
+
+/// Remember the sizes of the installed binaries, for `colb size`
+fn record_sizes(workspace: &str, packages: &[String]) {
+    if commands_only() {
+        return;
+    }
+    for package in packages {
+        let Some(sizes) = package_sizes(Path::new(workspace), package) else {
+            continue;
+        };
+        if let Err(e) = size::record(Path::new(workspace), package, &sizes) {
+            eprintln!("Couldn't record the sizes of '{package}': {e}");
+        }
+    }
+}
+
+/// Build `packages` and the packages between them, like `b` for `a` depending on `b` depending on `c`,
+/// one at a time in dependency order with the package configuration. Their other dependencies are
+/// built first, unless skipped
+fn build_ordered(
+    workspace: &str,
+    config: &Config,
+    packages: Vec<String>,
+    skip_dependencies: bool,
+    skip: &[String],
+) {
+    let with = |option: &'static str| {
+        let mut selection = vec![option];
+        selection.extend(packages.iter().map(|p| p.as_str()));
+        selection
+    };
+    let above = colcon_list(workspace, &with("--packages-above"))
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    let mut up_to = with("--packages-up-to");
+    up_to.insert(0, "--topological-order");
+    let ordered = colcon_list(workspace, &up_to)
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| above.contains(name) && !skip.contains(name))
+        .collect::<Vec<_>>();
+    if ordered.len() > packages.len() {
+        let between = ordered
+            .iter()
+            .filter(|p| !packages.contains(p))
+            .cloned()
+            .collect::<Vec<_>>();
+        context!("Also building '{}' between them", between.join("', '"));
+    }
+    // The hooks run once around everything, as the dependencies may need what pre_build generates
+    let status = config.hooks.around_build(workspace, &ordered, || {
+        if !skip_dependencies {
+            header!("Building dependencies for '{}'", ordered.join("', '"));
+            let status = build_dependencies(workspace, config, &ordered, skip);
+            if !status.success() {
+                return status;
+            }
+        }
+        for package in &ordered {
+            let package = std::slice::from_ref(package);
+            let status =
+                build_without_hooks(workspace, config, package, true, skip, Strategy::Split);
+            if !status.success() {
+                return status;
+            }
+        }
+        ExitStatus::default()
+    });
+    exit_on_error(status);
+}
+
+/// Split packages into those whose sources only changed in comments since their last build with
+/// `abi_skip` and the others, with the keys to record once they are built
+fn abi_skip(
+    workspace: &str,
+    sources: Vec<(String, PathBuf)>,
+) -> (Vec<String>, Vec<(String, String)>) {
+    let mut unchanged = vec![];
+    let mut keys = vec![];
+    for (package, source) in sources {
+        let Ok(key) = InstallCache::semantic_key(&source) else {
+            continue;
+        };
+        let build_dir = Path::new(workspace).join(bases::build()).join(&package);
+        if InstallCache::semantically_unchanged(&build_dir, &key) {
+            context!("Only comments changed in '{package}', skipping it (abi_skip)");
+            unchanged.push(package);
+        } else {
+            keys.push((package, key));
+        }
+    }
+    (unchanged, keys)
+}
+
+fn record_semantic_keys(workspace: &str, keys: &[(String, String)]) {
+    if commands_only() {
+        return;
+    }
+    for (package, key) in keys {
+        let build_dir = Path::new(workspace).join(bases::build()).join(package);
+        if build_dir.is_dir() {
+            let _ = InstallCache::record_semantic(&build_dir, key);
+        }
+    }
+}
+
+/// Build the dependencies of `packages` except `skip`, restoring unchanged ones from the install cache if enabled
+fn build_dependencies(
+    workspace: &str,
+    config: &Config,
+    packages: &[String],
+    skip: &[String],
+) -> ExitStatus {
+    let mut skip = skip.to_vec();
+    let mut keys = vec![];
+    if config.abi_skip {
+        let sources = dependency_sources(workspace, packages)
+            .into_iter()
+            .filter(|(dep, _)| !skip.contains(dep))
+            .collect();
+        let unchanged;
+        (unchanged, keys) = abi_skip(workspace, sources);
+        skip.extend(unchanged);
+    }
+    let status = build_dependencies_cached(workspace, config, packages, &skip);
+    if status.success() {
+        record_semantic_keys(workspace, &keys);
+    }
+    if status.success() && !commands_only() {
+        // Remember what the installed dependencies were built from, for --rebuild-dependencies auto
+        for (dep, source) in dependency_sources(workspace, packages) {
+            let state = bases::package_state(Path::new(workspace), &dep);
+            if let Ok(key) = InstallCache::interface_key(&source) {
+                let _ = InstallCache::record_interface(&state, &key);
+            }
+        }
+        if cache::load_fingerprint(Path::new(workspace)).is_some() {
+            let keys = dependency_keys(workspace, config, packages);
+            if let Err(e) = cache::store_fingerprint(Path::new(workspace), &keys) {
+                eprintln!("Couldn't update the dependency fingerprint: {e}");
+            }
+        }
+    }
+    status
+}
+
+fn build_dependencies_cached(
+    workspace: &str,
+    config: &Config,
+    packages: &[String],
+    skip: &[String],
+) -> ExitStatus {
+    let mut build = ColconInvocation::new(workspace, false)
+        .build(&config.build_output())
+        .configure(&config.upstream)
+        .overrides(&config.packages);
+    build.skip.extend(skip.iter().cloned());
+    let what = What::DependenciesFor(packages.to_vec());
+    for pinned in &config.pinned {
+        if bases::is_installed(Path::new(workspace), pinned) {
+            context!("Skipping pinned '{pinned}'");
+            build.skip.push(pinned.clone());
+        }
+    }
+    let fingerprint = cache::load_fingerprint(Path::new(workspace));
+    if fingerprint.is_none() && !config.cache.enabled {
+        return build.run(&what);
+    }
+    let keys = dependency_keys(workspace, config, packages);
+    if let Some(fingerprint) = fingerprint {
+        // Skip what `colb prefetch` (or the last dependency build) already built from the same sources
+        let mut unchanged = true;
+        for (dep, key) in &keys {
+            if build.skip.contains(dep) {
+                continue;
+            }
+            if fingerprint.get(dep) == Some(key) && bases::is_installed(Path::new(workspace), dep) {
+                build.skip.push(dep.clone());
+            } else {
+                unchanged = false;
+            }
+        }
+        if unchanged {
+            context!("Dependencies are unchanged since they were last built");
+            return ExitStatus::default();
+        }
+    }
+    if !config.cache.enabled {
+        return build.run(&what);
+    }
+    if config.merge_install {
+        context!("The install cache needs a prefix per package, building without it");
+        return build.run(&what);
+    }
+    let cache = InstallCache::new(config.cache.directory.as_deref());
+    let remote = config.cache.remote.as_deref().map(cache::Remote::parse);
+    let mut to_store = vec![];
+    for (dep, key) in keys {
+        if build.skip.contains(&dep) {
+            continue;
+        }
+        let install = Path::new(workspace).join(bases::install()).join(&dep);
+        if InstallCache::is_current(&install, &key) {
+            build.skip.push(dep);
+            continue;
+        }
+        if let Some(remote) = &remote {
+            if let Err(e) = cache.pull(&key, remote) {
+                eprintln!("Couldn't pull '{dep}' from the remote cache: {e}");
+            }
+        }
+        match cache.restore(&key, &install) {
+            Ok(true) => {
+                context!("Restored '{dep}' from the install cache");
+                build.skip.push(dep);
+            }
+            Ok(false) => to_store.push((dep, key)),
+            Err(e) => {
+                eprintln!("Couldn't restore '{dep}' from the install cache: {e}");
+                to_store.push((dep, key));
+            }
+        }
+    }
+    let status = build.run(&what);
+    if status.success() {
+        for (dep, key) in to_store {
+            let install = Path::new(workspace).join(bases::install()).join(&dep);
+            if !install.is_dir() {
+                continue;
+            }
+            if let Err(e) = cache.store(&key, &install) {
+                eprintln!("Couldn't add '{dep}' to the install cache: {e}");
+            }
+        }
+    }
+    status
+}
+
+/// The backend building `package`, looking up its sources only if a configured backend needs them
+fn backend_of(workspace: &str, package: &str) -> &'static dyn Backend {
+    if !backends::needs_source() {
+        return backends::of(package, None);
+    }
+    // All packages are listed once, rather than each package on its own
+    let source = colcon_list(workspace, &[])
+        .into_iter()
+        .find(|(name, _)| name == package)
+        .map(|(_, source)| source);
+    backends::of(package, source.as_deref())
+}
+
+/// Whether `package` is a plain CMake project built without colcon, see [`backends::DirectCmake`]
+fn is_direct_cmake(workspace: &str, package: &str) -> bool {
+    backend_of(workspace, package).name() == backends::DirectCmake.name()
+}
+
+/// Run a C++ executable of a package, and whenever its sources change rebuild it with ninja and
+/// restart it if that succeeded. A failed rebuild leaves the previous binary running
+fn dev_executable(
+    ws: &str,
+    config: &Config,
+    package: &str,
+    exe: &str,
+    skip_dependencies: bool,
+    args: &[String],
+) {
+    let sources = colcon_list(ws, &["--packages-select", package])
+        .into_iter()
+        .map(|(_, source)| source)
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        eprintln!("Package '{package}' not found in the workspace");
+        exit(-1);
+    }
+    build_packages(
+        ws,
+        config,
+        vec![package.to_string()],
+        skip_dependencies,
+        &[],
+        Strategy::Split,
+    );
+    let ws_path = Path::new(ws);
+    let build_dir = ws_path.join(bases::build()).join(package);
+    let setup = ws_path.join(bases::install()).join("setup.bash");
+    let configured_args = config
+        .dev
+        .get(package)
+        .map(|d| d.args.clone())
+        .unwrap_or_default();
+    let start = || {
+        header!("Running '{exe}'");
+        let sourced = environment::sourced(&setup).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(-1);
+        });
+        let executable = if commands_only() {
+            build_dir.join(exe)
+        } else {
+            dev::built_executable(&build_dir, exe).unwrap_or_else(|| {
+                eprintln!("No executable '{exe}' in '{}'", build_dir.to_string_lossy());
+                exit(-1);
+            })
+        };
+        let mut cmd = environment::tool(executable);
+        cmd.args(&configured_args)
+            .args(args)
+            .envs(sourced)
+            .envs(simulation::asset_paths(ws_path, &config.asset_dirs));
+        if commands_only() {
+            print_command(&cmd);
+            return None;
+        }
+        Some(dev::Node::start(cmd).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(-1);
+        }))
+    };
+    let Some(mut node) = start() else {
+        return;
+    };
+    let watcher = watch::Watcher::new(&sources).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(-1);
+    });
+    for rebuild in 1.. {
+        context!(
+            "Watching {} for changes (Ctrl-C to stop)",
+            sources
+                .iter()
+                .map(|s| s.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let changed = watcher.wait_for_changes();
+        let shown = changed[0].strip_prefix(ws).unwrap_or(&changed[0]);
+        match changed.len() {
+            1 => header!("Rebuild #{rebuild}: '{}' changed", shown.to_string_lossy()),
+            n => header!(
+                "Rebuild #{rebuild}: '{}' and {} more files changed",
+                shown.to_string_lossy(),
+                n - 1
+            ),
+        }
+        let packages = [package.to_string()];
+        let status = config
+            .hooks
+            .around_build(ws, &packages, || build_target(ws, package, exe));
+        if !status.success() {
+            context!("Build failed, '{exe}' keeps running the previous build");
+            continue;
+        }
+        node.stop();
+        node = start().expect("commands are run");
+    }
+}
+
+/// The package with the source folder containing `file`, the innermost one if they are nested
+fn package_of_file(workspace: &str, file: &Path) -> Option<String> {
+    let file = file.canonicalize().ok()?;
+    colcon_list(workspace, &[])
+        .into_iter()
+        .filter_map(|(name, source)| Some((name, source.canonicalize().ok()?)))
+        .filter(|(_, source)| file.starts_with(source))
+        .max_by_key(|(_, source)| source.components().count())
+        .map(|(name, _)| name)
+}
+
+/// Rebuild a single target of an already configured package
+fn build_target(workspace: &str, package: &str, target: &str) -> ExitStatus {
+    backends::run(backend_of(workspace, package).build_target(workspace, package, target))
+}
+
+fn run_single_ctest(
+    workspace: &str,
+    package: &str,
+    test_regex: Option<&str>,
+    options: &CtestOptions,
+) -> ExitStatus {
+    let mut cmd = environment::tool("ctest");
+    cmd.arg("--test-dir");
+    cmd.arg(format!(
+        "{workspace}/{}/{package}",
+        bases::build().to_string_lossy()
+    ));
+    cmd.args(options.args(test_regex));
+    print_command(&cmd);
+    run(&mut cmd).expect("'ctest' not found")
+}
+
+/// Run the tests up to `runs` times, or until a run fails, then print how often each test failed
+fn repeat_tests(
+    build_dir: &Path,
+    runs: u32,
+    until_failure: bool,
+    run_once: &mut dyn FnMut() -> ExitStatus,
+) -> ExitStatus {
+    let mut counts: BTreeMap<String, (u32, u32)> = BTreeMap::new();
+    let mut failed_runs = 0;
+    let mut res = ExitStatus::default();
+    let mut done = 0;
+    for i in 1..=runs {
+        header!("Run {i} of {runs}");
+        let status = run_once();
+        done = i;
+        let results = ctest::last_results(build_dir);
+        for (test, outcome) in &results {
+            let (passed, failed) = counts.entry(test.clone()).or_default();
+            if outcome == "passed" {
+                *passed += 1;
+            } else {
+                *failed += 1;
+            }
+        }
+        // colcon test succeeds even if tests fail
+        if status.success() && results.iter().all(|(_, o)| o == "passed") {
+            continue;
+        }
+        failed_runs += 1;
+        res = if status.success() { failure() } else { status };
+        if until_failure {
+            break;
+        }
+    }
+    if commands_only() {
+        return res;
+    }
+    header!("{failed_runs} of {done} runs failed");
+    for (test, (passed, failed)) in counts.iter().filter(|(_, (_, f))| *f > 0) {
+        context!("'{test}' failed {failed} of {} times", passed + failed);
+    }
+    res
+}
+
+/// Run a test under `rr record` until it fails, keeping only the trace of the failing run
+fn record_until_failure(workspace: &str, package: &str, test: &str) -> ExitStatus {
+    let build_dir = Path::new(workspace).join(bases::build()).join(package);
+    let Some(ctest) = ctest::find_test(&build_dir, test) else {
+        eprintln!(
+            "Could not find test '{test}' in '{}'",
+            build_dir.to_string_lossy()
+        );
+        exit(-1);
+    };
+    let trace_dir = build_dir.join("rr").join(test);
+    let trace_str = trace_dir.to_string_lossy().to_string();
+    let mut run = 1;
+    loop {
+        let _ = fs::remove_dir_all(&trace_dir);
+        if let Some(parent) = trace_dir.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let mut cmd = ctest
+            .direct_command(&["rr", "record", "--output-trace-dir", &trace_str])
+            .expect("launcher is not empty");
+        context!("Run {run}");
+        print_command(&cmd);
+        let status = run_attached(&mut cmd).expect("'rr' not found");
+        if commands_only() {
+            return status;
+        }
+        if !status.success() {
+            header!("Recorded failure of '{test}' after {run} runs");
+            print_command_arrow(&format!("rr replay {trace_str}\n"));
+            return status;
+        }
+        run += 1;
+    }
+}
+
+/// Run the program of a test under a debugger like `gdb --args`, with the working directory and
+/// environment ctest would use. Tests sharing an executable are debugged by running it as a whole
+/// The test of `target` to debug, None if ctest doesn't know it. Several tests running the same
+/// program (e.g. from `gtest_discover_tests`) become one running it without arguments, exits if
+/// they run different programs
+fn test_of_target(build_dir: &Path, target: &targets::Target) -> Option<ctest::CTest> {
+    let mut tests = ctest::tests_in(build_dir)
+        .into_iter()
+        .filter(|t| target.tests.contains(&t.name))
+        .collect::<Vec<_>>();
+    if tests.is_empty() {
+        return None;
+    }
+    let program = |t: &ctest::CTest| t.program().first().cloned();
+    if tests.len() == 1 {
+        Some(tests.remove(0))
+    } else if tests.iter().all(|t| program(t) == program(&tests[0])) {
+        Some(tests[0].without_arguments())
+    } else {
+        eprintln!("The tests run different programs, pick one of:");
+        for t in &tests {
+            eprintln!("  {}", t.name);
+        }
+        exit(-1);
+    }
+}
+
+fn debug_test(
+    workspace: &str,
+    package: &str,
+    target: &targets::Target,
+    debugger: &[&str],
+) -> ExitStatus {
+    let build_dir = Path::new(workspace).join(bases::build()).join(package);
+    let Some(test) = test_of_target(&build_dir, target) else {
+        eprintln!(
+            "Could not find test '{}' in '{}'",
+            target.tests.join("', '"),
+            build_dir.to_string_lossy()
+        );
+        exit(-1);
+    };
+    let Some(mut cmd) = test.direct_command(debugger) else {
+        eprintln!("Test '{}' has no command", test.name);
+        exit(-1);
+    };
+    print_command(&cmd);
+    run_attached(&mut cmd).unwrap_or_else(|e| {
+        eprintln!("Couldn't run '{}': {e}", debugger[0]);
+        exit(-1);
+    })
+}
+
+/// The values of a matrix axis, or a single unset value if the axis is empty
+fn axis<T>(values: &[T]) -> Vec<Option<&T>> {
+    if values.is_empty() {
+        vec![None]
+    } else {
+        values.iter().map(Some).collect()
+    }
+}
+
+/// Run and check the tests of `packages` built into the bases below `prefix`
+fn matrix_tests(workspace: &str, prefix: &str, packages: &[String], config: &Config) -> ExitStatus {
+    let tests = &config.tests;
+    let mut res = ExitStatus::default();
+    for package in packages {
+        let status = ColconInvocation::new(workspace, true)
+            .bases(prefix)
+            .test(
+                &config.build_output(),
+                &TestConfiguration {
+                    package: package.clone(),
+                    test: None,
+                    event_handlers: EventHandlers::silent(),
+                    ctest: CtestOptions::default(),
+                    python: false,
+                },
+            )
+            .run();
+        if !status.success() {
+            res = status;
+            continue;
+        }
+        let status = test_results(
+            workspace,
+            &Path::new(prefix).join("build"),
+            package,
+            false,
+            0,
+            tests,
+        );
+        if !status.success() {
+            res = status;
+        }
+    }
+    res
+}
+
+/// Print the test results in the build folder of `package` below `build_base`, fails if a test failed
+fn test_results(
+    workspace: &str,
+    build_base: &Path,
+    package: &str,
+    all: bool,
+    quarantined: usize,
+    tests: &TestsConfig,
+) -> ExitStatus {
+    if commands_only() {
+        return ExitStatus::default();
+    }
+    let build_dir = Path::new(workspace).join(build_base).join(package);
+    let results = test_summary::collect(&build_dir);
+    if results.is_empty() {
+        context!("No test results in '{}'", build_dir.to_string_lossy());
+        return ExitStatus::default();
+    }
+    if test_summary::print(&build_dir, &results, all, quarantined, &tests.owners) {
+        return ExitStatus::default();
+    }
+    for (pattern, e) in owners::invalid(&tests.owners) {
+        eprintln!("Ignoring the owner of \"{pattern}\", it isn't a valid regular expression: {e}");
+    }
+    if !tests.webhooks.is_empty() {
+        let cases = results.iter().flat_map(|(_, c)| c).collect::<Vec<_>>();
+        owners::notify(&tests.owners, &tests.webhooks, package, &cases);
+    }
+    failure()
+}
+
+/// Core files written by the kernel since `since`, according to `/proc/sys/kernel/core_pattern`
+fn find_core_files(pattern: &str, working_dirs: &[PathBuf], since: SystemTime) -> Vec<PathBuf> {
+    let pattern = Path::new(pattern);
+    let dirs = match pattern.parent() {
+        Some(p) if pattern.is_absolute() => vec![p.to_path_buf()],
+        _ => working_dirs.to_vec(),
+    };
+    let prefix = pattern
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or("core".into());
+    let prefix = prefix.split('%').next().unwrap_or("core");
+    let mut res = vec![];
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for e in entries.flatten() {
+            let recent = e
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|m| m >= since);
+            if recent && e.file_name().to_string_lossy().starts_with(prefix) {
+                res.push(e.path());
+            }
+        }
+    }
+    res
+}
+
+/// Whether a core file was produced by `exe` (the mapped file notes contain its path)
+fn core_belongs_to(core: &Path, exe: &Path) -> bool {
+    let Ok(mut f) = fs::File::open(core) else {
+        return false;
+    };
+    let mut head = vec![];
+    let _ = (&mut f).take(4 << 20).read_to_end(&mut head);
+    let needle = exe.as_os_str().as_encoded_bytes();
+    head.windows(needle.len()).any(|w| w == needle)
+}
+
+fn print_backtrace(exe: &Path, core: &Path) {
+    header!("Backtrace of crashed '{}'", exe.to_string_lossy());
+    let mut cmd = environment::tool("gdb");
+    cmd.args(["-batch", "-ex", "thread apply all bt"]);
+    cmd.arg(exe).arg(core);
+    print_command(&cmd);
+    if let Err(e) = run_attached(&mut cmd) {
+        eprintln!("Couldn't run 'gdb': {e}");
+    }
+}
+
+/// Print backtraces for core dumps that test executables of `package` left behind since `since`
+fn print_crash_backtraces(workspace: &str, package: &str, since: SystemTime) {
+    let build_dir = Path::new(workspace).join(bases::build()).join(package);
+    let tests = ctest::tests_in(&build_dir);
+    let mut executables = tests
+        .iter()
+        .filter_map(|t| t.program().first().map(PathBuf::from))
+        .filter(|p| p.starts_with(workspace))
+        .collect::<Vec<_>>();
+    executables.sort();
+    executables.dedup();
+    let pattern = fs::read_to_string("/proc/sys/kernel/core_pattern").unwrap_or_default();
+    if pattern.starts_with('|') {
+        // Piped to a handler, assume systemd-coredump
+        let Ok(epoch) = since.duration_since(SystemTime::UNIX_EPOCH) else {
+            return;
+        };
+        let core = build_dir.join("core.colb");
+        for exe in executables {
+            let dumped = environment::tool("coredumpctl")
+                .args(["-q", "--no-pager"])
+                .arg(format!("--since=@{}", epoch.as_secs()))
+                .arg("--output")
+                .arg(&core)
+                .arg("dump")
+                .arg(&exe)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success());
+            if dumped {
+                print_backtrace(&exe, &core);
+                let _ = fs::remove_file(&core);
+            }
+        }
+        return;
+    }
+    let mut working_dirs = tests
+        .iter()
+        .filter_map(|t| t.working_directory.clone())
+        .collect::<Vec<_>>();
+    working_dirs.push(build_dir);
+    working_dirs.sort();
+    working_dirs.dedup();
+    for core in find_core_files(pattern.trim(), &working_dirs, since) {
+        if let Some(exe) = executables.iter().find(|e| core_belongs_to(&core, e)) {
+            print_backtrace(exe, &core);
+        }
+    }
+}
+
+/// Print sanitizer reports found in the last ctest log of `package`, one section per report
+fn print_sanitizer_reports(workspace: &str, package: &str) {
+    let log = Path::new(workspace)
+        .join(bases::build())
+        .join(package)
+        .join("Testing/Temporary/LastTest.log");
+    let Ok(content) = fs::read_to_string(log) else {
+        return;
+    };
+    for mut report in sanitizer::reports_in_log(&content) {
+        header!("{} in '{}'", report.title, report.test);
+        for line in report.lines.iter_mut() {
+            match line {
+                sanitizer::Line::Text(text) => context!("{text}"),
+                sanitizer::Line::Frame(frame) => {
+                    sanitizer::symbolize(frame);
+                    let function = frame.function.as_deref().unwrap_or("??");
+                    let location = match (&frame.location, &frame.module) {
+                        (Some(l), _) => l.clone(),
+                        (None, Some((binary, offset))) => format!("({binary}+{offset})"),
+                        (None, None) => String::new(),
+                    };
+                    let ours = location.starts_with(workspace);
+                    if use_color() && !ours {
+                        emit(format_args!(
+                            "   {DECO}{} {function} {location}{DECO:#}\n",
+                            frame.index
+                        ));
+                    } else {
+                        emit(format_args!("   {} {function} {location}\n", frame.index));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Collect everything that helps understanding why tests failed
+fn report_test_failure(workspace: &str, package: &str, since: SystemTime) {
+    print_sanitizer_reports(workspace, package);
+    print_crash_backtraces(workspace, package, since);
+}
+
+/// The program and arguments `colb debug --remote` runs for `target`: its test's command if ctest
+/// knows it, otherwise the executable the target builds, wherever in the build folder it ends up
+fn remote_program(build_dir: &Path, target: &targets::Target) -> Vec<String> {
+    if let Some(test) = test_of_target(build_dir, target) {
+        return test.program().to_vec();
+    }
+    let executable = target
+        .build
+        .as_deref()
+        .and_then(|build| dev::built_executable(build_dir, build));
+    let Some(executable) = executable else {
+        eprintln!(
+            "No executable for '{}' in '{}'",
+            target
+                .build
+                .as_deref()
+                .unwrap_or(&target.tests.join("', '")),
+            build_dir.to_string_lossy()
+        );
+        exit(-1);
+    };
+    vec![executable.to_string_lossy().to_string()]
+}
+
+/// Copy a freshly built binary to a remote machine, serve it with gdbserver and attach a local gdb
+fn debug_remote(
+    workspace: &str,
+    program: &[String],
+    remote: &str,
+    sysroot: Option<&str>,
+) -> ExitStatus {
+    let Some((host, port)) = remote.rsplit_once(':') else {
+        eprintln!("Remote must be given as <host>:<port>, got '{remote}'");
+        exit(-1);
+    };
+    let Some((exe, args)) = program.split_first() else {
+        eprintln!("There is no program to debug");
+        exit(-1);
+    };
+    let exe = Path::new(exe);
+    // Arguments naming local paths, like ament's `--gtest_output=xml:<build folder>/...`, don't
+    // exist on the remote machine
+    let args = args.iter().filter(|a| !a.contains(workspace));
+    let remote_exe = format!(
+        "/tmp/colb-{}",
+        exe.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let mut deploy = environment::tool("scp");
+    deploy.arg(exe).arg(format!("{host}:{remote_exe}"));
+    print_command(&deploy);
+    let status = run_attached(&mut deploy).expect("'scp' not found");
+    if !status.success() {
+        return status;
+    }
+
+    let mut server = environment::tool("ssh");
+    server.arg(host).arg("gdbserver").arg(format!(":{port}"));
+    server.arg(&remote_exe).args(args);
+    print_command(&server);
+    let mut server = if commands_only() {
+        None
+    } else {
+        Some(server.spawn().expect("'ssh' not found"))
+    };
+
+    let address = host.rsplit_once('@').map(|(_, h)| h).unwrap_or(host);
+    let mut cmd = environment::tool("gdb");
+    if let Some(sysroot) = sysroot {
+        cmd.arg("-ex").arg(format!("set sysroot {sysroot}"));
+    }
+    cmd.arg("-ex").arg(format!("directory {workspace}/src"));
+    cmd.args(["-ex", "set tcp connect-timeout 30"]);
+    cmd.arg("-ex")
+        .arg(format!("target remote {address}:{port}"));
+    cmd.arg(exe);
+    print_command(&cmd);
+    let status = run_attached(&mut cmd).expect("'gdb' not found");
+    if let Some(server) = &mut server {
+        let _ = server.kill();
+        let _ = server.wait();
+    }
+    status
+}
+
+/// Try to open a TCP connection to the host of `url`
+fn check_connectivity(url: &str) -> Result<(), String> {
+    let (scheme, rest) = url.split_once("://").ok_or("Not a URL")?;
+    let authority = rest.split(['/', '|']).next().unwrap_or(rest);
+    // Strip credentials
+    let host_port = authority.rsplit('@').next().unwrap_or(authority);
+    let address = if host_port
+        .rsplit_once(':')
+        .is_some_and(|(_, p)| p.parse::<u16>().is_ok())
+    {
+        host_port.to_string()
+    } else {
+        let port = match scheme {
+            "redis" => 6379,
+            "rediss" => 6380,
+            "https" => 443,
+            _ => 80,
+        };
+        format!("{host_port}:{port}")
+    };
+    let addresses = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Couldn't resolve '{address}': {e}"))?;
+    for a in addresses {
+        if TcpStream::connect_timeout(&a, Duration::from_secs(3)).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(format!("Couldn't connect to '{address}'"))
+}
+
+/// Check that the tools and services the configuration relies on are available
+fn doctor(config: &Config) -> bool {
+    let mut ok = true;
+    let mut tools = vec!["colcon", "cmake", "ctest"];
+    for mixin in config.upstream.mixins.iter().chain(&config.package.mixins) {
+        let tool = match mixin.as_str() {
+            "ninja" => "ninja",
+            "mold" => "mold",
+            "ccache" => "ccache",
+            _ => continue,
+        };
+        if !tools.contains(&tool) {
+            tools.push(tool);
+        }
+    }
+    header!("Tools");
+    for tool in tools {
+        let found = match tool {
+            "colcon" => environment::colcon_executable(),
+            _ => environment::which(tool),
+        };
+        match found {
+            Some(path) => context!("{tool}: {}", path.to_string_lossy()),
+            None => {
+                context!("{tool}: not found");
+                ok = false;
+            }
+        }
+    }
+    if let Some(url) = &config.ccache.remote_storage {
+        header!("ccache remote storage");
+        match environment::expand(url).and_then(|url| check_connectivity(&url)) {
+            Ok(()) => context!("{url}: reachable"),
+            Err(e) => {
+                context!("{url}: {e}");
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// The files of `package` in a merged install space: what CMake installed according to its
+/// install manifest, and the package's share folder and colcon's marker for it
+fn merged_install_files(workspace: &Path, package: &str) -> Option<Vec<PathBuf>> {
+    let install = workspace.join(bases::install());
+    let manifest = workspace
+        .join(bases::build())
+        .join(package)
+        .join("install_manifest.txt");
+    let mut res = fs::read_to_string(manifest)
+        .ok()?
+        .lines()
+        .map(PathBuf::from)
+        .filter(|p| p.starts_with(&install))
+        .collect::<Vec<_>>();
+    res.push(install.join("share").join(package));
+    res.push(
+        install
+            .join("share")
+            .join("colcon-core")
+            .join("packages")
+            .join(package),
+    );
+    Some(res)
+}
+
+/// Report a removed build or install folder as an event, `package` is `None` for whole bases
+fn clean_event(package: Option<&str>, folder: &Path) {
+    event(
+        "clean",
+        &[
+            ("package", package.map_or("null".into(), json_string)),
+            ("path", json_string(&folder.to_string_lossy())),
+            ("removed", (!dry_run()).to_string()),
+        ],
+    );
+}
+
+/// What `colb clean` removes of a package
+#[derive(Clone, Copy, PartialEq)]
+enum CleanScope {
+    All,
+    Build,
+    Install,
+    /// `CMakeCache.txt` and `CMakeFiles`, so the next build configures from scratch
+    Cache,
+}
+
+fn clean_package(workspace: &Path, package: &str, scope: CleanScope, trash: bool) {
+    let build_folder = workspace.join(bases::build()).join(package);
+    let install = workspace.join(bases::install());
+    let mut paths = match scope {
+        CleanScope::All | CleanScope::Build => vec![build_folder.clone()],
+        CleanScope::Install => vec![],
+        CleanScope::Cache => vec![
+            build_folder.join("CMakeCache.txt"),
+            build_folder.join("CMakeFiles"),
+        ],
+    };
+    if matches!(scope, CleanScope::All | CleanScope::Install) {
+        if bases::is_merged(&install) {
+            let Some(files) = merged_install_files(workspace, package) else {
+                eprintln!(
+                    "'{package}' is installed into a merged install space and has no install manifest, \
+                     so its files can't be told apart from the others. Remove '{}' to start over",
+                    install.to_string_lossy()
+                );
+                exit(-1);
+            };
+            paths.extend(files);
+        } else {
+            paths.push(install.join(package));
+        }
+    }
+    remove_paths(Some(package), paths, trash);
+}
+
+/// Remove files and folders, or move them to the trash
+fn remove_paths(package: Option<&str>, paths: Vec<PathBuf>, trash: bool) {
+    // Installed files can be dangling symlinks, so don't use exists()
+    let paths = paths
+        .into_iter()
+        .filter_map(|path| Some((fs::symlink_metadata(&path).ok()?, path)))
+        .collect::<Vec<_>>();
+    if commands_only() && !dry_run() {
+        for (_, path) in &paths {
+            if trash {
+                println!("{}", command_line(&remove::trash_command(path)));
+            } else {
+                println!("rm -r {}", shell_quote(&path.to_string_lossy()));
+            }
+        }
+        return;
+    }
+    if paths.is_empty() {
+        print_command_arrow("# Nothing to clean up\n");
+    }
+    for (metadata, path) in paths {
+        print_command_arrow(if trash { "gio trash " } else { "rm -r " });
+        emit(format_args!("'{}'\n", path.to_string_lossy()));
+        clean_event(package, &path);
+        if dry_run() {
+            continue;
+        }
+        let res = if trash {
+            remove::trash(&path)
+        } else if metadata.is_dir() {
+            remove::remove_tree(&path)
+        } else {
+            fs::remove_file(path).map_err(|e| e.to_string())
+        };
+        if let Err(err) = res {
+            eprintln!("Error: {err}");
+        }
+    }
+}
+
+/// The packages matching `patterns` with `*` and `?`: in the workspace, or with build or install
+/// folders left over from packages that were removed since
+fn matching_packages(workspace: &str, patterns: &[String]) -> Vec<String> {
+    let mut known = colcon_list(workspace, &[])
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    for base in [bases::build(), bases::install()] {
+        let Ok(entries) = fs::read_dir(Path::new(workspace).join(base)) else {
+            continue;
+        };
+        known.extend(
+            entries
+                .flatten()
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| !name.starts_with('.')),
+        );
+    }
+    known.sort();
+    known.dedup();
+    let mut res = vec![];
+    for pattern in patterns {
+        if !pattern.contains(['*', '?']) {
+            res.push(pattern.clone());
+            continue;
+        }
+        let matched = known
+            .iter()
+            .filter(|name| targets::glob(pattern, name))
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            eprintln!("No package matches '{pattern}'");
+        }
+        res.extend(matched.into_iter().cloned());
+    }
+    // Patterns can overlap, e.g. `nav2_*` and `*_msgs`
+    res.sort();
+    res.dedup();
+    res
+}
+
+fn contains_marker(path: &Path, markers: &[&str]) -> bool {
+    for m in markers {
+        let candidate = path.join(m);
+        if let Ok(x) = candidate.try_exists() {
+            if x {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Search upward, and if we hit a package.xml, use that folder name as the package
+fn find_upwards(markers: &[&str]) -> Option<PathBuf> {
+    let mut cwd = env::current_dir().and_then(|p| p.canonicalize()).ok();
+    while let Some(p) = cwd {
+        if contains_marker(&p, markers) {
+            return Some(p.to_path_buf());
+        }
+        cwd = p.parent().map(|x| x.to_path_buf());
+    }
+    None
+}
+
+fn package_or(package: Option<String>) -> Option<String> {
+    if package.is_some() {
+        return package;
+    }
+    let dir = find_upwards(&["package.xml"])?;
+    package_xml::name(&dir).or_else(|| dir.file_name().map(|n| n.to_string_lossy().to_string()))
+}
+
+const COLB_CONFIG_FILENAME: &str = ".colb.toml";
+
+/// The settings of `config` for the file at `path` that differ from the config it extends and from
+/// the user config
+fn config_overrides(path: &Path, config: &Config) -> Result<toml::Table, String> {
+    let mut table = toml::Table::try_from(config).expect("Config should be serializable");
+    let user = extends::user_config_file().exists();
+    if user || config.extends.is_some() {
+        let mut base = if user {
+            Config::user_defaults()?
+        } else {
+            toml::Table::new()
+        };
+        if let Some(extends) = &config.extends {
+            extends::merge(&mut base, extends::base(extends, path)?);
+        }
+        table = extends::overrides(&base, table);
+    }
+    if let Some(base) = &config.extends {
+        table.insert("extends".into(), toml::Value::String(base.clone()));
+    }
+    Ok(table)
+}
+
+/// Write `config` to `path`, only keeping the settings that differ from the config it extends
+fn save_config(path: &Path, config: &Config) -> Result<(), String> {
+    let table = config_overrides(path, config)?;
+    fs::write(
+        path,
+        toml::to_string_pretty(&table).expect("Config should be serializable"),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn detect_workspace() -> Option<String> {
+    find_upwards(&["build", COLB_CONFIG_FILENAME]).map(|n| n.to_string_lossy().to_string())
+}
+
+/// Warn about setups where colcon or its extensions come from outside the virtualenv
+fn check_virtualenv(venv: &Path, config: &Config) {
+    let mut required = vec!["colcon_core", "colcon_cmake"];
+    if !config.upstream.mixins.is_empty() || !config.package.mixins.is_empty() {
+        required.push("colcon_mixin");
+    }
+    let missing = environment::missing_modules(venv, &required);
+    if !missing.is_empty() {
+        eprintln!(
+            "The virtualenv lacks {}, install the colcon extensions into it",
+            missing.join(", ")
+        );
+    }
+    if let Some(colcon) = environment::colcon_executable() {
+        if !colcon.starts_with(venv) {
+            eprintln!(
+                "'{}' is outside the virtualenv and runs with a different Python",
+                colcon.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// Finish everything that was armed for this run, then exit with `code`. The repro bundle and the
+/// issue template are only written on failure
+fn exit(code: i32) -> ! {
+    if code != 0 {
+        repro::write_bundle(code);
+        issue::write(code);
+    }
+    env_history::finish(code);
+    usage::finish(code);
+    metrics::finish(code);
+    std::process::exit(code);
+}
+
+fn exit_on_error(status: ExitStatus) {
+    if !status.success() {
+        explain::print_hints();
+        exit(status.code().unwrap_or(-1));
+    }
+}
+
+/// Where the verb is in the command line `args`, skipping the global options and their values
+fn verb_position(cmd: &clap::Command, args: &[String], verb: &clap::Command) -> Option<usize> {
+    let takes_value = |token: &str| {
+        cmd.get_arguments().any(|a| {
+            a.get_action().takes_values()
+                && (a.get_long().is_some_and(|l| token == format!("--{l}"))
+                    || a.get_short().is_some_and(|s| token == format!("-{s}")))
+        })
+    };
+    let mut tokens = args.iter().enumerate().skip(1);
+    while let Some((index, token)) = tokens.next() {
+        if token == "--" {
+            return None;
+        }
+        if token.starts_with('-') {
+            if takes_value(token) {
+                tokens.next();
+            }
+            continue;
+        }
+        return (token == verb.get_name() || verb.get_all_aliases().any(|a| a == token))
+            .then_some(index);
+    }
+    None
+}
+
+/// The command line `args` with the configured defaults for the verb added right after it, unless
+/// they were given explicitly. After the verb, they can't end up among arguments after `--`
+fn with_defaults(
+    matches: &ArgMatches,
+    defaults: &BTreeMap<String, toml::Table>,
+    mut args: Vec<String>,
+) -> Result<Option<Vec<String>>, String> {
+    let Some((verb, verb_matches)) = matches.subcommand() else {
+        return Ok(None);
+    };
+    let Some(defaults) = defaults.get(verb) else {
+        return Ok(None);
+    };
+    let cmd = cli::command();
+    let sub = cmd
+        .find_subcommand(verb)
+        .ok_or(format!("Unknown verb '{verb}' in defaults"))?;
+    let mut position = verb_position(&cmd, &args, sub)
+        .ok_or(format!("Couldn't find '{verb}' in the command line"))?
+        + 1;
+    let mut added = false;
+    for (key, value) in defaults {
+        let arg = sub
+            .get_arguments()
+            .find(|a| a.get_id() == key && !a.is_positional())
+            .ok_or(format!("'{verb}' has no flag '{key}'"))?;
+        if verb_matches.value_source(key) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", arg.get_long().unwrap_or(key));
+        let values = match value {
+            toml::Value::Boolean(true) => vec![None],
+            toml::Value::Boolean(false) => vec![],
+            toml::Value::String(s) => vec![Some(s.clone())],
+            toml::Value::Array(a) => a
+                .iter()
+                .map(|v| match v {
+                    toml::Value::String(s) => Some(s.clone()),
+                    v => Some(v.to_string()),
+                })
+                .collect(),
+            v => vec![Some(v.to_string())],
+        };
+        for value in values {
+            // Joined, as flags with optional values only take them with `=`
+            args.insert(
+                position,
+                match value {
+                    Some(value) => format!("{flag}={value}"),
+                    None => flag.clone(),
+                },
+            );
+            position += 1;
+            added = true;
+        }
+    }
+    Ok(added.then_some(args))
+}
+
+/// The global options to pass on to colb invocations started by this one
+fn global_args(cli: &Cli, workspace: &str) -> Vec<String> {
+    let mut res = vec!["--workspace".to_string(), workspace.to_string()];
+    if cli.print_command_only {
+        res.push("--print-command-only".into());
+    }
+    if cli.dry_run {
+        res.push("--dry-run".into());
+    }
+    if cli.output != OutputMode::Human {
+        res.extend(["--output".into(), cli.output.name().into()]);
+    }
+    if let Some(profile) = &cli.profile {
+        res.extend(["--profile".into(), profile.clone()]);
+    }
+    if cli.offline {
+        res.push("--offline".into());
+    }
+    for (set, flag) in [
+        (cli.asan, "--asan"),
+        (cli.tsan, "--tsan"),
+        (cli.ubsan, "--ubsan"),
+    ] {
+        if set {
+            res.push(flag.into());
+        }
+    }
+    res
+}
+
+/// The verb (including nested actions) and the flags given for it on the command line
+fn used_verb(matches: &ArgMatches) -> (String, Vec<String>) {
+    let mut names = vec![];
+    let mut flags = vec![];
+    let mut cmd = cli::command();
+    let mut matches = matches;
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        names.push(name.to_string());
+        let Some(sub) = cmd.find_subcommand(name).cloned() else {
+            break;
+        };
+        for arg in sub.get_arguments().filter(|a| !a.is_positional()) {
+            let id = arg.get_id().as_str();
+            if sub_matches.value_source(id) == Some(ValueSource::CommandLine) {
+                flags.push(arg.get_long().unwrap_or(id).to_string());
+            }
+        }
+        cmd = sub;
+        matches = sub_matches;
+    }
+    (names.join(" "), flags)
+}
+
+/// `colb help <verb> --examples` only prints the recipes for the verb, the rest of `help` is
+/// clap's own
+fn print_examples(args: &[String]) -> bool {
+    let mut rest = args.iter().skip(1).map(String::as_str).collect::<Vec<_>>();
+    if rest.first() != Some(&"help") {
+        return false;
+    }
+    let Some(flag) = rest.iter().position(|a| *a == "--examples" || *a == "-e") else {
+        return false;
+    };
+    rest.remove(flag);
+    let [_, verb] = rest[..] else {
+        eprintln!("Usage: colb help <VERB> --examples");
+        exit(-1);
+    };
+    let examples = cli::examples(verb);
+    if examples.is_empty() {
+        eprintln!("There are no examples for '{verb}'");
+        exit(-1);
+    }
+    for (description, command) in examples {
+        println!("  # {description}");
+        println!("  {command}");
+    }
+    true
 }
 
 // TODOs:
@@ -568,22 +2711,52 @@ fn exit_on_error(status: ExitStatus) {
 fn main() {
     let exit_on_not_found = || {
         eprintln!("Could not detect package, try specifying it explicitly!");
-        std::process::exit(-1);
+        exit(-1);
     };
 
     let config_file_err = |err| {
         eprintln!("Could not open config file: {}", err);
-        std::process::exit(-1);
+        exit(-1);
     };
 
     let config_parse_err = |err| {
         eprintln!("Could not parse config file: {}", err);
-        std::process::exit(-1);
+        exit(-1);
     };
 
-    let cli = Cli::parse();
+    environment::snapshot();
+    if print_examples(&env::args().collect::<Vec<_>>()) {
+        return;
+    }
+    let matches = cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match &cli.verb {
+        Verbs::Completions { shell } => {
+            print!("{}", completions::script(cli::command(), *shell));
+            return;
+        }
+        Verbs::CompletePackages {} => {
+            let ws = cli.workspace.clone().or_else(detect_workspace);
+            let src = Path::new(ws.as_deref().unwrap_or(".")).join("src");
+            for package in package_xml::discover(&src) {
+                println!("{}", package.name);
+            }
+            return;
+        }
+        _ => {}
+    }
+    // Keep stdout clean for the shell or program reading it
+    output::set_mode(match cli.verb {
+        Verbs::Alias { .. } | Verbs::List { json: true } | Verbs::Report { file: None, .. } => {
+            OutputMode::Porcelain
+        }
+        _ => cli.output,
+    });
+    output::set_commands_only(cli.print_command_only);
+    output::set_dry_run(cli.dry_run);
     let ws = cli
         .workspace
+        .clone()
         .or_else(detect_workspace)
         .unwrap_or(".".into());
     let ws_str = Path::new(&ws)
@@ -592,189 +2765,2119 @@ fn main() {
         .unwrap_or(ws.clone());
     let cfg_file_path = Path::new(&ws).join(COLB_CONFIG_FILENAME);
     header!("Workspace");
-    let mut config = if cfg_file_path.exists() {
+    event(
+        "workspace",
+        &[
+            ("path", json_string(&ws_str)),
+            ("configured", cfg_file_path.exists().to_string()),
+            ("dry_run", cli.dry_run.to_string()),
+        ],
+    );
+    let mut table = Config::user_defaults().map_err(config_file_err).unwrap();
+    if cfg_file_path.exists() {
         context!(
             "{} (Using configuration from {})",
             &ws_str,
             COLB_CONFIG_FILENAME
         );
-        let data = std::fs::read_to_string(&cfg_file_path)
+        let workspace_table = extends::load(&cfg_file_path)
             .map_err(config_file_err)
             .unwrap();
-        toml::from_str::<Config>(&data)
-            .map_err(config_parse_err)
-            .unwrap()
+        if let Some(toml::Value::String(base)) = workspace_table.get("extends") {
+            context!("Extending {base}");
+        }
+        extends::merge(&mut table, workspace_table);
     } else {
         context!("{} (Unconfigured)", &ws_str);
-        Config::default()
+    }
+    if extends::user_config_file().exists() {
+        context!(
+            "With defaults from {}",
+            extends::user_config_file().to_string_lossy()
+        );
+    }
+    if cli.dry_run {
+        context!("Dry run, nothing is run or removed");
+    }
+    let mut config = toml::Value::Table(table)
+        .try_into::<Config>()
+        .map_err(config_parse_err)
+        .unwrap();
+    if let Some(conda_env) = &config.conda_environment {
+        let vars = environment::evaluated(&["conda", "shell.bash", "activate", conda_env])
+            .unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+        for (k, v) in vars {
+            env::set_var(k, v);
+        }
+    }
+    if let Some(prefix) = environment::conda_prefix() {
+        context!("Conda environment at {prefix}");
+    }
+    let venv = config
+        .virtualenv
+        .as_ref()
+        .map(|v| Path::new(&ws_str).join(v))
+        .or(environment::virtualenv().map(PathBuf::from));
+    if let Some(venv) = venv {
+        context!("Virtualenv at {}", venv.to_string_lossy());
+        let vars = environment::virtualenv_activation(&venv).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(-1);
+        });
+        for (k, v) in vars {
+            env::set_var(k, v);
+        }
+        check_virtualenv(&venv, &config);
+    }
+    for underlay in &config.underlays {
+        let path = Path::new(&ws).join(underlay);
+        let Some(script) = underlay::setup_script(&path) else {
+            eprintln!("No setup script in underlay '{underlay}'");
+            continue;
+        };
+        context!("Underlay {}", path.to_string_lossy());
+        let vars = environment::sourced(&script).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(-1);
+        });
+        for (k, v) in vars {
+            env::set_var(k, v);
+        }
+    }
+    if let Err(e) = config.ccache.apply() {
+        eprintln!("Invalid ccache configuration: {e}");
+        exit(-1);
+    }
+    environment::set_wrapper(config.shell_wrapper.clone());
+    let mut prefix = None;
+    if let Some(name) = &cli.profile {
+        let Some(profile) = config.profile.get(name).cloned() else {
+            eprintln!("There is no profile '{name}', configured are:");
+            for name in config.profile.keys() {
+                eprintln!("  {name}");
+            }
+            exit(-1);
+        };
+        context!("Profile '{name}'");
+        profile.apply(&mut config);
+        prefix = Some(profile.base(name));
+    }
+    if !config.env.is_empty() {
+        let names = config.env.keys().cloned().collect::<Vec<_>>();
+        context!("Environment {}", names.join(", "));
+        for (k, v) in &config.env {
+            let v = environment::expand(v).unwrap_or_else(|e| {
+                eprintln!("Invalid value of {k} in [env]: {e}");
+                exit(-1);
+            });
+            env::set_var(k, v);
+        }
+    }
+    let mut sanitizers = config.sanitizers.enabled.clone();
+    let requested = [
+        (cli.asan, sanitizer::Sanitizer::Asan),
+        (cli.tsan, sanitizer::Sanitizer::Tsan),
+        (cli.ubsan, sanitizer::Sanitizer::Ubsan),
+    ]
+    .into_iter()
+    .filter(|(set, s)| *set && !sanitizers.contains(s))
+    .map(|(_, s)| s)
+    .collect::<Vec<_>>();
+    sanitizers.extend(&requested);
+    if !sanitizers.is_empty() {
+        if let Err(e) = sanitizer::check(&sanitizers) {
+            eprintln!("{e}");
+            exit(-1);
+        }
+        let names = sanitizers
+            .iter()
+            .map(sanitizer::Sanitizer::name)
+            .collect::<Vec<_>>();
+        context!("Sanitizers {}", names.join(", "));
+        sanitizer::add_cmake_args(&mut config.package.cmake_args, &sanitizers);
+        sanitizer::enable(&sanitizers, &config.sanitizers.options);
+        // Configured sanitizers are part of the regular build, the ones from the command line
+        // get their own base so switching them on and off doesn't rebuild everything
+        if !requested.is_empty() {
+            let base = prefix.as_deref().unwrap_or(".colb");
+            prefix = Some(format!("{base}/sanitizers/{}", names.join("-")));
+        }
+    }
+    if let Some(prefix) = &prefix {
+        bases::set_prefix(prefix);
+    }
+    colcon::ignore(&config.ignore);
+    if config.graph_cache {
+        colcon::cache_lists(Path::new(&ws_str).join(bases::build()).join(".colb-lists"));
+    }
+    if cli.offline {
+        context!("Offline");
+        offline::enable();
+        for build in [&mut config.upstream, &mut config.package] {
+            build
+                .cmake_args
+                .extend(offline::CMAKE_ARGS.iter().map(|a| a.to_string()));
+        }
+    }
+    if let Some(bundle) = &cli.repro_bundle {
+        let bundle = env::current_dir().unwrap_or_default().join(bundle);
+        let resolved = toml::to_string_pretty(&config).expect("Config should be serializable");
+        repro::arm(&bundle, Path::new(&ws_str), resolved);
+    }
+    if let Some(file) = &cli.issue_template {
+        let file = env::current_dir().unwrap_or_default().join(file);
+        issue::arm(&file, &used_verb(&matches).0, Path::new(&ws_str));
+    }
+    match with_defaults(&matches, &config.defaults, env::args().collect()) {
+        Ok(Some(args)) => {
+            cli.verb = cli::command()
+                .try_get_matches_from(args)
+                .and_then(|m| Cli::from_arg_matches(&m))
+                .unwrap_or_else(|e| e.exit())
+                .verb;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Invalid defaults in {COLB_CONFIG_FILENAME}: {e}");
+            exit(-1);
+        }
+    }
+    let sandbox = match cli.verb {
+        Verbs::Build { sandbox: true, .. } => {
+            // Shared FetchContent downloads, see `colb prefetch-deps`
+            let downloads = [&config.upstream, &config.package]
+                .iter()
+                .filter_map(|c| c.fetchcontent_dir.as_ref())
+                .map(|d| Path::new(&ws_str).join(d))
+                .collect::<Vec<_>>();
+            Some(
+                sandbox::prefix(Path::new(&ws_str), &downloads).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    exit(-1);
+                }),
+            )
+        }
+        _ => None,
     };
+    let configured = backends::configure(
+        &config.backends,
+        &config.bazel,
+        config.direct_cmake,
+        sandbox,
+    );
+    if let Err(e) = configured {
+        eprintln!("Invalid backends in {COLB_CONFIG_FILENAME}: {e}");
+        exit(-1);
+    }
+    if config.usage_stats && !matches!(cli.verb, Verbs::Stats { .. }) {
+        let (verb, flags) = used_verb(&matches);
+        usage::start(&verb, flags);
+    }
+    if matches!(cli.verb, Verbs::Build { .. }) && !commands_only() {
+        env_history::arm(Path::new(&ws_str));
+    }
+    if matches!(cli.verb, Verbs::Build { .. } | Verbs::Test { .. }) && !commands_only() {
+        let metrics = metrics::user_config().unwrap_or_else(|e| {
+            eprintln!("{e}");
+            exit(-1);
+        });
+        if metrics.enabled() {
+            metrics::arm(&used_verb(&matches).0, &metrics);
+        }
+    }
     match &cli.verb {
-        Verbs::Init { force } => {
+        Verbs::Init { force, layout } => {
             if cfg_file_path.exists() && !force {
                 println!(
                     "Will not overwrite '{}' without --force",
                     cfg_file_path.to_string_lossy()
                 );
-                std::process::exit(-1);
+                exit(-1);
+            }
+            match std::fs::File::create(&cfg_file_path) {
+                Ok(mut f) => {
+                    let mut config = toml::Value::Table(
+                        Config::user_defaults().map_err(config_file_err).unwrap(),
+                    )
+                    .try_into::<Config>()
+                    .map_err(config_parse_err)
+                    .unwrap();
+                    let mut content = String::new();
+                    let user = extends::user_config_file();
+                    if user.exists() {
+                        content.push_str(&format!(
+                            "# Overrides of the defaults in {}\n",
+                            user.to_string_lossy()
+                        ));
+                    }
+                    if let Some(layout) = layout {
+                        layout.apply(&mut config);
+                        content.push_str(&format!("# Defaults for a {} layout\n", layout.name()));
+                    }
+                    let table = config_overrides(&cfg_file_path, &config)
+                        .map_err(config_file_err)
+                        .unwrap();
+                    content.push_str(
+                        &toml::to_string_pretty(&table)
+                            .expect("Default config should be serializable"),
+                    );
+                    let res = f.write_all(content.as_bytes());
+                    if res.is_ok() {
+                        println!(
+                            "Initialized default configuration at '{}'",
+                            &cfg_file_path.to_string_lossy()
+                        );
+                        exit(0);
+                    }
+                    eprintln!(
+                        "Could not cerate '{}': {}",
+                        cfg_file_path.to_string_lossy(),
+                        res.unwrap_err()
+                    );
+                    exit(-1);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Could not cerate '{}': {}",
+                        cfg_file_path.to_string_lossy(),
+                        e
+                    );
+                    exit(-1);
+                }
+            }
+        }
+
+        Verbs::Build {
+            packages,
+            skip_dependencies,
+            skip_tests,
+            build_type,
+            select_regex,
+            paths,
+            skip,
+            strategy,
+            downstream,
+            ordered,
+            sandbox: _,
+            timings,
+            advise,
+            symlink_install,
+            merge_install,
+            abi_skip,
+            pass_to,
+            colcon_args,
+        } => {
+            if !colcon_args.is_empty() {
+                let args = |enabled: bool| {
+                    if enabled {
+                        colcon_args.clone()
+                    } else {
+                        vec![]
+                    }
+                };
+                colcon::pass_through(
+                    args(*pass_to != PassTo::Package),
+                    args(*pass_to != PassTo::Dependencies),
+                );
+            }
+            if (*timings || *advise) && !commands_only() {
+                if let Err(e) = gantt::record(Path::new(&ws_str)) {
+                    eprintln!(
+                        "Couldn't prepare {}: {e}",
+                        gantt::timings_dir(Path::new(&ws_str)).to_string_lossy()
+                    );
+                    exit(-1);
+                }
+            }
+            if *skip_tests {
+                config.upstream.build_tests = false;
+                config.package.build_tests = false;
+            }
+            config.symlink_install |= symlink_install;
+            if let Some(merge_install) = merge_install {
+                config.merge_install = *merge_install;
+            }
+            config.abi_skip |= abi_skip;
+            let packages = if select_regex.is_empty() && paths.is_empty() {
+                if packages.is_empty() {
+                    vec![package_or(None)
+                        .or_else(exit_on_not_found)
+                        .expect("should have exited")]
+                } else {
+                    packages.clone()
+                }
+            } else {
+                let mut selection = vec![];
+                if !paths.is_empty() {
+                    selection.push("--paths");
+                    selection.extend(paths.iter().map(|p| p.as_str()));
+                }
+                if !select_regex.is_empty() {
+                    selection.push("--packages-select-regex");
+                    selection.extend(select_regex.iter().map(|r| r.as_str()));
+                }
+                let packages = colcon_list(&ws, &selection)
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| !skip.contains(name))
+                    .collect::<Vec<_>>();
+                if packages.is_empty() {
+                    eprintln!("No packages match the selection");
+                    exit(-1);
+                }
+                packages
+            };
+            let packages = if *downstream {
+                let mut selection = vec!["--packages-above"];
+                selection.extend(packages.iter().map(|p| p.as_str()));
+                let above = colcon_list(&ws, &selection)
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| !skip.contains(name))
+                    .collect::<Vec<_>>();
+                context!(
+                    "Also building {} packages depending on '{}'",
+                    above.len().saturating_sub(packages.len()),
+                    packages.join("', '")
+                );
+                above
+            } else {
+                packages
+            };
+            if let Some(t) = build_type {
+                config.package.build_type = t.clone();
+            }
+            // Packages colcon doesn't know, like Bazel targets, come first, the others may use
+            // what they install
+            let own = backends::own_packages();
+            let (first, packages): (Vec<_>, Vec<_>) =
+                packages.into_iter().partition(|p| own.contains(p));
+            if !first.is_empty() {
+                build_packages(
+                    &ws,
+                    &config,
+                    first,
+                    *skip_dependencies,
+                    skip,
+                    Strategy::Split,
+                );
+            }
+            if !packages.is_empty() && *ordered {
+                build_ordered(&ws, &config, packages, *skip_dependencies, skip);
+            } else if !packages.is_empty() {
+                build_packages(&ws, &config, packages, *skip_dependencies, skip, *strategy);
+            }
+            if gantt::log_base().is_some() {
+                let workspace = Path::new(&ws_str);
+                let durations = gantt::load(&gantt::timings_dir(workspace))
+                    .and_then(|jobs| Ok((gantt::update_durations(workspace, &jobs)?, jobs)));
+                match durations {
+                    Ok((durations, jobs)) if *advise => {
+                        header!("Advice");
+                        let advice = gantt::advice(&jobs, &durations, &config.pinned);
+                        if advice.is_empty() {
+                            context!("Nothing to suggest");
+                        }
+                        for line in advice {
+                            context!("{line}");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Couldn't read the build timings: {e}"),
+                }
+            }
+        }
+
+        Verbs::Test {
+            package,
+            test,
+            failed,
+            file,
+            include_quarantined,
+            direct,
+            skip_rebuild,
+            rebuild_dependencies,
+            record,
+            gdb,
+            lldb,
+            repeat,
+            until_failure,
+            ctest,
+            hermetic,
+            rmw,
+            preflight,
+            kill_orphans,
+        } => {
+            let location = file.as_deref().map(targets::Location::parse);
+            let package = package
+                .clone()
+                .or_else(|| package_of_file(&ws, &location.as_ref()?.file));
+            let package = package_or(package)
+                .or_else(exit_on_not_found)
+                .expect("should have exited");
+            if let Some(bazel) = config.bazel.get(&package) {
+                let this_package = std::slice::from_ref(&package);
+                // `bazel test` builds and tests in one go, so the hooks of both wrap it
+                exit_on_error(config.hooks.pre_build(&ws, this_package));
+                let status = hooks::run(
+                    "pre_test",
+                    config.hooks.pre_test.as_deref(),
+                    &ws,
+                    this_package,
+                    &[],
+                );
+                exit_on_error(status);
+                header!("Testing '{package}' with Bazel");
+                let mut status = backends::run(vec![bazel.test(&ws, test.as_deref())]);
+                if status.success() {
+                    status = hooks::run(
+                        "post_build",
+                        config.hooks.post_build.as_deref(),
+                        &ws,
+                        this_package,
+                        &[],
+                    );
+                }
+                let code = status.code().unwrap_or(-1).to_string();
+                let hook = hooks::run(
+                    "post_test",
+                    config.hooks.post_test.as_deref(),
+                    &ws,
+                    this_package,
+                    &[("COLB_TEST_STATUS", code)],
+                );
+                exit_on_error(if status.success() { hook } else { status });
+                exit(0);
+            }
+            // Single tests of Rust packages are built and run by cargo instead of ninja and ctest
+            let cargo_source = test
+                .as_ref()
+                .and_then(|_| colcon_list(&ws, &["--packages-select", &package]).pop())
+                .map(|(_, source)| source)
+                .filter(|source| cargo::is_cargo_package(source));
+            if cargo_source.is_some() && (*record || *gdb || *lldb) {
+                eprintln!("--record, --gdb and --lldb aren't supported for cargo packages");
+                exit(-1);
+            }
+            // ament_python packages have no CMake build folder, colcon runs their tests with pytest
+            let python = colcon_list(&ws, &["--packages-select", &package])
+                .pop()
+                .and_then(|(_, source)| package_xml::build_type(&source))
+                .is_some_and(|t| t == "ament_python");
+            if python && (*direct || *record || *gdb || *lldb || ctest.shard.is_some()) {
+                eprintln!(
+                    "--direct, --record, --gdb, --lldb and --shard aren't supported for ament_python packages"
+                );
+                exit(-1);
+            }
+            let target = test
+                .as_deref()
+                .filter(|_| cargo_source.is_none() && !python)
+                .map(|t| targets::resolve(&ws, &package, t));
+            if location.is_some() && (cargo_source.is_some() || python) {
+                eprintln!("--file is only supported for CMake packages");
+                exit(-1);
+            }
+            let target = match (target, &location) {
+                (_, Some(location)) => {
+                    let source = colcon_list(&ws, &["--packages-select", &package])
+                        .pop()
+                        .map_or(PathBuf::from(&ws), |(_, path)| path);
+                    let (target, filter) = targets::from_file(&ws, &package, &source, location);
+                    if let Some(filter) = filter {
+                        env::set_var("GTEST_FILTER", filter);
+                    }
+                    Some(target)
+                }
+                (Some(target), None) => Some(target),
+                (None, None) if *failed && !python => {
+                    let build_dir = Path::new(&ws).join(bases::build()).join(&package);
+                    let tests = ctest::last_failed(&build_dir);
+                    if tests.is_empty() {
+                        context!("No tests failed in the last run of '{package}'");
+                        exit(0);
+                    }
+                    Some(targets::Target { build: None, tests })
+                }
+                (None, None) => None,
+            };
+            let quarantined = match config.tests.quarantine.get(&package) {
+                Some(tests) if !include_quarantined => tests.clone(),
+                _ => vec![],
+            };
+            let mut ctest = ctest.clone();
+            if !quarantined.is_empty() {
+                context!(
+                    "Leaving out {} quarantined tests, run them with --include-quarantined",
+                    quarantined.len()
+                );
+                ctest.exclude = quarantined.clone();
+            }
+            if python && *failed {
+                ctest.rerun_failed = true;
+            }
+            let ctest = &ctest;
+            let rebuild_dependencies = match rebuild_dependencies {
+                _ if *skip_rebuild => false,
+                RebuildDependencies::Never => false,
+                RebuildDependencies::Always => true,
+                RebuildDependencies::Auto => {
+                    let changed =
+                        dependencies_with_changed_interface(&ws, std::slice::from_ref(&package));
+                    if !changed.is_empty() {
+                        context!("Interface of '{}' changed", changed.join("', '"));
+                    }
+                    !changed.is_empty()
+                }
+            };
+            let builds = rebuild_dependencies || (!skip_rebuild && cargo_source.is_none());
+            let this_package = std::slice::from_ref(&package);
+            if builds {
+                let status = config.hooks.around_build(&ws, this_package, || {
+                    if rebuild_dependencies {
+                        header!("Building dependencies for '{}'", package);
+                        let status = build_dependencies(&ws, &config, this_package, &[]);
+                        if !status.success() {
+                            return status;
+                        }
+                        if target.is_some() {
+                            header!("Building '{package}'");
+                            let status = ColconInvocation::new(&ws, false)
+                                .build(&config.build_output())
+                                .configure(&config.package)
+                                .overrides(&config.packages)
+                                .run(&What::ThisPackage(package.clone()));
+                            if !status.success() {
+                                return status;
+                            }
+                        }
+                    }
+                    if *skip_rebuild || cargo_source.is_some() {
+                        return ExitStatus::default();
+                    }
+                    if let Some(target) = &target {
+                        let build = target.build.as_deref().unwrap_or("all");
+                        header!("Building test '{build}' in '{package}'");
+                        build_target(&ws, &package, build)
+                    } else {
+                        header!("Building '{package}'");
+                        ColconInvocation::new(&ws, false)
+                            .build(&config.build_output())
+                            .configure(&config.package)
+                            .overrides(&config.packages)
+                            .run(&What::ThisPackage(package.clone()))
+                    }
+                });
+                exit_on_error(status);
+            }
+            if let Some(rmw) = rmw {
+                rmw.activate(&ws);
+            }
+            let hermetic = hermetic.then(|| {
+                environment::Hermetic::enter().unwrap_or_else(|e| {
+                    eprintln!("Couldn't create a temporary home: {e}");
+                    exit(-1);
+                })
+            });
+            if let Some(hermetic) = &hermetic {
+                context!("Hermetic home at {}", hermetic.dir().to_string_lossy());
+            }
+            if *preflight && !commands_only() {
+                header!("Checking the test environment");
+                let problems = preflight::check(Path::new(&ws));
+                for problem in &problems {
+                    let kind = if problem.fatal { "error" } else { "warning" };
+                    eprintln!("{kind}: {}\n  fix: {}", problem.what, problem.fix);
+                }
+                if problems.iter().any(|p| p.fatal) {
+                    exit(-1);
+                }
+                if problems.is_empty() {
+                    context!("No problems found");
+                }
+            }
+            if !commands_only() {
+                let leftovers = orphans::from_earlier_runs(Path::new(&ws));
+                if !leftovers.is_empty() {
+                    header!("Processes left over from earlier test runs");
+                    orphans::report(&leftovers, *kill_orphans);
+                }
+                orphans::tag(Path::new(&ws));
+            }
+            let plain_cmake = is_direct_cmake(&ws, &package);
+            let build_dir = Path::new(&ws).join(bases::build()).join(&package);
+            let repeated = |run_once: &mut dyn FnMut() -> ExitStatus| match repeat {
+                Some(runs) => repeat_tests(&build_dir, *runs, *until_failure, run_once),
+                None => run_once(),
+            };
+            let status = hooks::run(
+                "pre_test",
+                config.hooks.pre_test.as_deref(),
+                &ws,
+                this_package,
+                &[],
+            );
+            exit_on_error(status);
+            let started = SystemTime::now();
+            let status = 'tests: {
+                if let (Some(source), Some(filter)) = (&cargo_source, test) {
+                    header!("Running test '{filter}' in '{package}' with cargo");
+                    break 'tests cargo::test(&ws, &package, source, filter, ctest.verbose);
+                }
+                if let Some(target) = &target {
+                    if *record {
+                        let [test] = &target.tests[..] else {
+                            eprintln!(
+                                "'{}' runs several tests, pick one of:",
+                                test.as_deref().unwrap_or_default()
+                            );
+                            for t in &target.tests {
+                                eprintln!("  {t}");
+                            }
+                            exit(-1);
+                        };
+                        header!("Recording test '{test}' in '{package}' until it fails");
+                        break 'tests record_until_failure(&ws, &package, test);
+                    }
+                    let debugger: &[&str] = match (gdb, lldb) {
+                        (true, _) => &["gdb", "--args"],
+                        (_, true) => &["lldb", "--"],
+                        _ => &[],
+                    };
+                    if !debugger.is_empty() {
+                        header!(
+                            "Debugging test '{}' in '{package}'",
+                            target.tests.join("', '")
+                        );
+                        break 'tests debug_test(&ws, &package, target, debugger);
+                    }
+                    header!(
+                        "Running test '{}' in '{package}'",
+                        target.tests.join("', '")
+                    );
+                    if *direct || plain_cmake {
+                        let regex = target.test_regex();
+                        let status =
+                            repeated(&mut || run_single_ctest(&ws, &package, Some(&regex), ctest));
+                        if !status.success() {
+                            report_test_failure(&ws, &package, started);
+                        }
+                        break 'tests status;
+                    }
+                } else {
+                    header!("Running tests for '{package}'");
+                    // There are no colcon test results for packages colcon didn't build
+                    if plain_cmake {
+                        let status = repeated(&mut || run_single_ctest(&ws, &package, None, ctest));
+                        if !status.success() {
+                            report_test_failure(&ws, &package, started);
+                        }
+                        break 'tests status;
+                    }
+                }
+                let status = repeated(&mut || {
+                    ColconInvocation::new(&ws, true)
+                        .test(
+                            &config.build_output(),
+                            &TestConfiguration {
+                                package: package.clone(),
+                                test: match &target {
+                                    Some(target) => Some(target.test_regex()),
+                                    None if python => test.clone(),
+                                    None => None,
+                                },
+                                event_handlers: EventHandlers::silent(),
+                                ctest: ctest.clone(),
+                                python,
+                            },
+                        )
+                        .run()
+                });
+                if !status.success() {
+                    // Failing tests only fail colcon test when repeating
+                    if repeat.is_some() {
+                        report_test_failure(&ws, &package, started);
+                    }
+                    break 'tests status;
+                }
+                if !dry_run() {
+                    header!("Test results for '{package}'");
+                }
+                let status = test_results(
+                    &ws,
+                    &bases::build(),
+                    &package,
+                    true,
+                    quarantined.len(),
+                    &config.tests,
+                );
+                if !status.success() {
+                    report_test_failure(&ws, &package, started);
+                }
+                status
+            };
+            drop(hermetic);
+            if !commands_only() {
+                let lingering = orphans::lingering(Path::new(&ws));
+                if !lingering.is_empty() {
+                    header!("Processes left behind by the tests");
+                    orphans::report(&lingering, *kill_orphans);
+                }
+                let source = colcon_list(&ws, &["--packages-select", &package])
+                    .pop()
+                    .map_or(PathBuf::from(&ws), |(_, path)| path);
+                let build_type = config.package.build_type.name();
+                match results::record(Path::new(&ws), &package, &source, build_type, started) {
+                    Ok(Some(id)) => context!("Recorded as run {id}, see `colb results`"),
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Couldn't record the test results: {e}"),
+                }
+            }
+            let code = status.code().unwrap_or(-1).to_string();
+            let hook = hooks::run(
+                "post_test",
+                config.hooks.post_test.as_deref(),
+                &ws,
+                this_package,
+                &[("COLB_TEST_STATUS", code)],
+            );
+            exit_on_error(if status.success() { hook } else { status });
+        }
+
+        Verbs::Coverage {
+            packages,
+            skip_rebuild,
+            check,
+        } => {
+            let packages = if packages.is_empty() {
+                vec![package_or(None)
+                    .or_else(exit_on_not_found)
+                    .expect("should have exited")]
+            } else {
+                packages.clone()
+            };
+            if !skip_rebuild {
+                let status = config.hooks.around_build(&ws, &packages, || {
+                    // In a base of their own, so the regular build doesn't keep the coverage flags
+                    header!("Building dependencies for '{}'", packages.join("', '"));
+                    let status = ColconInvocation::new(&ws, false)
+                        .bases(coverage::PREFIX)
+                        .build(&config.build_output())
+                        .configure(&config.upstream)
+                        .overrides(&config.packages)
+                        .run(&What::DependenciesFor(packages.clone()));
+                    if !status.success() {
+                        return status;
+                    }
+                    header!("Building '{}' with coverage", packages.join("', '"));
+                    let mut build_config = config.package.clone();
+                    add_compiler_flags(&mut build_config.cmake_args, coverage::FLAGS);
+                    ColconInvocation::new(&ws, false)
+                        .bases(coverage::PREFIX)
+                        .build(&config.build_output())
+                        .configure(&build_config)
+                        .run(&What::ThesePackages(packages.clone()))
+                });
+                exit_on_error(status);
+                for package in &packages {
+                    header!("Running tests for '{package}'");
+                    let status = ColconInvocation::new(&ws, true)
+                        .bases(coverage::PREFIX)
+                        .test(
+                            &config.build_output(),
+                            &TestConfiguration {
+                                package: package.clone(),
+                                test: None,
+                                event_handlers: EventHandlers::silent(),
+                                ctest: CtestOptions::default(),
+                                python: false,
+                            },
+                        )
+                        .run();
+                    exit_on_error(status);
+                }
+            }
+            let thresholds = &config.min_coverage;
+            let mut all = vec![];
+            let mut below = false;
+            for package in &packages {
+                header!("Coverage of '{package}'");
+                let Some((_, source)) = colcon_list(&ws, &["--packages-select", package]).pop()
+                else {
+                    eprintln!("Package '{package}' not found in the workspace");
+                    exit(-1);
+                };
+                let tracefile =
+                    coverage::capture(Path::new(&ws), package, &source).unwrap_or_else(|e| {
+                        eprintln!("{e}");
+                        exit(-1);
+                    });
+                let report = coverage::html(&tracefile);
+                if commands_only() {
+                    continue;
+                }
+                let files = coverage::parse(&tracefile, &source).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    exit(-1);
+                });
+                for file in &files {
+                    let path = file.path.strip_prefix(&source).unwrap_or(&file.path);
+                    if json() {
+                        event(
+                            "coverage",
+                            &[
+                                ("package", json_string(package)),
+                                ("path", json_string(&path.to_string_lossy())),
+                                ("lines", file.lines.to_string()),
+                                ("covered", file.covered.to_string()),
+                            ],
+                        );
+                        continue;
+                    }
+                    println!(
+                        "{:>5.1}%  {:>5} of {:>5} lines  {}",
+                        coverage::percent(file.lines, file.covered),
+                        file.covered,
+                        file.lines,
+                        path.to_string_lossy()
+                    );
+                }
+                let (lines, covered) = coverage::total(&files);
+                let percent = coverage::percent(lines, covered);
+                context!("{percent:.1}% of {lines} lines in {} files", files.len());
+                match report {
+                    Ok(index) => context!("Report at {}", index.to_string_lossy()),
+                    Err(e) => eprintln!("No HTML report: {e}"),
+                }
+                if let Some(min) = thresholds.for_package(package).filter(|_| *check) {
+                    if percent < min {
+                        below = true;
+                        eprintln!("'{package}' is below the minimum of {min:.1}%, the largest gaps are in:");
+                        for file in coverage::largest_gaps(&files, 10) {
+                            let path = file.path.strip_prefix(&source).unwrap_or(&file.path);
+                            eprintln!(
+                                "  {:>5} uncovered lines  {:>5.1}%  {}",
+                                file.uncovered(),
+                                coverage::percent(file.lines, file.covered),
+                                path.to_string_lossy()
+                            );
+                        }
+                    }
+                }
+                all.extend(files);
+            }
+            if packages.len() > 1 && !commands_only() {
+                let (lines, covered) = coverage::total(&all);
+                let percent = coverage::percent(lines, covered);
+                header!("Overall coverage");
+                context!("{percent:.1}% of {lines} lines");
+                if let Some(min) = thresholds.overall.filter(|_| *check) {
+                    if percent < min {
+                        below = true;
+                        eprintln!("The packages together are below the minimum of {min:.1}%");
+                    }
+                }
+            }
+            if below {
+                exit(-1);
+            }
+        }
+        Verbs::Matrix { packages } => {
+            let packages = if packages.is_empty() {
+                vec![package_or(None)
+                    .or_else(exit_on_not_found)
+                    .expect("should have exited")]
+            } else {
+                packages.clone()
+            };
+            let matrix = &config.matrix;
+            let build_types = axis(&matrix.build_types);
+            let compilers = axis(&matrix.compilers);
+            let rmws = axis(&matrix.rmw_implementations);
+            let columns = rmws
+                .iter()
+                .map(|r| r.cloned().unwrap_or("tests".into()))
+                .collect::<Vec<_>>();
+            let mut rows = vec![];
+            let mut cells = vec![];
+            let mut failure = None;
+            for build_type in &build_types {
+                for compiler in &compilers {
+                    let name = matrix::name(&[
+                        build_type.map(BuildType::name),
+                        compiler.map(String::as_str),
+                    ]);
+                    header!("Building '{name}'");
+                    if let Some(compiler) = compiler {
+                        let (cc, cxx) = matrix::compilers(compiler);
+                        env::set_var("CC", cc);
+                        env::set_var("CXX", cxx);
+                    }
+                    let mut build_config = config.package.clone();
+                    if let Some(build_type) = build_type {
+                        build_config.build_type = (*build_type).clone();
+                    }
+                    let prefix = format!(".colb/matrix/{name}");
+                    let status = config.hooks.around_build(&ws, &packages, || {
+                        ColconInvocation::new(&ws, false)
+                            .bases(&prefix)
+                            .build(&config.build_output())
+                            .configure(&build_config)
+                            .run(&What::ThesePackagesAndUpTo(packages.clone()))
+                    });
+                    let mut row = vec![];
+                    for rmw in &rmws {
+                        if !status.success() {
+                            failure = Some(status);
+                            row.push(matrix::Outcome::BuildFailed);
+                            continue;
+                        }
+                        if let Some(rmw) = rmw {
+                            env::set_var("RMW_IMPLEMENTATION", rmw);
+                            header!("Testing '{name}' with '{rmw}'");
+                        } else {
+                            header!("Testing '{name}'");
+                        }
+                        let status = matrix_tests(&ws, &prefix, &packages, &config);
+                        if status.success() {
+                            row.push(matrix::Outcome::Passed);
+                        } else {
+                            failure = Some(status);
+                            row.push(matrix::Outcome::TestsFailed);
+                        }
+                    }
+                    rows.push(name);
+                    cells.push(row);
+                }
+            }
+            header!("Matrix results");
+            print!("{}", matrix::grid(&rows, &columns, &cells));
+            if let Some(status) = failure {
+                exit_on_error(status);
+            }
+        }
+
+        Verbs::Launch {
+            package,
+            file,
+            rmw,
+            print_env,
+            args,
+        } => {
+            let ws_path = Path::new(&ws_str);
+            let mut vars = simulation::asset_paths(ws_path, &config.asset_dirs);
+            if let Some(rmw) = rmw {
+                rmw.activate(&ws);
+                vars.push(("RMW_IMPLEMENTATION".into(), rmw.implementation().into()));
+            }
+            if *print_env {
+                for (k, v) in &vars {
+                    println!("{k}={}", shell_quote(v));
+                }
+                exit(0);
+            }
+            let setup = ws_path.join(bases::install()).join("setup.bash");
+            if !setup.exists() {
+                eprintln!(
+                    "'{}' doesn't exist, build the workspace first",
+                    setup.to_string_lossy()
+                );
+                exit(-1);
+            }
+            let sourced = environment::sourced(&setup).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            header!("Launching '{file}' from '{package}'");
+            let mut cmd = environment::tool("ros2");
+            cmd.envs(sourced).envs(vars);
+            cmd.args(["launch", package, file]).args(args);
+            print_command(&cmd);
+            let status = run_attached(&mut cmd).expect("'ros2' not found");
+            exit_on_error(status);
+        }
+
+        Verbs::Run {
+            executable,
+            package,
+            skip_rebuild,
+            skip_dependencies,
+            rmw,
+            args,
+        } => {
+            let package = package_or(package.clone())
+                .or_else(exit_on_not_found)
+                .expect("should have exited");
+            if !skip_rebuild {
+                let packages = vec![package.clone()];
+                build_packages(
+                    &ws,
+                    &config,
+                    packages,
+                    *skip_dependencies,
+                    &[],
+                    Strategy::Split,
+                );
             }
-            match std::fs::File::create(&cfg_file_path) {
-                Ok(mut f) => {
-                    let res = f.write_all(
-                        toml::to_string_pretty(&Config::default())
-                            .expect("Default config should be serializable")
-                            .as_bytes(),
-                    );
-                    if res.is_ok() {
-                        println!(
-                            "Initialized default configuration at '{}'",
-                            &cfg_file_path.to_string_lossy()
-                        );
-                        std::process::exit(0);
+            let ws_path = Path::new(&ws_str);
+            let prefix = bases::package_install(ws_path, &package);
+            let candidates = [prefix.join("lib").join(&package), prefix.join("bin")];
+            let Some(path) = candidates
+                .iter()
+                .map(|dir| dir.join(executable))
+                .find(|p| p.is_file())
+            else {
+                eprintln!("'{package}' has no executable '{executable}', it has:");
+                for dir in &candidates {
+                    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                        eprintln!("  {}", entry.file_name().to_string_lossy());
                     }
-                    eprintln!(
-                        "Could not cerate '{}': {}",
-                        cfg_file_path.to_string_lossy(),
-                        res.unwrap_err()
-                    );
-                    std::process::exit(-1);
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Could not cerate '{}': {}",
-                        cfg_file_path.to_string_lossy(),
-                        e
-                    );
-                    std::process::exit(-1);
                 }
+                exit(-1);
+            };
+            if let Some(rmw) = rmw {
+                rmw.activate(&ws);
             }
+            let setup = ws_path.join(bases::install()).join("setup.bash");
+            let sourced = environment::sourced(&setup).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            header!("Running '{executable}' from '{package}'");
+            let mut cmd = environment::tool(&path);
+            cmd.envs(sourced)
+                .envs(simulation::asset_paths(ws_path, &config.asset_dirs));
+            cmd.args(args);
+            print_command(&cmd);
+            let status = run_attached(&mut cmd).unwrap_or_else(|e| {
+                eprintln!("Couldn't run '{}': {e}", path.to_string_lossy());
+                exit(-1);
+            });
+            exit_on_error(status);
         }
 
-        Verbs::Build {
+        Verbs::Watch {
             package,
+            test,
             skip_dependencies,
-            skip_tests,
-            build_type,
         } => {
-            if *skip_tests {
-                config.upstream.build_tests = false;
-                config.package.build_tests = false;
-            }
             let package = package_or(package.clone())
                 .or_else(exit_on_not_found)
                 .expect("should have exited");
+            let sources = colcon_list(&ws, &["--packages-select", &package])
+                .into_iter()
+                .map(|(_, source)| source)
+                .collect::<Vec<_>>();
+            if sources.is_empty() {
+                eprintln!("Package '{package}' not found in the workspace");
+                exit(-1);
+            }
+            let step = |run: String| pipeline::Step {
+                run,
+                on_failure: Default::default(),
+            };
+            let mut steps = vec![step(format!("build {package} --skip-dependencies"))];
+            if let Some(test) = test {
+                let test = shell_quote(test);
+                steps.push(step(format!("test {package} --test {test} --skip-rebuild")));
+            }
+            let global_args = global_args(&cli, &ws);
+            let watcher = watch::Watcher::new(&sources).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            let mut first = steps.clone();
             if !skip_dependencies {
-                header!("Building dependencies for '{}'", package);
-                let status = ColconInvocation::new(&ws, false)
-                    .build(&BuildOutput::default())
-                    .configure(&config.upstream)
-                    .run(&What::DependenciesFor(package.clone()));
-                exit_on_error(status);
+                first[0] = step(format!("build {package}"));
+            }
+            let run_steps = |steps: &[pipeline::Step]| {
+                pipeline::run(steps, &global_args).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    exit(-1);
+                })
+            };
+            let mut outcomes = run_steps(&first);
+            for rebuild in 1.. {
+                let result = if outcomes.iter().all(|o| o.success == Some(true)) {
+                    "succeeded"
+                } else {
+                    "failed"
+                };
+                context!(
+                    "Last run {result}, watching {} for changes (Ctrl-C to stop)",
+                    sources
+                        .iter()
+                        .map(|s| s.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                let changed = watcher.wait_for_changes();
+                let shown = changed[0].strip_prefix(&ws).unwrap_or(&changed[0]);
+                match changed.len() {
+                    1 => header!("Rebuild #{rebuild}: '{}' changed", shown.to_string_lossy()),
+                    n => header!(
+                        "Rebuild #{rebuild}: '{}' and {} more files changed",
+                        shown.to_string_lossy(),
+                        n - 1
+                    ),
+                }
+                outcomes = run_steps(&steps);
+            }
+        }
+        Verbs::Dev {
+            package,
+            skip_dependencies,
+            exe,
+            args,
+        } => {
+            let package = package_or(package.clone())
+                .or_else(exit_on_not_found)
+                .expect("should have exited");
+            if let Some(exe) = exe {
+                dev_executable(&ws, &config, &package, exe, *skip_dependencies, args);
+                exit(0);
+            }
+            let Some(dev) = config.dev.get(&package) else {
+                eprintln!("Configure the executable or launch file to run in [dev.{package}]");
+                exit(-1);
+            };
+            let sources = colcon_list(&ws, &["--packages-select", &package])
+                .into_iter()
+                .map(|(_, source)| source)
+                .collect::<Vec<_>>();
+            if sources.is_empty() {
+                eprintln!("Package '{package}' not found in the workspace");
+                exit(-1);
+            }
+            // Python modules are linked into the install folder, so most changes need no rebuild
+            config.symlink_install = true;
+            build_packages(
+                &ws,
+                &config,
+                vec![package.clone()],
+                *skip_dependencies,
+                &[],
+                Strategy::Split,
+            );
+            let ws_path = Path::new(&ws_str);
+            let setup = ws_path.join(bases::install()).join("setup.bash");
+            let watcher = watch::Watcher::new(&sources).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            let mut built = true;
+            for restart in 1.. {
+                let mut node = None;
+                if built {
+                    header!("Running '{package}'");
+                    let sourced = environment::sourced(&setup).unwrap_or_else(|e| {
+                        eprintln!("{e}");
+                        exit(-1);
+                    });
+                    let mut cmd = dev.command(&package, args).unwrap_or_else(|e| {
+                        eprintln!("{e}");
+                        exit(-1);
+                    });
+                    cmd.envs(sourced)
+                        .envs(simulation::asset_paths(ws_path, &config.asset_dirs));
+                    if commands_only() {
+                        print_command(&cmd);
+                        exit(0);
+                    }
+                    node = Some(dev::Node::start(cmd).unwrap_or_else(|e| {
+                        eprintln!("{e}");
+                        exit(-1);
+                    }));
+                }
+                context!(
+                    "Watching {} for changes (Ctrl-C to stop)",
+                    sources
+                        .iter()
+                        .map(|s| s.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                let changed = watcher.wait_for_changes();
+                let shown = changed[0].strip_prefix(&ws).unwrap_or(&changed[0]);
+                match changed.len() {
+                    1 => header!("Restart #{restart}: '{}' changed", shown.to_string_lossy()),
+                    n => header!(
+                        "Restart #{restart}: '{}' and {} more files changed",
+                        shown.to_string_lossy(),
+                        n - 1
+                    ),
+                }
+                if let Some(node) = node {
+                    node.stop();
+                }
+                if !built || dev::needs_rebuild(&changed) {
+                    header!("Building '{package}'");
+                    built = config
+                        .hooks
+                        .around_build(&ws, std::slice::from_ref(&package), || {
+                            ColconInvocation::new(&ws, false)
+                                .build(&config.build_output())
+                                .configure(&config.package)
+                                .overrides(&config.packages)
+                                .run(&What::ThisPackage(package.clone()))
+                        })
+                        .success();
+                    if !built {
+                        context!("Build failed, the node starts again once it builds");
+                    }
+                }
+            }
+        }
+        Verbs::Gantt { log, output } => {
+            let dir = log
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| gantt::timings_dir(Path::new(&ws_str)));
+            let jobs = gantt::load(&dir).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            let file = output
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| gantt::timings_dir(Path::new(&ws_str)).join("gantt.html"));
+            if let Err(e) = fs::write(&file, gantt::render("Build timings", &jobs)) {
+                eprintln!("Couldn't write '{}': {e}", file.to_string_lossy());
+                exit(-1);
+            }
+            header!("Critical path");
+            for job in gantt::critical_path(&jobs) {
+                println!("{:>8.1}s  {}", job.end - job.start, job.package);
+            }
+            context!("Wrote {}", file.to_string_lossy());
+        }
+        Verbs::Colcon { args } => {
+            header!("Running 'colcon {}'", args.join(" "));
+            let status = ColconInvocation::new(&ws, true)
+                .verb(args)
+                .environment(&config.package)
+                .run();
+            exit_on_error(status);
+        }
+
+        Verbs::List { json } => {
+            let src = Path::new(&ws_str).join("src");
+            let root = if src.is_dir() {
+                src
+            } else {
+                PathBuf::from(&ws_str)
+            };
+            let packages = package_xml::discover(&root);
+            let relative = |p: &Path| {
+                p.strip_prefix(&ws_str)
+                    .unwrap_or(p)
+                    .to_string_lossy()
+                    .to_string()
+            };
+            if *json {
+                let entries = packages
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "  {{\"name\": {}, \"path\": {}, \"build_type\": {}}}",
+                            json_string(&p.name),
+                            json_string(&relative(&p.path)),
+                            json_string(&p.build_type)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                if entries.is_empty() {
+                    println!("[]");
+                } else {
+                    println!("[\n{}\n]", entries.join(",\n"));
+                }
+            } else {
+                let name_width = packages.iter().map(|p| p.name.len()).max().unwrap_or(0);
+                let type_width = packages
+                    .iter()
+                    .map(|p| p.build_type.len())
+                    .max()
+                    .unwrap_or(0);
+                for p in &packages {
+                    println!(
+                        "{:<name_width$}  {:<type_width$}  {}",
+                        p.name,
+                        p.build_type,
+                        relative(&p.path)
+                    );
+                }
+            }
+        }
+        Verbs::Clean {
+            packages,
+            all,
+            trash,
+            build_only,
+            install_only,
+            cache_only,
+        } => {
+            if packages.iter().any(|p| p.is_empty()) {
+                eprintln!("Package argument must not be empty!",);
+            }
+            let workspace = Path::new(&ws_str);
+            if *all {
+                let mut paths = vec![];
+                if !install_only {
+                    paths.push(workspace.join(bases::build()));
+                }
+                if !build_only {
+                    paths.push(workspace.join(bases::install()));
+                }
+                header!("Cleaning up the workspace");
+                remove_paths(None, paths, *trash);
+            } else {
+                let packages = if packages.is_empty() {
+                    vec![package_or(None)
+                        .or_else(exit_on_not_found)
+                        .expect("should have exited")]
+                } else {
+                    matching_packages(&ws, packages)
+                };
+                if packages.is_empty() {
+                    exit(-1);
+                }
+                let scope = match (build_only, install_only, cache_only) {
+                    (true, _, _) => CleanScope::Build,
+                    (_, true, _) => CleanScope::Install,
+                    (_, _, true) => CleanScope::Cache,
+                    _ => CleanScope::All,
+                };
+                for package in packages {
+                    header!("Cleaning up '{package}'");
+                    clean_package(workspace, &package, scope, *trash);
+                }
+            }
+        }
+        Verbs::Rebuild {
+            packages,
+            skip_dependencies,
+            build_type,
+        } => {
+            let packages = if packages.is_empty() {
+                vec![package_or(None)
+                    .or_else(exit_on_not_found)
+                    .expect("should have exited")]
+            } else {
+                packages.clone()
+            };
+            for package in &packages {
+                header!("Cleaning up '{package}'");
+                clean_package(Path::new(&ws_str), package, CleanScope::All, false);
             }
             if let Some(t) = build_type {
                 config.package.build_type = t.clone();
             }
-            header!("Building '{package}'");
-            let status = ColconInvocation::new(&ws, false)
-                .build(&BuildOutput::default())
-                .configure(&config.package)
-                .run(&What::ThisPackage(package.clone()));
-            exit_on_error(status);
+            build_packages(
+                &ws,
+                &config,
+                packages,
+                *skip_dependencies,
+                &[],
+                Strategy::Split,
+            );
         }
 
-        Verbs::Test {
+        Verbs::Debug {
             package,
-            test,
-            direct,
+            target,
+            remote,
+            sysroot,
             skip_rebuild,
-            rebuild_dependencies,
         } => {
             let package = package_or(package.clone())
                 .or_else(exit_on_not_found)
                 .expect("should have exited");
-            if *rebuild_dependencies && !skip_rebuild {
-                header!("Building dependencies for '{}'", package);
-                let status = ColconInvocation::new(&ws, false)
-                    .build(&BuildOutput::default())
-                    .configure(&config.upstream)
-                    .run(&What::DependenciesFor(package.clone()));
-                exit_on_error(status);
-                if test.is_some() {
-                    header!("Building '{package}'");
-                    let status = ColconInvocation::new(&ws, false)
-                        .build(&BuildOutput::default())
-                        .configure(&config.package)
-                        .run(&What::ThisPackage(package.clone()));
+            let cargo_source = colcon_list(&ws, &["--packages-select", &package])
+                .pop()
+                .map(|(_, source)| source)
+                .filter(|source| cargo::is_cargo_package(source));
+            let build_dir = Path::new(&ws_str).join(bases::build()).join(&package);
+            let program = if let Some(source) = &cargo_source {
+                if !skip_rebuild {
+                    header!("Building '{target}' in '{package}' with cargo");
+                    let status =
+                        config
+                            .hooks
+                            .around_build(&ws, std::slice::from_ref(&package), || {
+                                cargo::build(&ws, &package, source, target)
+                            });
                     exit_on_error(status);
                 }
-            }
-            if !skip_rebuild {
-                if let Some(test) = test {
-                    header!("Building test '{test}' in '{package}'");
-                    let status = ninja_build_target(&ws, &package, test);
+                let executable = build_dir.join("debug").join(target);
+                vec![executable.to_string_lossy().to_string()]
+            } else {
+                let resolved = targets::resolve(&ws, &package, target);
+                let Some(target) = resolved.build.clone() else {
+                    eprintln!("'{target}' isn't built by a target in '{package}'");
+                    exit(-1);
+                };
+                if !skip_rebuild {
+                    header!("Building '{target}' in '{package}'");
+                    let status =
+                        config
+                            .hooks
+                            .around_build(&ws, std::slice::from_ref(&package), || {
+                                build_target(&ws, &package, &target)
+                            });
                     exit_on_error(status);
+                }
+                remote_program(&build_dir, &resolved)
+            };
+            header!("Debugging '{target}' on '{remote}'");
+            let status = debug_remote(&ws_str, &program, remote, sysroot.as_deref());
+            exit_on_error(status);
+        }
+
+        Verbs::Pin { package } | Verbs::Unpin { package } => {
+            let pin = matches!(cli.verb, Verbs::Pin { .. });
+            if pin && !config.pinned.contains(package) {
+                config.pinned.push(package.clone());
+            } else if !pin {
+                config.pinned.retain(|p| p != package);
+            }
+            if let Err(e) = save_config(&cfg_file_path, &config) {
+                eprintln!("Couldn't update '{}': {e}", cfg_file_path.to_string_lossy());
+                exit(-1);
+            }
+            if pin {
+                context!("Pinned '{package}'");
+            } else {
+                context!("Unpinned '{package}'");
+            }
+        }
+
+        Verbs::Underlay {
+            action: UnderlayAction::Fetch { manifest_url },
+        } => {
+            header!("Fetching underlay from '{manifest_url}'");
+            let ws_path = Path::new(&ws_str);
+            let dir = underlay::fetch(ws_path, manifest_url).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            let relative = dir
+                .strip_prefix(ws_path)
+                .unwrap_or(&dir)
+                .to_string_lossy()
+                .to_string();
+            if !config.underlays.contains(&relative) {
+                config.underlays.push(relative);
+            }
+            if let Err(e) = save_config(&cfg_file_path, &config) {
+                eprintln!("Couldn't update '{}': {e}", cfg_file_path.to_string_lossy());
+                exit(-1);
+            }
+            context!("Added underlay {}", dir.to_string_lossy());
+        }
+
+        Verbs::Cache { action } => {
+            let Some(remote) = config.cache.remote.as_deref().map(cache::Remote::parse) else {
+                eprintln!("No remote cache configured, set cache.remote in {COLB_CONFIG_FILENAME}");
+                exit(-1);
+            };
+            let cache = InstallCache::new(config.cache.directory.as_deref());
+            match action {
+                CacheAction::Push {} => {
+                    header!("Pushing install cache");
+                    let pushed = cache.push_all(&remote);
+                    context!("Pushed {pushed} entries");
+                }
+                CacheAction::Pull { package } => {
+                    let package = package_or(package.clone())
+                        .or_else(exit_on_not_found)
+                        .expect("should have exited");
+                    header!("Pulling install cache for dependencies of '{package}'");
+                    let mut failed = false;
+                    for (dep, key) in dependency_keys(&ws, &config, std::slice::from_ref(&package))
+                    {
+                        match cache.pull(&key, &remote) {
+                            Ok(true) => context!("'{dep}' is available"),
+                            Ok(false) => context!("'{dep}' is not in the remote cache"),
+                            Err(e) => {
+                                eprintln!("Couldn't pull '{dep}': {e}");
+                                failed = true;
+                            }
+                        }
+                    }
+                    if failed {
+                        exit(-1);
+                    }
+                }
+            }
+        }
+
+        Verbs::Service { action } => {
+            let status = match action {
+                ServiceAction::Install {
+                    package,
+                    executable,
+                    name,
+                    args,
+                } => {
+                    let name = name.as_deref().unwrap_or(executable);
+                    header!("Installing service '{name}'");
+                    service::install(Path::new(&ws_str), name, package, executable, args)
+                }
+                ServiceAction::Uninstall { name } => {
+                    header!("Removing service '{name}'");
+                    service::uninstall(name)
+                }
+                ServiceAction::Restart { name } => Ok(service::systemctl(&["restart", name])),
+                ServiceAction::Status { name } => Ok(service::systemctl(&["status", name])),
+                ServiceAction::Logs { name, follow } => Ok(service::logs(name, *follow)),
+            };
+            let status = status.unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            exit_on_error(status);
+        }
+        Verbs::Image {
+            action: ImageAction::Build { base, tag },
+        } => {
+            let base = base
+                .clone()
+                .or_else(|| env::var("ROS_DISTRO").ok().map(|d| format!("ros:{d}")))
+                .unwrap_or_else(|| {
+                    eprintln!("No ROS distro sourced, give the image to build on with --base");
+                    exit(-1);
+                });
+            let tag = tag
+                .clone()
+                .unwrap_or_else(|| image::default_tag(Path::new(&ws_str)));
+            header!("Building image '{tag}' on '{base}'");
+            let status = image::build(Path::new(&ws_str), &base, &tag).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            exit_on_error(status);
+        }
+        Verbs::Sysroot { action } => {
+            let ws_path = Path::new(&ws_str);
+            let (target, source) = match action {
+                SysrootAction::Create {
+                    target,
+                    from,
+                    suite,
+                    arch,
+                    mirror,
+                } => (
+                    target,
+                    sysroot::Source {
+                        from: from.clone(),
+                        suite: suite.clone(),
+                        arch: arch.clone(),
+                        mirror: mirror.clone(),
+                    },
+                ),
+                SysrootAction::Update { target } => match sysroot::load_source(ws_path, target) {
+                    Some(source) => (target, source),
+                    None => {
+                        eprintln!("No sysroot '{target}' to update, create it first");
+                        exit(-1);
+                    }
+                },
+            };
+            header!("Assembling sysroot '{target}'");
+            let status = sysroot::create(ws_path, target, &source);
+            exit_on_error(status);
+            context!(
+                "Use it by setting sysroot = \"{target}\" in {}",
+                COLB_CONFIG_FILENAME
+            );
+        }
+
+        Verbs::Alias { shell } => {
+            print!("{}", shell_aliases(&config.aliases, *shell));
+        }
+
+        Verbs::Doctor {} => {
+            if !doctor(&config) {
+                exit(-1);
+            }
+        }
+        Verbs::Size { package, symbols } => {
+            let package = package_or(package.clone())
+                .or_else(exit_on_not_found)
+                .expect("should have exited");
+            let workspace = Path::new(&ws_str);
+            let Some(sizes) = package_sizes(workspace, &package) else {
+                eprintln!("'{package}' isn't installed, build it first");
+                exit(-1);
+            };
+            let previous = size::previous(workspace, &package, &sizes);
+            header!("Sizes of '{package}'");
+            if previous.is_none() {
+                context!("No earlier build recorded, sizes are recorded by `colb build`");
+            }
+            let mut rows = sizes.iter().collect::<Vec<_>>();
+            rows.sort_by_key(|(path, bytes)| (std::cmp::Reverse(**bytes), *path));
+            let size_event = |path: &str, bytes: Option<u64>| {
+                let before = previous.as_ref().map(|p| p.get(path));
+                event(
+                    "size",
+                    &[
+                        ("package", json_string(&package)),
+                        ("path", json_string(path)),
+                        ("bytes", bytes.map_or("null".into(), |b| b.to_string())),
+                        (
+                            "previous",
+                            before.flatten().map_or("null".into(), |b| b.to_string()),
+                        ),
+                    ],
+                );
+            };
+            for (path, bytes) in &rows {
+                if json() {
+                    size_event(path, Some(**bytes));
+                    continue;
+                }
+                let change = match previous.as_ref().map(|p| p.get(*path)) {
+                    Some(Some(before)) if before != *bytes => size::growth(*before, **bytes),
+                    Some(None) => "new".into(),
+                    _ => String::new(),
+                };
+                println!("{:>10}  {change:<20}  {path}", size::human(**bytes));
+            }
+            for path in previous.iter().flat_map(|p| p.keys()) {
+                if sizes.contains_key(path) {
+                    continue;
+                }
+                if json() {
+                    size_event(path, None);
                 } else {
-                    header!("Building '{package}'");
-                    let status = ColconInvocation::new(&ws, false)
-                        .build(&BuildOutput::default())
-                        .configure(&config.package)
-                        .run(&What::ThisPackage(package.clone()));
-                    exit_on_error(status);
+                    println!("{:>10}  {:<20}  {path}", "", "removed");
                 }
             }
-            if let Some(test) = test {
-                header!("Running test '{test}' in '{package}'");
-                if *direct {
-                    let status = run_single_ctest(&ws, &package, test);
-                    exit_on_error(status);
-                    return;
+            let total = sizes.values().sum::<u64>();
+            let change = previous
+                .as_ref()
+                .map(|p| p.values().sum::<u64>())
+                .filter(|before| *before != total)
+                .map(|before| size::growth(before, total))
+                .unwrap_or_default();
+            if !json() {
+                println!("{:>10}  {change:<20}  total", size::human(total));
+            }
+            if let Some(count) = symbols {
+                for (path, _) in rows {
+                    header!("Largest symbols in '{path}'");
+                    let found = size::largest_symbols(
+                        &bases::package_install(workspace, &package).join(path),
+                        *count,
+                    );
+                    if found.is_empty() {
+                        context!("No symbols, the binary may be stripped");
+                    }
+                    for (bytes, symbol) in found {
+                        if json() {
+                            event(
+                                "symbol",
+                                &[
+                                    ("package", json_string(&package)),
+                                    ("path", json_string(path)),
+                                    ("symbol", json_string(&symbol)),
+                                    ("bytes", bytes.to_string()),
+                                ],
+                            );
+                        } else {
+                            println!("{:>10}  {symbol}", size::human(bytes));
+                        }
+                    }
                 }
+            }
+        }
+        Verbs::VerifyInstall {
+            packages,
+            relocatable,
+        } => {
+            let workspace = Path::new(&ws_str);
+            let install = workspace.join(bases::install());
+            let merged = bases::is_merged(&install);
+            let packages = if packages.is_empty() {
+                colcon_list(&ws, &[])
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| merged || install.join(name).is_dir())
+                    .collect()
+            } else {
+                packages.clone()
+            };
+            let setup = install.join("setup.bash");
+            let env = if setup.exists() {
+                environment::sourced(&setup).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    exit(-1);
+                })
             } else {
-                header!("Running tests for '{package}'");
+                vec![]
+            };
+            let mut problems = 0;
+            for package in &packages {
+                header!("Verifying the install of '{package}'");
+                let paths = if merged {
+                    merged_install_files(workspace, package).unwrap_or_default()
+                } else {
+                    vec![install.join(package)]
+                };
+                if !paths.iter().any(|p| fs::symlink_metadata(p).is_ok()) {
+                    context!("Not installed");
+                    continue;
+                }
+                let mut found = verify::check(&paths, &env);
+                if *relocatable {
+                    let build = workspace.join(bases::build());
+                    found.extend(verify::non_relocatable(&paths, workspace, &build));
+                }
+                if found.is_empty() {
+                    context!("No problems found");
+                }
+                for problem in &found {
+                    let path = problem.path.strip_prefix(&install).unwrap_or(&problem.path);
+                    if json() {
+                        event(
+                            "install_problem",
+                            &[
+                                ("package", json_string(package)),
+                                ("path", json_string(&path.to_string_lossy())),
+                                ("problem", json_string(&problem.what)),
+                            ],
+                        );
+                    } else {
+                        println!("{}: {}", path.to_string_lossy(), problem.what);
+                    }
+                }
+                problems += found.len();
             }
-            let status = ColconInvocation::new(&ws, true)
-                .test(&TestConfiguration {
-                    package: package.clone(),
-                    test: test.clone(),
-                    event_handlers: EventHandlers::silent(),
+            if problems > 0 {
+                eprintln!("Found {problems} problems in {}", install.to_string_lossy());
+                exit(-1);
+            }
+        }
+        Verbs::Report { format, file } => {
+            header!("Generating workspace report");
+            let packages = colcon_list(&ws, &[]);
+            let sections = report::generate(Path::new(&ws_str), &packages);
+            let title = format!("Workspace report for {ws_str}");
+            let rendered = report::render(&title, &sections, *format);
+            match file {
+                Some(file) => {
+                    if let Err(e) = fs::write(file, rendered) {
+                        eprintln!("Couldn't write '{file}': {e}");
+                        exit(-1);
+                    }
+                    context!("Wrote {file}");
+                }
+                None => print!("{rendered}"),
+            }
+        }
+
+        Verbs::Results {
+            action: ResultsAction::List {},
+        } => {
+            header!("Recorded test runs");
+            print!("{}", results::list(Path::new(&ws)));
+        }
+        Verbs::Results {
+            action: ResultsAction::Diff { a, b },
+        } => {
+            let load = |id: &str| {
+                results::load(Path::new(&ws), id).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    exit(-1);
                 })
-                .run();
+            };
+            let (a_id, a) = load(a);
+            let (b_id, b) = load(b);
+            header!("Changes from run {a_id} to run {b_id}");
+            print!("{}", results::diff((a_id, &a), (b_id, &b)));
+        }
+        Verbs::Prefetch { packages, no_pull } => {
+            let src = Path::new(&ws_str).join("src");
+            if !no_pull && src.is_dir() {
+                header!("Pulling sources");
+                let mut cmd = environment::tool("vcs");
+                cmd.arg("pull").arg(&src);
+                print_command(&cmd);
+                let status = run(&mut cmd).expect("'vcs' not found, install vcstool");
+                exit_on_error(status);
+            }
+            // Only the dependencies are built, but they may need what the hook generates
+            exit_on_error(config.hooks.pre_build(&ws, packages));
+            header!("Building dependencies of '{}'", packages.join("', '"));
+            let status = build_dependencies(&ws, &config, packages, &[]);
             exit_on_error(status);
-            header!("Test results for '{package}'");
-            let status = ColconInvocation::new(&ws, false)
-                .test_result(&TestResultConfig {
-                    package: package.clone(),
-                    verbose: true,
-                    all: true,
-                })
-                .run();
+            if !commands_only() {
+                let keys = dependency_keys(&ws, &config, packages);
+                if let Err(e) = cache::store_fingerprint(Path::new(&ws_str), &keys) {
+                    eprintln!("Couldn't record the dependency fingerprint: {e}");
+                    exit(-1);
+                }
+                context!("Recorded the fingerprint of {} dependencies", keys.len());
+            }
+        }
+        Verbs::PrefetchDeps {
+            packages,
+            skip_dependencies,
+        } => {
+            let packages = if packages.is_empty() {
+                vec![package_or(None)
+                    .or_else(exit_on_not_found)
+                    .expect("should have exited")]
+            } else {
+                packages.clone()
+            };
+            let Some(dir) = &config.package.fetchcontent_dir else {
+                eprintln!("Set fetchcontent_dir in [upstream] and [package], so builds find the downloads");
+                exit(-1);
+            };
+            if config.upstream.fetchcontent_dir.is_none() {
+                eprintln!("Only [package] sets fetchcontent_dir, dependencies will download again");
+            }
+            // Configuring needs the generated sources as well, so the hook runs either way
+            exit_on_error(config.hooks.pre_build(&ws, &packages));
+            if !skip_dependencies {
+                header!("Building dependencies of '{}'", packages.join("', '"));
+                let status = build_dependencies(&ws, &config, &packages, &[]);
+                exit_on_error(status);
+            }
+            header!("Configuring '{}'", packages.join("', '"));
+            context!(
+                "Downloading into {}",
+                Path::new(&ws_str).join(dir).to_string_lossy()
+            );
+            let mut verb = ColconInvocation::new(&ws, false).build(&config.build_output());
+            // Building a target that doesn't exist skips the build and the install after configuring
+            verb.args.args([
+                "--cmake-target",
+                "colb_configure_only",
+                "--cmake-target-skip-unavailable",
+            ]);
+            let status = verb
+                .configure(&config.package)
+                .overrides(&config.packages)
+                .run(&What::ThesePackages(packages));
             exit_on_error(status);
         }
-
-        Verbs::Clean { package } => {
-            if package.is_empty() {
-                eprintln!("Package argument must not be empty!",);
+        Verbs::Pipeline { name } => {
+            let Some(steps) = config.pipelines.get(name) else {
+                eprintln!("There is no pipeline '{name}', configured are:");
+                for name in config.pipelines.keys() {
+                    eprintln!("  {name}");
+                }
+                exit(-1);
+            };
+            let outcomes = pipeline::run(steps, &global_args(&cli, &ws)).unwrap_or_else(|e| {
+                eprintln!("Pipeline '{name}': {e}");
+                exit(-1);
+            });
+            header!("Pipeline '{name}'");
+            print!("{}", pipeline::summary(&outcomes));
+            if outcomes.iter().any(|o| o.success == Some(false)) {
+                exit(-1);
+            }
+        }
+        Verbs::EnvDiff { a, b } => {
+            header!("Environment changes");
+            match env_history::diff(Path::new(&ws_str), *a, *b) {
+                Ok(diff) => print!("{diff}"),
+                Err(e) => {
+                    eprintln!("{e}");
+                    exit(-1);
+                }
+            }
+        }
+        Verbs::Stats {
+            action: StatsAction::Usage { reset },
+        } => {
+            let file = usage::usage_file();
+            if *reset {
+                if file.exists() {
+                    if let Err(e) = fs::remove_file(&file) {
+                        eprintln!("Couldn't remove '{}': {e}", file.to_string_lossy());
+                        exit(-1);
+                    }
+                }
+                context!("Removed recorded usage");
+            } else {
+                header!("Usage");
+                context!("Recorded in {}", file.to_string_lossy());
+                match usage::summary() {
+                    Some(summary) => print!("{summary}"),
+                    None => context!("Nothing recorded yet"),
+                }
+                if !config.usage_stats {
+                    context!("Recording is disabled by usage_stats = false");
+                }
+            }
+        }
+        Verbs::Version { verbose } => {
+            println!("colb {}", env!("CARGO_PKG_VERSION"));
+            if *verbose {
+                for (name, version) in versions::tool_versions() {
+                    println!("{name}: {version}");
+                }
+            }
+        }
+        Verbs::SelfUpdate { check } => {
+            let url = config.self_update.url.trim_end_matches('/');
+            header!("Checking {url} for updates");
+            let available = update::update_available(url).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(-1);
+            });
+            if !available {
+                context!("colb is up to date");
+            } else if *check {
+                context!("An update is available");
+            } else {
+                header!("Updating colb");
+                match update::self_update(url, config.self_update.public_key.as_deref()) {
+                    Ok(exe) => context!("Replaced {}", exe.to_string_lossy()),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        exit(-1);
+                    }
+                }
             }
-            header!("Cleaning up '{package}'");
-            clean_package(Path::new(&ws_str), package)
+        }
+        Verbs::Completions { .. } | Verbs::CompletePackages {} => {
+            unreachable!("handled before loading the config")
         }
 
-        Verbs::Config {} => match std::env::var("EDITOR") {
-            Ok(editor) => match Command::new(&editor).arg(cfg_file_path).status() {
+        Verbs::Config {
+            action: Some(ConfigAction::Doctor {}),
+        } => {
+            header!("Checking the configuration");
+            let findings = config_doctor::check(&config);
+            for finding in &findings {
+                eprintln!("warning: {}\n  fix: {}", finding.warning, finding.fix);
+            }
+            if !findings.is_empty() {
+                exit(-1);
+            }
+            context!("No problems found");
+        }
+        Verbs::Config {
+            action: Some(ConfigAction::Diff { a, b }),
+        } => {
+            let args = |name: &str| {
+                let (config, base) = match name {
+                    "upstream" => (config.upstream.clone(), None),
+                    "package" => (config.package.clone(), None),
+                    _ => match config.profile.get(name) {
+                        Some(profile) => (
+                            profile.settings.apply(&config.package),
+                            Some(PathBuf::from(profile.base(name))),
+                        ),
+                        None => {
+                            eprintln!("'{name}' is neither upstream, package nor a profile, configured are:");
+                            for name in config.profile.keys() {
+                                eprintln!("  {name}");
+                            }
+                            exit(-1);
+                        }
+                    },
+                };
+                let (build, install) = match base {
+                    Some(base) => (base.join("build"), base.join("install")),
+                    None => (bases::build(), bases::install()),
+                };
+                let mut res = vec![
+                    "--build-base".to_string(),
+                    build.to_string_lossy().to_string(),
+                    "--install-base".to_string(),
+                    install.to_string_lossy().to_string(),
+                ];
+                res.extend(config.args(&ws).iter().cloned());
+                res
+            };
+            header!("Differences between the colcon arguments of '{a}' and '{b}'");
+            let diff = config_diff::diff(&args(a), &args(b));
+            if diff.is_empty() {
+                context!("None");
+            }
+            print!("{diff}");
+        }
+        Verbs::Config { action: None } => match std::env::var("EDITOR") {
+            Ok(editor) => match environment::tool(&editor).arg(cfg_file_path).status() {
                 Ok(s) => {
                     if let Some(code) = s.code() {
-                        std::process::exit(code);
+                        exit(code);
                     }
-                    std::process::exit(-1);
+                    exit(-1);
                 }
                 Err(e) => {
                     eprintln!("Couldn't run $EDITOR '{editor}': {e}");
-                    std::process::exit(-1);
+                    exit(-1);
                 }
             },
             Err(e) => {
                 eprintln!("Couldn't read $EDITOR: {e}");
-                std::process::exit(-1);
+                exit(-1);
             }
         },
     }
+    exit(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults(verb: &str, table: &str) -> BTreeMap<String, toml::Table> {
+        BTreeMap::from([(verb.to_string(), toml::from_str(table).unwrap())])
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn apply(line: &str, defaults: &BTreeMap<String, toml::Table>) -> Option<Vec<String>> {
+        let matches = cli::command().try_get_matches_from(args(line)).unwrap();
+        with_defaults(&matches, defaults, args(line)).unwrap()
+    }
+
+    #[test]
+    fn defaults_go_before_colcon_arguments() {
+        let defaults = defaults("build", "skip_tests = true");
+        assert_eq!(
+            apply("colb build pkg -- --cmake-args -DX=1", &defaults),
+            Some(args("colb build --skip-tests pkg -- --cmake-args -DX=1"))
+        );
+    }
+
+    #[test]
+    fn defaults_skip_global_option_values() {
+        let defaults = defaults("build", "skip_tests = true");
+        assert_eq!(
+            apply("colb --workspace build build pkg", &defaults),
+            Some(args("colb --workspace build build --skip-tests pkg"))
+        );
+    }
+
+    #[test]
+    fn default_values_are_joined_to_their_flags() {
+        let defaults = defaults("test", "rebuild_dependencies = \"auto\"");
+        let args = apply("colb test pkg", &defaults).unwrap();
+        assert_eq!(
+            args,
+            ["colb", "test", "--rebuild-dependencies=auto", "pkg"].map(String::from)
+        );
+        assert!(cli::command().try_get_matches_from(args).is_ok());
+    }
+
+    #[test]
+    fn explicit_flags_win_over_defaults() {
+        let defaults = defaults("build", "skip_tests = true");
+        assert_eq!(apply("colb build --skip-tests pkg", &defaults), None);
+    }
 }
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+/// How one cell of the matrix turned out
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Passed,
+    BuildFailed,
+    TestsFailed,
+}
+
+impl Outcome {
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::Passed => "passed",
+            Outcome::BuildFailed => "build failed",
+            Outcome::TestsFailed => "tests failed",
+        }
+    }
+}
+
+/// C and C++ compiler for a `compilers` entry, either `cc:c++` or a C compiler like `gcc-12`
+pub fn compilers(entry: &str) -> (String, String) {
+    if let Some((cc, cxx)) = entry.split_once(':') {
+        return (cc.into(), cxx.into());
+    }
+    let cxx = if entry.contains("clang") {
+        entry.replacen("clang", "clang++", 1)
+    } else if entry.contains("gcc") {
+        entry.replacen("gcc", "g++", 1)
+    } else {
+        entry.into()
+    };
+    (entry.into(), cxx)
+}
+
+/// Name of a combination, also used as its folder below `.colb/matrix`
+pub fn name(parts: &[Option<&str>]) -> String {
+    let parts = parts
+        .iter()
+        .flatten()
+        .map(|p| {
+            let p = p.split(':').next().unwrap_or(p);
+            let p = Path::new(p)
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or(p.into());
+            p.to_lowercase()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || "+-._".contains(c) {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "default".into()
+    } else {
+        parts.join("-")
+    }
+}
+
+/// A plain text table with one row per build and one column per test setup
+pub fn grid(rows: &[String], columns: &[String], cells: &[Vec<Outcome>]) -> String {
+    let first = rows.iter().map(String::len).max().unwrap_or(0);
+    let widths = columns
+        .iter()
+        .map(|c| c.len().max(Outcome::BuildFailed.label().len()))
+        .collect::<Vec<_>>();
+    let mut lines = vec![];
+    let mut line = format!("{:first$}", "");
+    for (column, width) in columns.iter().zip(&widths) {
+        line.push_str(&format!("  {column:width$}"));
+    }
+    lines.push(line);
+    for (row, outcomes) in rows.iter().zip(cells) {
+        let mut line = format!("{row:first$}");
+        for (outcome, width) in outcomes.iter().zip(&widths) {
+            line.push_str(&format!("  {:width$}", outcome.label()));
+        }
+        lines.push(line);
+    }
+    lines
+        .iter()
+        .map(|l| format!("{}\n", l.trim_end()))
+        .collect()
+}
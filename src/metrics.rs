@@ -0,0 +1,182 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    process::Stdio,
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{environment, extends};
+
+/// Where to push the duration and outcome of builds and test runs, disabled if neither is set
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct MetricsConfig {
+    /// Prometheus pushgateway, e.g. `http://pushgateway:9091`
+    pub pushgateway: Option<String>,
+    /// OTLP/HTTP endpoint of an OpenTelemetry collector, e.g. `http://collector:4318`
+    pub otlp: Option<String>,
+    /// Labels added to every metric, e.g. `team = "perception"`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl MetricsConfig {
+    pub fn enabled(&self) -> bool {
+        self.pushgateway.is_some() || self.otlp.is_some()
+    }
+}
+
+/// The `[metrics]` of the user config, so one user pushes the same metrics from every workspace
+pub fn user_config() -> Result<MetricsConfig, String> {
+    let Some(mut user) = extends::load_user()? else {
+        return Ok(MetricsConfig::default());
+    };
+    match user.remove("metrics") {
+        Some(metrics) => metrics.try_into().map_err(|e| {
+            format!(
+                "Invalid [metrics] in '{}': {e}",
+                extends::user_config_file().to_string_lossy()
+            )
+        }),
+        None => Ok(MetricsConfig::default()),
+    }
+}
+
+/// Run being measured: when it started, the verb and where to push the result
+static CURRENT: OnceLock<(Instant, String, MetricsConfig)> = OnceLock::new();
+
+/// Push metrics for this run when colb exits, see [`finish`]
+pub fn arm(verb: &str, config: &MetricsConfig) {
+    let _ = CURRENT.set((Instant::now(), verb.into(), config.clone()));
+}
+
+pub fn hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or("unknown".into())
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Escape a value for a JSON string or a Prometheus label
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The metrics in the Prometheus text format, the host and verb identify the group in the pushgateway
+fn prometheus(labels: &BTreeMap<String, String>, seconds: f64, code: i32) -> String {
+    let labels = labels
+        .iter()
+        .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "# TYPE colb_run_duration_seconds gauge\n\
+         colb_run_duration_seconds{{{labels}}} {seconds:.3}\n\
+         # TYPE colb_run_success gauge\n\
+         colb_run_success{{{labels}}} {}\n\
+         # TYPE colb_run_exit_code gauge\n\
+         colb_run_exit_code{{{labels}}} {code}\n\
+         # TYPE colb_run_timestamp_seconds gauge\n\
+         colb_run_timestamp_seconds{{{labels}}} {}\n",
+        i32::from(code == 0),
+        now().as_secs(),
+    )
+}
+
+/// The metrics as an OTLP/HTTP JSON export request
+fn otlp(labels: &BTreeMap<String, String>, seconds: f64, code: i32) -> String {
+    let attributes = labels
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{{\"key\":\"{}\",\"value\":{{\"stringValue\":\"{}\"}}}}",
+                escape(k),
+                escape(v)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let time = now().as_nanos();
+    let gauge = |name: &str, unit: &str, value: String| {
+        format!(
+            "{{\"name\":\"{name}\",\"unit\":\"{unit}\",\"gauge\":{{\"dataPoints\":[\
+             {{\"attributes\":[{attributes}],\"timeUnixNano\":\"{time}\",{value}}}]}}}}"
+        )
+    };
+    let metrics = [
+        gauge(
+            "colb.run.duration",
+            "s",
+            format!("\"asDouble\":{seconds:.3}"),
+        ),
+        gauge(
+            "colb.run.success",
+            "1",
+            format!("\"asInt\":\"{}\"", i32::from(code == 0)),
+        ),
+        gauge("colb.run.exit_code", "1", format!("\"asInt\":\"{code}\"")),
+    ];
+    format!(
+        "{{\"resourceMetrics\":[{{\"resource\":{{\"attributes\":[\
+         {{\"key\":\"service.name\",\"value\":{{\"stringValue\":\"colb\"}}}}]}},\
+         \"scopeMetrics\":[{{\"scope\":{{\"name\":\"colb\"}},\"metrics\":[{}]}}]}}]}}",
+        metrics.join(",")
+    )
+}
+
+/// POST the body with curl, failing after a few seconds
+pub fn post(url: &str, content_type: &str, body: &str) -> Result<(), String> {
+    let status = environment::tool("curl")
+        .args(["-fsS", "--max-time", "5", "-o", "/dev/null"])
+        .args(["-H", &format!("Content-Type: {content_type}")])
+        .args(["--data-binary", body, url])
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|e| format!("Couldn't run 'curl': {e}"))?;
+    if !status.success() {
+        return Err(format!("Couldn't push metrics to {url}"));
+    }
+    Ok(())
+}
+
+/// Push the duration and exit code of the run, if metrics were armed
+pub fn finish(code: i32) {
+    let Some((started, verb, config)) = CURRENT.get() else {
+        return;
+    };
+    let seconds = started.elapsed().as_secs_f64();
+    let host = hostname();
+    let mut labels = config.labels.clone();
+    labels.insert("verb".into(), verb.clone());
+    labels.insert("host".into(), host.clone());
+    // Pushing is best effort, never fail the actual command because of it
+    if let Some(url) = &config.pushgateway {
+        let url = format!(
+            "{}/metrics/job/colb/host/{host}/verb/{verb}",
+            url.trim_end_matches('/')
+        );
+        let body = prometheus(&config.labels, seconds, code);
+        if let Err(e) = post(&url, "text/plain; version=0.0.4", &body) {
+            eprintln!("{e}");
+        }
+    }
+    if let Some(url) = &config.otlp {
+        let url = format!("{}/v1/metrics", url.trim_end_matches('/'));
+        if let Err(e) = post(&url, "application/json", &otlp(&labels, seconds, code)) {
+            eprintln!("{e}");
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use std::env;
+
+/// Nothing listens on the discard port, so tools going through the proxy fail right away instead of timing out
+const DEAD_PROXY: &str = "http://127.0.0.1:9";
+
+/// Keeps FetchContent from updating or downloading, it only uses what is already in FETCHCONTENT_BASE_DIR
+pub const CMAKE_ARGS: &[&str] = &["-DFETCHCONTENT_FULLY_DISCONNECTED=ON"];
+
+/// Keep the tools colb starts from the network: HTTP(S) goes to a dead proxy,
+/// git only clones local repositories and pip, cargo and npm stay offline
+pub fn enable() {
+    for var in [
+        "http_proxy",
+        "https_proxy",
+        "HTTP_PROXY",
+        "HTTPS_PROXY",
+        "ALL_PROXY",
+    ] {
+        env::set_var(var, DEAD_PROXY);
+    }
+    for var in ["no_proxy", "NO_PROXY"] {
+        env::remove_var(var);
+    }
+    env::set_var("GIT_ALLOW_PROTOCOL", "file");
+    env::set_var("PIP_NO_INDEX", "1");
+    env::set_var("CARGO_NET_OFFLINE", "true");
+    env::set_var("npm_config_offline", "true");
+}
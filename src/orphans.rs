@@ -0,0 +1,87 @@
+use std::{env, path::Path, process, thread, time::Duration};
+
+use crate::{
+    environment,
+    preflight::{self, Process},
+};
+
+/// Marks the processes of a test run, inherited even by children that daemonize or change their process group
+const TAG: &str = "COLB_TEST_RUN";
+
+fn run_tag(workspace: &Path, pid: u32) -> String {
+    format!("{}:{pid}", workspace.to_string_lossy())
+}
+
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Mark everything started from now on as part of this test run
+pub fn tag(workspace: &Path) {
+    env::set_var(TAG, run_tag(workspace, process::id()));
+}
+
+/// Processes of earlier test runs in the workspace whose colb is gone, e.g. after Ctrl-C or without --kill-orphans
+pub fn from_earlier_runs(workspace: &Path) -> Vec<Process> {
+    let prefix = format!("{}:", workspace.to_string_lossy());
+    preflight::processes()
+        .into_iter()
+        .filter(|p| {
+            preflight::var(&p.environ, TAG)
+                .strip_prefix(&prefix)
+                .and_then(|pid| pid.parse().ok())
+                .is_some_and(|pid| !is_running(pid))
+        })
+        .collect()
+}
+
+/// Processes of this test run that are still running, after giving them a moment to shut down
+pub fn lingering(workspace: &Path) -> Vec<Process> {
+    let tag = run_tag(workspace, process::id());
+    let mut res = vec![];
+    for _ in 0..10 {
+        res = preflight::processes()
+            .into_iter()
+            .filter(|p| preflight::var(&p.environ, TAG) == tag)
+            .collect();
+        if res.is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    res
+}
+
+fn signal(signal: &str, pids: &[u32]) {
+    let _ = environment::tool("kill")
+        .arg(format!("-{signal}"))
+        .args(pids.iter().map(u32::to_string))
+        .output();
+}
+
+/// Terminate the processes, killing the ones that don't exit within two seconds
+pub fn kill(processes: &[Process]) {
+    let mut pids = processes.iter().map(|p| p.pid).collect::<Vec<_>>();
+    signal("TERM", &pids);
+    for _ in 0..10 {
+        pids.retain(|pid| is_running(*pid));
+        if pids.is_empty() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    signal("KILL", &pids);
+}
+
+/// List the processes, and kill them if `kill` is set
+pub fn report(processes: &[Process], kill_them: bool) {
+    for p in processes {
+        eprintln!("  {:>7}  {}", p.pid, p.cmdline);
+    }
+    if kill_them {
+        kill(processes);
+        context!("Stopped {} processes", processes.len());
+    } else {
+        context!("Stop them with --kill-orphans");
+    }
+}
@@ -0,0 +1,430 @@
+use anstyle::{AnsiColor, Color, Style};
+use colb::events::{self, Event, Progress};
+use std::{
+    env, fmt,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    os::unix::process::ExitStatusExt,
+    process::{Command, ExitStatus, Stdio},
+    sync::{Mutex, OnceLock},
+    thread,
+};
+
+pub const DECO: Style = Style::new().fg_color(Some(Color::Ansi(AnsiColor::BrightBlack)));
+pub const HEADER: Style = Style::new()
+    .bold()
+    .fg_color(Some(Color::Ansi(AnsiColor::BrightBlue)));
+const ERROR: Style = Style::new()
+    .bold()
+    .fg_color(Some(Color::Ansi(AnsiColor::BrightRed)));
+const WARNING: Style = Style::new().fg_color(Some(Color::Ansi(AnsiColor::Yellow)));
+
+pub use crate::cli::OutputMode;
+
+impl OutputMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputMode::Human => "human",
+            OutputMode::Porcelain => "porcelain",
+            OutputMode::Json => "json",
+        }
+    }
+}
+
+static MODE: OnceLock<OutputMode> = OnceLock::new();
+static COMMANDS_ONLY: OnceLock<bool> = OnceLock::new();
+static DRY_RUN: OnceLock<bool> = OnceLock::new();
+/// Every command printed so far, e.g. for repro bundles
+static HISTORY: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+pub fn set_mode(mode: OutputMode) {
+    let _ = MODE.set(mode);
+}
+
+/// Only print the commands that would run, as plain shell lines, instead of running them
+pub fn set_commands_only(commands_only: bool) {
+    let _ = COMMANDS_ONLY.set(commands_only);
+}
+
+/// Show what would run with the usual output, without running or removing anything
+pub fn set_dry_run(dry_run: bool) {
+    let _ = DRY_RUN.set(dry_run);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.get().copied().unwrap_or(false)
+}
+
+/// Whether commands are only printed, as plain shell lines or in a dry run, instead of run
+pub fn commands_only() -> bool {
+    plain_commands() || dry_run()
+}
+
+fn plain_commands() -> bool {
+    COMMANDS_ONLY.get().copied().unwrap_or(false)
+}
+
+fn mode() -> OutputMode {
+    MODE.get().copied().unwrap_or_default()
+}
+
+/// Whether colb's output is JSON events rather than decorations
+pub fn json() -> bool {
+    mode() == OutputMode::Json
+}
+
+pub fn use_color() -> bool {
+    match mode() {
+        OutputMode::Human => io::stdout().is_terminal(),
+        OutputMode::Porcelain => io::stderr().is_terminal(),
+        OutputMode::Json => false,
+    }
+}
+
+/// Print colb's own decorations to wherever the output mode wants them, JSON events replace them
+pub fn emit(args: fmt::Arguments) {
+    if plain_commands() {
+        return;
+    }
+    let _ = match mode() {
+        OutputMode::Human => io::stdout().write_fmt(args),
+        OutputMode::Porcelain => io::stderr().write_fmt(args),
+        OutputMode::Json => Ok(()),
+    };
+}
+
+/// Print an event as a line of JSON with `--output json`. The values of the fields are JSON
+/// already, e.g. from [`json_string`]
+pub fn event(name: &str, fields: &[(&str, String)]) {
+    if !json() || plain_commands() {
+        return;
+    }
+    let mut line = format!("{{\"event\":{}", json_string(name));
+    for (key, value) in fields {
+        line.push_str(&format!(",{}:{value}", json_string(key)));
+    }
+    line.push('}');
+    let mut out = io::stdout().lock();
+    let _ = writeln!(out, "{line}");
+    let _ = out.flush();
+}
+
+macro_rules! header {
+    ($($l:tt)*) => {
+        $crate::output::print_header(&format!($($l)*))
+    };
+}
+macro_rules! context {
+    ($($l:tt)*) => {
+        $crate::output::print_context(&format!($($l)*))
+    };
+}
+
+pub fn print_header(text: &str) {
+    event("header", &[("text", json_string(text))]);
+    if use_color() {
+        emit(format_args!(
+            "{DECO}┌[{DECO:#} {HEADER}{text}{HEADER:#} {DECO}]{DECO:#}\n"
+        ));
+    } else {
+        emit(format_args!("┌[ {text} ]\n"));
+    }
+}
+
+pub fn print_context(text: &str) {
+    event("context", &[("text", json_string(text))]);
+    if use_color() {
+        emit(format_args!("{DECO}└>{DECO:#} {text}\n"));
+    } else {
+        emit(format_args!("└> {text}\n"));
+    }
+}
+
+/// Quote `arg` for a POSIX shell, if necessary
+pub fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// `text` as a JSON string, including the quotes
+pub fn json_string(text: &str) -> String {
+    let mut res = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// The command as a line that can be pasted into a shell
+pub fn command_line(command: &Command) -> String {
+    let mut res = shell_quote(&command.get_program().to_string_lossy());
+    for arg in command.get_args() {
+        res.push(' ');
+        res.push_str(&shell_quote(&arg.to_string_lossy()));
+    }
+    res
+}
+
+/// The commands printed so far, as lines that can be pasted into a shell
+pub fn command_history() -> Vec<String> {
+    HISTORY.lock().map(|h| h.clone()).unwrap_or_default()
+}
+
+pub fn print_command(command: &Command) {
+    let line = match command.get_current_dir() {
+        Some(dir) => format!(
+            "cd {} && {}",
+            shell_quote(&dir.to_string_lossy()),
+            command_line(command)
+        ),
+        None => command_line(command),
+    };
+    if let Ok(mut history) = HISTORY.lock() {
+        history.push(line.clone());
+    }
+    if plain_commands() {
+        println!("{line}");
+        return;
+    }
+    let cwd = command
+        .get_current_dir()
+        .map_or("null".into(), |d| json_string(&d.to_string_lossy()));
+    event(
+        "command",
+        &[
+            ("command", json_string(&command_line(command))),
+            ("cwd", cwd),
+        ],
+    );
+    print_command_arrow(&command_line(command));
+    emit(format_args!("\n"));
+    if !dry_run() {
+        divider();
+    }
+}
+
+pub fn print_command_arrow(command: &str) {
+    if use_color() {
+        emit(format_args!("{DECO}└>{DECO:#} {}", command));
+    } else {
+        emit(format_args!("└> {}", command));
+    }
+}
+
+pub fn divider() {
+    if use_color() {
+        emit(format_args!(
+            "{DECO}[ \\ \\ \\{DECO:#} Output {DECO}/ / / ]{DECO:#}\n"
+        ));
+    } else {
+        emit(format_args!("[ \\ \\ \\ Output / / / ]\n"));
+    }
+}
+
+fn highlight(line: &str) -> Option<Style> {
+    let lower = line.to_lowercase();
+    if lower.starts_with("error") || lower.contains(" error:") || lower.contains("fatal error") {
+        Some(ERROR)
+    } else if lower.starts_with("warning") || lower.contains(" warning:") {
+        Some(WARNING)
+    } else {
+        None
+    }
+}
+
+/// The status of a failed command, for failures colb detects itself
+pub fn failure() -> ExitStatus {
+    ExitStatus::from_raw(1 << 8)
+}
+
+/// Report how a command exited as an event
+fn exited(cmd: &Command, status: &io::Result<ExitStatus>) {
+    let Ok(status) = status else {
+        return;
+    };
+    let code = status.code().map_or("null".into(), |c| c.to_string());
+    let signal = status.signal().map_or("null".into(), |s| s.to_string());
+    event(
+        "exit",
+        &[
+            ("command", json_string(&command_line(cmd))),
+            ("code", code),
+            ("signal", signal),
+        ],
+    );
+}
+
+/// Keep stdout for the events, the invoked tools write to stderr instead
+fn redirect_stdout(cmd: &mut Command) {
+    if json() {
+        cmd.stdout(io::stderr());
+    }
+}
+
+/// Run a command attached to the terminal, unless only commands are printed
+pub fn run_attached(cmd: &mut Command) -> io::Result<ExitStatus> {
+    if commands_only() {
+        return Ok(ExitStatus::from_raw(0));
+    }
+    redirect_stdout(cmd);
+    let status = cmd.status();
+    exited(cmd, &status);
+    status
+}
+
+/// Whether the stderr of the tools is colored, by them or by colb
+fn stderr_color() -> bool {
+    !json() && io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+/// Variables making tools color their output although colb pipes it: CMake, ninja and other
+/// tools following `CLICOLOR_FORCE`, the compilers through CMake, Python tools and cargo
+const FORCE_COLOR: &[(&str, &str)] = &[
+    ("CLICOLOR_FORCE", "1"),
+    ("CMAKE_COLOR_DIAGNOSTICS", "ON"),
+    ("FORCE_COLOR", "1"),
+    ("CARGO_TERM_COLOR", "always"),
+];
+
+/// Keep the colors of the tools whose output colb pipes, unless the user set the variables
+fn force_color(cmd: &mut Command) {
+    if !stderr_color() {
+        return;
+    }
+    for (name, value) in FORCE_COLOR {
+        let set = cmd.get_envs().any(|(k, _)| k == *name) || env::var_os(name).is_some();
+        if !set {
+            cmd.env(name, value);
+        }
+    }
+}
+
+/// `line` without ANSI escape sequences
+fn strip_ansi(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            res.push(c);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Pass the stderr of a command through, with errors and warnings highlighted unless the tool
+/// colored them already
+fn forward_stderr(stderr: impl io::Read + Send + 'static) -> thread::JoinHandle<()> {
+    let color = stderr_color();
+    thread::spawn(move || {
+        let mut out = io::stderr();
+        for line in BufReader::new(stderr).split(b'\n').map_while(Result::ok) {
+            let line = String::from_utf8_lossy(&line);
+            let colored = line.contains('\x1b');
+            let plain = if colored {
+                strip_ansi(&line)
+            } else {
+                line.to_string()
+            };
+            crate::explain::observe(&plain);
+            let style = highlight(&plain);
+            if style == Some(ERROR) {
+                crate::issue::observe_error(&plain);
+            }
+            let _ = match style.filter(|_| color && !colored) {
+                Some(style) => writeln!(out, "{style}{line}{style:#}"),
+                None => writeln!(out, "{line}"),
+            };
+        }
+    })
+}
+
+/// Run a command, passing its stderr through separately with errors and warnings highlighted
+pub fn run(cmd: &mut Command) -> io::Result<ExitStatus> {
+    if commands_only() {
+        return Ok(ExitStatus::from_raw(0));
+    }
+    redirect_stdout(cmd);
+    force_color(cmd);
+    cmd.stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    let forward = forward_stderr(child.stderr.take().expect("stderr is piped"));
+    let status = child.wait();
+    let _ = forward.join();
+    exited(cmd, &status);
+    status
+}
+
+/// Prints colcon's output as it would have, and its progress as `package` and `summary` events
+struct ColconProgress;
+
+impl Progress for ColconProgress {
+    fn on_event(&mut self, progress: &Event) {
+        let (state, package, time, code) = match progress {
+            Event::Started { package } => ("started", package, None, None),
+            Event::Finished { package, time } => ("finished", package, Some(time), None),
+            Event::Failed {
+                package,
+                time,
+                code,
+            } => ("failed", package, Some(time), *code),
+            Event::Aborted { package } => ("aborted", package, None, None),
+            Event::Summary { text } => {
+                event("summary", &[("text", json_string(text))]);
+                return;
+            }
+        };
+        event(
+            "package",
+            &[
+                ("package", json_string(package)),
+                ("state", json_string(state)),
+                ("time", time.map_or("null".into(), |t| json_string(t))),
+                ("code", code.map_or("null".into(), |c| c.to_string())),
+            ],
+        );
+    }
+
+    fn on_output(&mut self, line: &str) {
+        let _ = if json() {
+            writeln!(io::stderr(), "{line}")
+        } else {
+            writeln!(io::stdout(), "{line}")
+        };
+    }
+}
+
+/// Run a command like [`run`], following colcon's output on stdout through [`events::run`]
+pub fn run_colcon(cmd: &mut Command) -> io::Result<ExitStatus> {
+    if commands_only() {
+        return Ok(ExitStatus::from_raw(0));
+    }
+    force_color(cmd);
+    let (reader, writer) = io::pipe()?;
+    cmd.stderr(writer);
+    let forward = forward_stderr(reader);
+    let status = events::run(cmd, &mut ColconProgress);
+    // The command keeps its end of the pipe open until stderr is set again
+    cmd.stderr(Stdio::inherit());
+    let _ = forward.join();
+    exited(cmd, &status);
+    status
+}
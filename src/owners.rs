@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::{
+    metrics,
+    test_summary::{Case, Outcome},
+};
+
+/// Owners of the tests matching each regular expression, `tests.owners` in the configuration
+pub type Owners = BTreeMap<String, String>;
+
+/// The patterns that aren't valid regular expressions, with the reason
+pub fn invalid(owners: &Owners) -> Vec<(&str, String)> {
+    owners
+        .keys()
+        .filter_map(|p| Regex::new(p).err().map(|e| (p.as_str(), e.to_string())))
+        .collect()
+}
+
+/// Everyone owning the test, there may be several if more than one pattern matches. Invalid
+/// patterns match nothing
+pub fn of<'a>(owners: &'a Owners, test: &str) -> Vec<&'a str> {
+    let mut res = vec![];
+    for (pattern, owner) in owners {
+        let matches = Regex::new(pattern).is_ok_and(|r| r.is_match(test));
+        if matches && !res.contains(&owner.as_str()) {
+            res.push(owner.as_str());
+        }
+    }
+    res
+}
+
+/// Tell the owners of the failing tests through their webhooks, with a Slack-compatible message
+/// listing their tests. Failures without an owner or without a webhook for it are left out
+pub fn notify(
+    owners: &Owners,
+    webhooks: &BTreeMap<String, String>,
+    package: &str,
+    cases: &[&Case],
+) {
+    let mut failed: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for case in cases.iter().filter(|c| c.outcome == Outcome::Failed) {
+        for owner in of(owners, &case.name) {
+            failed.entry(owner).or_default().push(&case.name);
+        }
+    }
+    let host = metrics::hostname();
+    for (owner, tests) in failed {
+        let Some(url) = webhooks.get(owner) else {
+            continue;
+        };
+        let text = format!(
+            "{} {} of '{package}' owned by {owner} failed on {host}: {}",
+            tests.len(),
+            if tests.len() == 1 { "test" } else { "tests" },
+            tests.join(", ")
+        );
+        let body = format!("{{\"text\":\"{}\"}}", metrics::escape(&text));
+        // Best effort like the metrics, the test results are what matters
+        if metrics::post(url, "application/json", &body).is_err() {
+            eprintln!("Couldn't tell {owner} about the failing tests through {url}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Text of the first `<tag>` element in `content`, ignoring attributes
+fn element<'a>(content: &'a str, tag: &str) -> Option<&'a str> {
+    let start = content.find(&format!("<{tag}"))?;
+    let rest = &content[start + tag.len() + 1..];
+    if !rest.starts_with(['>', ' ', '\t', '\n']) {
+        return None;
+    }
+    let rest = &rest[rest.find('>')? + 1..];
+    let end = rest.find(&format!("</{tag}>"))?;
+    Some(rest[..end].trim())
+}
+
+/// The package name declared in the package.xml in `source`, which may differ from the folder name
+pub fn name(source: &Path) -> Option<String> {
+    let content = fs::read_to_string(source.join("package.xml")).ok()?;
+    element(&content, "name").map(String::from)
+}
+
+/// The build type exported by the package in `source`, e.g. `ament_cmake`
+pub fn build_type(source: &Path) -> Option<String> {
+    let content = fs::read_to_string(source.join("package.xml")).ok()?;
+    let export = element(&content, "export")?;
+    element(export, "build_type").map(String::from)
+}
+
+/// Tags of the dependencies a package is built against, everything but test and doc dependencies
+const BUILD_DEPENDENCY_TAGS: &[&str] = &[
+    "depend",
+    "build_depend",
+    "buildtool_depend",
+    "build_export_depend",
+    "buildtool_export_depend",
+    "exec_depend",
+    "run_depend",
+];
+
+/// The build and runtime dependencies declared in the package.xml in `source`, including
+/// conditional ones
+pub fn dependencies(source: &Path) -> Option<Vec<String>> {
+    let content = fs::read_to_string(source.join("package.xml")).ok()?;
+    let mut res = vec![];
+    for tag in BUILD_DEPENDENCY_TAGS {
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find(&format!("<{tag}")) {
+            rest = &rest[start..];
+            if let Some(name) = element(rest, tag) {
+                res.push(name.to_string());
+            }
+            rest = &rest[tag.len() + 1..];
+        }
+    }
+    res.sort();
+    res.dedup();
+    Some(res)
+}
+
+/// A package found in the workspace
+pub struct Package {
+    pub name: String,
+    /// The folder containing the package.xml
+    pub path: PathBuf,
+    /// `ament_cmake`, `ament_python`, `cmake`, ..., `catkin` if the package.xml doesn't export one
+    pub build_type: String,
+}
+
+/// Files that make colcon skip a folder and everything below it
+const IGNORE_MARKERS: &[&str] = &["COLCON_IGNORE", "AMENT_IGNORE", "CATKIN_IGNORE"];
+
+fn discover_in(dir: &Path, res: &mut Vec<Package>) {
+    if IGNORE_MARKERS.iter().any(|m| dir.join(m).exists()) {
+        return;
+    }
+    if dir.join("package.xml").is_file() {
+        let name = name(dir).unwrap_or_else(|| {
+            dir.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+        res.push(Package {
+            name,
+            path: dir.into(),
+            build_type: build_type(dir).unwrap_or("catkin".into()),
+        });
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut dirs = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    for dir in dirs {
+        discover_in(&dir, res);
+    }
+}
+
+/// The packages below `root`, like colcon finds them, sorted by path
+pub fn discover(root: &Path) -> Vec<Package> {
+    let mut res = vec![];
+    discover_in(root, &mut res);
+    res
+}
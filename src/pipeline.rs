@@ -0,0 +1,169 @@
+use std::{env, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{environment, usage};
+
+/// What to do when a step of a pipeline fails
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnFailure {
+    /// Skip the remaining steps
+    #[default]
+    Stop,
+    /// Run the remaining steps anyway, the pipeline still fails
+    Continue,
+}
+
+/// A colb invocation in a pipeline, e.g. `run = "test --rerun-failed"`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Step {
+    /// colb arguments, split and quoted like in a shell
+    pub run: String,
+    #[serde(default)]
+    pub on_failure: OnFailure,
+}
+
+impl Step {
+    /// The arguments of `run`, with quotes and backslashes removed
+    pub fn args(&self) -> Result<Vec<String>, String> {
+        split(&self.run).map_err(|e| format!("Can't split '{}': {e}", self.run))
+    }
+}
+
+/// Split `line` into words like a shell does, without expanding anything
+fn split(line: &str) -> Result<Vec<String>, String> {
+    let mut res = vec![];
+    // None between words, so quoted empty words are kept
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => res.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".into()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".into()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".into()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".into()),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    res.extend(word);
+    Ok(res)
+}
+
+pub struct Outcome {
+    pub step: String,
+    /// None if the step was skipped
+    pub success: Option<bool>,
+    pub seconds: f64,
+}
+
+/// Run the steps as separate colb invocations, passing `global_args` to each of them. Nothing
+/// runs if a step can't be split into arguments
+pub fn run(steps: &[Step], global_args: &[String]) -> Result<Vec<Outcome>, String> {
+    let args = steps
+        .iter()
+        .map(Step::args)
+        .collect::<Result<Vec<_>, _>>()?;
+    let exe = env::current_exe().unwrap_or("colb".into());
+    let mut res = vec![];
+    let mut stopped = false;
+    for (i, (step, args)) in steps.iter().zip(args).enumerate() {
+        if stopped {
+            res.push(Outcome {
+                step: step.run.clone(),
+                success: None,
+                seconds: 0.0,
+            });
+            continue;
+        }
+        header!("Step {}/{}: {}", i + 1, steps.len(), step.run);
+        let started = Instant::now();
+        let success = environment::tool(&exe)
+            .args(global_args)
+            .args(args)
+            .status()
+            .is_ok_and(|s| s.success());
+        stopped = !success && step.on_failure == OnFailure::Stop;
+        res.push(Outcome {
+            step: step.run.clone(),
+            success: Some(success),
+            seconds: started.elapsed().as_secs_f64(),
+        });
+    }
+    Ok(res)
+}
+
+/// One line per step with its result and duration
+pub fn summary(outcomes: &[Outcome]) -> String {
+    let width = outcomes
+        .iter()
+        .map(|o| o.step.len())
+        .max()
+        .unwrap_or(0)
+        .max("step".len());
+    let mut res = format!("{:<width$}  {:<7}  {:>8}\n", "step", "result", "time");
+    for o in outcomes {
+        let (result, time) = match o.success {
+            Some(true) => ("passed", usage::format_duration(o.seconds)),
+            Some(false) => ("failed", usage::format_duration(o.seconds)),
+            None => ("skipped", String::new()),
+        };
+        res.push_str(format!("{:<width$}  {result:<7}  {time:>8}", o.step).trim_end());
+        res.push('\n');
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_split_like_in_a_shell() {
+        let words = split(r#"test pkg --ctest-args "-R my\ test" '' --test a\ b 'it''s'"#);
+        assert_eq!(
+            words.unwrap(),
+            [
+                "test",
+                "pkg",
+                "--ctest-args",
+                "-R my\\ test",
+                "",
+                "--test",
+                "a b",
+                "its"
+            ]
+        );
+        assert!(split("test 'pkg").is_err());
+        assert!(split("test \"pkg").is_err());
+    }
+}
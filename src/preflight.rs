@@ -0,0 +1,175 @@
+use std::{env, fs, path::Path, process};
+
+use crate::environment;
+
+/// Something in the environment likely to make integration tests fail or hang
+pub struct Problem {
+    pub what: String,
+    pub fix: String,
+    /// Whether the tests don't run because of it, warnings are only reported
+    pub fatal: bool,
+}
+
+fn ros2(args: &[&str]) -> Option<String> {
+    let output = environment::tool("ros2").args(args).output().ok()?;
+    let mut res = String::from_utf8_lossy(&output.stdout).to_string();
+    res.push_str(&String::from_utf8_lossy(&output.stderr));
+    Some(res)
+}
+
+pub struct Process {
+    pub pid: u32,
+    pub cmdline: String,
+    /// Empty for processes of other users
+    pub environ: Vec<(String, String)>,
+}
+
+/// All other running processes
+pub fn processes() -> Vec<Process> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return vec![];
+    };
+    let own = process::id();
+    let mut res = vec![];
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|p| p.parse().ok()) else {
+            continue;
+        };
+        if pid == own {
+            continue;
+        }
+        let Ok(cmdline) = fs::read(entry.path().join("cmdline")) else {
+            continue;
+        };
+        let cmdline = String::from_utf8_lossy(&cmdline)
+            .split('\0')
+            .filter(|a| !a.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let environ = fs::read(entry.path().join("environ"))
+            .map(|e| {
+                String::from_utf8_lossy(&e)
+                    .split('\0')
+                    .filter_map(|v| v.split_once('='))
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        res.push(Process {
+            pid,
+            cmdline,
+            environ,
+        });
+    }
+    res
+}
+
+pub fn var(environ: &[(String, String)], name: &str) -> String {
+    environ
+        .iter()
+        .find(|(k, _)| k == name)
+        .map_or("", |(_, v)| v.as_str())
+        .to_string()
+}
+
+/// A ros2 daemon of this user started with another middleware or domain serves stale discovery data
+fn daemon(processes: &[Process]) -> Option<Problem> {
+    let daemon = processes
+        .iter()
+        .find(|p| p.cmdline.contains("_ros2_daemon") && !p.environ.is_empty())?;
+    let mismatch = ["RMW_IMPLEMENTATION", "ROS_DOMAIN_ID", "ROS_LOCALHOST_ONLY"]
+        .into_iter()
+        .filter(|name| var(&daemon.environ, name) != env::var(name).unwrap_or_default())
+        .collect::<Vec<_>>();
+    if mismatch.is_empty() {
+        return None;
+    }
+    Some(Problem {
+        what: format!(
+            "The ros2 daemon (pid {}) runs with a different {}",
+            daemon.pid,
+            mismatch.join(", ")
+        ),
+        fix: "ros2 daemon stop".into(),
+        fatal: true,
+    })
+}
+
+/// Processes still running executables from the workspace, e.g. nodes of an aborted launch test
+fn leftovers(workspace: &Path, processes: &[Process]) -> Option<Problem> {
+    let dirs = ["build", "install"].map(|d| workspace.join(d).to_string_lossy().to_string());
+    let pids = processes
+        .iter()
+        .filter(|p| dirs.iter().any(|d| p.cmdline.contains(d.as_str())))
+        .map(|p| p.pid.to_string())
+        .collect::<Vec<_>>();
+    if pids.is_empty() {
+        return None;
+    }
+    Some(Problem {
+        what: format!(
+            "{} processes from the workspace are still running",
+            pids.len()
+        ),
+        fix: format!("kill {}", pids.join(" ")),
+        fatal: true,
+    })
+}
+
+/// Nodes that are already on the domain, before the tests started any
+fn domain_users() -> Option<Problem> {
+    let output = ros2(&["node", "list", "--no-daemon", "--spin-time", "2"])?;
+    let nodes = output
+        .lines()
+        .filter(|l| l.starts_with('/'))
+        .collect::<Vec<_>>();
+    if nodes.is_empty() {
+        return None;
+    }
+    let domain = env::var("ROS_DOMAIN_ID").unwrap_or("0".into());
+    Some(Problem {
+        what: format!(
+            "{} nodes are already on ROS_DOMAIN_ID {domain}, e.g. {}",
+            nodes.len(),
+            nodes[0]
+        ),
+        fix: "export ROS_DOMAIN_ID=<unused id> or ROS_LOCALHOST_ONLY=1".into(),
+        fatal: true,
+    })
+}
+
+/// What `ros2 doctor` reports, its warnings aren't fatal but its errors are
+fn doctor() -> Vec<Problem> {
+    let Some(output) = ros2(&["doctor"]) else {
+        return vec![];
+    };
+    output
+        .lines()
+        .filter_map(|l| {
+            let (fatal, what) = match l.split_once("ERROR: ") {
+                Some((_, error)) => (true, error),
+                None => (false, l.split_once("UserWarning: ")?.1),
+            };
+            Some(Problem {
+                what: what.trim().to_string(),
+                fix: "ros2 doctor --report".into(),
+                fatal,
+            })
+        })
+        .collect()
+}
+
+/// Check the environment for common causes of mysteriously failing integration tests
+pub fn check(workspace: &Path) -> Vec<Problem> {
+    let processes = processes();
+    let mut res = vec![];
+    res.extend(daemon(&processes));
+    res.extend(leftovers(workspace, &processes));
+    if environment::which("ros2").is_some() {
+        res.extend(domain_users());
+        res.extend(doctor());
+    } else {
+        context!("ros2 not found, skipping the checks of the ROS network");
+    }
+    res
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use rayon::prelude::*;
+
+use crate::{environment, output::*};
+
+struct Removal {
+    removed: AtomicUsize,
+    /// Paths that couldn't be removed, with the reason
+    failed: Mutex<Vec<(PathBuf, io::Error)>>,
+}
+
+impl Removal {
+    fn fail(&self, path: &Path, e: io::Error) {
+        if let Ok(mut failed) = self.failed.lock() {
+            failed.push((path.into(), e));
+        }
+    }
+
+    /// Remove `folder` after everything below it, returns whether it is gone. Failures don't stop
+    /// the removal of the other entries
+    fn remove(&self, folder: &Path) -> bool {
+        let entries = match folder.read_dir() {
+            Ok(entries) => entries.flatten().collect::<Vec<_>>(),
+            Err(e) => {
+                self.fail(folder, e);
+                return false;
+            }
+        };
+        let kept = entries
+            .par_iter()
+            .map(|entry| {
+                let path = entry.path();
+                // The file type doesn't follow symlinks, so symlinked folders are unlinked
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    return self.remove(&path);
+                }
+                match fs::remove_file(&path) {
+                    Ok(()) => {
+                        self.removed.fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                    Err(e) => {
+                        self.fail(&path, e);
+                        false
+                    }
+                }
+            })
+            .filter(|gone| !gone)
+            .count();
+        if kept > 0 {
+            return false;
+        }
+        match fs::remove_dir(folder) {
+            Ok(()) => true,
+            Err(e) => {
+                self.fail(folder, e);
+                false
+            }
+        }
+    }
+}
+
+/// Show how many files are gone while a removal takes longer, on terminals only
+fn progress(removed: &AtomicUsize, done: &AtomicBool) {
+    if json() || commands_only() || !io::stderr().is_terminal() {
+        return;
+    }
+    let mut shown = false;
+    loop {
+        thread::sleep(Duration::from_millis(200));
+        if done.load(Ordering::Relaxed) {
+            break;
+        }
+        eprint!("\r{} files removed", removed.load(Ordering::Relaxed));
+        shown = true;
+    }
+    if shown {
+        eprint!("\r\x1b[K");
+    }
+}
+
+/// Remove the folder `path` and everything below it, unlinking files on rayon's thread pool.
+/// Build folders have hundreds of thousands of files, removing them one after the other is what
+/// makes `fs::remove_dir_all` slow. On failure, the error lists every path that is left
+pub fn remove_tree(path: &Path) -> Result<(), String> {
+    let removal = Removal {
+        removed: AtomicUsize::new(0),
+        failed: Mutex::new(vec![]),
+    };
+    let done = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| progress(&removal.removed, &done));
+        removal.remove(path);
+        done.store(true, Ordering::Relaxed);
+    });
+    let mut failed = removal.failed.into_inner().unwrap_or_default();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    failed.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut res = format!(
+        "Couldn't remove {} of '{}', {} files were removed:",
+        if failed.len() == 1 {
+            "1 path".to_string()
+        } else {
+            format!("{} paths", failed.len())
+        },
+        path.to_string_lossy(),
+        removal.removed.into_inner()
+    );
+    for (path, e) in failed {
+        res.push_str(&format!("\n  {}: {e}", path.to_string_lossy()));
+    }
+    Err(res)
+}
+
+/// The command moving `path` to the desktop's trash, from where file managers can restore it
+pub fn trash_command(path: &Path) -> std::process::Command {
+    let mut cmd = environment::tool("gio");
+    cmd.arg("trash").arg(path);
+    cmd
+}
+
+/// Move `path` to the trash with `gio`, which ships with GLib on most desktops
+pub fn trash(path: &Path) -> Result<(), String> {
+    let output = trash_command(path).output().map_err(|e| {
+        format!("Moving to the trash needs 'gio', e.g. from the libglib2.0-bin package: {e}")
+    })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{bases, environment};
+
+pub use crate::cli::ReportFormat as Format;
+
+/// A titled table of the report
+pub struct Section {
+    title: String,
+    note: Option<String>,
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Section {
+    fn new(title: &str, columns: &[&'static str]) -> Section {
+        Section {
+            title: title.into(),
+            note: None,
+            columns: columns.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    fn note(mut self, note: &str) -> Section {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+/// How many entries the top-N tables show
+const TOP: usize = 10;
+
+/// Size of a file or folder in bytes, not following symlinks
+pub fn disk_usage(path: &Path) -> io::Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut size = 0;
+    for e in fs::read_dir(path)? {
+        size += disk_usage(&e?.path()).unwrap_or(0);
+    }
+    Ok(size)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", units[unit])
+    }
+}
+
+/// Durations of the most recent run of each ninja step in `build_dir`, in milliseconds
+pub fn ninja_steps(build_dir: &Path) -> BTreeMap<String, u64> {
+    let Ok(log) = fs::read_to_string(build_dir.join(".ninja_log")) else {
+        return BTreeMap::new();
+    };
+    // Later lines overwrite earlier runs of the same output
+    log.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let fields = l.split('\t').collect::<Vec<_>>();
+            let [start, end, _, output, ..] = fields[..] else {
+                return None;
+            };
+            let duration = end.parse::<u64>().ok()?.checked_sub(start.parse().ok()?)?;
+            Some((output.to_string(), duration))
+        })
+        .collect()
+}
+
+/// Direct dependency edges as (dependency, dependent), from the output of `colcon graph`
+fn graph_edges(workspace: &Path) -> Option<Vec<(String, String)>> {
+    let output = environment::colcon()
+        .current_dir(workspace)
+        .args(["--log-base", "/dev/null", "graph"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let lines = output.lines().collect::<Vec<_>>();
+    let names = lines
+        .iter()
+        .filter_map(|l| l.split_whitespace().next())
+        .collect::<Vec<_>>();
+    let width = names.iter().map(|n| n.len()).max()?;
+    let mut edges = vec![];
+    for (line, name) in lines.iter().zip(&names) {
+        // Column j marks how package j depends on the package in this row
+        let matrix = line.get(width + 1..).unwrap_or_default();
+        for (j, mark) in matrix.chars().enumerate() {
+            if !" +.".contains(mark) {
+                if let Some(dependent) = names.get(j) {
+                    edges.push((name.to_string(), dependent.to_string()));
+                }
+            }
+        }
+    }
+    Some(edges)
+}
+
+/// Test outcome from a ctest progress line like ` 1/3 Test #1: name ....   Passed    0.01 sec`
+fn ctest_outcome(line: &str) -> Option<(String, bool)> {
+    let (_, rest) = line.split_once(" Test ")?;
+    let (number, rest) = rest.trim_start().split_once(": ")?;
+    if !number.starts_with('#') {
+        return None;
+    }
+    let (name, outcome) = rest.split_once(" ")?;
+    let outcome = outcome.trim_start_matches([' ', '.']);
+    if outcome.starts_with("Passed") {
+        Some((name.to_string(), true))
+    } else if outcome.starts_with("***") || outcome.starts_with("Failed") {
+        Some((name.to_string(), false))
+    } else {
+        None
+    }
+}
+
+/// Log files of all recorded test runs of `package`
+fn test_logs(workspace: &Path, package: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(workspace.join("log")) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("test_"))
+        .map(|e| e.path().join(package).join("stdout_stderr.log"))
+        .filter(|p| p.exists())
+        .collect()
+}
+
+fn packages_section(
+    packages: &[(String, PathBuf)],
+    edges: &Option<Vec<(String, String)>>,
+) -> Section {
+    let mut section = Section::new("Packages", &["", "Value"]);
+    section
+        .rows
+        .push(vec!["Packages".into(), packages.len().to_string()]);
+    let Some(edges) = edges else {
+        return section.note("Dependency statistics need `colcon graph`");
+    };
+    section
+        .rows
+        .push(vec!["Direct dependencies".into(), edges.len().to_string()]);
+    let mut dependents = BTreeMap::<&str, usize>::new();
+    let mut dependencies = BTreeMap::<&str, usize>::new();
+    for (dependency, dependent) in edges {
+        *dependents.entry(dependency).or_default() += 1;
+        *dependencies.entry(dependent).or_default() += 1;
+    }
+    let leaves = packages
+        .iter()
+        .filter(|(p, _)| !dependencies.contains_key(p.as_str()))
+        .count();
+    section
+        .rows
+        .push(vec!["Without dependencies".into(), leaves.to_string()]);
+    if let Some((p, n)) = dependents.iter().max_by_key(|(_, n)| **n) {
+        section
+            .rows
+            .push(vec!["Most dependents".into(), format!("{p} ({n})")]);
+    }
+    if let Some((p, n)) = dependencies.iter().max_by_key(|(_, n)| **n) {
+        section
+            .rows
+            .push(vec!["Most dependencies".into(), format!("{p} ({n})")]);
+    }
+    section
+}
+
+fn sizes_section(workspace: &Path, packages: &[(String, PathBuf)]) -> Section {
+    let mut sizes = packages
+        .iter()
+        .map(|(p, _)| {
+            let build = disk_usage(&workspace.join(bases::build()).join(p)).unwrap_or(0);
+            let install = disk_usage(&workspace.join(bases::install()).join(p)).unwrap_or(0);
+            (p, build, install)
+        })
+        .filter(|(_, b, i)| b + i > 0)
+        .collect::<Vec<_>>();
+    sizes.sort_by_key(|(_, b, i)| std::cmp::Reverse(b + i));
+    let total = sizes.iter().map(|(_, b, i)| b + i).sum::<u64>();
+    let mut section = Section::new("Artifact sizes", &["Package", "Build", "Install"])
+        .note(&format!("{} in total", format_size(total)));
+    for (p, build, install) in sizes.into_iter().take(TOP) {
+        section
+            .rows
+            .push(vec![p.clone(), format_size(build), format_size(install)]);
+    }
+    section
+}
+
+fn build_time_sections(workspace: &Path, packages: &[(String, PathBuf)]) -> Vec<Section> {
+    let mut per_package = vec![];
+    let mut steps = vec![];
+    for (p, _) in packages {
+        let package_steps = ninja_steps(&workspace.join(bases::build()).join(p));
+        if package_steps.is_empty() {
+            continue;
+        }
+        per_package.push((p.clone(), package_steps.values().sum::<u64>()));
+        steps.extend(package_steps.into_iter().map(|(o, d)| (p.clone(), o, d)));
+    }
+    per_package.sort_by_key(|(_, d)| std::cmp::Reverse(*d));
+    steps.sort_by_key(|(_, _, d)| std::cmp::Reverse(*d));
+    let seconds = |ms: u64| format!("{:.1}s", ms as f64 / 1000.0);
+    let mut packages_section = Section::new("Slowest packages", &["Package", "Build time"])
+        .note("Sum of the most recent ninja steps, so parallel steps add up");
+    for (p, d) in per_package.into_iter().take(TOP) {
+        packages_section.rows.push(vec![p, seconds(d)]);
+    }
+    let mut steps_section = Section::new("Slowest build steps", &["Package", "Output", "Time"]);
+    for (p, o, d) in steps.into_iter().take(TOP) {
+        steps_section.rows.push(vec![p, o, seconds(d)]);
+    }
+    vec![packages_section, steps_section]
+}
+
+fn warnings_section(workspace: &Path, packages: &[(String, PathBuf)]) -> Section {
+    let log_dir = workspace.join("log").join("latest_build");
+    let mut section = Section::new("Compiler warnings", &["Package", "Warnings"]);
+    if !log_dir.is_dir() {
+        return section.note("No build logs, colb builds with `--log-base /dev/null`");
+    }
+    let mut counts = packages
+        .iter()
+        .filter_map(|(p, _)| {
+            let log = fs::read_to_string(log_dir.join(p).join("stderr.log")).ok()?;
+            let count = log.lines().filter(|l| l.contains("warning:")).count();
+            (count > 0).then_some((p.clone(), count))
+        })
+        .collect::<Vec<_>>();
+    counts.sort_by_key(|(_, c)| std::cmp::Reverse(*c));
+    for (p, c) in counts {
+        section.rows.push(vec![p, c.to_string()]);
+    }
+    section.note("From the most recent build in log/latest_build")
+}
+
+fn flaky_section(workspace: &Path, packages: &[(String, PathBuf)]) -> Section {
+    let mut section = Section::new("Flaky tests", &["Package", "Test", "Passed", "Failed"])
+        .note("Tests that both passed and failed in the test logs in log/");
+    for (p, _) in packages {
+        let mut outcomes = BTreeMap::<String, (usize, usize)>::new();
+        for log in test_logs(workspace, p) {
+            let Ok(content) = fs::read_to_string(log) else {
+                continue;
+            };
+            for (test, passed) in content.lines().filter_map(ctest_outcome) {
+                let entry = outcomes.entry(test).or_default();
+                if passed {
+                    entry.0 += 1;
+                } else {
+                    entry.1 += 1;
+                }
+            }
+        }
+        for (test, (passed, failed)) in outcomes {
+            if passed > 0 && failed > 0 {
+                section.rows.push(vec![
+                    p.clone(),
+                    test,
+                    passed.to_string(),
+                    failed.to_string(),
+                ]);
+            }
+        }
+    }
+    section
+}
+
+fn stale_section(workspace: &Path, packages: &[(String, PathBuf)]) -> Section {
+    let known = packages
+        .iter()
+        .map(|(p, _)| p.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut section = Section::new("Stale artifacts", &["Folder", "Size"])
+        .note("Build and install folders of packages that are no longer in the workspace");
+    let install = workspace.join(bases::install());
+    // A merged install space has no per-package folders
+    let merged = fs::read_to_string(install.join(".colcon_install_layout"))
+        .is_ok_and(|l| l.trim() == "merged");
+    let folders = if merged {
+        vec![workspace.join(bases::build())]
+    } else {
+        vec![workspace.join(bases::build()), install]
+    };
+    for base in folders {
+        let Ok(entries) = fs::read_dir(&base) else {
+            continue;
+        };
+        let mut stale = entries
+            .flatten()
+            .filter(|e| e.path().is_dir())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|n| !n.starts_with('.') && !known.contains(n.as_str()))
+            .collect::<Vec<_>>();
+        stale.sort();
+        for name in stale {
+            let path = base.join(&name);
+            let size = disk_usage(&path).unwrap_or(0);
+            let relative = path.strip_prefix(workspace).unwrap_or(&path);
+            section.rows.push(vec![
+                relative.to_string_lossy().to_string(),
+                format_size(size),
+            ]);
+        }
+    }
+    section
+}
+
+/// Collect all sections of the workspace report
+pub fn generate(workspace: &Path, packages: &[(String, PathBuf)]) -> Vec<Section> {
+    let edges = graph_edges(workspace);
+    let mut res = vec![
+        packages_section(packages, &edges),
+        sizes_section(workspace, packages),
+    ];
+    res.extend(build_time_sections(workspace, packages));
+    res.push(warnings_section(workspace, packages));
+    res.push(flaky_section(workspace, packages));
+    res.push(stale_section(workspace, packages));
+    res
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn render(title: &str, sections: &[Section], format: Format) -> String {
+    let mut res = String::new();
+    match format {
+        Format::Markdown => {
+            res.push_str(&format!("# {title}\n"));
+            for s in sections {
+                res.push_str(&format!("\n## {}\n\n", s.title));
+                if let Some(note) = &s.note {
+                    res.push_str(&format!("{note}\n\n"));
+                }
+                if s.rows.is_empty() {
+                    res.push_str("None\n");
+                    continue;
+                }
+                let cell = |c: &str| c.replace('|', "\\|");
+                res.push_str(&format!("| {} |\n", s.columns.join(" | ")));
+                res.push_str(&format!("|{}\n", "---|".repeat(s.columns.len())));
+                for row in &s.rows {
+                    let row = row.iter().map(|c| cell(c)).collect::<Vec<_>>();
+                    res.push_str(&format!("| {} |\n", row.join(" | ")));
+                }
+            }
+        }
+        Format::Html => {
+            let title = escape_html(title);
+            res.push_str(&format!(
+                "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n"
+            ));
+            for s in sections {
+                res.push_str(&format!("<h2>{}</h2>\n", escape_html(&s.title)));
+                if let Some(note) = &s.note {
+                    res.push_str(&format!("<p>{}</p>\n", escape_html(note)));
+                }
+                if s.rows.is_empty() {
+                    res.push_str("<p>None</p>\n");
+                    continue;
+                }
+                res.push_str("<table>\n<tr>");
+                for c in &s.columns {
+                    res.push_str(&format!("<th>{}</th>", escape_html(c)));
+                }
+                res.push_str("</tr>\n");
+                for row in &s.rows {
+                    res.push_str("<tr>");
+                    for c in row {
+                        res.push_str(&format!("<td>{}</td>", escape_html(c)));
+                    }
+                    res.push_str("</tr>\n");
+                }
+                res.push_str("</table>\n");
+            }
+            res.push_str("</body>\n</html>\n");
+        }
+    }
+    res
+}
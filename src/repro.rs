@@ -0,0 +1,133 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use crate::{bases, cache, environment, output::*, versions};
+
+struct Armed {
+    bundle: PathBuf,
+    workspace: PathBuf,
+    config: String,
+    started: SystemTime,
+}
+
+static ARMED: OnceLock<Armed> = OnceLock::new();
+
+/// Write a repro bundle to `bundle` if a build or test fails later on
+pub fn arm(bundle: &Path, workspace: &Path, config: String) {
+    let _ = ARMED.set(Armed {
+        bundle: bundle.into(),
+        workspace: workspace.into(),
+        config,
+        started: SystemTime::now(),
+    });
+}
+
+fn modified_since(path: &Path, since: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|m| m >= since)
+}
+
+/// ctest logs of all packages whose tests ran during this invocation
+pub fn test_logs(workspace: &Path, since: SystemTime) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(workspace.join(bases::build())) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|e| {
+            let package = e.file_name().to_string_lossy().to_string();
+            let log = e
+                .path()
+                .join("Testing")
+                .join("Temporary")
+                .join("LastTest.log");
+            (package, log)
+        })
+        .filter(|(_, log)| modified_since(log, since))
+        .collect()
+}
+
+fn collect(armed: &Armed, dir: &Path, code: i32) -> io::Result<()> {
+    fs::write(dir.join("config.toml"), &armed.config)?;
+    let mut commands = format!(
+        "# colb {}\n# exited with {code}\n",
+        env::args().skip(1).collect::<Vec<_>>().join(" ")
+    );
+    for line in command_history() {
+        commands.push_str(&line);
+        commands.push('\n');
+    }
+    fs::write(dir.join("commands.sh"), commands)?;
+    let mut diff = String::new();
+    for (name, old, new) in environment::changes() {
+        if let Some(old) = old {
+            diff.push_str(&format!("-{name}={old}\n"));
+        }
+        if let Some(new) = new {
+            diff.push_str(&format!("+{name}={new}\n"));
+        }
+    }
+    fs::write(dir.join("environment.diff"), diff)?;
+    let versions = versions::tool_versions()
+        .iter()
+        .map(|(name, version)| format!("{name}: {version}\n"))
+        .collect::<String>();
+    fs::write(
+        dir.join("versions.txt"),
+        format!("colb: {}\n{versions}", env!("CARGO_PKG_VERSION")),
+    )?;
+    let logs = dir.join("logs");
+    fs::create_dir_all(&logs)?;
+    let latest = armed.workspace.join("log").join("latest");
+    if modified_since(&latest, armed.started) {
+        if let Ok(latest) = latest.canonicalize() {
+            cache::copy_tree(&latest, &logs.join("colcon"))?;
+        }
+    }
+    for (package, log) in test_logs(&armed.workspace, armed.started) {
+        fs::copy(log, logs.join(format!("{package}.LastTest.log")))?;
+    }
+    Ok(())
+}
+
+/// Package everything needed to reproduce the failure, if a bundle was requested
+pub fn write_bundle(code: i32) {
+    let Some(armed) = ARMED.get() else {
+        return;
+    };
+    if commands_only() {
+        return;
+    }
+    let dir = env::temp_dir().join(format!("colb-repro-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let res = fs::create_dir_all(&dir)
+        .and_then(|_| collect(armed, &dir, code))
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            let packed = environment::tool("tar")
+                .arg("-czf")
+                .arg(&armed.bundle)
+                .arg("-C")
+                .arg(&dir)
+                .arg(".")
+                .status()
+                .is_ok_and(|s| s.success());
+            packed
+                .then_some(())
+                .ok_or("Couldn't pack the bundle".into())
+        });
+    let _ = fs::remove_dir_all(&dir);
+    match res {
+        Ok(()) => {
+            header!("Repro bundle");
+            context!("Wrote {}", armed.bundle.to_string_lossy());
+        }
+        Err(e) => eprintln!("Couldn't write repro bundle: {e}"),
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{bases, ctest, environment};
+
+/// Number of runs kept, older ones are removed when recording a new one
+const KEEP: usize = 100;
+
+/// A recorded test run with the setup it ran in
+#[derive(Serialize, Deserialize)]
+pub struct Run {
+    pub package: String,
+    /// When the run finished, in UTC
+    pub time: String,
+    pub build_type: String,
+    pub rmw: String,
+    pub domain_id: String,
+    /// Commit of the package's repository, with `-dirty` if it had local changes
+    pub commit: String,
+    /// Outcome per ctest name
+    pub tests: BTreeMap<String, String>,
+}
+
+fn results_dir(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("results")
+}
+
+/// Ids of the recorded runs, oldest first
+pub fn ids(workspace: &Path) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(results_dir(workspace)) else {
+        return vec![];
+    };
+    let mut res = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.strip_suffix(".toml")?.parse().ok()
+        })
+        .collect::<Vec<u64>>();
+    res.sort();
+    res
+}
+
+/// The run with id `id`, or the most recent one for `latest`
+pub fn load(workspace: &Path, id: &str) -> Result<(u64, Run), String> {
+    let id = match id {
+        "latest" => *ids(workspace).last().ok_or("No test runs recorded yet")?,
+        id => id
+            .parse()
+            .map_err(|_| format!("'{id}' is not a run id or 'latest'"))?,
+    };
+    let file = results_dir(workspace).join(format!("{id}.toml"));
+    let content = fs::read_to_string(&file).map_err(|_| format!("There is no run {id}"))?;
+    let run = toml::from_str(&content).map_err(|e| format!("Couldn't parse run {id}: {e}"))?;
+    Ok((id, run))
+}
+
+fn commit(source: &Path) -> String {
+    let git = |args: &[&str]| {
+        environment::tool("git")
+            .arg("-C")
+            .arg(source)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+    let Some(head) = git(&["rev-parse", "--short", "HEAD"]) else {
+        return "unknown".into();
+    };
+    match git(&["status", "--porcelain", "--untracked-files=no", "."]) {
+        Some(changes) if !changes.is_empty() => format!("{head}-dirty"),
+        _ => head,
+    }
+}
+
+/// Format seconds since the epoch as a UTC date and time
+fn utc(seconds: u64) -> String {
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let secs = seconds % 86400;
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Record the results of the ctest run in the build folder of `package`, if it ran since `since`
+pub fn record(
+    workspace: &Path,
+    package: &str,
+    source: &Path,
+    build_type: &str,
+    since: SystemTime,
+) -> Result<Option<u64>, String> {
+    let build_dir = workspace.join(bases::build()).join(package);
+    let log = build_dir
+        .join("Testing")
+        .join("Temporary")
+        .join("LastTest.log");
+    let ran = fs::metadata(&log)
+        .and_then(|m| m.modified())
+        .is_ok_and(|m| m >= since);
+    if !ran {
+        return Ok(None);
+    }
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let run = Run {
+        package: package.into(),
+        time: utc(now),
+        build_type: build_type.into(),
+        rmw: env::var("RMW_IMPLEMENTATION").unwrap_or("default".into()),
+        domain_id: env::var("ROS_DOMAIN_ID").unwrap_or("0".into()),
+        commit: commit(source),
+        tests: ctest::last_results(&build_dir).into_iter().collect(),
+    };
+    let dir = results_dir(workspace);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let ids = ids(workspace);
+    let id = ids.last().map_or(1, |last| last + 1);
+    let content = toml::to_string(&run).map_err(|e| e.to_string())?;
+    fs::write(dir.join(format!("{id}.toml")), content).map_err(|e| e.to_string())?;
+    for old in ids.iter().rev().skip(KEEP - 1) {
+        let _ = fs::remove_file(dir.join(format!("{old}.toml")));
+    }
+    Ok(Some(id))
+}
+
+fn table(rows: &[[String; 3]]) -> String {
+    let widths = [0, 1].map(|i| rows.iter().map(|r| r[i].len()).max().unwrap_or(0));
+    rows.iter()
+        .map(|[a, b, c]| format!("{a:w0$}  {b:w1$}  {c}\n", w0 = widths[0], w1 = widths[1]))
+        .collect()
+}
+
+/// The setup and the tests that differ between two runs
+pub fn diff(a: (u64, &Run), b: (u64, &Run)) -> String {
+    let (a_id, a) = a;
+    let (b_id, b) = b;
+    let mut setup = vec![["".into(), format!("run {a_id}"), format!("run {b_id}")]];
+    let fields = [
+        ("package", &a.package, &b.package),
+        ("time", &a.time, &b.time),
+        ("build type", &a.build_type, &b.build_type),
+        ("rmw", &a.rmw, &b.rmw),
+        ("domain id", &a.domain_id, &b.domain_id),
+        ("commit", &a.commit, &b.commit),
+    ];
+    for (name, a, b) in fields {
+        if a != b || name == "time" {
+            setup.push([name.into(), a.clone(), b.clone()]);
+        }
+    }
+    let mut res = table(&setup);
+    let names = a
+        .tests
+        .keys()
+        .chain(b.tests.keys())
+        .collect::<BTreeSet<_>>();
+    let missing = "-".to_string();
+    let mut changed = vec![];
+    for name in names {
+        let a = a.tests.get(name).unwrap_or(&missing);
+        let b = b.tests.get(name).unwrap_or(&missing);
+        if a != b {
+            changed.push([name.clone(), a.clone(), b.clone()]);
+        }
+    }
+    res.push('\n');
+    if changed.is_empty() {
+        res.push_str("No test changed its outcome\n");
+    } else {
+        res.push_str(&table(&changed));
+    }
+    res
+}
+
+/// One line per recorded run, oldest first
+pub fn list(workspace: &Path) -> String {
+    let mut rows = vec![];
+    for id in ids(workspace) {
+        let Ok((_, run)) = load(workspace, &id.to_string()) else {
+            continue;
+        };
+        let failed = run.tests.values().filter(|o| *o != "passed").count();
+        rows.push([
+            id.to_string(),
+            format!("{} {}", run.time, run.package),
+            format!(
+                "{}/{} passed, {}, {}, {}",
+                run.tests.len() - failed,
+                run.tests.len(),
+                run.build_type,
+                run.rmw,
+                run.commit
+            ),
+        ]);
+    }
+    table(&rows)
+}
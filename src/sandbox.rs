@@ -0,0 +1,119 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::environment;
+
+/// System folders visible (read-only) in the sandbox
+const SYSTEM: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc"];
+
+/// Variables listing the install spaces of underlays, which stay visible (read-only)
+const PREFIX_PATHS: &[&str] = &[
+    "AMENT_PREFIX_PATH",
+    "CMAKE_PREFIX_PATH",
+    "COLCON_PREFIX_PATH",
+];
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+/// Where ccache keeps its files, which builds need to write to
+fn ccache_dirs() -> Vec<PathBuf> {
+    if let Some(dir) = env::var_os("CCACHE_DIR") {
+        return vec![dir.into()];
+    }
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home().map(|h| h.join(".cache")));
+    cache
+        .map(|c| c.join("ccache"))
+        .into_iter()
+        .chain(home().map(|h| h.join(".ccache")))
+        .collect()
+}
+
+/// Folders outside the workspace the build reads from: underlays, conda and virtualenvs,
+/// colcon's mixins and user-installed Python packages
+fn read_only_dirs(workspace: &Path) -> Vec<PathBuf> {
+    let mut res = vec![];
+    for var in PREFIX_PATHS {
+        if let Some(paths) = env::var_os(var) {
+            res.extend(env::split_paths(&paths));
+        }
+    }
+    for var in ["CONDA_PREFIX", "VIRTUAL_ENV"] {
+        res.extend(env::var_os(var).map(PathBuf::from));
+    }
+    if let Some(home) = home() {
+        res.push(home.join(".colcon"));
+        res.push(home.join(".local"));
+    }
+    res.retain(|p| p.is_absolute() && !p.starts_with(workspace));
+    res.sort();
+    res.dedup();
+    res
+}
+
+fn bind(args: &mut Vec<String>, flag: &str, path: &Path) {
+    let path = path.to_string_lossy().to_string();
+    args.extend([flag.to_string(), path.clone(), path]);
+}
+
+fn bwrap(workspace: &Path, writable: &[PathBuf]) -> Vec<String> {
+    // Mounts are applied in order, so the workspace is bound after the fresh /tmp it may be in
+    let mut res = [
+        "bwrap",
+        "--unshare-net",
+        "--die-with-parent",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+    ]
+    .map(String::from)
+    .to_vec();
+    for dir in SYSTEM {
+        let path = Path::new(dir);
+        match path.read_link() {
+            // Merged /usr: /bin -> usr/bin and the like
+            Ok(target) => {
+                res.extend([
+                    "--symlink".to_string(),
+                    target.to_string_lossy().to_string(),
+                    dir.to_string(),
+                ]);
+            }
+            Err(_) if path.is_dir() => bind(&mut res, "--ro-bind", path),
+            Err(_) => {}
+        }
+    }
+    for dir in read_only_dirs(workspace) {
+        bind(&mut res, "--ro-bind-try", &dir);
+    }
+    for dir in ccache_dirs().iter().chain(writable) {
+        bind(&mut res, "--bind-try", dir);
+    }
+    bind(&mut res, "--bind", workspace);
+    res.push("--".into());
+    res
+}
+
+/// The command prefix running builds without network access, and with bubblewrap also with only
+/// the system, the workspace, its underlays and the `writable` directories visible.
+/// Falls back to `unshare`, which only isolates the network
+pub fn prefix(workspace: &Path, writable: &[PathBuf]) -> Result<Vec<String>, String> {
+    if environment::which("bwrap").is_some() {
+        Ok(bwrap(workspace, writable))
+    } else if environment::which("unshare").is_some() {
+        context!("bwrap not found, the sandbox only disables the network");
+        Ok(["unshare", "--map-root-user", "--net", "--"]
+            .map(String::from)
+            .to_vec())
+    } else {
+        Err("Sandboxing needs 'bwrap' (bubblewrap) or 'unshare' (util-linux)".into())
+    }
+}
@@ -0,0 +1,230 @@
+use std::{collections::BTreeMap, env};
+
+use serde::{Deserialize, Serialize};
+
+use crate::environment;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitizer {
+    Asan,
+    Tsan,
+    Ubsan,
+}
+
+impl Sanitizer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sanitizer::Asan => "asan",
+            Sanitizer::Tsan => "tsan",
+            Sanitizer::Ubsan => "ubsan",
+        }
+    }
+
+    /// Compiler and linker flags
+    fn flags(&self) -> &'static str {
+        match self {
+            Sanitizer::Asan => "-fsanitize=address -fno-omit-frame-pointer",
+            Sanitizer::Tsan => "-fsanitize=thread",
+            // Make undefined behaviour fail the test instead of only printing a warning
+            Sanitizer::Ubsan => "-fsanitize=undefined -fno-sanitize-recover=undefined",
+        }
+    }
+
+    /// Runtime options for the tests, by environment variable
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Sanitizer::Asan => &[
+                (
+                    "ASAN_OPTIONS",
+                    "detect_leaks=1:detect_stack_use_after_return=1",
+                ),
+                ("LSAN_OPTIONS", "print_suppressions=0"),
+            ],
+            Sanitizer::Tsan => &[("TSAN_OPTIONS", "second_deadlock_stack=1:halt_on_error=1")],
+            Sanitizer::Ubsan => &[("UBSAN_OPTIONS", "print_stacktrace=1:halt_on_error=1")],
+        }
+    }
+}
+
+/// Sanitizers to build the packages with and how to run their tests
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct SanitizerConfig {
+    /// Always build with these, in addition to `--asan`, `--tsan` and `--ubsan`
+    #[serde(default)]
+    pub enabled: Vec<Sanitizer>,
+    /// Runtime options replacing colb's, by environment variable, e.g. `ASAN_OPTIONS = "detect_leaks=0"`
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+/// Error out on combinations the compilers refuse
+pub fn check(sanitizers: &[Sanitizer]) -> Result<(), String> {
+    if sanitizers.contains(&Sanitizer::Asan) && sanitizers.contains(&Sanitizer::Tsan) {
+        return Err("asan and tsan can't be combined, build with one at a time".into());
+    }
+    Ok(())
+}
+
+/// Add the compiler and linker flags of the sanitizers to CMake arguments
+pub fn add_cmake_args(args: &mut Vec<String>, sanitizers: &[Sanitizer]) {
+    let flags = sanitizers
+        .iter()
+        .map(Sanitizer::flags)
+        .collect::<Vec<_>>()
+        .join(" ");
+    crate::add_compiler_flags(args, &flags);
+}
+
+/// Set the runtime options of the sanitizers for the tests, the configured ones take precedence
+pub fn enable(sanitizers: &[Sanitizer], configured: &BTreeMap<String, String>) {
+    for sanitizer in sanitizers {
+        for (var, value) in sanitizer.options() {
+            env::set_var(var, value);
+        }
+    }
+    for (var, value) in configured {
+        env::set_var(var, value);
+    }
+}
+
+/// One stack frame of a sanitizer report
+pub struct Frame {
+    pub index: String,
+    /// Function name, if already symbolized
+    pub function: Option<String>,
+    /// `file:line:col`, if already symbolized
+    pub location: Option<String>,
+    /// Binary and offset for unsymbolized frames, e.g. `(/ws/build/pkg/test_foo+0x1234)`
+    pub module: Option<(String, String)>,
+}
+
+pub enum Line {
+    Text(String),
+    Frame(Frame),
+}
+
+/// A single sanitizer report from the output of a test
+pub struct Report {
+    pub test: String,
+    pub title: String,
+    pub lines: Vec<Line>,
+}
+
+fn strip_pid(line: &str) -> &str {
+    // ASan/LSan prefix their lines with `==<pid>==`
+    if let Some(rest) = line.strip_prefix("==") {
+        if let Some((pid, rest)) = rest.split_once("==") {
+            if !pid.is_empty() && pid.chars().all(|c| c.is_ascii_digit()) {
+                return rest;
+            }
+        }
+    }
+    line
+}
+
+fn is_report_start(line: &str) -> bool {
+    let line = strip_pid(line);
+    (line.starts_with("ERROR: ") || line.starts_with("WARNING: ")) && line.contains("Sanitizer")
+        || line.contains(": runtime error: ")
+}
+
+fn parse_frame(line: &str) -> Option<Frame> {
+    let line = line.trim();
+    let (index, rest) = line.split_once(' ')?;
+    if !index.starts_with('#') || !index[1..].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut rest = rest.trim();
+    // ASan frames start with the address, TSan frames don't
+    if rest.starts_with("0x") {
+        rest = rest.split_once(' ').map(|(_, r)| r.trim()).unwrap_or("");
+    }
+    let mut frame = Frame {
+        index: index.to_string(),
+        function: None,
+        location: None,
+        module: None,
+    };
+    // Trailing groups like `(/ws/build/pkg/test_foo+0x1234)` or `(BuildId: 1234)`
+    while let Some((head, group)) = rest.strip_suffix(')').and_then(|r| r.rsplit_once('(')) {
+        if let Some((binary, offset)) = group.rsplit_once("+0x") {
+            frame.module = Some((binary.to_string(), format!("0x{offset}")));
+        }
+        rest = head.trim_end();
+    }
+    let rest = rest.strip_prefix("in ").unwrap_or(rest);
+    if !rest.is_empty() {
+        match rest.rsplit_once(' ') {
+            Some((function, location)) if location.contains(':') => {
+                frame.function = Some(function.to_string());
+                frame.location = Some(location.to_string());
+            }
+            _ => frame.function = Some(rest.to_string()),
+        }
+    }
+    Some(frame)
+}
+
+/// Extract sanitizer reports from a ctest `LastTest.log`, grouped by the test that produced them
+pub fn reports_in_log(content: &str) -> Vec<Report> {
+    let mut res: Vec<Report> = vec![];
+    let mut test = String::new();
+    let mut open = false;
+    for line in content.lines() {
+        if let Some((_, name)) = line.split_once(" Testing: ") {
+            test = name.trim().to_string();
+            open = false;
+            continue;
+        }
+        if is_report_start(line) {
+            res.push(Report {
+                test: test.clone(),
+                title: strip_pid(line).trim().to_string(),
+                lines: vec![],
+            });
+            open = true;
+            continue;
+        }
+        if !open {
+            continue;
+        }
+        let report = res.last_mut().expect("open report exists");
+        let stripped = strip_pid(line);
+        if stripped.starts_with("SUMMARY: ") || line == "<end of output>" {
+            open = false;
+        } else if let Some(frame) = parse_frame(stripped) {
+            report.lines.push(Line::Frame(frame));
+        } else if !stripped.trim().is_empty() && !stripped.starts_with("=====") {
+            report.lines.push(Line::Text(stripped.trim().to_string()));
+        }
+    }
+    res
+}
+
+/// Fill in function and location of an unsymbolized frame using `llvm-symbolizer`
+pub fn symbolize(frame: &mut Frame) {
+    if frame.location.is_some() {
+        return;
+    }
+    let Some((binary, offset)) = &frame.module else {
+        return;
+    };
+    let Ok(output) = environment::tool("llvm-symbolizer")
+        .arg(format!("--obj={binary}"))
+        .arg(offset)
+        .output()
+    else {
+        return;
+    };
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    if let (Some(function), Some(location)) = (lines.next(), lines.next()) {
+        if function != "??" {
+            frame.function = Some(function.to_string());
+        }
+        if !location.starts_with("??") {
+            frame.location = Some(location.to_string());
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use crate::{bases, environment, output::*};
+
+/// Where systemd looks for units of the current user
+fn unit_dir() -> PathBuf {
+    env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".config")))
+        .unwrap_or(env::temp_dir())
+        .join("systemd")
+        .join("user")
+}
+
+/// The unit of the service `name`, which has to stay inside the unit folder
+pub fn unit_file(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains(['/', '\0']) {
+        return Err(format!(
+            "'{name}' can't be a service name, it must not contain '/'"
+        ));
+    }
+    Ok(unit_dir().join(format!("{name}.service")))
+}
+
+/// Escape the specifiers systemd expands in every setting of a unit
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Quote `assignment` for `Environment=`, which takes C-style escapes in double quotes
+fn environment_quote(assignment: &str) -> String {
+    let escaped = escape_specifiers(assignment)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// Quote `arg` as a single word of a systemd command line, without variable or specifier expansion
+fn systemd_quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%$+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg
+    } else {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// A unit running `ros2 run <package> <executable>` with the workspace sourced, restarting it when it exits
+fn unit(workspace: &Path, package: &str, executable: &str, args: &[String]) -> String {
+    let setup = workspace.join(bases::install()).join("setup.bash");
+    let mut command = format!(
+        "/bin/bash -c 'source \"$$0\" && exec ros2 run \"$$@\"' {} {} {}",
+        systemd_quote(&setup.to_string_lossy()),
+        systemd_quote(package),
+        systemd_quote(executable)
+    );
+    for arg in args {
+        command.push(' ');
+        command.push_str(&systemd_quote(arg));
+    }
+    let mut environment = String::new();
+    for var in ["RMW_IMPLEMENTATION", "ROS_DOMAIN_ID", "ROS_LOCALHOST_ONLY"] {
+        if let Ok(value) = env::var(var) {
+            let assignment = environment_quote(&format!("{var}={value}"));
+            environment.push_str(&format!("Environment={assignment}\n"));
+        }
+    }
+    format!(
+        "[Unit]\n\
+         Description={} {} (colb)\n\
+         \n\
+         [Service]\n\
+         WorkingDirectory={}\n\
+         {environment}\
+         ExecStart={command}\n\
+         Restart=on-failure\n\
+         RestartSec=2\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        escape_specifiers(package),
+        escape_specifiers(executable),
+        escape_specifiers(&workspace.to_string_lossy()),
+    )
+}
+
+pub fn systemctl(args: &[&str]) -> ExitStatus {
+    let mut cmd = environment::tool("systemctl");
+    cmd.arg("--user").args(args);
+    print_command(&cmd);
+    run_attached(&mut cmd).expect("'systemctl' not found")
+}
+
+pub fn logs(name: &str, follow: bool) -> ExitStatus {
+    let mut cmd = environment::tool("journalctl");
+    cmd.args(["--user", "-u", name]);
+    if follow {
+        cmd.arg("-f");
+    }
+    print_command(&cmd);
+    run_attached(&mut cmd).expect("'journalctl' not found")
+}
+
+/// Write the unit for the node, then enable and start it
+pub fn install(
+    workspace: &Path,
+    name: &str,
+    package: &str,
+    executable: &str,
+    args: &[String],
+) -> Result<ExitStatus, String> {
+    let file = unit_file(name)?;
+    if !commands_only() {
+        fs::create_dir_all(unit_dir()).map_err(|e| e.to_string())?;
+        fs::write(&file, unit(workspace, package, executable, args))
+            .map_err(|e| format!("Couldn't write '{}': {e}", file.to_string_lossy()))?;
+    }
+    context!("Wrote {}", file.to_string_lossy());
+    let status = systemctl(&["daemon-reload"]);
+    if !status.success() {
+        return Ok(status);
+    }
+    Ok(systemctl(&["enable", "--now", name]))
+}
+
+/// Stop and disable the unit, then remove it
+pub fn uninstall(name: &str) -> Result<ExitStatus, String> {
+    let file = unit_file(name)?;
+    if !file.exists() && !commands_only() {
+        return Err(format!("There is no service '{name}'"));
+    }
+    let status = systemctl(&["disable", "--now", name]);
+    if !commands_only() {
+        fs::remove_file(&file)
+            .map_err(|e| format!("Couldn't remove '{}': {e}", file.to_string_lossy()))?;
+    }
+    if !status.success() {
+        return Ok(status);
+    }
+    Ok(systemctl(&["daemon-reload"]))
+}
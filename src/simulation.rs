@@ -0,0 +1,69 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::bases;
+
+/// Variables Gazebo Classic, Gazebo (gz sim) and Ignition look up models and worlds in
+const VARIABLES: &[&str] = &[
+    "GAZEBO_MODEL_PATH",
+    "GZ_SIM_RESOURCE_PATH",
+    "IGN_GAZEBO_RESOURCE_PATH",
+];
+
+/// `share` folders of the installed packages, so `model://<package>/...` URIs resolve,
+/// plus their `models` folders for models referenced by their name only
+fn install_dirs(install: &Path) -> Vec<PathBuf> {
+    let mut shares = vec![];
+    let merged = install.join("share");
+    if merged.is_dir() {
+        shares.push(merged);
+    } else if let Ok(entries) = fs::read_dir(install) {
+        let mut packages = entries
+            .flatten()
+            .map(|e| e.path().join("share"))
+            .filter(|p| p.is_dir())
+            .collect::<Vec<_>>();
+        packages.sort();
+        shares.extend(packages);
+    }
+    let mut res = vec![];
+    for share in shares {
+        let Ok(entries) = fs::read_dir(&share) else {
+            continue;
+        };
+        let mut models = entries
+            .flatten()
+            .map(|e| e.path().join("models"))
+            .filter(|p| p.is_dir())
+            .collect::<Vec<_>>();
+        models.sort();
+        res.push(share);
+        res.extend(models);
+    }
+    res
+}
+
+/// The simulation resource paths for the workspace's install space and `extra` folders (relative
+/// to the workspace), prepended to what is already set
+pub fn asset_paths(workspace: &Path, extra: &[String]) -> Vec<(String, String)> {
+    let mut dirs = extra.iter().map(|d| workspace.join(d)).collect::<Vec<_>>();
+    dirs.extend(install_dirs(&workspace.join(bases::install())));
+    if dirs.is_empty() {
+        return vec![];
+    }
+    VARIABLES
+        .iter()
+        .map(|var| {
+            let mut paths = dirs.clone();
+            if let Some(old) = env::var_os(var) {
+                paths.extend(env::split_paths(&old).filter(|p| !dirs.contains(p)));
+            }
+            let value = env::join_paths(paths)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (var.to_string(), value)
+        })
+        .collect()
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{environment, verify};
+
+/// Sizes in bytes of the executables and libraries of a package, by path in its install folder
+pub type Sizes = BTreeMap<String, u64>;
+
+fn dir(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("sizes")
+}
+
+/// The executables and libraries (ELF files and static archives) among `paths`, by path relative to
+/// the install prefix `install_dir`
+pub fn measure(install_dir: &Path, paths: &[PathBuf]) -> Sizes {
+    let mut files = vec![];
+    verify::files(paths, &mut files);
+    files
+        .into_iter()
+        .filter(|f| !f.is_symlink())
+        .filter(|f| verify::is_elf(f) || f.extension().is_some_and(|e| e == "a"))
+        .filter_map(|f| {
+            let size = f.metadata().ok()?.len();
+            let relative = f.strip_prefix(install_dir).ok()?;
+            Some((relative.to_string_lossy().to_string(), size))
+        })
+        .collect()
+}
+
+fn load(file: &Path) -> Option<Sizes> {
+    let content = fs::read_to_string(file).ok()?;
+    Some(
+        content
+            .lines()
+            .filter_map(|l| {
+                let (path, size) = l.rsplit_once('\t')?;
+                Some((path.to_string(), size.parse().ok()?))
+            })
+            .collect(),
+    )
+}
+
+fn store(file: &Path, sizes: &Sizes) -> io::Result<()> {
+    let content = sizes
+        .iter()
+        .map(|(path, size)| format!("{path}\t{size}\n"))
+        .collect::<String>();
+    fs::write(file, content)
+}
+
+/// Remember the sizes after a build. The sizes of the build before are kept to compare to, unless
+/// nothing changed
+pub fn record(workspace: &Path, package: &str, sizes: &Sizes) -> io::Result<()> {
+    let dir = dir(workspace);
+    fs::create_dir_all(&dir)?;
+    let current = dir.join(format!("{package}.tsv"));
+    match load(&current) {
+        Some(previous) if previous == *sizes => return Ok(()),
+        Some(_) => fs::rename(&current, dir.join(format!("{package}.previous.tsv")))?,
+        None => {}
+    }
+    store(&current, sizes)
+}
+
+/// The sizes to compare `sizes` to: those of the build before the last one, or of the last one if
+/// the package was built without colb since
+pub fn previous(workspace: &Path, package: &str, sizes: &Sizes) -> Option<Sizes> {
+    let dir = dir(workspace);
+    let current = load(&dir.join(format!("{package}.tsv")))?;
+    if current != *sizes {
+        return Some(current);
+    }
+    load(&dir.join(format!("{package}.previous.tsv")))
+}
+
+/// Bytes in a readable unit
+pub fn human(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{b} B"),
+    }
+}
+
+/// The change from `before` to `after`, e.g. `+1.2 KiB (+3.4%)`
+pub fn growth(before: u64, after: u64) -> String {
+    let sign = if after >= before { '+' } else { '-' };
+    let percent = if before == 0 {
+        String::new()
+    } else {
+        format!(
+            " ({sign}{:.1}%)",
+            after.abs_diff(before) as f64 * 100.0 / before as f64
+        )
+    };
+    format!("{sign}{}{percent}", human(after.abs_diff(before)))
+}
+
+/// The largest symbols of a binary with their sizes, from `bloaty` if it is installed, otherwise `nm`
+pub fn largest_symbols(file: &Path, count: usize) -> Vec<(u64, String)> {
+    let bloaty = environment::tool("bloaty")
+        .args(["-d", "symbols", "--csv", "-n"])
+        .arg(count.to_string())
+        .arg(file)
+        .output();
+    if let Some(output) = bloaty.ok().filter(|o| o.status.success()) {
+        // symbols,vmsize,filesize
+        return String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip(1)
+            .filter_map(|l| {
+                let (rest, filesize) = l.rsplit_once(',')?;
+                let (symbol, _) = rest.rsplit_once(',')?;
+                Some((filesize.parse().ok()?, symbol.trim_matches('"').to_string()))
+            })
+            .filter(|(_, symbol)| !symbol.starts_with('['))
+            .take(count)
+            .collect();
+    }
+    let Ok(output) = environment::tool("nm")
+        .args([
+            "--print-size",
+            "--size-sort",
+            "--reverse-sort",
+            "--demangle",
+        ])
+        .arg(file)
+        .output()
+    else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| {
+            // address size type name
+            let mut fields = l.splitn(4, ' ');
+            let size = u64::from_str_radix(fields.nth(1)?, 16).ok()?;
+            Some((size, fields.nth(1)?.to_string()))
+        })
+        .take(count)
+        .collect()
+}
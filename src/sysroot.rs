@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    process::ExitStatus,
+};
+
+use crate::{environment, output::*};
+
+/// Where a sysroot was assembled from, so it can be updated later
+#[derive(Serialize, Deserialize)]
+pub struct Source {
+    /// A rootfs tarball or directory
+    pub from: Option<String>,
+    /// The distribution suite to debootstrap (e.g. `jammy`), if not assembled from `from`
+    pub suite: Option<String>,
+    pub arch: Option<String>,
+    pub mirror: Option<String>,
+}
+
+pub fn sysroots_dir(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("sysroots")
+}
+
+pub fn sysroot_dir(workspace: &Path, target: &str) -> PathBuf {
+    sysroots_dir(workspace).join(target)
+}
+
+fn source_file(workspace: &Path, target: &str) -> PathBuf {
+    sysroots_dir(workspace).join(format!("{target}.toml"))
+}
+
+pub fn load_source(workspace: &Path, target: &str) -> Option<Source> {
+    let data = fs::read_to_string(source_file(workspace, target)).ok()?;
+    toml::from_str(&data).ok()
+}
+
+fn populate(source: &Source, dir: &Path) -> ExitStatus {
+    let mut cmd = match (&source.from, &source.suite) {
+        (Some(from), _) if Path::new(from).is_dir() => {
+            let mut cmd = environment::tool("cp");
+            cmd.arg("-a").arg(format!("{from}/.")).arg(dir);
+            cmd
+        }
+        (Some(from), _) => {
+            let mut cmd = environment::tool("tar");
+            cmd.arg("-xf").arg(from).arg("-C").arg(dir);
+            cmd.args([
+                "--exclude=./dev/*",
+                "--exclude=./proc/*",
+                "--exclude=./sys/*",
+            ]);
+            cmd
+        }
+        (None, Some(suite)) => {
+            let mut cmd = environment::tool("debootstrap");
+            cmd.args(["--foreign", "--variant=minbase"]);
+            if let Some(arch) = &source.arch {
+                cmd.arg(format!("--arch={arch}"));
+            }
+            cmd.arg(suite).arg(dir);
+            if let Some(mirror) = &source.mirror {
+                cmd.arg(mirror);
+            }
+            cmd
+        }
+        (None, None) => {
+            eprintln!("Either a rootfs to assemble from or a suite to debootstrap is needed");
+            crate::exit(-1);
+        }
+    };
+    print_command(&cmd);
+    run_attached(&mut cmd).unwrap_or_else(|e| {
+        eprintln!(
+            "Couldn't run '{}': {e}",
+            cmd.get_program().to_string_lossy()
+        );
+        crate::exit(-1);
+    })
+}
+
+/// Rewrite absolute symlinks (e.g. `/usr/lib/libfoo.so -> /lib/libfoo.so.1`) to point inside the sysroot
+fn relativize_symlinks(root: &Path, dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut fixed = 0;
+    for e in entries.flatten() {
+        let path = e.path();
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if meta.is_dir() {
+            fixed += relativize_symlinks(root, &path);
+            continue;
+        }
+        if !meta.is_symlink() {
+            continue;
+        }
+        let Ok(target) = fs::read_link(&path) else {
+            continue;
+        };
+        if !target.is_absolute() {
+            continue;
+        }
+        let depth = dir
+            .strip_prefix(root)
+            .map(|d| d.components().count())
+            .unwrap_or(0);
+        let mut relative = PathBuf::new();
+        for _ in 0..depth {
+            relative.push("..");
+        }
+        relative.extend(target.components().filter(|c| *c != Component::RootDir));
+        if fs::remove_file(&path).is_ok() && std::os::unix::fs::symlink(relative, &path).is_ok() {
+            fixed += 1;
+        }
+    }
+    fixed
+}
+
+/// Assemble the sysroot for `target` from scratch and remember where it came from
+pub fn create(workspace: &Path, target: &str, source: &Source) -> ExitStatus {
+    let dir = sysroot_dir(workspace, target);
+    if dir.exists() {
+        print_command_arrow("rm -r ");
+        emit(format_args!("'{}'\n", dir.to_string_lossy()));
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            eprintln!("Couldn't remove old sysroot: {e}");
+            crate::exit(-1);
+        }
+    }
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Couldn't create '{}': {e}", dir.to_string_lossy());
+        crate::exit(-1);
+    }
+    let status = populate(source, &dir);
+    if !status.success() {
+        return status;
+    }
+    let fixed = relativize_symlinks(&dir, &dir);
+    context!("Made {fixed} absolute symlinks relative");
+    let data = toml::to_string_pretty(source).expect("Sysroot source should be serializable");
+    if let Err(e) = fs::write(source_file(workspace, target), data) {
+        eprintln!("Couldn't record sysroot source: {e}");
+    }
+    status
+}
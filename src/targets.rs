@@ -0,0 +1,332 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{backends::Generator, bases, ctest, environment};
+
+/// What a name given with `-t` refers to: the ninja or make target to build and the ctest tests to run
+pub struct Target {
+    /// ninja or make target producing the test executable, None for tests without one (e.g. scripts)
+    pub build: Option<String>,
+    /// Names of the ctest tests running it
+    pub tests: Vec<String>,
+}
+
+impl Target {
+    /// A ctest regex matching exactly the tests of this target
+    pub fn test_regex(&self) -> String {
+        let names = self.tests.iter().map(|t| escape(t)).collect::<Vec<_>>();
+        match &names[..] {
+            [only] => format!("^{only}$"),
+            _ => format!("^({})$", names.join("|")),
+        }
+    }
+}
+
+fn escape(name: &str) -> String {
+    let mut res = String::new();
+    for c in name.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res
+}
+
+/// Names of the ninja or make targets in the build folder of a package, without intermediate files
+fn build_targets(build_dir: &Path) -> Option<Vec<String>> {
+    let generator = Generator::of(build_dir);
+    let mut cmd = match generator {
+        Generator::Ninja => environment::tool("ninja"),
+        Generator::Make => environment::tool("make"),
+    };
+    cmd.arg("-C").arg(build_dir);
+    match generator {
+        Generator::Ninja => cmd.args(["-t", "targets", "all"]),
+        Generator::Make => cmd.args(["--no-print-directory", "help"]),
+    };
+    let output = cmd.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let targets = match generator {
+        Generator::Ninja => stdout
+            .lines()
+            .filter_map(|l| l.rsplit_once(": ").map(|(t, _)| t.to_string()))
+            .collect::<Vec<_>>(),
+        // Lines like `... my_test` or `... all (the default if no target is provided)`
+        Generator::Make => stdout
+            .lines()
+            .filter_map(|l| l.strip_prefix("... ")?.split_whitespace().next())
+            .filter(|t| ![".o", ".i", ".s"].iter().any(|e| t.ends_with(e)))
+            .map(String::from)
+            .collect(),
+    };
+    Some(targets.into_iter().filter(|t| !t.contains('/')).collect())
+}
+
+/// Tests and targets of a package, linked through the executables the tests run
+struct Index {
+    targets: Vec<String>,
+    /// ctest name and the target building its executable
+    tests: Vec<(String, Option<String>)>,
+}
+
+impl Index {
+    fn new(build_dir: &Path) -> Option<Index> {
+        let targets = build_targets(build_dir)?;
+        let tests = ctest::tests_in(build_dir)
+            .into_iter()
+            .map(|t| {
+                let executable = t
+                    .program()
+                    .first()
+                    .and_then(|p| Path::new(p).file_name())
+                    .map(|f| f.to_string_lossy().to_string());
+                let target = executable.filter(|e| targets.contains(e));
+                (t.name, target)
+            })
+            .collect();
+        Some(Index { targets, tests })
+    }
+
+    /// The target for a ctest name, build target or executable name, if it is known exactly
+    fn get(&self, name: &str) -> Option<Target> {
+        if let Some((test, target)) = self.tests.iter().find(|(t, _)| t == name) {
+            return Some(Target {
+                build: target.clone(),
+                tests: vec![test.clone()],
+            });
+        }
+        let target = self.targets.iter().find(|t| *t == name)?;
+        let mut tests = self
+            .tests
+            .iter()
+            .filter(|(_, t)| t.as_ref() == Some(target))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        if tests.is_empty() {
+            tests.push(target.clone());
+        }
+        Some(Target {
+            build: Some(target.clone()),
+            tests,
+        })
+    }
+
+    fn names(&self) -> BTreeSet<&String> {
+        self.targets
+            .iter()
+            .chain(self.tests.iter().map(|(t, _)| t))
+            .collect()
+    }
+}
+
+/// Resolve `name`, which may be a ctest name, a ninja or make target or (the path of) a test executable.
+/// Falls back to the only close match, and exits listing the candidates if there is none or several
+pub fn resolve(workspace: &str, package: &str, name: &str) -> Target {
+    let build_dir = Path::new(workspace).join(bases::build()).join(package);
+    let Some(index) = Index::new(&build_dir) else {
+        return Target {
+            build: Some(name.into()),
+            tests: vec![name.into()],
+        };
+    };
+    let name = if name.contains('/') {
+        Path::new(name)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or(name.into())
+    } else {
+        name.to_string()
+    };
+    if let Some(target) = index.get(&name) {
+        return target;
+    }
+    let names = index.names();
+    let affixed = [
+        format!("test_{name}"),
+        format!("{name}_test"),
+        format!("{name}_tests"),
+        format!("test_{name}s"),
+    ];
+    let mut candidates = names
+        .iter()
+        .filter(|t| affixed.contains(t))
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        candidates = names.iter().filter(|t| t.contains(&name)).collect();
+    }
+    match candidates[..] {
+        [only] => {
+            context!("Using '{only}' for '{name}'");
+            index.get(only).expect("candidates are known names")
+        }
+        [] => {
+            eprintln!("No target or test in '{package}' matches '{name}'");
+            crate::exit(-1);
+        }
+        _ => {
+            eprintln!("'{name}' is ambiguous in '{package}', did you mean one of:");
+            for c in candidates {
+                eprintln!("  {c}");
+            }
+            crate::exit(-1);
+        }
+    }
+}
+
+/// A test named by its source file and optionally a line in it, `test/foo_test.cpp:42`
+pub struct Location {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+}
+
+impl Location {
+    pub fn parse(arg: &str) -> Location {
+        match arg.rsplit_once(':') {
+            Some((file, line)) if line.parse::<usize>().is_ok() => Location {
+                file: file.into(),
+                line: line.parse().ok(),
+            },
+            _ => Location {
+                file: arg.into(),
+                line: None,
+            },
+        }
+    }
+}
+
+/// The target with the object file `object` below the folder, from CMake's
+/// `CMakeFiles/<target>.dir/<path in the package>.o`
+fn target_compiling(dir: &Path, object: &Path) -> Option<String> {
+    let mut folders = vec![];
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() && !path.is_symlink() {
+            folders.push(path);
+        } else if path.ends_with(object) {
+            let dir = path
+                .ancestors()
+                .filter_map(|a| a.file_name()?.to_str()?.strip_suffix(".dir"))
+                .next()?;
+            return Some(dir.to_string());
+        }
+    }
+    folders.sort();
+    folders.iter().find_map(|f| target_compiling(f, object))
+}
+
+const GTEST_MACROS: [&str; 5] = ["TEST", "TEST_F", "TEST_P", "TYPED_TEST", "TYPED_TEST_P"];
+
+/// The gtest filter for the test defined at or above `line`, e.g. `Suite.Name` for `TEST_F(Suite, Name)`
+fn gtest_case(content: &str, line: usize) -> Option<String> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let end = line.min(lines.len());
+    (0..end).rev().find_map(|i| {
+        let text = lines[i].trim_start();
+        let name = text
+            .split(|c: char| !c.is_ascii_uppercase() && c != '_')
+            .next()?;
+        if !GTEST_MACROS.contains(&name) {
+            return None;
+        }
+        let args = lines[i..].join(" ");
+        let args = args[args.find(name)? + name.len()..]
+            .trim_start()
+            .strip_prefix('(')?;
+        let (suite, case) = args.split_once(')')?.0.split_once(',')?;
+        let (suite, case) = (suite.trim(), case.trim());
+        Some(match name {
+            "TEST_P" => format!("*/{suite}.{case}/*"),
+            "TYPED_TEST" | "TYPED_TEST_P" => format!("{suite}/*.{case}"),
+            _ => format!("{suite}.{case}"),
+        })
+    })
+}
+
+fn glob_chars(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob_chars(&pattern[1..], name) || (!name.is_empty() && glob_chars(pattern, &name[1..]))
+        }
+        (Some(p), Some(n)) if *p == '?' || p == n => glob_chars(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Whether a pattern with `*` and `?`, like a gtest filter, matches the whole name
+pub fn glob(pattern: &str, name: &str) -> bool {
+    glob_chars(
+        &pattern.chars().collect::<Vec<_>>(),
+        &name.chars().collect::<Vec<_>>(),
+    )
+}
+
+/// The target compiling the file of `location` in a package that was built before, and the tests
+/// to run. With a line, only the gtest test defined there runs: the matching ctest tests if they
+/// were registered by `gtest_discover_tests`, otherwise the returned `GTEST_FILTER`
+pub fn from_file(
+    workspace: &str,
+    package: &str,
+    source_dir: &Path,
+    location: &Location,
+) -> (Target, Option<String>) {
+    let build_dir = Path::new(workspace).join(bases::build()).join(package);
+    let file = location.file.canonicalize().unwrap_or_else(|e| {
+        eprintln!("Couldn't find '{}': {e}", location.file.to_string_lossy());
+        crate::exit(-1);
+    });
+    let source_dir = source_dir.canonicalize().unwrap_or(source_dir.into());
+    let Ok(relative) = file.strip_prefix(&source_dir) else {
+        eprintln!(
+            "'{}' is not in '{package}' at '{}'",
+            file.to_string_lossy(),
+            source_dir.to_string_lossy()
+        );
+        crate::exit(-1);
+    };
+    let object = relative.with_file_name(format!(
+        "{}.o",
+        relative.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let Some(name) = target_compiling(&build_dir, &object) else {
+        eprintln!(
+            "No target in '{package}' compiles '{}', it needs to be built once",
+            relative.to_string_lossy()
+        );
+        crate::exit(-1);
+    };
+    let mut target = Index::new(&build_dir)
+        .and_then(|index| index.get(&name))
+        .unwrap_or(Target {
+            build: Some(name.clone()),
+            tests: vec![name.clone()],
+        });
+    context!("'{}' is compiled into '{name}'", relative.to_string_lossy());
+    let Some(line) = location.line else {
+        return (target, None);
+    };
+    let content = fs::read_to_string(&file).unwrap_or_default();
+    let Some(filter) = gtest_case(&content, line) else {
+        context!("No gtest test at line {line}, running all tests of '{name}'");
+        return (target, None);
+    };
+    let discovered = target
+        .tests
+        .iter()
+        .filter(|t| glob(&filter, t))
+        .cloned()
+        .collect::<Vec<_>>();
+    if discovered.is_empty() {
+        context!("Only running '{filter}' (GTEST_FILTER)");
+        return (target, Some(filter));
+    }
+    target.tests = discovered;
+    (target, None)
+}
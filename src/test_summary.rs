@@ -0,0 +1,558 @@
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anstyle::{AnsiColor, Color, Style};
+
+use crate::{
+    output::{self, json_string, DECO},
+    owners,
+};
+
+const PASSED: Style = Style::new().fg_color(Some(Color::Ansi(AnsiColor::Green)));
+const FAILED: Style = Style::new()
+    .bold()
+    .fg_color(Some(Color::Ansi(AnsiColor::BrightRed)));
+const SKIPPED: Style = Style::new().fg_color(Some(Color::Ansi(AnsiColor::Yellow)));
+
+/// Lines of captured output shown per failing test
+const OUTPUT_LINES: usize = 40;
+
+/// An XML element, just enough of it for JUnit and CTest result files
+#[derive(Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// All elements called `name` below this one, at any depth
+    fn descendants<'a>(&'a self, name: &str, res: &mut Vec<&'a Element>) {
+        for child in &self.children {
+            if child.name == name {
+                res.push(child);
+            }
+            child.descendants(name, res);
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|n| u32::from_str_radix(n, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                res.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                res.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+fn parse_attributes(mut text: &str) -> Vec<(String, String)> {
+    let mut res = vec![];
+    while let Some((name, rest)) = text.split_once('=') {
+        let rest = rest.trim_start();
+        let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some((value, rest)) = rest[1..].split_once(quote) else {
+            break;
+        };
+        res.push((name.trim().to_string(), unescape(value)));
+        text = rest;
+    }
+    res
+}
+
+/// The position of the `>` ending the tag at the start of `text`, skipping quoted attribute values
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Parse an XML document into its root element, ignoring declarations, comments and namespaces.
+/// Elements left open at the end, e.g. by a test crashing while writing its results, are closed
+fn parse(content: &str) -> Option<Element> {
+    let mut stack = vec![Element::default()];
+    let mut rest = content;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            stack.last_mut()?.text.push_str(&unescape(rest));
+            break;
+        };
+        stack.last_mut()?.text.push_str(&unescape(&rest[..start]));
+        rest = &rest[start..];
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let (text, after) = cdata.split_once("]]>")?;
+            stack.last_mut()?.text.push_str(text);
+            rest = after;
+        } else if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.split_once("-->")?.1;
+        } else if let Some(instruction) = rest.strip_prefix("<?") {
+            rest = instruction.split_once("?>")?.1;
+        } else if rest.starts_with("<!") {
+            rest = &rest[tag_end(rest)? + 1..];
+        } else if let Some(end) = rest.strip_prefix("</") {
+            rest = end.split_once('>')?.1;
+            if stack.len() > 1 {
+                let element = stack.pop()?;
+                stack.last_mut()?.children.push(element);
+            }
+        } else {
+            let end = tag_end(rest)?;
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            let (tag, closed) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            let element = Element {
+                name: name.to_string(),
+                attributes: parse_attributes(attributes),
+                ..Default::default()
+            };
+            if closed {
+                stack.last_mut()?.children.push(element);
+            } else {
+                stack.push(element);
+            }
+        }
+    }
+    while stack.len() > 1 {
+        let element = stack.pop()?;
+        stack.last_mut()?.children.push(element);
+    }
+    stack.pop()?.children.pop()
+}
+
+#[derive(PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// A test case from a result file
+pub struct Case {
+    pub name: String,
+    pub outcome: Outcome,
+    pub seconds: f64,
+    /// Failure message and captured output, for failed tests
+    pub output: String,
+}
+
+/// Test cases of a JUnit file, e.g. from gtest or pytest
+fn junit_cases(root: &Element) -> Vec<Case> {
+    let mut cases = vec![];
+    if root.name == "testcase" {
+        cases.push(root);
+    }
+    root.descendants("testcase", &mut cases);
+    cases
+        .into_iter()
+        .map(|case| {
+            let name = match case.attribute("classname").filter(|c| !c.is_empty()) {
+                Some(class) => format!("{class}.{}", case.attribute("name").unwrap_or_default()),
+                None => case.attribute("name").unwrap_or_default().to_string(),
+            };
+            let failure = case.child("failure").or(case.child("error"));
+            let outcome = if failure.is_some() {
+                Outcome::Failed
+            } else if case.child("skipped").is_some()
+                || matches!(case.attribute("result"), Some("skipped" | "suppressed"))
+                || case.attribute("status") == Some("notrun")
+            {
+                Outcome::Skipped
+            } else {
+                Outcome::Passed
+            };
+            let mut output = String::new();
+            if let Some(failure) = failure {
+                // gtest repeats the message as the text
+                let message = failure.attribute("message").unwrap_or_default().trim();
+                output.push_str(message);
+                output.push('\n');
+                let text = failure.text.trim();
+                if !text.is_empty() && text != message {
+                    output.push_str(text);
+                    output.push('\n');
+                }
+                for stream in ["system-out", "system-err"] {
+                    if let Some(text) = case.child(stream).map(|s| s.text.trim()) {
+                        output.push_str(text);
+                        output.push('\n');
+                    }
+                }
+            }
+            Case {
+                name,
+                outcome,
+                seconds: case
+                    .attribute("time")
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or(0.0),
+                output,
+            }
+        })
+        .collect()
+}
+
+/// Tests of a CTest `Test.xml`
+fn ctest_cases(root: &Element) -> Vec<Case> {
+    let mut tests = vec![];
+    root.descendants("Test", &mut tests);
+    tests
+        .into_iter()
+        .filter_map(|test| {
+            let status = test.attribute("Status")?;
+            let results = test.child("Results");
+            let seconds = results
+                .into_iter()
+                .flat_map(|r| r.children("NamedMeasurement"))
+                .find(|m| m.attribute("name") == Some("Execution Time"))
+                .and_then(|m| m.child("Value"))
+                .and_then(|v| v.text.trim().parse().ok())
+                .unwrap_or(0.0);
+            let outcome = match status {
+                "passed" => Outcome::Passed,
+                "notrun" => Outcome::Skipped,
+                _ => Outcome::Failed,
+            };
+            let output = match outcome {
+                Outcome::Failed => results
+                    .and_then(|r| r.child("Measurement"))
+                    .and_then(|m| m.child("Value"))
+                    .map(|v| v.text.trim().to_string())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            };
+            Some(Case {
+                name: test.child("Name")?.text.trim().to_string(),
+                outcome,
+                seconds,
+                output,
+            })
+        })
+        .collect()
+}
+
+fn xml_files(dir: &Path, res: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() && !path.is_symlink() && name != "CMakeFiles" && name != "Testing" {
+            xml_files(&path, res);
+        } else if name.ends_with(".xml") {
+            res.push(path);
+        }
+    }
+}
+
+/// The `Test.xml` of the most recent ctest run, in `Testing/<timestamp>/`
+fn latest_ctest_file(build_dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(build_dir.join("Testing"))
+        .ok()?
+        .flatten()
+        .map(|e| e.path().join("Test.xml"))
+        .filter(|p| p.is_file())
+        .max_by_key(|p| {
+            p.metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        })
+}
+
+/// The test cases of a CTest or JUnit result file, `None` for other files
+fn cases(content: &str) -> Option<Vec<Case>> {
+    let root = parse(content)?;
+    match root.name.as_str() {
+        "Site" => Some(ctest_cases(&root)),
+        "testsuites" | "testsuite" => Some(junit_cases(&root)),
+        _ => None,
+    }
+}
+
+/// The result files in the build folder of a package with their test cases
+pub fn collect(build_dir: &Path) -> Vec<(PathBuf, Vec<Case>)> {
+    let mut files = vec![];
+    xml_files(build_dir, &mut files);
+    files.sort();
+    let mut res = vec![];
+    for file in latest_ctest_file(build_dir).into_iter().chain(files) {
+        let Some(cases) = fs::read_to_string(&file).ok().and_then(|c| cases(&c)) else {
+            continue;
+        };
+        if !cases.is_empty() {
+            res.push((file, cases));
+        }
+    }
+    res
+}
+
+fn styled(text: &str, style: Style) -> String {
+    if io::stdout().is_terminal() {
+        format!("{style}{text}{style:#}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// The counts and failures as a `test_summary` event, instead of the table with `--output json`
+fn summary_event(build_dir: &Path, results: &[(PathBuf, Vec<Case>)], quarantined: usize) -> bool {
+    let cases = results.iter().flat_map(|(_, c)| c).collect::<Vec<_>>();
+    let count = |outcome: Outcome| cases.iter().filter(|c| c.outcome == outcome).count();
+    let failed = cases
+        .iter()
+        .filter(|c| c.outcome == Outcome::Failed)
+        .map(|c| json_string(&c.name))
+        .collect::<Vec<_>>();
+    let package = build_dir.file_name().unwrap_or_default().to_string_lossy();
+    output::event(
+        "test_summary",
+        &[
+            ("package", json_string(&package)),
+            ("passed", count(Outcome::Passed).to_string()),
+            ("failed", failed.len().to_string()),
+            ("skipped", count(Outcome::Skipped).to_string()),
+            ("quarantined", quarantined.to_string()),
+            ("failures", format!("[{}]", failed.join(","))),
+        ],
+    );
+    failed.is_empty()
+}
+
+/// Print the results as a table per file, all tests or only the failing ones, followed by the
+/// output of the failures with their owners and how many tests were left out as quarantined.
+/// Returns whether all tests passed or were skipped
+pub fn print(
+    build_dir: &Path,
+    results: &[(PathBuf, Vec<Case>)],
+    all: bool,
+    quarantined: usize,
+    owners: &owners::Owners,
+) -> bool {
+    if output::json() {
+        return summary_event(build_dir, results, quarantined);
+    }
+    let mut counts = [0; 3];
+    for (file, cases) in results {
+        let path = file.strip_prefix(build_dir).unwrap_or(file);
+        println!("{}", styled(&path.to_string_lossy(), DECO));
+        for case in cases {
+            let (label, style, count) = match case.outcome {
+                Outcome::Passed => ("passed", PASSED, &mut counts[0]),
+                Outcome::Failed => ("FAILED", FAILED, &mut counts[1]),
+                Outcome::Skipped => ("skipped", SKIPPED, &mut counts[2]),
+            };
+            *count += 1;
+            if all || case.outcome == Outcome::Failed {
+                println!(
+                    "  {}  {:>8.3}s  {}",
+                    styled(&format!("{label:<7}"), style),
+                    case.seconds,
+                    case.name
+                );
+            }
+        }
+    }
+    for (_, cases) in results {
+        for case in cases.iter().filter(|c| c.outcome == Outcome::Failed) {
+            let owned_by = owners::of(owners, &case.name);
+            if owned_by.is_empty() {
+                println!("\n{}", styled(&format!("{}:", case.name), FAILED));
+            } else {
+                println!(
+                    "\n{} (owner: {})",
+                    styled(&format!("{}:", case.name), FAILED),
+                    owned_by.join(", ")
+                );
+            }
+            let lines = case.output.trim().lines().collect::<Vec<_>>();
+            if lines.len() > OUTPUT_LINES {
+                println!("  ... {} lines before", lines.len() - OUTPUT_LINES);
+            }
+            for line in &lines[lines.len().saturating_sub(OUTPUT_LINES)..] {
+                println!("  {line}");
+            }
+        }
+    }
+    let [passed, failed, skipped] = counts;
+    let mut summary = format!(
+        "\n{} tests: {}, {}, {}",
+        passed + failed + skipped,
+        styled(&format!("{passed} passed"), PASSED),
+        styled(
+            &format!("{failed} failed"),
+            if failed > 0 { FAILED } else { DECO }
+        ),
+        styled(&format!("{skipped} skipped"), SKIPPED)
+    );
+    if quarantined > 0 {
+        summary.push_str(&format!(", {quarantined} quarantined left out"));
+    }
+    println!("{summary}");
+    failed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(cases: &[Case]) -> Vec<(&str, &str)> {
+        cases
+            .iter()
+            .map(|c| {
+                let outcome = match c.outcome {
+                    Outcome::Passed => "passed",
+                    Outcome::Failed => "failed",
+                    Outcome::Skipped => "skipped",
+                };
+                (c.name.as_str(), outcome)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gtest() {
+        let cases = cases(include_str!("../tests/fixtures/gtest.xml")).unwrap();
+        assert_eq!(
+            summary(&cases),
+            [
+                ("PlannerTest.PlansAroundObstacle", "passed"),
+                ("PlannerTest.KeepsDistance", "failed"),
+                ("PlannerTest.DISABLED_Replans", "skipped"),
+            ]
+        );
+        assert_eq!(cases[1].seconds, 0.002);
+        // The message is also the CDATA text, it is only shown once
+        assert_eq!(
+            cases[1].output,
+            "/ws/src/planner/test/test_planner.cpp:25\n\
+             Expected: (distance) > (0.5), actual: 0.2 vs 0.5\n"
+        );
+    }
+
+    #[test]
+    fn pytest() {
+        let cases = cases(include_str!("../tests/fixtures/pytest.xml")).unwrap();
+        assert_eq!(
+            summary(&cases),
+            [
+                ("test.test_parser.test_parses_empty", "passed"),
+                ("test.test_parser.test_rejects[<tag>]", "failed"),
+                ("test.test_parser.test_needs_network", "skipped"),
+            ]
+        );
+        let output = &cases[1].output;
+        assert!(output.starts_with("AssertionError: assert 'a < b' == 'a & b'\n  - a & b\n"));
+        assert!(output.contains(">       assert parse(tag) == 'a & b'"));
+        assert!(output.ends_with("parsing <tag>\n"));
+    }
+
+    #[test]
+    fn ctest() {
+        let cases = cases(include_str!("../tests/fixtures/Test.xml")).unwrap();
+        assert_eq!(
+            summary(&cases),
+            [("test_planner", "passed"), ("copyright", "failed")]
+        );
+        assert_eq!(cases[0].seconds, 0.0123);
+        assert!(cases[0].output.is_empty());
+        assert!(cases[1]
+            .output
+            .starts_with("src/planner.cpp: could not find copyright notice\n"));
+        assert!(cases[1].output.ends_with("<testsuite> has 1 failure"));
+    }
+
+    #[test]
+    fn markup_in_attributes() {
+        let root = parse(
+            "<testsuite><testcase name=\"a>b\" classname='x\"y' time=\"1\"/>\
+             <testcase name=\"c\"><failure message=\"1 > 0\"/></testcase></testsuite>",
+        )
+        .unwrap();
+        let cases = junit_cases(&root);
+        assert_eq!(summary(&cases), [("x\"y.a>b", "passed"), ("c", "failed")]);
+        assert_eq!(cases[1].output, "1 > 0\n");
+    }
+
+    #[test]
+    fn entities_and_cdata() {
+        let root =
+            parse("<a>x &lt;&#62;&#x26;&amp;lt; <![CDATA[<b>&amp;]]><!-- <c> --></a>").unwrap();
+        assert_eq!(root.text, "x <>&&lt; <b>&amp;");
+        assert!(root.children.is_empty());
+    }
+
+    #[test]
+    fn unclosed_elements() {
+        let cases = cases(
+            "<testsuites><testsuite name=\"s\"><testcase classname=\"s\" name=\"ok\"/>\
+             <testcase classname=\"s\" name=\"crashed\"><failure message=\"killed\">",
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&cases),
+            [("s.ok", "passed"), ("s.crashed", "failed")]
+        );
+    }
+}
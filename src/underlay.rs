@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{cache, environment, output::*};
+
+/// Describes a prebuilt install space, e.g. a nightly CI artifact
+#[derive(Deserialize)]
+struct Manifest {
+    /// Folder name in `.colb/underlays`
+    name: String,
+    /// Where to download the install space tarball from
+    url: String,
+    sha256: Option<String>,
+}
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tgz", ".tar.xz", ".tar.zst", ".tar"];
+
+pub fn underlays_dir(workspace: &Path) -> PathBuf {
+    workspace.join(".colb").join("underlays")
+}
+
+fn download(url: &str, to: &Path) -> Result<(), String> {
+    let mut cmd = environment::tool("curl");
+    cmd.args(["-fSL", "-o"]).arg(to).arg(url);
+    print_command(&cmd);
+    let status = run_attached(&mut cmd).map_err(|e| format!("Couldn't run 'curl': {e}"))?;
+    if !status.success() {
+        return Err(format!("Downloading '{url}' failed"));
+    }
+    Ok(())
+}
+
+fn load_manifest(url: &str) -> Result<Manifest, String> {
+    if let Some(ext) = ARCHIVE_EXTENSIONS.iter().find(|e| url.ends_with(*e)) {
+        // A plain tarball, name it after the file
+        let file = url.rsplit('/').next().unwrap_or(url);
+        return Ok(Manifest {
+            name: file.trim_end_matches(ext).to_string(),
+            url: url.into(),
+            sha256: None,
+        });
+    }
+    let output = environment::tool("curl")
+        .args(["-fsSL", url])
+        .output()
+        .map_err(|e| format!("Couldn't run 'curl': {e}"))?;
+    if !output.status.success() {
+        return Err(format!("Downloading '{url}' failed"));
+    }
+    toml::from_str(&String::from_utf8_lossy(&output.stdout))
+        .map_err(|e| format!("Couldn't parse manifest: {e}"))
+}
+
+/// Whether `name` is a single folder name, so the manifest can't point outside `.colb/underlays`
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// Download and unpack the install space described by the manifest at `url`, returning its folder
+pub fn fetch(workspace: &Path, url: &str) -> Result<PathBuf, String> {
+    let manifest = load_manifest(url)?;
+    if !is_plain_name(&manifest.name) {
+        return Err(format!(
+            "Invalid underlay name '{}' in '{url}', it must be a single folder name",
+            manifest.name
+        ));
+    }
+    let dir = underlays_dir(workspace);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let tarball = dir.join(format!("{}.download", manifest.name));
+    download(&manifest.url, &tarball)?;
+    if let Some(expected) = &manifest.sha256 {
+        if cache::sha256(&tarball).as_deref() != Some(expected.as_str()) {
+            let _ = fs::remove_file(&tarball);
+            return Err(format!("Checksum mismatch for '{}'", manifest.url));
+        }
+    }
+    let target = dir.join(&manifest.name);
+    if target.exists() {
+        print_command_arrow("rm -r ");
+        emit(format_args!("'{}'\n", target.to_string_lossy()));
+        fs::remove_dir_all(&target).map_err(|e| e.to_string())?;
+    }
+    fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+    let mut tar = environment::tool("tar");
+    tar.arg("-xf").arg(&tarball).arg("-C").arg(&target);
+    print_command(&tar);
+    let unpacked = run_attached(&mut tar).is_ok_and(|s| s.success());
+    let _ = fs::remove_file(&tarball);
+    if !unpacked {
+        return Err(format!("Couldn't unpack '{}'", manifest.url));
+    }
+    Ok(target)
+}
+
+/// The setup script of an underlay, which may be nested in an `install` folder inside the tarball
+pub fn setup_script(underlay: &Path) -> Option<PathBuf> {
+    [underlay.to_path_buf(), underlay.join("install")]
+        .into_iter()
+        .map(|p| p.join("setup.bash"))
+        .find(|p| p.exists())
+}
@@ -0,0 +1,93 @@
+use std::{
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{cache, environment, output::*};
+
+/// Name of the release artifact for the platform colb was built for
+pub fn asset_name() -> String {
+    format!("colb-{}-{}", env::consts::ARCH, env::consts::OS)
+}
+
+fn download(url: &str, to: &Path) -> Result<(), String> {
+    let mut cmd = environment::tool("curl");
+    cmd.args(["-fsSL", "-o"]).arg(to).arg(url);
+    print_command(&cmd);
+    let status = run_attached(&mut cmd).map_err(|e| format!("Couldn't run 'curl': {e}"))?;
+    if !status.success() {
+        return Err(format!("Downloading '{url}' failed"));
+    }
+    Ok(())
+}
+
+/// Checksum published next to the artifact, `sha256sum` output or just the hash
+fn published_checksum(base_url: &str) -> Result<String, String> {
+    let url = format!("{base_url}/{}.sha256", asset_name());
+    let output = environment::tool("curl")
+        .args(["-fsSL", &url])
+        .output()
+        .map_err(|e| format!("Couldn't run 'curl': {e}"))?;
+    if !output.status.success() {
+        return Err(format!("Downloading '{url}' failed"));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(String::from)
+        .ok_or(format!("'{url}' is empty"))
+}
+
+fn verify_signature(file: &Path, signature: &Path, public_key: &str) -> Result<(), String> {
+    let mut cmd = environment::tool("minisign");
+    cmd.arg("-Vm")
+        .arg(file)
+        .arg("-x")
+        .arg(signature)
+        .args(["-P", public_key]);
+    print_command(&cmd);
+    let status = run_attached(&mut cmd).map_err(|e| format!("Couldn't run 'minisign': {e}"))?;
+    if !status.success() {
+        return Err("Signature verification failed".into());
+    }
+    Ok(())
+}
+
+/// Whether the running executable differs from the published one
+pub fn update_available(base_url: &str) -> Result<bool, String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let published = published_checksum(base_url)?;
+    Ok(cache::sha256(&exe).as_deref() != Some(published.as_str()))
+}
+
+/// Replace the running executable with the published one, returning its path
+pub fn self_update(base_url: &str, public_key: Option<&str>) -> Result<PathBuf, String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let expected = published_checksum(base_url)?;
+    // Download next to the executable, so the final rename doesn't cross file systems
+    let new = exe.with_extension("new");
+    let cleanup = |e: String| {
+        let _ = fs::remove_file(&new);
+        let _ = fs::remove_file(new.with_extension("minisig"));
+        e
+    };
+    let asset_url = format!("{base_url}/{}", asset_name());
+    download(&asset_url, &new).map_err(cleanup)?;
+    if commands_only() {
+        return Ok(exe);
+    }
+    if cache::sha256(&new).as_deref() != Some(expected.as_str()) {
+        return Err(cleanup(format!("Checksum mismatch for '{asset_url}'")));
+    }
+    if let Some(key) = public_key {
+        let signature = new.with_extension("minisig");
+        download(&format!("{asset_url}.minisig"), &signature).map_err(cleanup)?;
+        verify_signature(&new, &signature, key).map_err(cleanup)?;
+        let _ = fs::remove_file(&signature);
+    }
+    fs::set_permissions(&new, fs::Permissions::from_mode(0o755))
+        .map_err(|e| cleanup(e.to_string()))?;
+    fs::rename(&new, &exe).map_err(|e| cleanup(format!("Couldn't replace executable: {e}")))?;
+    Ok(exe)
+}
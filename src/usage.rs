@@ -0,0 +1,126 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Instant, SystemTime},
+};
+
+/// Invocation being recorded: when it started, the verb and the flags given on the command line
+static CURRENT: OnceLock<(Instant, String, Vec<String>)> = OnceLock::new();
+
+/// Local file with one line per invocation, never sent anywhere
+pub fn usage_file() -> PathBuf {
+    env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".local").join("share")))
+        .unwrap_or(env::temp_dir())
+        .join("colb")
+        .join("usage.tsv")
+}
+
+/// Start recording the current invocation, it is written by [`finish`]
+pub fn start(verb: &str, flags: Vec<String>) {
+    let _ = CURRENT.set((Instant::now(), verb.into(), flags));
+}
+
+/// Append the current invocation with its duration and exit code, if recording was started
+pub fn finish(code: i32) {
+    let Some((started, verb, flags)) = CURRENT.get() else {
+        return;
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let file = usage_file();
+    if let Some(dir) = file.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    // Recording is best effort, never fail the actual command because of it
+    if let Ok(mut f) = fs::OpenOptions::new().create(true).append(true).open(&file) {
+        let _ = writeln!(
+            f,
+            "{timestamp}\t{verb}\t{}\t{:.3}\t{code}",
+            flags.join(","),
+            started.elapsed().as_secs_f64()
+        );
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    count: usize,
+    failures: usize,
+    seconds: f64,
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    let s = seconds.round() as u64;
+    if s >= 3600 {
+        format!("{}h {:02}m", s / 3600, (s % 3600) / 60)
+    } else if s >= 60 {
+        format!("{}m {:02}s", s / 60, s % 60)
+    } else {
+        format!("{seconds:.1}s")
+    }
+}
+
+fn table(title: &str, counters: BTreeMap<String, Counter>) -> String {
+    let mut rows = counters.into_iter().collect::<Vec<_>>();
+    rows.sort_by(|a, b| b.1.seconds.total_cmp(&a.1.seconds));
+    let width = rows
+        .iter()
+        .map(|(k, _)| k.len())
+        .max()
+        .unwrap_or(0)
+        .max(title.len());
+    let mut res = format!(
+        "{title:<width$}  {:>6}  {:>8}  {:>10}  {:>10}\n",
+        "runs", "failed", "total", "mean"
+    );
+    for (name, c) in rows {
+        res.push_str(&format!(
+            "{name:<width$}  {:>6}  {:>8}  {:>10}  {:>10}\n",
+            c.count,
+            c.failures,
+            format_duration(c.seconds),
+            format_duration(c.seconds / c.count as f64)
+        ));
+    }
+    res
+}
+
+/// Time spent per verb and per flag, from the recorded invocations
+pub fn summary() -> Option<String> {
+    let data = fs::read_to_string(usage_file()).ok()?;
+    let mut verbs = BTreeMap::<String, Counter>::new();
+    let mut flags = BTreeMap::<String, Counter>::new();
+    for line in data.lines() {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let [_, verb, used, seconds, code] = fields[..] else {
+            continue;
+        };
+        let seconds = seconds.parse::<f64>().unwrap_or_default();
+        let failed = code != "0";
+        let add = |c: &mut Counter| {
+            c.count += 1;
+            c.seconds += seconds;
+            c.failures += failed as usize;
+        };
+        add(verbs.entry(verb.into()).or_default());
+        for flag in used.split(',').filter(|f| !f.is_empty()) {
+            add(flags.entry(format!("{verb} --{flag}")).or_default());
+        }
+    }
+    if verbs.is_empty() {
+        return None;
+    }
+    let mut res = table("verb", verbs);
+    if !flags.is_empty() {
+        res.push('\n');
+        res.push_str(&table("flag", flags));
+    }
+    Some(res)
+}
@@ -0,0 +1,177 @@
+use std::{
+    env, fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::environment;
+
+/// Something in an install folder that will fail at runtime
+pub struct Problem {
+    pub path: PathBuf,
+    pub what: String,
+}
+
+/// Checks `python3` runs on the installed modules, without writing byte code next to them
+const COMPILE: &str = "import sys
+for f in sys.argv[1:]:
+    try:
+        compile(open(f, 'rb').read(), f, 'exec')
+    except (SyntaxError, ValueError) as e:
+        print(f'{f}\\t{getattr(e, \"lineno\", None) or 0}\\t{e.msg if isinstance(e, SyntaxError) else e}')
+";
+
+/// Files below `paths` without following symlinks, with the symlinks among them
+pub fn files(paths: &[PathBuf], res: &mut Vec<PathBuf>) {
+    for path in paths {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .map(|e| e.flatten().map(|e| e.path()).collect::<Vec<_>>())
+                .unwrap_or_default();
+            entries.sort();
+            files(&entries, res);
+        } else {
+            res.push(path.clone());
+        }
+    }
+}
+
+pub fn is_elf(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok_and(|_| magic == *b"\x7fELF")
+}
+
+/// Libraries `ldd` can't find for an executable or shared library
+fn missing_libraries(path: &Path, env: &[(String, String)]) -> Vec<String> {
+    let Ok(output) = environment::tool("ldd")
+        .arg(path)
+        .envs(env.to_vec())
+        .output()
+    else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| l.contains("=> not found"))
+        .filter_map(|l| l.split_whitespace().next().map(String::from))
+        .collect()
+}
+
+/// Broken symlinks, e.g. into sources deleted after a `--symlink-install` build, executables and
+/// libraries with missing shared libraries and Python modules that don't compile, in `paths`. Shared
+/// libraries are looked up in the environment `env` of the sourced workspace
+pub fn check(paths: &[PathBuf], env: &[(String, String)]) -> Vec<Problem> {
+    let mut all = vec![];
+    files(paths, &mut all);
+    let mut res = vec![];
+    let mut modules = vec![];
+    for path in all {
+        let is_symlink = fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink());
+        if is_symlink && !path.exists() {
+            let target = fs::read_link(&path).unwrap_or_default();
+            res.push(Problem {
+                what: format!("broken symlink to '{}'", target.to_string_lossy()),
+                path,
+            });
+            continue;
+        }
+        if path.extension().is_some_and(|e| e == "py") {
+            modules.push(path);
+        } else if is_elf(&path) {
+            for library in missing_libraries(&path, env) {
+                res.push(Problem {
+                    path: path.clone(),
+                    what: format!("'{library}' not found"),
+                });
+            }
+        }
+    }
+    if modules.is_empty() {
+        return res;
+    }
+    let output = environment::tool("python3")
+        .args(["-c", COMPILE])
+        .args(&modules)
+        .output();
+    let Ok(output) = output else {
+        return res;
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(path), Some(line), Some(message)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        res.push(Problem {
+            path: path.into(),
+            what: format!("doesn't compile, line {line}: {message}"),
+        });
+    }
+    res
+}
+
+/// The RPATH and RUNPATH entries of an executable or shared library, as `readelf` prints them
+fn search_paths(path: &Path) -> Vec<(&'static str, String)> {
+    let Ok(output) = environment::tool("readelf").arg("-d").arg(path).output() else {
+        return vec![];
+    };
+    let mut res = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let kind = if line.contains("(RUNPATH)") {
+            "RUNPATH"
+        } else if line.contains("(RPATH)") {
+            "RPATH"
+        } else {
+            continue;
+        };
+        let Some((_, paths)) = line.split_once('[') else {
+            continue;
+        };
+        let paths = paths.trim_end().trim_end_matches(']');
+        res.extend(
+            paths
+                .split(':')
+                .filter(|p| !p.is_empty())
+                .map(|p| (kind, p.to_string())),
+        );
+    }
+    res
+}
+
+/// Executables and libraries in `paths` with RPATH or RUNPATH entries that only exist on this
+/// machine: in the build folder `build`, elsewhere in the `workspace`, in a home folder or `/tmp`.
+/// Entries relative to `$ORIGIN` and system paths like `/opt/ros` are fine
+pub fn non_relocatable(paths: &[PathBuf], workspace: &Path, build: &Path) -> Vec<Problem> {
+    let mut all = vec![];
+    files(paths, &mut all);
+    let home = env::var("HOME").ok().filter(|h| h != "/");
+    let mut res = vec![];
+    for path in all.into_iter().filter(|p| p.exists() && is_elf(p)) {
+        for (kind, entry) in search_paths(&path) {
+            let entry_path = Path::new(&entry);
+            let place = if entry.starts_with("$ORIGIN") || entry.starts_with("${ORIGIN}") {
+                continue;
+            } else if entry_path.starts_with(build) {
+                "the build folder"
+            } else if entry_path.starts_with(workspace) {
+                "the workspace"
+            } else if home.as_ref().is_some_and(|h| entry_path.starts_with(h)) {
+                "a home folder"
+            } else if entry_path.starts_with("/tmp") {
+                "/tmp"
+            } else {
+                continue;
+            };
+            res.push(Problem {
+                path: path.clone(),
+                what: format!("{kind} '{entry}' points into {place}"),
+            });
+        }
+    }
+    res
+}
@@ -0,0 +1,49 @@
+use std::{env, sync::OnceLock};
+
+use crate::environment;
+
+/// First line of a tool's version output, if it could be run
+fn first_line(program: &str, args: &[&str]) -> Option<String> {
+    let output = environment::tool(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|l| l.trim().to_string())
+}
+
+static VERSIONS: OnceLock<Vec<(&'static str, String)>> = OnceLock::new();
+
+/// Versions of the tools a build depends on, for bug reports. Each tool only runs once per invocation
+pub fn tool_versions() -> &'static [(&'static str, String)] {
+    VERSIONS.get_or_init(|| {
+        let compiler = env::var("CXX").unwrap_or("c++".into());
+        let probes: [(&str, &str, &[&str]); 6] = [
+            (
+                "colcon",
+                "python3",
+                &[
+                    "-c",
+                    "import importlib.metadata as m; print('colcon-core', m.version('colcon-core'))",
+                ],
+            ),
+            ("cmake", "cmake", &["--version"]),
+            ("ninja", "ninja", &["--version"]),
+            ("ccache", "ccache", &["--version"]),
+            ("mold", "mold", &["--version"]),
+            ("compiler", &compiler, &["--version"]),
+        ];
+        let mut res = probes
+            .into_iter()
+            .map(|(name, program, args)| {
+                let version = first_line(program, args).unwrap_or("not found".into());
+                (name, version)
+            })
+            .collect::<Vec<_>>();
+        res.push((
+            "ROS distro",
+            env::var("ROS_DISTRO").unwrap_or("not sourced".into()),
+        ));
+        res
+    })
+}
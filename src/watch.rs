@@ -0,0 +1,129 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+/// How long the sources have to stay unchanged before rebuilding, so saving several files only rebuilds once
+const DEBOUNCE: Duration = Duration::from_millis(800);
+
+/// Folders written by builds and interpreters rather than edited, also when they end up inside a
+/// source folder, e.g. from an in-source CMake build, `pip install -e` or `cargo build`
+const GENERATED_DIRS: &[&str] = &[
+    "__pycache__",
+    "build",
+    "install",
+    "log",
+    "target",
+    "CMakeFiles",
+];
+/// Extensions of generated files, e.g. bytecode compiled when a test imports a module
+const GENERATED_EXTENSIONS: &[&str] = &["pyc", "pyo"];
+
+/// Whether changes of `name` don't warrant a rebuild
+fn ignored(name: &str, is_dir: bool) -> bool {
+    if name.starts_with('.') || name.ends_with('~') {
+        return true;
+    }
+    if is_dir {
+        return GENERATED_DIRS.contains(&name) || name.ends_with(".egg-info");
+    }
+    Path::new(name)
+        .extension()
+        .is_some_and(|e| GENERATED_EXTENSIONS.iter().any(|g| e == *g))
+}
+
+/// Whether `path` below one of `dirs` is a source file, rather than hidden, a backup or generated
+fn relevant(dirs: &[PathBuf], path: &Path) -> bool {
+    let Some(relative) = dirs.iter().find_map(|d| path.strip_prefix(d).ok()) else {
+        return false;
+    };
+    let names = relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let Some((file, folders)) = names.split_last() else {
+        return false;
+    };
+    !folders.iter().any(|f| ignored(f, true)) && !ignored(file, path.is_dir())
+}
+
+/// Watches source folders for changes through the operating system (inotify on Linux), without
+/// reading the folders again while nothing changes
+pub struct Watcher {
+    dirs: Vec<PathBuf>,
+    events: Receiver<notify::Result<notify::Event>>,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl Watcher {
+    /// Start watching everything below `dirs`, changes from now on are reported by
+    /// [`Watcher::wait_for_changes`]
+    pub fn new(dirs: &[PathBuf]) -> Result<Watcher, String> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|e| format!("Couldn't watch for changes: {e}"))?;
+        for dir in dirs {
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .map_err(|e| format!("Couldn't watch '{}': {e}", dir.to_string_lossy()))?;
+        }
+        // Events name canonical paths
+        let dirs = dirs
+            .iter()
+            .map(|d| d.canonicalize().unwrap_or(d.clone()))
+            .collect();
+        Ok(Watcher {
+            dirs,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// The source files an event is about, reads and other accesses don't count
+    fn changed(&self, event: notify::Result<notify::Event>) -> Vec<PathBuf> {
+        let Ok(event) = event else {
+            return vec![];
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return vec![];
+        }
+        event
+            .paths
+            .into_iter()
+            .filter(|p| relevant(&self.dirs, p))
+            .collect()
+    }
+
+    /// Block until files change and then stay unchanged for a moment, returns the changed files
+    pub fn wait_for_changes(&self) -> Vec<PathBuf> {
+        let mut res = vec![];
+        while res.is_empty() {
+            let Ok(event) = self.events.recv() else {
+                return res;
+            };
+            res.extend(self.changed(event));
+        }
+        // Wait for the editor or git checkout to finish, ignored files don't extend the wait
+        let mut quiet_from = Instant::now() + DEBOUNCE;
+        while let Some(left) = quiet_from.checked_duration_since(Instant::now()) {
+            let Ok(event) = self.events.recv_timeout(left) else {
+                break;
+            };
+            let changed = self.changed(event);
+            if !changed.is_empty() {
+                res.extend(changed);
+                quiet_from = Instant::now() + DEBOUNCE;
+            }
+        }
+        res.sort();
+        res.dedup();
+        res
+    }
+}